@@ -1,38 +1,314 @@
 -- pg_lexo upgrade from 0.5.0 to 0.6.0
--- 
+--
 -- This migration removes the lexo schema and moves all objects to the default schema.
 -- Type renamed: lexo.lexorank -> lexo
 -- Functions renamed: lexo.X() -> lexo_X()
 --
--- IMPORTANT: This is a breaking change. After running this migration:
--- 1. Tables with lexo.lexorank columns need to be altered
--- 2. All function calls need to be updated
+-- IMPORTANT: This is a breaking change. Before running this migration, convert
+-- columns of type lexo.lexorank to text; the update refuses to run while any
+-- remain, since dropping the old type would drop those columns with it:
+--
+--   ALTER TABLE items ALTER COLUMN position TYPE text USING position::text;
+--   ALTER EXTENSION pg_lexo UPDATE TO '0.6.0';
+--   ALTER TABLE items ALTER COLUMN position TYPE lexo USING position::lexo;
 --
--- Note: The actual type and function definitions are auto-generated by pgrx
--- during the extension upgrade process.
+-- All function calls need to be updated to the new names.
+
+\echo Use "ALTER EXTENSION pg_lexo UPDATE TO '0.6.0'" to load this file. \quit
+
+DO $$
+DECLARE
+    columns text;
+BEGIN
+    IF to_regtype('lexo.lexorank') IS NULL THEN
+        RETURN;
+    END IF;
+
+    SELECT string_agg(a.attrelid::regclass || '.' || quote_ident(a.attname), ', ')
+    INTO columns
+    FROM pg_catalog.pg_attribute a
+    JOIN pg_catalog.pg_class c ON c.oid = a.attrelid
+    WHERE a.atttypid = to_regtype('lexo.lexorank')
+      AND NOT a.attisdropped
+      AND c.relkind IN ('r', 'p', 'm', 'f');
+
+    IF columns IS NOT NULL THEN
+        RAISE EXCEPTION 'columns still use lexo.lexorank: %', columns
+            USING HINT = 'Convert them to text before updating, then to lexo afterwards.';
+    END IF;
+END $$;
+
+-- Drop old schema-based objects
+DROP FUNCTION IF EXISTS lexo.first();
+DROP FUNCTION IF EXISTS lexo.after(lexo.lexorank);
+DROP FUNCTION IF EXISTS lexo.before(lexo.lexorank);
+DROP FUNCTION IF EXISTS lexo.between(lexo.lexorank, lexo.lexorank);
+DROP FUNCTION IF EXISTS lexo.next(text, text, text, text);
+DROP FUNCTION IF EXISTS lexo.add_lexo_column_to(text, text);
+DROP FUNCTION IF EXISTS lexo.rebalance(text, text, text, text);
+DROP FUNCTION IF EXISTS lexo.after_text(text);
+DROP FUNCTION IF EXISTS lexo.before_text(text);
+DROP FUNCTION IF EXISTS lexo.first_text();
+DROP FUNCTION IF EXISTS lexo.between_text(text, text);
 
--- Drop old schema-based objects if they exist
+-- Drops the operators, operator classes and I/O functions along with the type
+DROP TYPE IF EXISTS lexo.lexorank CASCADE;
+
+-- Drop the lexo schema, unless it holds objects of its own
 DO $$
 BEGIN
-    -- Drop old functions from lexo schema
-    DROP FUNCTION IF EXISTS lexo.first() CASCADE;
-    DROP FUNCTION IF EXISTS lexo.after(lexo.lexorank) CASCADE;
-    DROP FUNCTION IF EXISTS lexo.before(lexo.lexorank) CASCADE;
-    DROP FUNCTION IF EXISTS lexo.between(lexo.lexorank, lexo.lexorank) CASCADE;
-    DROP FUNCTION IF EXISTS lexo.next(text, text, text, text) CASCADE;
-    DROP FUNCTION IF EXISTS lexo.add_lexo_column_to(text, text) CASCADE;
-    DROP FUNCTION IF EXISTS lexo.rebalance(text, text, text, text) CASCADE;
-    DROP FUNCTION IF EXISTS lexo.after_text(text) CASCADE;
-    DROP FUNCTION IF EXISTS lexo.before_text(text) CASCADE;
-    DROP FUNCTION IF EXISTS lexo.first_text() CASCADE;
-    DROP FUNCTION IF EXISTS lexo.between_text(text, text) CASCADE;
-    
-    -- Drop old type from lexo schema
-    DROP TYPE IF EXISTS lexo.lexorank CASCADE;
-    
-    -- Drop lexo schema if empty
-    DROP SCHEMA IF EXISTS lexo CASCADE;
-EXCEPTION
-    WHEN OTHERS THEN
-        RAISE NOTICE 'Error during cleanup: %', SQLERRM;
+    IF to_regnamespace('lexo') IS NULL THEN
+        RETURN;
+    END IF;
+
+    IF EXISTS (SELECT 1 FROM pg_catalog.pg_class WHERE relnamespace = to_regnamespace('lexo'))
+       OR EXISTS (SELECT 1 FROM pg_catalog.pg_proc WHERE pronamespace = to_regnamespace('lexo'))
+       OR EXISTS (SELECT 1 FROM pg_catalog.pg_type WHERE typnamespace = to_regnamespace('lexo')) THEN
+        RAISE NOTICE 'schema lexo is not empty and was left in place';
+    ELSE
+        DROP SCHEMA lexo;
+    END IF;
 END $$;
+
+-- Objects of 0.6.0, as generated by pgrx
+
+-- pg_lexo::Lexo
+CREATE TYPE Lexo;
+
+-- pg_lexo::lexo_in
+CREATE FUNCTION "lexo_in"(
+	"input" cstring /* core::option::Option<&core::ffi::c_str::CStr> */
+) RETURNS Lexo /* core::option::Option<pg_lexo::Lexo> */
+IMMUTABLE PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_in_wrapper';
+
+-- pg_lexo::lexo_out
+CREATE FUNCTION "lexo_out"(
+	"input" Lexo /* pg_lexo::Lexo */
+) RETURNS cstring /* alloc::ffi::c_str::CString */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_out_wrapper';
+
+-- pg_lexo::Lexo
+CREATE TYPE Lexo (
+	INTERNALLENGTH = variable,
+	INPUT = lexo_in, /* pg_lexo::lexo_in */
+	OUTPUT = lexo_out, /* pg_lexo::lexo_out */
+	STORAGE = extended
+);
+
+-- pg_lexo::schema::lexo_add_column
+CREATE FUNCTION "lexo_add_column"(
+	"table_name" TEXT, /* &str */
+	"column_name" TEXT /* &str */
+) RETURNS void
+STRICT
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_add_column_wrapper';
+
+-- pg_lexo::schema::lexo_after
+CREATE FUNCTION "lexo_after"(
+	"current" Lexo /* pg_lexo::Lexo */
+) RETURNS Lexo /* pg_lexo::Lexo */
+STRICT
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_after_wrapper';
+
+-- pg_lexo::schema::lexo_before
+CREATE FUNCTION "lexo_before"(
+	"current" Lexo /* pg_lexo::Lexo */
+) RETURNS Lexo /* pg_lexo::Lexo */
+STRICT
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_before_wrapper';
+
+-- pg_lexo::schema::lexo_between
+CREATE FUNCTION "lexo_between"(
+	"before_pos" Lexo, /* core::option::Option<pg_lexo::Lexo> */
+	"after_pos" Lexo /* core::option::Option<pg_lexo::Lexo> */
+) RETURNS Lexo /* pg_lexo::Lexo */
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_between_wrapper';
+
+-- pg_lexo::lexo_cmp
+CREATE FUNCTION "lexo_cmp"(
+	"left" Lexo, /* pg_lexo::Lexo */
+	"right" Lexo /* pg_lexo::Lexo */
+) RETURNS INT /* i32 */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_cmp_wrapper';
+
+-- pg_lexo::lexo_eq
+CREATE FUNCTION "lexo_eq"(
+	"left" Lexo, /* pg_lexo::Lexo */
+	"right" Lexo /* pg_lexo::Lexo */
+) RETURNS bool /* bool */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_eq_wrapper';
+
+-- pg_lexo::lexo_eq
+CREATE OPERATOR = (
+	PROCEDURE="lexo_eq",
+	LEFTARG=Lexo, /* pg_lexo::Lexo */
+	RIGHTARG=Lexo, /* pg_lexo::Lexo */
+	COMMUTATOR = =,
+	NEGATOR = <>,
+	RESTRICT = eqsel,
+	JOIN = eqjoinsel,
+	HASHES,
+	MERGES
+);
+
+-- pg_lexo::schema::lexo_first
+CREATE FUNCTION "lexo_first"() RETURNS Lexo /* pg_lexo::Lexo */
+STRICT
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_first_wrapper';
+
+-- pg_lexo::lexo_ge
+CREATE FUNCTION "lexo_ge"(
+	"left" Lexo, /* pg_lexo::Lexo */
+	"right" Lexo /* pg_lexo::Lexo */
+) RETURNS bool /* bool */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_ge_wrapper';
+
+-- pg_lexo::lexo_ge
+CREATE OPERATOR >= (
+	PROCEDURE="lexo_ge",
+	LEFTARG=Lexo, /* pg_lexo::Lexo */
+	RIGHTARG=Lexo, /* pg_lexo::Lexo */
+	COMMUTATOR = <=,
+	NEGATOR = <,
+	RESTRICT = scalargesel,
+	JOIN = scalargejoinsel
+);
+
+-- pg_lexo::lexo_gt
+CREATE FUNCTION "lexo_gt"(
+	"left" Lexo, /* pg_lexo::Lexo */
+	"right" Lexo /* pg_lexo::Lexo */
+) RETURNS bool /* bool */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_gt_wrapper';
+
+-- pg_lexo::lexo_gt
+CREATE OPERATOR > (
+	PROCEDURE="lexo_gt",
+	LEFTARG=Lexo, /* pg_lexo::Lexo */
+	RIGHTARG=Lexo, /* pg_lexo::Lexo */
+	COMMUTATOR = <,
+	NEGATOR = <=,
+	RESTRICT = scalargtsel,
+	JOIN = scalargtjoinsel
+);
+
+-- pg_lexo::lexo_hash
+CREATE FUNCTION "lexo_hash"(
+	"value" Lexo /* pg_lexo::Lexo */
+) RETURNS INT /* i32 */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_hash_wrapper';
+
+-- pg_lexo::lexo_le
+CREATE FUNCTION "lexo_le"(
+	"left" Lexo, /* pg_lexo::Lexo */
+	"right" Lexo /* pg_lexo::Lexo */
+) RETURNS bool /* bool */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_le_wrapper';
+
+-- pg_lexo::lexo_le
+CREATE OPERATOR <= (
+	PROCEDURE="lexo_le",
+	LEFTARG=Lexo, /* pg_lexo::Lexo */
+	RIGHTARG=Lexo, /* pg_lexo::Lexo */
+	COMMUTATOR = >=,
+	NEGATOR = >,
+	RESTRICT = scalarlesel,
+	JOIN = scalarlejoinsel
+);
+
+-- pg_lexo::lexo_lt
+CREATE FUNCTION "lexo_lt"(
+	"left" Lexo, /* pg_lexo::Lexo */
+	"right" Lexo /* pg_lexo::Lexo */
+) RETURNS bool /* bool */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_lt_wrapper';
+
+-- pg_lexo::lexo_lt
+CREATE OPERATOR < (
+	PROCEDURE="lexo_lt",
+	LEFTARG=Lexo, /* pg_lexo::Lexo */
+	RIGHTARG=Lexo, /* pg_lexo::Lexo */
+	COMMUTATOR = >,
+	NEGATOR = >=,
+	RESTRICT = scalarltsel,
+	JOIN = scalarltjoinsel
+);
+
+-- pg_lexo::lexo_ne
+CREATE FUNCTION "lexo_ne"(
+	"left" Lexo, /* pg_lexo::Lexo */
+	"right" Lexo /* pg_lexo::Lexo */
+) RETURNS bool /* bool */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_ne_wrapper';
+
+-- pg_lexo::lexo_ne
+CREATE OPERATOR <> (
+	PROCEDURE="lexo_ne",
+	LEFTARG=Lexo, /* pg_lexo::Lexo */
+	RIGHTARG=Lexo, /* pg_lexo::Lexo */
+	COMMUTATOR = <>,
+	NEGATOR = =,
+	RESTRICT = neqsel,
+	JOIN = neqjoinsel
+);
+
+-- pg_lexo::schema::lexo_next
+CREATE FUNCTION "lexo_next"(
+	"table_name" TEXT, /* &str */
+	"lexo_column_name" TEXT, /* &str */
+	"identifier_column_name" TEXT, /* core::option::Option<&str> */
+	"identifier_value" TEXT /* core::option::Option<&str> */
+) RETURNS Lexo /* pg_lexo::Lexo */
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_next_wrapper';
+
+-- pg_lexo::schema::lexo_rebalance
+CREATE FUNCTION "lexo_rebalance"(
+	"table_name" TEXT, /* &str */
+	"lexo_column_name" TEXT, /* &str */
+	"key_column_name" TEXT, /* core::option::Option<&str> */
+	"key_value" TEXT /* core::option::Option<&str> */
+) RETURNS bigint /* i64 */
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_rebalance_wrapper';
+
+-- pg_lexo::Lexo
+CREATE OPERATOR FAMILY Lexo_btree_ops USING btree;
+CREATE OPERATOR CLASS Lexo_btree_ops DEFAULT FOR TYPE Lexo USING btree FAMILY Lexo_btree_ops AS
+	OPERATOR 1 <,
+	OPERATOR 2 <=,
+	OPERATOR 3 =,
+	OPERATOR 4 >=,
+	OPERATOR 5 >,
+	FUNCTION 1 lexo_cmp(Lexo, Lexo);
+
+-- pg_lexo::Lexo
+CREATE OPERATOR FAMILY Lexo_hash_ops USING hash;
+CREATE OPERATOR CLASS Lexo_hash_ops DEFAULT FOR TYPE Lexo USING hash FAMILY Lexo_hash_ops AS
+	OPERATOR    1   =  (Lexo, Lexo),
+	FUNCTION    1   lexo_hash(Lexo);
@@ -0,0 +1,1800 @@
+-- pg_lexo upgrade from 0.6.0 to 0.7.0
+--
+-- Adds the objects introduced since 0.6.0. Existing objects, and the data in
+-- lexo columns, are left in place; functions whose signature changed are
+-- dropped and recreated.
+
+\echo Use "ALTER EXTENSION pg_lexo UPDATE TO '0.7.0'" to load this file. \quit
+
+CREATE TYPE smalllexo;
+
+CREATE TABLE lexo_rebalance_schedules (
+    table_name regclass NOT NULL,
+    column_name name NOT NULL,
+    key_column name,
+    schedule text NOT NULL,
+    max_length integer NOT NULL CHECK (max_length > 0),
+    job_id bigint NOT NULL,
+    PRIMARY KEY (table_name, column_name)
+);
+SELECT pg_catalog.pg_extension_config_dump('lexo_rebalance_schedules', '');
+
+CREATE FUNCTION lexo_schedules()
+RETURNS TABLE (table_name regclass, column_name name, key_column name,
+               schedule text, max_length integer, job_id bigint)
+LANGUAGE sql STABLE
+AS $$
+    SELECT table_name, column_name, key_column, schedule, max_length, job_id
+    FROM @extschema@.lexo_rebalance_schedules
+    ORDER BY table_name::text, column_name
+$$;
+
+CREATE TABLE lexo_autorebalance_targets (
+    table_name regclass NOT NULL,
+    column_name name NOT NULL,
+    key_column name,
+    max_length integer NOT NULL DEFAULT 16 CHECK (max_length > 0),
+    PRIMARY KEY (table_name, column_name)
+);
+SELECT pg_catalog.pg_extension_config_dump('lexo_autorebalance_targets', '');
+
+CREATE TABLE lexo_ordered_views (
+    view_name regclass PRIMARY KEY,
+    table_name regclass NOT NULL,
+    column_name name NOT NULL,
+    key_column name
+);
+SELECT pg_catalog.pg_extension_config_dump('lexo_ordered_views', '');
+
+CREATE TABLE lexo_sequence_appends (
+    table_name regclass NOT NULL,
+    column_name name NOT NULL,
+    prefix text NOT NULL,
+    sequence_name regclass NOT NULL,
+    key_column name,
+    PRIMARY KEY (table_name, column_name)
+);
+SELECT pg_catalog.pg_extension_config_dump('lexo_sequence_appends', '');
+
+CREATE FUNCTION lexo_unique_check() RETURNS trigger
+LANGUAGE plpgsql
+SET search_path = pg_catalog, @extschema@, pg_temp AS $$
+DECLARE
+    rank_column text := TG_ARGV[0];
+    key_column text := TG_ARGV[1];
+    rank text;
+    list_key text;
+    matches bigint;
+BEGIN
+    EXECUTE format('SELECT ($1).%I::text', rank_column) INTO rank USING NEW;
+    IF rank IS NULL THEN
+        RETURN NULL;
+    END IF;
+
+    IF key_column IS NULL THEN
+        EXECUTE format('SELECT count(*) FROM %I.%I WHERE %I = ($1).%I',
+                       TG_TABLE_SCHEMA, TG_TABLE_NAME, rank_column, rank_column)
+            INTO matches USING NEW;
+    ELSE
+        EXECUTE format('SELECT ($1).%I::text', key_column) INTO list_key USING NEW;
+        EXECUTE format('SELECT count(*) FROM %I.%I WHERE %I = ($1).%I AND %I IS NOT DISTINCT FROM ($1).%I',
+                       TG_TABLE_SCHEMA, TG_TABLE_NAME, rank_column, rank_column, key_column, key_column)
+            INTO matches USING NEW;
+    END IF;
+
+    IF matches > 1 THEN
+        RAISE EXCEPTION USING
+            ERRCODE = 'unique_violation',
+            MESSAGE = format('duplicate position %L in %s.%s', rank, TG_TABLE_NAME, rank_column),
+            DETAIL = CASE WHEN key_column IS NULL
+                THEN format('%s rows share position %L.', matches, rank)
+                ELSE format('%s rows with %I = %L share position %L.', matches, key_column, list_key, rank)
+            END,
+            HINT = 'Re-read the neighbouring positions and retry, or generate the position with lexo_between_unique().';
+    END IF;
+    RETURN NULL;
+END
+$$;
+
+CREATE FUNCTION lexo_ordinal_refresh() RETURNS trigger
+LANGUAGE plpgsql
+SET search_path = pg_catalog, @extschema@, pg_temp AS $$
+DECLARE
+    order_by text := TG_ARGV[0];
+    ordinal_column text := TG_ARGV[1];
+    key_column text := TG_ARGV[2];
+    touched text;
+    window_clause text := format('ORDER BY %s', order_by);
+    filter text := '';
+BEGIN
+    -- Rows written by another trigger are renumbered by the statement that
+    -- fired it.
+    IF pg_trigger_depth() > 1 THEN
+        RETURN NULL;
+    END IF;
+    -- Statement triggers fire even when no row changed.
+    IF TG_OP = 'DELETE' THEN
+        PERFORM 1 FROM lexo_old_rows LIMIT 1;
+    ELSIF TG_OP = 'INSERT' THEN
+        PERFORM 1 FROM lexo_new_rows LIMIT 1;
+    END IF;
+    IF TG_OP <> 'UPDATE' AND NOT FOUND THEN
+        RETURN NULL;
+    END IF;
+    IF key_column <> '' THEN
+        window_clause := format('PARTITION BY %I %s', key_column, window_clause);
+    END IF;
+    IF key_column <> '' AND TG_OP <> 'UPDATE' THEN
+        touched := format('SELECT %I FROM %s', key_column,
+                          CASE TG_OP WHEN 'INSERT' THEN 'lexo_new_rows' ELSE 'lexo_old_rows' END);
+        filter := format(
+            'WHERE %1$I IN (SELECT v FROM (%2$s) k(v)) '
+            'OR (%1$I IS NULL AND EXISTS (SELECT 1 FROM (%2$s) k(v) WHERE v IS NULL))',
+            key_column, touched);
+    END IF;
+    EXECUTE format(
+        'UPDATE %1$s t SET %2$I = s.ordinal '
+        'FROM (SELECT tableoid AS rel, ctid AS row_id, row_number() OVER (%3$s) AS ordinal '
+        'FROM %1$s %4$s) s '
+        'WHERE t.tableoid = s.rel AND t.ctid = s.row_id AND t.%2$I IS DISTINCT FROM s.ordinal',
+        TG_RELID::regclass, ordinal_column, window_clause, filter);
+    RETURN NULL;
+END
+$$;
+
+CREATE TABLE lexo_history (
+    id bigserial PRIMARY KEY,
+    table_name regclass NOT NULL,
+    column_name name NOT NULL,
+    pk text,
+    old_rank lexo,
+    new_rank lexo,
+    changed_at timestamptz NOT NULL DEFAULT now(),
+    changed_by name NOT NULL DEFAULT current_user,
+    detail text
+);
+CREATE INDEX lexo_history_row_idx ON lexo_history (table_name, pk);
+CREATE INDEX lexo_history_changed_at_idx ON lexo_history (changed_at);
+SELECT pg_catalog.pg_extension_config_dump('lexo_history', '');
+SELECT pg_catalog.pg_extension_config_dump('lexo_history_id_seq', '');
+
+CREATE FUNCTION lexo_prune_history(older_than interval) RETURNS bigint
+LANGUAGE sql
+SET search_path = pg_catalog, @extschema@, pg_temp AS $$
+    WITH pruned AS (
+        DELETE FROM lexo_history WHERE changed_at < now() - older_than RETURNING 1
+    )
+    SELECT count(*) FROM pruned
+$$;
+
+-- Its arguments are the position column and the primary key column. A failed
+-- insert into lexo_history only raises a WARNING, so it never fails the UPDATE.
+CREATE FUNCTION lexo_history_record() RETURNS trigger
+LANGUAGE plpgsql
+SET search_path = pg_catalog, @extschema@, pg_temp AS $$
+DECLARE
+    rank_column text := TG_ARGV[0];
+    pk_column text := TG_ARGV[1];
+    pk text;
+    old_rank lexo;
+    new_rank lexo;
+BEGIN
+    IF current_setting('lexo.record_history', true) = 'off' THEN
+        RETURN NULL;
+    END IF;
+    BEGIN
+        EXECUTE format('SELECT ($1).%I::text, ($2).%I::lexo, ($1).%I::lexo',
+                       pk_column, rank_column, rank_column)
+            INTO pk, old_rank, new_rank USING NEW, OLD;
+        INSERT INTO lexo_history (table_name, column_name, pk, old_rank, new_rank)
+        VALUES (TG_RELID, rank_column, pk, old_rank, new_rank);
+    EXCEPTION WHEN OTHERS THEN
+        RAISE WARNING 'could not record the position change of %.% in lexo_history: %',
+            TG_TABLE_NAME, rank_column, SQLERRM;
+    END;
+    RETURN NULL;
+END
+$$;
+
+CREATE FUNCTION lexo_validate_position() RETURNS trigger
+LANGUAGE plpgsql
+SET search_path = pg_catalog, @extschema@, pg_temp AS $$
+DECLARE
+    rank_column text := TG_ARGV[0];
+    key_column text := nullif(TG_ARGV[1], '');
+    min_rank lexo := nullif(TG_ARGV[2], '');
+    max_rank lexo := nullif(TG_ARGV[3], '');
+    rank lexo;
+    list_key text;
+    changed boolean := true;
+    taken boolean;
+BEGIN
+    EXECUTE format('SELECT ($1).%I::lexo', rank_column) INTO rank USING NEW;
+    IF rank IS NULL THEN
+        RETURN NEW;
+    END IF;
+
+    IF rank::text = '' THEN
+        RAISE EXCEPTION USING
+            ERRCODE = 'check_violation',
+            MESSAGE = format('empty position in %s.%s', TG_TABLE_NAME, rank_column),
+            HINT = 'Generate positions with lexo_first(), lexo_after() or lexo_between().';
+    END IF;
+
+    IF rank < min_rank OR rank > max_rank THEN
+        RAISE EXCEPTION USING
+            ERRCODE = 'check_violation',
+            MESSAGE = format('position %L in %s.%s is outside the allowed range', rank, TG_TABLE_NAME, rank_column),
+            DETAIL = CASE
+                WHEN min_rank IS NULL THEN format('Positions must be at most %L.', max_rank)
+                WHEN max_rank IS NULL THEN format('Positions must be at least %L.', min_rank)
+                ELSE format('Positions must be between %L and %L.', min_rank, max_rank)
+            END;
+    END IF;
+
+    IF key_column IS NULL THEN
+        RETURN NEW;
+    END IF;
+
+    IF TG_OP = 'UPDATE' THEN
+        EXECUTE format('SELECT ($1).%I IS DISTINCT FROM ($2).%I OR ($1).%I IS DISTINCT FROM ($2).%I',
+                       rank_column, rank_column, key_column, key_column)
+            INTO changed USING NEW, OLD;
+    END IF;
+
+    IF changed THEN
+        EXECUTE format('SELECT ($1).%I::text', key_column) INTO list_key USING NEW;
+        EXECUTE format('SELECT EXISTS (SELECT 1 FROM %I.%I WHERE %I::lexo = $2 AND %I IS NOT DISTINCT FROM ($1).%I)',
+                       TG_TABLE_SCHEMA, TG_TABLE_NAME, rank_column, key_column, key_column)
+            INTO taken USING NEW, rank;
+        IF taken THEN
+            RAISE WARNING USING
+                MESSAGE = format('position %L is already used in %s.%s for %s = %L', rank, TG_TABLE_NAME, rank_column, key_column, list_key),
+                HINT = 'Re-read the neighbouring positions, or generate the position with lexo_between_unique().';
+        END IF;
+    END IF;
+    RETURN NEW;
+END
+$$;
+
+CREATE DOMAIN lexo_nonempty AS lexo
+    CHECK (VALUE::text <> '');
+CREATE DOMAIN lexo_short AS lexo
+    CHECK (length(VALUE::text) <= 16);
+
+DROP FUNCTION "lexo_add_column"(TEXT, TEXT);
+CREATE FUNCTION "lexo_add_column"(
+	"table_name" TEXT,
+	"column_name" TEXT,
+	"type_name" TEXT DEFAULT NULL
+) RETURNS void
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_add_column_wrapper';
+
+CREATE FUNCTION "lexo_advance"(
+	"rank" Lexo,
+	"steps" bigint
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_advance_wrapper';
+
+CREATE OR REPLACE FUNCTION "lexo_after"(
+	"current" Lexo
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_after_wrapper';
+
+CREATE FUNCTION "lexo_after"(
+	"current" TEXT
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_after_from_text_wrapper';
+
+CREATE FUNCTION "lexo_after"(
+	"current" Lexo,
+	"gap" INT
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_after_gap_wrapper';
+
+CREATE FUNCTION "lexo_after_or_first"(
+	"current" Lexo
+) RETURNS Lexo
+IMMUTABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_after_or_first_wrapper';
+
+CREATE FUNCTION "lexo_is_valid"(
+	"value" TEXT
+) RETURNS bool
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_is_valid_wrapper';
+
+CREATE DOMAIN lexo_text AS text
+    CHECK (lexo_is_valid(VALUE));
+
+CREATE FUNCTION "lexo_after_text"(
+	"current" TEXT
+) RETURNS lexo_text
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_after_text_wrapper';
+
+CREATE FUNCTION "lexo_array_fill_between"(
+	"before_pos" Lexo,
+	"after_pos" Lexo,
+	"count" INT
+) RETURNS Lexo[]
+IMMUTABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_array_fill_between_wrapper';
+
+CREATE FUNCTION "lexo_array_insert_at"(
+	"ranks" Lexo[],
+	"index" INT
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_array_insert_at_wrapper';
+
+CREATE FUNCTION "lexo_array_is_sorted"(
+	"ranks" Lexo[]
+) RETURNS bool
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_array_is_sorted_wrapper';
+
+CREATE FUNCTION "lexo_array_sort"(
+	"ranks" Lexo[]
+) RETURNS Lexo[]
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_array_sort_wrapper';
+
+CREATE FUNCTION "lexo_array_with_insert"(
+	"ranks" Lexo[],
+	"index" INT
+) RETURNS Lexo[]
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_array_with_insert_wrapper';
+
+CREATE FUNCTION "lexo_assert_matches"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"pk_column_name" TEXT,
+	"expected" jsonb,
+	"key_column_name" TEXT DEFAULT NULL,
+	"key_value" TEXT DEFAULT NULL
+) RETURNS void
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_assert_matches_wrapper';
+
+CREATE FUNCTION "lexo_assert_max_length"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"max_length" INT
+) RETURNS void
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_assert_max_length_wrapper';
+
+CREATE FUNCTION "lexo_assert_ordered"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_column_name" TEXT DEFAULT NULL,
+	"allow_nulls" bool DEFAULT false
+) RETURNS void
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_assert_ordered_wrapper';
+
+CREATE FUNCTION "lexo_at_fraction"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"fraction" double precision,
+	"identifier_column_name" TEXT DEFAULT NULL,
+	"identifier_value" TEXT DEFAULT NULL
+) RETURNS Lexo
+COST 100
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_at_fraction_wrapper';
+
+CREATE FUNCTION "lexo_attach_validation"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_column_name" TEXT DEFAULT NULL,
+	"min_rank" Lexo DEFAULT NULL,
+	"max_rank" Lexo DEFAULT NULL
+) RETURNS void
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_attach_validation_wrapper';
+
+CREATE OR REPLACE FUNCTION "lexo_before"(
+	"current" Lexo
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_before_wrapper';
+
+CREATE FUNCTION "lexo_before"(
+	"current" TEXT
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_before_from_text_wrapper';
+
+CREATE FUNCTION "lexo_before"(
+	"current" Lexo,
+	"gap" INT
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_before_gap_wrapper';
+
+CREATE FUNCTION "lexo_before_or_first"(
+	"current" Lexo
+) RETURNS Lexo
+IMMUTABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_before_or_first_wrapper';
+
+CREATE FUNCTION "lexo_before_text"(
+	"current" TEXT
+) RETURNS lexo_text
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_before_text_wrapper';
+
+CREATE OR REPLACE FUNCTION "lexo_between"(
+	"before_pos" Lexo,
+	"after_pos" Lexo
+) RETURNS Lexo
+IMMUTABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_between_wrapper';
+
+CREATE FUNCTION "lexo_between"(
+	"before_pos" TEXT,
+	"after_pos" TEXT
+) RETURNS Lexo
+IMMUTABLE PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_between_from_text_wrapper';
+
+CREATE FUNCTION "lexo_between"(
+	"before_pos" Lexo,
+	"after_pos" TEXT
+) RETURNS Lexo
+IMMUTABLE PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_between_lexo_text_wrapper';
+
+CREATE FUNCTION "lexo_between"(
+	"before_pos" TEXT,
+	"after_pos" Lexo
+) RETURNS Lexo
+IMMUTABLE PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_between_text_lexo_wrapper';
+
+CREATE FUNCTION "lexo_between_avoiding"(
+	"before_pos" Lexo,
+	"after_pos" Lexo,
+	"taken" Lexo[],
+	"max_length" INT DEFAULT 64
+) RETURNS Lexo
+IMMUTABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_between_avoiding_wrapper';
+
+CREATE FUNCTION "lexo_between_locked"(
+	"list_key" TEXT,
+	"before_pos" Lexo,
+	"after_pos" Lexo
+) RETURNS Lexo
+COST 100
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_between_locked_wrapper';
+
+CREATE FUNCTION "lexo_between_seeded"(
+	"before_pos" Lexo,
+	"after_pos" Lexo,
+	"seed" TEXT
+) RETURNS Lexo
+IMMUTABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_between_seeded_wrapper';
+
+CREATE FUNCTION "lexo_between_tagged"(
+	"before_pos" Lexo,
+	"after_pos" Lexo
+) RETURNS Lexo
+STABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_between_tagged_wrapper';
+
+CREATE FUNCTION "lexo_between_text"(
+	"before_pos" TEXT,
+	"after_pos" TEXT
+) RETURNS lexo_text
+IMMUTABLE PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_between_text_wrapper';
+
+CREATE FUNCTION "lexo_between_unique"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"before_pos" Lexo,
+	"after_pos" Lexo,
+	"identifier_column_name" TEXT,
+	"identifier_value" TEXT
+) RETURNS Lexo
+COST 100
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_between_unique_wrapper';
+
+CREATE FUNCTION "lexo_child_between"(
+	"table_name" TEXT,
+	"pk_column_name" TEXT,
+	"parent_column_name" TEXT,
+	"lexo_column_name" TEXT,
+	"parent_pk" TEXT,
+	"after_sibling_pk" TEXT
+) RETURNS Lexo
+COST 100
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_child_between_wrapper';
+
+CREATE FUNCTION "lexo_child_first"(
+	"table_name" TEXT,
+	"pk_column_name" TEXT,
+	"parent_column_name" TEXT,
+	"lexo_column_name" TEXT,
+	"parent_pk" TEXT
+) RETURNS Lexo
+COST 100
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_child_first_wrapper';
+
+CREATE FUNCTION "lexo_claim_next"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"identifier_column_name" TEXT,
+	"identifier_value" TEXT,
+	"empty_default" Lexo DEFAULT NULL
+) RETURNS Lexo
+COST 100
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_claim_next_wrapper';
+
+CREATE FUNCTION "lexo_clone_order"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_column_name" TEXT,
+	"source_key" TEXT,
+	"target_key" TEXT,
+	"map_by" TEXT,
+	"source_column_name" TEXT DEFAULT NULL
+) RETURNS bigint
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_clone_order_wrapper';
+
+CREATE FUNCTION "lexo_common_prefix"(
+	"a" Lexo,
+	"b" Lexo
+) RETURNS TEXT
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_common_prefix_wrapper';
+
+CREATE FUNCTION "lexo_compare"(
+	"a" Lexo,
+	"b" Lexo
+) RETURNS INT
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_compare_wrapper';
+
+CREATE FUNCTION "lexo_concat"(
+	"prefix" Lexo,
+	"suffix" Lexo
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_concat_wrapper';
+
+CREATE FUNCTION "lexo_concat"(
+	"prefix" Lexo,
+	"suffix" TEXT
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_concat_text_wrapper';
+
+CREATE OPERATOR || (
+    LEFTARG = lexo,
+    RIGHTARG = lexo,
+    FUNCTION = lexo_concat
+);
+CREATE OPERATOR || (
+    LEFTARG = lexo,
+    RIGHTARG = text,
+    FUNCTION = lexo_concat
+);
+
+CREATE FUNCTION "lexo_convert_column"(
+	"table_name" TEXT,
+	"column_name" TEXT,
+	"target" TEXT
+) RETURNS void
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_convert_column_wrapper';
+
+CREATE FUNCTION "lexo_convert_text_column"(
+	"table_name" TEXT,
+	"column_name" TEXT,
+	"on_invalid" TEXT DEFAULT 'error'
+) RETURNS TABLE (
+	"rows_checked" bigint, 
+	"invalid_rows" bigint, 
+	"action" TEXT, 
+	"indexes_rebuilt" bigint 
+)
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_convert_text_column_wrapper';
+
+CREATE FUNCTION "lexo_count_between"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"low" Lexo,
+	"high" Lexo,
+	"identifier_column_name" TEXT DEFAULT NULL,
+	"identifier_value" TEXT DEFAULT NULL
+) RETURNS bigint
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_count_between_wrapper';
+
+CREATE FUNCTION "lexo_create_index"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_columns" TEXT[] DEFAULT '{}',
+	"is_unique" bool DEFAULT false,
+	"index_name" TEXT DEFAULT NULL
+) RETURNS TEXT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_create_index_wrapper';
+
+CREATE FUNCTION "lexo_create_index_sql"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_columns" TEXT[] DEFAULT '{}',
+	"is_unique" bool DEFAULT false,
+	"index_name" TEXT DEFAULT NULL
+) RETURNS TEXT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_create_index_sql_wrapper';
+
+CREATE FUNCTION "lexo_create_ordered_view"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"view_name" TEXT DEFAULT NULL,
+	"key_column_name" TEXT DEFAULT NULL
+) RETURNS TEXT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_create_ordered_view_wrapper';
+
+CREATE FUNCTION "lexo_decode_cursor"(
+	"token" TEXT
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_decode_cursor_wrapper';
+
+CREATE FUNCTION "lexo_detach_ordinal"(
+	"table_name" TEXT,
+	"ordinal_column_name" TEXT
+) RETURNS void
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_detach_ordinal_wrapper';
+
+CREATE FUNCTION "lexo_detach_validation"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT
+) RETURNS void
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_detach_validation_wrapper';
+
+CREATE FUNCTION "lexo_disable_history"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT
+) RETURNS void
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_disable_history_wrapper';
+
+CREATE FUNCTION "lexo_disable_sequence_append"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT
+) RETURNS void
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_disable_sequence_append_wrapper';
+
+CREATE FUNCTION "lexo_drop_ordered_views"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT DEFAULT NULL
+) RETURNS bigint
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_drop_ordered_views_wrapper';
+
+CREATE FUNCTION "lexo_drop_unique"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT
+) RETURNS void
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_drop_unique_wrapper';
+
+CREATE FUNCTION "lexo_enable_history"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"pk_column_name" TEXT
+) RETURNS void
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_enable_history_wrapper';
+
+CREATE FUNCTION "lexo_enable_sequence_append"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_column_name" TEXT DEFAULT NULL
+) RETURNS void
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_enable_sequence_append_wrapper';
+
+CREATE FUNCTION "lexo_encode_cursor"(
+	"rank" Lexo
+) RETURNS TEXT
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_encode_cursor_wrapper';
+
+CREATE FUNCTION "lexo_enforce_typmod"(
+	"rank" Lexo,
+	"typmod" INT,
+	"_is_explicit" bool
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_enforce_typmod_wrapper';
+
+CREATE FUNCTION "lexo_enforce_unique"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_column_name" TEXT DEFAULT NULL
+) RETURNS void
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_enforce_unique_wrapper';
+
+CREATE FUNCTION "lexo_enforce_unique_per_key"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_column_name" TEXT,
+	"fix" bool DEFAULT false
+) RETURNS TABLE (
+	"key_value" TEXT, 
+	"position" TEXT, 
+	"duplicates" bigint, 
+	"fixed" bool 
+)
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_enforce_unique_per_key_wrapper';
+
+CREATE FUNCTION "lexo_explain"(
+	"rank" Lexo
+) RETURNS jsonb
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_explain_wrapper';
+
+CREATE FUNCTION "lexo_export_order"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"pk_column_name" TEXT,
+	"key_column_name" TEXT DEFAULT NULL,
+	"key_value" TEXT DEFAULT NULL,
+	"with_positions" bool DEFAULT false
+) RETURNS jsonb
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_export_order_wrapper';
+
+CREATE OR REPLACE FUNCTION "lexo_first"() RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_first_wrapper';
+
+CREATE FUNCTION "lexo_first_desc"() RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_first_desc_wrapper';
+
+CREATE FUNCTION "lexo_from_int"(
+	"index" bigint,
+	"total" bigint
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_from_int_wrapper';
+
+CREATE FUNCTION "lexo_from_json"(
+	"value" json
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_from_json_wrapper';
+
+CREATE FUNCTION "lexo_from_jsonb"(
+	"value" jsonb
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_from_jsonb_wrapper';
+
+CREATE FUNCTION "lexo_from_numeric"(
+	"value" NUMERIC
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_from_numeric_wrapper';
+
+CREATE FUNCTION "lexo_health"(
+	"check_indexes" bool DEFAULT false
+) RETURNS TABLE (
+	"check" TEXT, 
+	"ok" bool, 
+	"detail" TEXT 
+)
+STRICT STABLE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_health_wrapper';
+
+CREATE FUNCTION "lexo_import_order"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"pk_column_name" TEXT,
+	"ordered_pks" jsonb,
+	"key_column_name" TEXT DEFAULT NULL,
+	"key_value" TEXT DEFAULT NULL,
+	"require_complete" bool DEFAULT false
+) RETURNS bigint
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_import_order_wrapper';
+
+CREATE FUNCTION "lexo_insert_at"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"index" bigint,
+	"identifier_column_name" TEXT DEFAULT NULL,
+	"identifier_value" TEXT DEFAULT NULL
+) RETURNS Lexo
+COST 100
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_insert_at_wrapper';
+
+CREATE FUNCTION "lexo_insert_sorted"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"sort_expr" TEXT,
+	"new_value" TEXT,
+	"key_column_name" TEXT DEFAULT NULL,
+	"key_value" TEXT DEFAULT NULL
+) RETURNS Lexo
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_insert_sorted_wrapper';
+
+CREATE FUNCTION "lexo_insertion_budget"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"identifier_column_name" TEXT DEFAULT NULL,
+	"identifier_value" TEXT DEFAULT NULL,
+	"max_length" INT DEFAULT 12
+) RETURNS TABLE (
+	"appends" bigint, 
+	"prepends" bigint, 
+	"worst_case_between" bigint, 
+	"typical_between" bigint, 
+	"deepest_prefix" INT 
+)
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_insertion_budget_wrapper';
+
+CREATE FUNCTION "lexo_is_between"(
+	"candidate" Lexo,
+	"before_pos" Lexo,
+	"after_pos" Lexo
+) RETURNS bool
+IMMUTABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_is_between_wrapper';
+
+CREATE FUNCTION "lexo_larger"(
+	"a" Lexo,
+	"b" Lexo
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_larger_wrapper';
+
+CREATE FUNCTION "lexo_length"(
+	"rank" Lexo
+) RETURNS INT
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_length_wrapper';
+
+CREATE OPERATOR CLASS lexo_minmax_ops DEFAULT FOR TYPE lexo USING brin AS
+    OPERATOR 1 <,
+    OPERATOR 2 <=,
+    OPERATOR 3 =,
+    OPERATOR 4 >=,
+    OPERATOR 5 >,
+    FUNCTION 1 brin_minmax_opcinfo(internal),
+    FUNCTION 2 brin_minmax_add_value(internal, internal, internal, internal),
+    FUNCTION 3 brin_minmax_consistent(internal, internal, internal),
+    FUNCTION 4 brin_minmax_union(internal, internal, internal);
+
+CREATE FUNCTION "lexo_maintain_ordinal"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"ordinal_column_name" TEXT,
+	"key_column_name" TEXT DEFAULT NULL
+) RETURNS void
+
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_maintain_ordinal_wrapper';
+
+CREATE FUNCTION "lexo_median"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"identifier_column_name" TEXT DEFAULT NULL,
+	"identifier_value" TEXT DEFAULT NULL
+) RETURNS Lexo
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_median_wrapper';
+
+CREATE FUNCTION "lexo_merge_lists"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_column_name" TEXT,
+	"source_key" TEXT,
+	"target_key" TEXT,
+	"mode" TEXT DEFAULT 'append',
+	"rewrite_key" bool DEFAULT true
+) RETURNS bigint
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_merge_lists_wrapper';
+
+CREATE FUNCTION "lexo_midpoints"(
+	"before_pos" Lexo,
+	"after_pos" Lexo,
+	"max_count" INT DEFAULT 1000000
+) RETURNS SETOF Lexo
+IMMUTABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_midpoints_wrapper';
+
+CREATE FUNCTION "lexo_migrate_from_numeric"(
+	"table_name" TEXT,
+	"numeric_column_name" TEXT,
+	"new_column_name" TEXT DEFAULT 'position',
+	"key_column_name" TEXT DEFAULT NULL,
+	"drop_old" bool DEFAULT false
+) RETURNS TABLE (
+	"group_key" TEXT, 
+	"rows_updated" bigint 
+)
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_migrate_from_numeric_wrapper';
+
+CREATE FUNCTION "lexo_move_subtree"(
+	"table_name" TEXT,
+	"pk_column_name" TEXT,
+	"parent_column_name" TEXT,
+	"lexo_column_name" TEXT,
+	"moving_pk" TEXT,
+	"new_parent_pk" TEXT,
+	"after_sibling_pk" TEXT
+) RETURNS Lexo
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_move_subtree_wrapper';
+
+DROP FUNCTION "lexo_next"(TEXT, TEXT, TEXT, TEXT);
+CREATE FUNCTION "lexo_next"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"identifier_column_name" TEXT,
+	"identifier_value" TEXT,
+	"empty_default" Lexo DEFAULT NULL,
+	"only" bool DEFAULT false
+) RETURNS Lexo
+COST 100
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_next_wrapper';
+
+CREATE FUNCTION "lexo_next_desc"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"identifier_column_name" TEXT,
+	"identifier_value" TEXT,
+	"empty_default" Lexo DEFAULT NULL,
+	"only" bool DEFAULT false
+) RETURNS Lexo
+COST 100
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_next_desc_wrapper';
+
+CREATE FUNCTION "lexo_nth_planned"(
+	"first_rank" Lexo,
+	"step" NUMERIC,
+	"i" bigint
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_nth_planned_wrapper';
+
+CREATE FUNCTION "lexo_order_divergence"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"pk_column_name" TEXT,
+	"expected" jsonb,
+	"key_column_name" TEXT DEFAULT NULL,
+	"key_value" TEXT DEFAULT NULL
+) RETURNS TABLE (
+	"index" bigint, 
+	"expected_pk" TEXT, 
+	"actual_pk" TEXT, 
+	"detail" TEXT 
+)
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_order_divergence_wrapper';
+
+CREATE FUNCTION "lexo_page_after"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"cursor" Lexo,
+	"page_size" INT,
+	"key_column_name" TEXT DEFAULT NULL,
+	"key_value" TEXT DEFAULT NULL,
+	"pk_column_name" TEXT DEFAULT NULL
+) RETURNS TABLE (
+	"pk" TEXT, 
+	"rank" Lexo 
+)
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_page_after_wrapper';
+
+CREATE FUNCTION "lexo_prefix_end"(
+	"prefix" TEXT
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_prefix_end_wrapper';
+
+CREATE FUNCTION lexo_starts_with(rank lexo, prefix text) RETURNS boolean
+LANGUAGE sql IMMUTABLE PARALLEL SAFE
+AS $$
+    SELECT rank >= prefix::lexo
+       AND (lexo_prefix_end(prefix) IS NULL OR rank < lexo_prefix_end(prefix))
+$$;
+CREATE OPERATOR ^@ (
+    LEFTARG = lexo,
+    RIGHTARG = text,
+    FUNCTION = lexo_starts_with
+);
+
+CREATE FUNCTION "lexo_random_between"(
+	"before_pos" Lexo,
+	"after_pos" Lexo
+) RETURNS Lexo
+VOLATILE PARALLEL RESTRICTED
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_random_between_wrapper';
+
+CREATE FUNCTION "lexo_range_diff"(
+	"a" Lexo,
+	"b" Lexo
+) RETURNS double precision
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_range_diff_wrapper';
+
+CREATE FUNCTION "lexo_rank_all"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"order_by" TEXT,
+	"key_column_name" TEXT DEFAULT NULL
+) RETURNS TABLE (
+	"group_key" TEXT, 
+	"rows_updated" bigint 
+)
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_rank_all_wrapper';
+
+CREATE FUNCTION "lexo_rank_at_percentile"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"pct" double precision,
+	"identifier_column_name" TEXT DEFAULT NULL,
+	"identifier_value" TEXT DEFAULT NULL
+) RETURNS Lexo
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_rank_at_percentile_wrapper';
+
+DROP FUNCTION "lexo_rebalance"(TEXT, TEXT, TEXT, TEXT);
+CREATE FUNCTION "lexo_rebalance"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_column_name" TEXT,
+	"key_value" TEXT,
+	"where_clause" TEXT DEFAULT NULL,
+	"mode" TEXT DEFAULT 'equal',
+	"target_length" INT DEFAULT NULL,
+	"descending" bool DEFAULT false,
+	"allow_text" bool DEFAULT false,
+	"include_nulls" TEXT DEFAULT 'append',
+	"empty_as" TEXT DEFAULT 'keep_order',
+	"allow_rls" bool DEFAULT false,
+	"only" bool DEFAULT false,
+	"pin_where" TEXT DEFAULT NULL
+) RETURNS bigint
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_rebalance_wrapper';
+
+CREATE FUNCTION "lexo_rebalance_array"(
+	"ranks" Lexo[]
+) RETURNS Lexo[]
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_rebalance_array_wrapper';
+
+CREATE FUNCTION "lexo_rebalance_if_needed"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_column_name" TEXT DEFAULT NULL,
+	"max_length" INT DEFAULT 12
+) RETURNS bigint
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_rebalance_if_needed_wrapper';
+
+CREATE FUNCTION "lexo_rebalance_jsonb"(
+	"doc" jsonb,
+	"rank_key" TEXT DEFAULT 'rank'
+) RETURNS jsonb
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_rebalance_jsonb_wrapper';
+
+CREATE FUNCTION "lexo_rebalance_summary"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_column_name" TEXT DEFAULT NULL,
+	"key_value" TEXT DEFAULT NULL,
+	"where_clause" TEXT DEFAULT NULL,
+	"mode" TEXT DEFAULT 'equal',
+	"target_length" INT DEFAULT NULL,
+	"descending" bool DEFAULT false,
+	"allow_text" bool DEFAULT false,
+	"include_nulls" TEXT DEFAULT 'append',
+	"empty_as" TEXT DEFAULT 'keep_order',
+	"allow_rls" bool DEFAULT false,
+	"only" bool DEFAULT false,
+	"pin_where" TEXT DEFAULT NULL
+) RETURNS TABLE (
+	"rows_updated" bigint, 
+	"rows_skipped" bigint, 
+	"null_count" bigint, 
+	"empty_count" bigint, 
+	"pinned_count" bigint, 
+	"groups_processed" bigint, 
+	"max_length_before" INT, 
+	"max_length_after" INT, 
+	"duration_ms" double precision 
+)
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_rebalance_summary_wrapper';
+
+CREATE FUNCTION "lexo_reserve_range"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"count" INT,
+	"identifier_column_name" TEXT,
+	"identifier_value" TEXT
+) RETURNS Lexo[]
+COST 100
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_reserve_range_wrapper';
+
+CREATE FUNCTION "lexo_retreat"(
+	"rank" Lexo,
+	"steps" bigint
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_retreat_wrapper';
+
+CREATE FUNCTION "lexo_reverse"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_column_name" TEXT DEFAULT NULL,
+	"key_value" TEXT DEFAULT NULL
+) RETURNS bigint
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_reverse_wrapper';
+
+CREATE FUNCTION "lexo_schedule_rebalance"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"schedule" TEXT DEFAULT '0 4 * * *',
+	"key_column_name" TEXT DEFAULT NULL,
+	"max_length" INT DEFAULT 12
+) RETURNS bigint
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_schedule_rebalance_wrapper';
+
+CREATE FUNCTION "lexo_seq_next"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_value" TEXT DEFAULT NULL
+) RETURNS Lexo
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_seq_next_wrapper';
+
+CREATE FUNCTION "lexo_shortest_between"(
+	"before_pos" Lexo,
+	"after_pos" Lexo
+) RETURNS Lexo
+IMMUTABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_shortest_between_wrapper';
+
+CREATE FUNCTION "lexo_simulate_insertions"(
+	"n" INT,
+	"pattern" TEXT,
+	"seed" bigint DEFAULT 0
+) RETURNS TABLE (
+	"step" INT, 
+	"rank" TEXT, 
+	"length" INT 
+)
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_simulate_insertions_wrapper';
+
+CREATE FUNCTION "lexo_simulate_insertions"(
+	"n" INT,
+	"mix" jsonb,
+	"seed" bigint DEFAULT 0
+) RETURNS TABLE (
+	"step" INT, 
+	"rank" TEXT, 
+	"length" INT 
+)
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_simulate_insertions_mix_wrapper';
+
+CREATE FUNCTION "lexo_slice"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"pk_column_name" TEXT,
+	"low" Lexo,
+	"high" Lexo,
+	"max_rows" INT,
+	"key_column_name" TEXT DEFAULT NULL,
+	"key_value" TEXT DEFAULT NULL,
+	"descending" bool DEFAULT false
+) RETURNS TABLE (
+	"pk" TEXT, 
+	"rank" Lexo 
+)
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_slice_wrapper';
+
+CREATE FUNCTION "lexo_smaller"(
+	"a" Lexo,
+	"b" Lexo
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_smaller_wrapper';
+
+CREATE AGGREGATE min(lexo) (
+    SFUNC = lexo_smaller,
+    STYPE = lexo,
+    COMBINEFUNC = lexo_smaller,
+    SORTOP = <,
+    PARALLEL = SAFE
+);
+CREATE AGGREGATE max(lexo) (
+    SFUNC = lexo_larger,
+    STYPE = lexo,
+    COMBINEFUNC = lexo_larger,
+    SORTOP = >,
+    PARALLEL = SAFE
+);
+
+CREATE FUNCTION "lexo_sortsupport"(
+	"ssup" internal
+) RETURNS void
+IMMUTABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_sortsupport_wrapper';
+
+CREATE FUNCTION "lexo_start_for_estimated_size"(
+	"expected_count" bigint
+) RETURNS TABLE (
+	"first_rank" Lexo, 
+	"rank_length" INT, 
+	"step" NUMERIC 
+)
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_start_for_estimated_size_wrapper';
+
+CREATE FUNCTION "lexo_stat_counters"() RETURNS TABLE (
+	"dbid" oid, 
+	"after_calls" bigint, 
+	"before_calls" bigint, 
+	"between_calls" bigint, 
+	"next_calls" bigint, 
+	"rebalance_calls" bigint, 
+	"rows_rebalanced" bigint, 
+	"long_ranks" bigint 
+)
+STRICT VOLATILE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_stat_counters_wrapper';
+
+CREATE VIEW lexo_stat AS
+SELECT s.dbid, d.datname, s.after_calls, s.before_calls, s.between_calls,
+       s.next_calls, s.rebalance_calls, s.rows_rebalanced, s.long_ranks
+FROM lexo_stat_counters() s
+LEFT JOIN pg_catalog.pg_database d ON d.oid = s.dbid;
+
+CREATE FUNCTION "lexo_stat_reset"() RETURNS void
+STRICT VOLATILE PARALLEL UNSAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_stat_reset_wrapper';
+
+CREATE FUNCTION "lexo_strip_prefix"(
+	"rank" Lexo,
+	"prefix" Lexo
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_strip_prefix_wrapper';
+
+CREATE FUNCTION "lexo_to_json"(
+	"rank" Lexo
+) RETURNS json
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_to_json_wrapper';
+
+CREATE FUNCTION "lexo_to_jsonb"(
+	"rank" Lexo
+) RETURNS jsonb
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_to_jsonb_wrapper';
+
+CREATE CAST (lexo AS json) WITH FUNCTION lexo_to_json(lexo);
+CREATE CAST (lexo AS jsonb) WITH FUNCTION lexo_to_jsonb(lexo);
+CREATE CAST (json AS lexo) WITH FUNCTION lexo_from_json(json);
+CREATE CAST (jsonb AS lexo) WITH FUNCTION lexo_from_jsonb(jsonb);
+
+CREATE FUNCTION "lexo_to_numeric"(
+	"rank" Lexo
+) RETURNS NUMERIC
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_to_numeric_wrapper';
+
+CREATE FUNCTION "lexo_transcode"(
+	"rank" TEXT,
+	"from_alphabet" TEXT
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_transcode_wrapper';
+
+CREATE FUNCTION "lexo_typmod_in"(
+	"mods" cstring[]
+) RETURNS INT
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_typmod_in_wrapper';
+
+CREATE FUNCTION "lexo_typmod_out"(
+	"typmod" INT
+) RETURNS cstring
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_typmod_out_wrapper';
+
+CREATE OR REPLACE FUNCTION lexo_in(cstring) RETURNS lexo
+IMMUTABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_in_typmod_wrapper';
+
+CREATE FUNCTION "lexo_recv"(
+	"buf" internal,
+	"_typioparam" oid,
+	"typmod" INT
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_recv_wrapper';
+
+CREATE FUNCTION "lexo_send"(
+	"rank" Lexo
+) RETURNS bytea
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_send_wrapper';
+
+ALTER TYPE lexo SET (
+    TYPMOD_IN = lexo_typmod_in,
+    TYPMOD_OUT = lexo_typmod_out,
+    RECEIVE = lexo_recv,
+    SEND = lexo_send
+);
+CREATE CAST (lexo AS lexo)
+    WITH FUNCTION lexo_enforce_typmod(lexo, integer, boolean) AS IMPLICIT;
+
+CREATE FUNCTION "lexo_unschedule_rebalance"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT
+) RETURNS bool
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_unschedule_rebalance_wrapper';
+
+CREATE FUNCTION "lexo_verify_max_length"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"max_length" INT
+) RETURNS bool
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_verify_max_length_wrapper';
+
+CREATE FUNCTION "lexo_verify_order"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"pk_column_name" TEXT,
+	"expected" jsonb,
+	"key_column_name" TEXT DEFAULT NULL,
+	"key_value" TEXT DEFAULT NULL
+) RETURNS bool
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_verify_order_wrapper';
+
+CREATE FUNCTION "lexo_verify_ordered"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"key_column_name" TEXT DEFAULT NULL,
+	"allow_nulls" bool DEFAULT false
+) RETURNS bool
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_verify_ordered_wrapper';
+
+CREATE FUNCTION "lexo_version"() RETURNS TABLE (
+	"extension_version" TEXT, 
+	"crate_version" TEXT, 
+	"algorithm_version" INT, 
+	"alphabet" TEXT, 
+	"mid_char" TEXT 
+)
+STRICT STABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_version_wrapper';
+
+CREATE FUNCTION "smalllexo_in"(
+	"input" cstring
+) RETURNS smalllexo
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_in_wrapper';
+
+CREATE FUNCTION "smalllexo_out"(
+	"value" smalllexo
+) RETURNS cstring
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_out_wrapper';
+
+CREATE TYPE smalllexo (
+    INPUT = smalllexo_in,
+    OUTPUT = smalllexo_out,
+    INTERNALLENGTH = 8,
+    PASSEDBYVALUE,
+    ALIGNMENT = double,
+    STORAGE = plain
+);
+
+CREATE FUNCTION "smalllexo_lt"(
+	"a" smalllexo,
+	"b" smalllexo
+) RETURNS bool
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_lt_wrapper';
+
+CREATE FUNCTION "smalllexo_ge"(
+	"a" smalllexo,
+	"b" smalllexo
+) RETURNS bool
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_ge_wrapper';
+
+CREATE FUNCTION "smalllexo_from_lexo"(
+	"value" Lexo
+) RETURNS smalllexo
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_from_lexo_wrapper';
+
+CREATE FUNCTION "smalllexo_eq"(
+	"a" smalllexo,
+	"b" smalllexo
+) RETURNS bool
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_eq_wrapper';
+
+CREATE FUNCTION "smalllexo_gt"(
+	"a" smalllexo,
+	"b" smalllexo
+) RETURNS bool
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_gt_wrapper';
+
+CREATE FUNCTION "smalllexo_cmp"(
+	"a" smalllexo,
+	"b" smalllexo
+) RETURNS INT
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_cmp_wrapper';
+
+CREATE FUNCTION "lexo_between"(
+	"before_pos" smalllexo,
+	"after_pos" smalllexo
+) RETURNS smalllexo
+IMMUTABLE PARALLEL SAFE  
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_between_small_wrapper';
+
+CREATE FUNCTION "smalllexo_larger"(
+	"a" smalllexo,
+	"b" smalllexo
+) RETURNS smalllexo
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_larger_wrapper';
+
+CREATE FUNCTION "smalllexo_le"(
+	"a" smalllexo,
+	"b" smalllexo
+) RETURNS bool
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_le_wrapper';
+
+CREATE FUNCTION "lexo_before"(
+	"current" smalllexo
+) RETURNS smalllexo
+IMMUTABLE STRICT PARALLEL SAFE  
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_before_small_wrapper';
+
+CREATE FUNCTION "smalllexo_ne"(
+	"a" smalllexo,
+	"b" smalllexo
+) RETURNS bool
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_ne_wrapper';
+
+CREATE FUNCTION "lexo_after"(
+	"current" smalllexo
+) RETURNS smalllexo
+IMMUTABLE STRICT PARALLEL SAFE  
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_after_small_wrapper';
+
+CREATE FUNCTION "smalllexo_hash"(
+	"value" smalllexo
+) RETURNS INT
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_hash_wrapper';
+
+CREATE FUNCTION "smalllexo_smaller"(
+	"a" smalllexo,
+	"b" smalllexo
+) RETURNS smalllexo
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_smaller_wrapper';
+
+CREATE FUNCTION "smalllexo_sortsupport"(
+	"ssup" internal
+) RETURNS void
+IMMUTABLE PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_sortsupport_wrapper';
+
+CREATE FUNCTION "smalllexo_to_lexo"(
+	"value" smalllexo
+) RETURNS Lexo
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'smalllexo_to_lexo_wrapper';
+
+CREATE OPERATOR = (
+    LEFTARG = smalllexo, RIGHTARG = smalllexo, FUNCTION = smalllexo_eq,
+    COMMUTATOR = =, NEGATOR = <>, RESTRICT = eqsel, JOIN = eqjoinsel, HASHES, MERGES
+);
+CREATE OPERATOR <> (
+    LEFTARG = smalllexo, RIGHTARG = smalllexo, FUNCTION = smalllexo_ne,
+    COMMUTATOR = <>, NEGATOR = =, RESTRICT = neqsel, JOIN = neqjoinsel
+);
+CREATE OPERATOR < (
+    LEFTARG = smalllexo, RIGHTARG = smalllexo, FUNCTION = smalllexo_lt,
+    COMMUTATOR = >, NEGATOR = >=, RESTRICT = scalarltsel, JOIN = scalarltjoinsel
+);
+CREATE OPERATOR <= (
+    LEFTARG = smalllexo, RIGHTARG = smalllexo, FUNCTION = smalllexo_le,
+    COMMUTATOR = >=, NEGATOR = >, RESTRICT = scalarlesel, JOIN = scalarlejoinsel
+);
+CREATE OPERATOR > (
+    LEFTARG = smalllexo, RIGHTARG = smalllexo, FUNCTION = smalllexo_gt,
+    COMMUTATOR = <, NEGATOR = <=, RESTRICT = scalargtsel, JOIN = scalargtjoinsel
+);
+CREATE OPERATOR >= (
+    LEFTARG = smalllexo, RIGHTARG = smalllexo, FUNCTION = smalllexo_ge,
+    COMMUTATOR = <=, NEGATOR = <, RESTRICT = scalargesel, JOIN = scalargejoinsel
+);
+
+CREATE OPERATOR CLASS smalllexo_btree_ops DEFAULT FOR TYPE smalllexo USING btree AS
+    OPERATOR 1 <,
+    OPERATOR 2 <=,
+    OPERATOR 3 =,
+    OPERATOR 4 >=,
+    OPERATOR 5 >,
+    FUNCTION 1 smalllexo_cmp(smalllexo, smalllexo),
+    FUNCTION 2 smalllexo_sortsupport(internal);
+CREATE OPERATOR CLASS smalllexo_hash_ops DEFAULT FOR TYPE smalllexo USING hash AS
+    OPERATOR 1 =,
+    FUNCTION 1 smalllexo_hash(smalllexo);
+CREATE OPERATOR CLASS smalllexo_minmax_ops DEFAULT FOR TYPE smalllexo USING brin AS
+    OPERATOR 1 <,
+    OPERATOR 2 <=,
+    OPERATOR 3 =,
+    OPERATOR 4 >=,
+    OPERATOR 5 >,
+    FUNCTION 1 brin_minmax_opcinfo(internal),
+    FUNCTION 2 brin_minmax_add_value(internal, internal, internal, internal),
+    FUNCTION 3 brin_minmax_consistent(internal, internal, internal),
+    FUNCTION 4 brin_minmax_union(internal, internal, internal);
+
+CREATE AGGREGATE min(smalllexo) (
+    SFUNC = smalllexo_smaller,
+    STYPE = smalllexo,
+    COMBINEFUNC = smalllexo_smaller,
+    SORTOP = <,
+    PARALLEL = SAFE
+);
+CREATE AGGREGATE max(smalllexo) (
+    SFUNC = smalllexo_larger,
+    STYPE = smalllexo,
+    COMBINEFUNC = smalllexo_larger,
+    SORTOP = >,
+    PARALLEL = SAFE
+);
+
+CREATE CAST (smalllexo AS lexo) WITH FUNCTION smalllexo_to_lexo(smalllexo) AS IMPLICIT;
+CREATE CAST (lexo AS smalllexo) WITH FUNCTION smalllexo_from_lexo(lexo) AS ASSIGNMENT;
+
+CREATE TYPE lexorange AS RANGE (
+    SUBTYPE = lexo,
+    SUBTYPE_OPCLASS = lexo_btree_ops,
+    SUBTYPE_DIFF = lexo_range_diff
+);
+ALTER OPERATOR FAMILY lexo_btree_ops USING btree
+    ADD FUNCTION 2 (lexo, lexo) lexo_sortsupport(internal);
+
+-- lexo_btree_ops compares with lexo_compare() from 0.7.0 on. ALTER OPERATOR
+-- FAMILY can't replace a function an operator class requires, so the support
+-- function is repointed in the catalog; lexo_cmp() returns the same results,
+-- so existing indexes stay valid.
+UPDATE pg_catalog.pg_depend d
+SET refobjid = 'lexo_compare(lexo, lexo)'::pg_catalog.regprocedure
+FROM pg_catalog.pg_amproc p
+JOIN pg_catalog.pg_opfamily f ON f.oid = p.amprocfamily
+WHERE f.opfname = 'lexo_btree_ops'
+  AND f.opfnamespace = (SELECT extnamespace FROM pg_catalog.pg_extension WHERE extname = 'pg_lexo')
+  AND p.amprocnum = 1
+  AND d.classid = 'pg_catalog.pg_amproc'::pg_catalog.regclass
+  AND d.objid = p.oid
+  AND d.refclassid = 'pg_catalog.pg_proc'::pg_catalog.regclass
+  AND d.refobjid = p.amproc;
+UPDATE pg_catalog.pg_amproc p
+SET amproc = 'lexo_compare(lexo, lexo)'::pg_catalog.regprocedure
+FROM pg_catalog.pg_opfamily f
+WHERE f.oid = p.amprocfamily
+  AND f.opfname = 'lexo_btree_ops'
+  AND f.opfnamespace = (SELECT extnamespace FROM pg_catalog.pg_extension WHERE extname = 'pg_lexo')
+  AND p.amprocnum = 1;
+
+CREATE FUNCTION "lexo_drop_column"(
+	"table_name" TEXT,
+	"column_name" TEXT
+) RETURNS void
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_drop_column_wrapper';
+
+CREATE TABLE lexo_sequence_keys (
+    table_name regclass NOT NULL,
+    column_name name NOT NULL,
+    key_value text NOT NULL,
+    prefix text NOT NULL,
+    sequence_name regclass NOT NULL,
+    PRIMARY KEY (table_name, column_name, key_value)
+);
+SELECT pg_catalog.pg_extension_config_dump('lexo_sequence_keys', '');
+
+CREATE FUNCTION lexo_assign_rank() RETURNS lexo
+WINDOW IMMUTABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_assign_rank_wrapper';
+
+CREATE FUNCTION "lexo_first_text"() RETURNS lexo_text
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_first_text_wrapper';
+
+CREATE FUNCTION "lexo_next_text"(
+	"table_name" TEXT,
+	"lexo_column_name" TEXT,
+	"identifier_column_name" TEXT,
+	"identifier_value" TEXT,
+	"empty_default" TEXT DEFAULT NULL,
+	"only" bool DEFAULT false
+) RETURNS lexo_text
+COST 100 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_next_text_wrapper';
+
+CREATE FUNCTION "lexo_rebalance_text"(
+	"ranks" TEXT[]
+) RETURNS lexo_text[]
+IMMUTABLE STRICT PARALLEL SAFE 
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_rebalance_text_wrapper';
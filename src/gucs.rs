@@ -0,0 +1,181 @@
+//! Configuration parameters (GUCs) for pg_lexo.
+//!
+//! All settings live under the reserved `lexo.` prefix and are registered from
+//! `_PG_init`, so they are available as soon as the library is loaded.
+
+use pgrx::prelude::*;
+use pgrx::{GucContext, GucFlags, GucRegistry, GucSetting};
+use std::ffi::CString;
+
+/// `lexo.autorebalance`: whether the background worker rebalances degraded lists
+pub static AUTOREBALANCE: GucSetting<bool> = GucSetting::<bool>::new(false);
+
+/// `lexo.autorebalance_naptime`: seconds between background worker cycles
+pub static AUTOREBALANCE_NAPTIME: GucSetting<i32> = GucSetting::<i32>::new(60);
+
+/// `lexo.autorebalance_max_rows`: maximum rows rebalanced per cycle
+pub static AUTOREBALANCE_MAX_ROWS: GucSetting<i32> = GucSetting::<i32>::new(100_000);
+
+/// `lexo.autorebalance_window`: maintenance window as `HH:MM-HH:MM`, empty for always
+pub static AUTOREBALANCE_WINDOW: GucSetting<Option<CString>> =
+    GucSetting::<Option<CString>>::new(None);
+
+/// `lexo.autorebalance_database`: database the background worker connects to
+pub static AUTOREBALANCE_DATABASE: GucSetting<Option<CString>> =
+    GucSetting::<Option<CString>>::new(Some(c"postgres"));
+
+/// `lexo.rebalance_report_every`: rows between rebalance progress notices, 0 for none
+pub static REBALANCE_REPORT_EVERY: GucSetting<i32> = GucSetting::<i32>::new(100_000);
+
+/// `lexo.rebalance_batch_size`: rows fetched per cursor batch while rebalancing
+pub static REBALANCE_BATCH_SIZE: GucSetting<i32> = GucSetting::<i32>::new(10_000);
+
+/// `lexo.writer_id`: Base62 character that `lexo_between_tagged()` appends for this writer
+pub static WRITER_ID: GucSetting<Option<CString>> = GucSetting::<Option<CString>>::new(None);
+
+/// `lexo.record_history`: whether history triggers record position changes
+pub static RECORD_HISTORY: GucSetting<bool> = GucSetting::<bool>::new(true);
+
+/// `lexo.history_summarize_rebalance`: log one history row per rebalance instead of one per row
+pub static HISTORY_SUMMARIZE_REBALANCE: GucSetting<bool> = GucSetting::<bool>::new(false);
+
+/// `lexo.next_cache`: whether `lexo_next()` remembers the positions it handed out in a transaction
+pub static NEXT_CACHE: GucSetting<bool> = GucSetting::<bool>::new(true);
+
+/// `lexo.debug`: log the inputs, branch and result of each generated position
+pub static DEBUG: GucSetting<bool> = GucSetting::<bool>::new(false);
+
+/// `lexo.slice_max_rows`: largest `max_rows` that `lexo_slice()` accepts
+pub static SLICE_MAX_ROWS: GucSetting<i32> = GucSetting::<i32>::new(10_000);
+
+/// Registers every pg_lexo GUC.
+pub fn init() {
+    GucRegistry::define_bool_guc(
+        c"lexo.autorebalance",
+        c"Enables automatic rebalancing of degraded lists.",
+        c"When on, the pg_lexo background worker rebalances lists registered in lexo_autorebalance_targets whose positions exceed their max_length.",
+        &AUTOREBALANCE,
+        GucContext::Sighup,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_int_guc(
+        c"lexo.autorebalance_naptime",
+        c"Time to sleep between automatic rebalance cycles.",
+        c"",
+        &AUTOREBALANCE_NAPTIME,
+        1,
+        86_400,
+        GucContext::Sighup,
+        GucFlags::UNIT_S,
+    );
+
+    GucRegistry::define_int_guc(
+        c"lexo.autorebalance_max_rows",
+        c"Maximum number of rows rebalanced per automatic rebalance cycle.",
+        c"Lists larger than this are never rebalanced automatically.",
+        &AUTOREBALANCE_MAX_ROWS,
+        1,
+        i32::MAX,
+        GucContext::Sighup,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_string_guc(
+        c"lexo.autorebalance_window",
+        c"Time of day during which automatic rebalancing may run.",
+        c"Written as HH:MM-HH:MM in server local time; the window may wrap past midnight. Empty means any time.",
+        &AUTOREBALANCE_WINDOW,
+        GucContext::Sighup,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_string_guc(
+        c"lexo.autorebalance_database",
+        c"Database the automatic rebalance worker connects to.",
+        c"",
+        &AUTOREBALANCE_DATABASE,
+        GucContext::Postmaster,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_int_guc(
+        c"lexo.rebalance_report_every",
+        c"Number of rows between rebalance progress notices.",
+        c"lexo_rebalance() raises a NOTICE with the rows done, the total and the elapsed time each time this many rows have been rewritten. 0 disables the notices.",
+        &REBALANCE_REPORT_EVERY,
+        0,
+        i32::MAX,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_int_guc(
+        c"lexo.rebalance_batch_size",
+        c"Number of rows fetched per cursor batch while rebalancing.",
+        c"lexo_rebalance() reads the rows of a list through a cursor instead of loading them all at once, so memory use stays bounded by this many rows. Larger batches mean fewer round trips through SPI.",
+        &REBALANCE_BATCH_SIZE,
+        1,
+        i32::MAX,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_string_guc(
+        c"lexo.writer_id",
+        c"Discriminator appended to positions generated by lexo_between_tagged().",
+        c"A single Base62 character other than 0, distinct for every writer that fills the same lists, e.g. one per replicated region.",
+        &WRITER_ID,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_bool_guc(
+        c"lexo.record_history",
+        c"Records position changes in lexo_history for columns with history enabled.",
+        c"Turn it off for a session to skip the history of bulk changes.",
+        &RECORD_HISTORY,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_bool_guc(
+        c"lexo.history_summarize_rebalance",
+        c"Records one lexo_history row per rebalance instead of one per rewritten row.",
+        c"Rebalancing a long list otherwise writes a history row for every position it changes. When on, lexo_rebalance() pauses lexo.record_history while it runs and then records a single row naming the list and the number of rows rewritten.",
+        &HISTORY_SUMMARIZE_REBALANCE,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_bool_guc(
+        c"lexo.next_cache",
+        c"Makes consecutive lexo_next() calls in a transaction return increasing positions.",
+        c"Each call returns a position after both the list's maximum and the last position it returned for that list in the transaction, so rows inserted by one INSERT ... SELECT get distinct positions. Turn it off to always derive the position from the rows the statement can see.",
+        &NEXT_CACHE,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_bool_guc(
+        c"lexo.debug",
+        c"Logs how lexo_after(), lexo_before() and lexo_between() generate each position.",
+        c"Each call writes a LOG line with its arguments, the branch taken and the result. Set client_min_messages to log to see them in the session.",
+        &DEBUG,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_int_guc(
+        c"lexo.slice_max_rows",
+        c"Largest number of rows a single lexo_slice() call may ask for.",
+        c"Calls with a larger max_rows fail with program_limit_exceeded instead of loading a runaway number of rows.",
+        &SLICE_MAX_ROWS,
+        1,
+        i32::MAX,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+
+    unsafe { pg_sys::MarkGUCPrefixReserved(c"lexo".as_ptr()) };
+}
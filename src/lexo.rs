@@ -0,0 +1,285 @@
+//! The `lexo` PostgreSQL type.
+
+use pgrx::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use crate::operations::{MID_CHAR, find_invalid_char, parse_base62_input};
+
+/// A lexicographic rank type for ordering items in PostgreSQL.
+///
+/// `Lexo` wraps a Base62-encoded string that maintains lexicographic ordering.
+/// This type can be used as a column type in PostgreSQL tables to efficiently
+/// manage the order of rows without needing to update other rows when inserting.
+///
+/// # Example
+/// ```sql
+/// CREATE TABLE items (
+///     id SERIAL PRIMARY KEY,
+///     position lexo NOT NULL
+///);
+///
+/// -- Insert items using the lexo functions
+/// INSERT INTO items (position) VALUES (lexo_first());
+/// ```
+///
+/// # Serialization
+/// `Lexo` serializes as a bare string (`"H"`) in every serde context, including
+/// the on-disk representation. Values written by earlier versions as
+/// `{"value": "H"}` are still accepted when deserializing.
+#[derive(Debug, Clone, Serialize, Deserialize, PostgresType, PostgresEq, PostgresHash)]
+#[serde(into = "String", from = "LexoRepr")]
+#[inoutfuncs]
+pub struct Lexo {
+    value: String,
+}
+
+/// Serialized forms accepted when deserializing a `Lexo`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LexoRepr {
+    /// Current representation: a bare string
+    Plain(String),
+    /// Struct representation used before serialization became transparent
+    Legacy { value: String },
+}
+
+impl From<LexoRepr> for Lexo {
+    fn from(repr: LexoRepr) -> Self {
+        match repr {
+            LexoRepr::Plain(value) | LexoRepr::Legacy { value } => Self { value },
+        }
+    }
+}
+
+impl Lexo {
+    /// Creates a new Lexo from a string value.
+    ///
+    /// # Arguments
+    /// * `value` - A Base62-encoded string
+    ///
+    /// # Panics
+    /// Panics if the value contains invalid Base62 characters.
+    pub fn new(value: String) -> Self {
+        if let Some((offset, character)) = find_invalid_char(&value) {
+            report_invalid(&value, offset, character);
+        }
+        Self { value }
+    }
+
+    /// Creates a new Lexo from a string reference.
+    ///
+    /// Leading and trailing ASCII whitespace is trimmed first, so this is the
+    /// entry point for positions typed by hand (type input and the `_text`
+    /// functions).
+    ///
+    /// # Arguments
+    /// * `value` - A Base62-encoded string reference
+    ///
+    /// # Panics
+    /// Panics if the value contains invalid Base62 characters.
+    pub fn from_str_ref(value: &str) -> Self {
+        match parse_base62_input(value) {
+            Ok(trimmed) => Self {
+                value: trimmed.to_string(),
+            },
+            Err((offset, character)) => report_invalid(value, offset, character),
+        }
+    }
+
+    /// Returns the first/initial Lexo value.
+    pub fn first() -> Self {
+        Self {
+            value: MID_CHAR.to_string(),
+        }
+    }
+
+    /// Returns the inner string value.
+    pub fn as_str(&self) -> &str {
+        &self.value
+    }
+
+    /// Consumes self and returns the inner string value.
+    pub fn into_inner(self) -> String {
+        self.value
+    }
+
+    /// Returns true if the Lexo is empty.
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+}
+
+/// Raises the error for a value that is not Base62, pointing at the first
+/// offending character
+pub(crate) fn report_invalid(value: &str, offset: usize, character: char) -> ! {
+    raise_error!(
+        PgSqlErrorCode::ERRCODE_INVALID_TEXT_REPRESENTATION,
+        format!(
+            "Invalid Lexo value '{}': must contain only Base62 characters (0-9, A-Z, a-z)",
+            value
+        ),
+        detail = format!(
+            "Invalid character {:?} at byte offset {}.",
+            character, offset
+        ),
+    );
+}
+
+impl Default for Lexo {
+    fn default() -> Self {
+        Self::first()
+    }
+}
+
+impl fmt::Display for Lexo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl FromStr for Lexo {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_base62_input(s) {
+            Ok(trimmed) => Ok(Self {
+                value: trimmed.to_string(),
+            }),
+            Err(_) => Err("Invalid Lexo: must contain only Base62 characters (0-9, A-Z, a-z)"),
+        }
+    }
+}
+
+impl PartialEq for Lexo {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for Lexo {}
+
+impl PartialOrd for Lexo {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Lexo {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value.cmp(&other.value)
+    }
+}
+
+impl Hash for Lexo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
+    }
+}
+
+impl InOutFuncs for Lexo {
+    fn input(input: &core::ffi::CStr) -> Self
+    where
+        Self: Sized,
+    {
+        let s = input.to_str().expect("Invalid UTF-8 in Lexo input");
+        Self::from_str_ref(s)
+    }
+
+    fn output(&self, buffer: &mut pgrx::StringInfo) {
+        buffer.push_str(&self.value);
+    }
+}
+
+impl From<String> for Lexo {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<&str> for Lexo {
+    fn from(value: &str) -> Self {
+        Self::from_str_ref(value)
+    }
+}
+
+impl From<Lexo> for String {
+    fn from(rank: Lexo) -> Self {
+        rank.into_inner()
+    }
+}
+
+impl AsRef<str> for Lexo {
+    fn as_ref(&self) -> &str {
+        &self.value
+    }
+}
+
+// The ordering operators are declared by hand rather than derived with
+// `PostgresOrd`, whose operator class would compare with a generated
+// `lexo_cmp()` instead of `lexo_compare()`.
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(<)]
+#[negator(>=)]
+#[commutator(>)]
+#[restrict(scalarltsel)]
+#[join(scalarltjoinsel)]
+fn lexo_lt(left: Lexo, right: Lexo) -> bool {
+    left < right
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(>)]
+#[negator(<=)]
+#[commutator(<)]
+#[restrict(scalargtsel)]
+#[join(scalargtjoinsel)]
+fn lexo_gt(left: Lexo, right: Lexo) -> bool {
+    left > right
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(<=)]
+#[negator(>)]
+#[commutator(>=)]
+#[restrict(scalarlesel)]
+#[join(scalarlejoinsel)]
+fn lexo_le(left: Lexo, right: Lexo) -> bool {
+    left <= right
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(>=)]
+#[negator(<)]
+#[commutator(<=)]
+#[restrict(scalargesel)]
+#[join(scalargejoinsel)]
+fn lexo_ge(left: Lexo, right: Lexo) -> bool {
+    left >= right
+}
+
+/// The btree comparison function of 0.6.0, kept for code that calls it.
+/// `lexo_compare()` returns the same result.
+#[pg_extern(immutable, parallel_safe)]
+fn lexo_cmp(left: Lexo, right: Lexo) -> i32 {
+    left.cmp(&right) as i32
+}
+
+extension_sql!(
+    r#"
+CREATE OPERATOR FAMILY lexo_btree_ops USING btree;
+CREATE OPERATOR CLASS lexo_btree_ops DEFAULT FOR TYPE lexo USING btree FAMILY lexo_btree_ops AS
+    OPERATOR 1 <,
+    OPERATOR 2 <=,
+    OPERATOR 3 =,
+    OPERATOR 4 >=,
+    OPERATOR 5 >,
+    FUNCTION 1 lexo_compare(lexo, lexo);
+"#,
+    name = "lexo_btree_ops",
+    requires = [lexo_eq, lexo_lt, lexo_le, lexo_gt, lexo_ge, lexo_compare]
+);
@@ -0,0 +1,58 @@
+//! `lexo_text`: positions returned as the text-backed domain.
+//!
+//! The domain itself is declared with the other domains in the schema module.
+//! Functions for `lexo_text` columns return this type so that their SQL
+//! signature, and what tools introspecting them see, is the domain rather
+//! than `lexo` or plain `text`.
+
+use pgrx::callconv::{BoxRet, FcInfo};
+use pgrx::datum::Datum;
+use pgrx::pgrx_sql_entity_graph::metadata::{
+    ArgumentError, Returns, ReturnsError, SqlMapping, SqlTranslatable,
+};
+use pgrx::prelude::*;
+
+use crate::Lexo;
+
+/// A valid position, passed to PostgreSQL as a `lexo_text` value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexoText(String);
+
+impl LexoText {
+    /// Returns the position as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<Lexo> for LexoText {
+    fn from(rank: Lexo) -> Self {
+        LexoText(rank.into_inner())
+    }
+}
+
+impl IntoDatum for LexoText {
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        self.0.into_datum()
+    }
+
+    fn type_oid() -> pg_sys::Oid {
+        pgrx::wrappers::regtypein("lexo_text")
+    }
+}
+
+unsafe impl BoxRet for LexoText {
+    unsafe fn box_into<'fcx>(self, fcinfo: &mut FcInfo<'fcx>) -> Datum<'fcx> {
+        unsafe { self.0.box_into(fcinfo) }
+    }
+}
+
+unsafe impl SqlTranslatable for LexoText {
+    fn argument_sql() -> Result<SqlMapping, ArgumentError> {
+        Ok(SqlMapping::literal("lexo_text"))
+    }
+
+    fn return_sql() -> Result<Returns, ReturnsError> {
+        Ok(Returns::One(SqlMapping::literal("lexo_text")))
+    }
+}
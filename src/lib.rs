@@ -22,191 +22,243 @@
 //! SELECT * FROM items ORDER BY position;
 //! ```
 
+#[cfg(feature = "pg")]
 use pgrx::prelude::*;
-use serde::{Deserialize, Serialize};
-use std::cmp::Ordering;
-use std::fmt;
-use std::hash::{Hash, Hasher};
-use std::str::FromStr;
-
-use crate::operations::{MID_CHAR, is_valid_base62};
 
+#[cfg(feature = "pg")]
 ::pgrx::pg_module_magic!();
 
+/// Raises an ERROR with an SQLSTATE, like `pgrx::ereport!(ERROR, ...)`, and
+/// optionally a detail and a hint, which `ereport!` has no slot for.
+#[cfg(feature = "pg")]
+macro_rules! raise_error {
+    ($errcode:expr, $message:expr $(, detail = $detail:expr)? $(, hint = $hint:expr)? $(,)?) => {{
+        ::pgrx::pg_sys::panic::ErrorReport::new($errcode, $message, ::pgrx::function_name!())
+            $(.set_detail($detail))?
+            $(.set_hint($hint))?
+            .report(::pgrx::PgLogLevel::ERROR);
+        unreachable!()
+    }};
+}
+
 // Module declarations
+#[cfg(feature = "pg")]
+mod gucs;
+#[cfg(feature = "pg")]
+mod lexo;
+#[cfg(feature = "pg")]
+mod lexo_text;
+#[cfg(feature = "pg")]
+mod next_cache;
 pub mod operations;
+pub mod rank;
+#[cfg(feature = "pg")]
 mod schema;
+#[cfg(feature = "pg")]
+mod small_lexo;
+#[cfg(feature = "pg")]
+mod stats;
+#[cfg(feature = "pg")]
+mod worker;
 
+#[cfg(feature = "pg")]
+pub use crate::lexo::Lexo;
+#[cfg(feature = "pg")]
+pub use crate::lexo_text::LexoText;
+#[cfg(feature = "pg")]
+pub use crate::small_lexo::SmallLexo;
 // Re-export all functions from schema module
+#[cfg(feature = "pg")]
 pub use crate::schema::*;
 
-/// A lexicographic rank type for ordering items in PostgreSQL.
-///
-/// `Lexo` wraps a Base62-encoded string that maintains lexicographic ordering.
-/// This type can be used as a column type in PostgreSQL tables to efficiently
-/// manage the order of rows without needing to update other rows when inserting.
-///
-/// # Example
-/// ```sql
-/// CREATE TABLE items (
-///     id SERIAL PRIMARY KEY,
-///     position lexo NOT NULL
-///);
-///
-/// -- Insert items using the lexo functions
-/// INSERT INTO items (position) VALUES (lexo_first());
-/// ```
-#[derive(
-    Debug, Clone, Serialize, Deserialize, PostgresType, PostgresEq, PostgresOrd, PostgresHash,
-)]
-#[inoutfuncs]
-pub struct Lexo {
-    value: String,
+#[cfg(feature = "pg")]
+#[pg_guard]
+pub extern "C-unwind" fn _PG_init() {
+    gucs::init();
+    stats::init();
+    worker::init();
 }
 
-impl Lexo {
-    /// Creates a new Lexo from a string value.
-    ///
-    /// # Arguments
-    /// * `value` - A Base62-encoded string
-    ///
-    /// # Panics
-    /// Panics if the value contains invalid Base62 characters.
-    pub fn new(value: String) -> Self {
-        if !value.is_empty() && !is_valid_base62(&value) {
-            pgrx::error!(
-                "Invalid Lexo value '{}': must contain only Base62 characters (0-9, A-Z, a-z)",
-                value
-            );
-        }
-        Self { value }
-    }
+#[cfg(all(test, feature = "pg"))]
+mod unit_tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::str::FromStr;
 
-    /// Creates a new Lexo from a string reference.
-    ///
-    /// # Arguments
-    /// * `value` - A Base62-encoded string reference
-    ///
-    /// # Panics
-    /// Panics if the value contains invalid Base62 characters.
-    pub fn from_str_ref(value: &str) -> Self {
-        Self::new(value.to_string())
+    #[test]
+    fn test_serializes_as_plain_string() {
+        let rank = Lexo::from_str("Hz").unwrap();
+        assert_eq!(serde_json::to_string(&rank).unwrap(), "\"Hz\"");
     }
 
-    /// Returns the first/initial Lexo value.
-    pub fn first() -> Self {
-        Self {
-            value: MID_CHAR.to_string(),
-        }
-    }
-
-    /// Returns the inner string value.
-    pub fn as_str(&self) -> &str {
-        &self.value
+    #[test]
+    fn test_deserializes_plain_string() {
+        let rank: Lexo = serde_json::from_str("\"Hz\"").unwrap();
+        assert_eq!(rank.as_str(), "Hz");
     }
 
-    /// Consumes self and returns the inner string value.
-    pub fn into_inner(self) -> String {
-        self.value
+    #[test]
+    fn test_deserializes_legacy_struct_form() {
+        let rank: Lexo = serde_json::from_str(r#"{"value": "Hz"}"#).unwrap();
+        assert_eq!(rank.as_str(), "Hz");
     }
 
-    /// Returns true if the Lexo is empty.
-    pub fn is_empty(&self) -> bool {
-        self.value.is_empty()
-    }
-}
+    #[test]
+    fn test_serde_round_trip_in_composite() {
+        #[derive(Serialize, Deserialize)]
+        struct Item {
+            id: i32,
+            position: Lexo,
+        }
 
-impl Default for Lexo {
-    fn default() -> Self {
-        Self::first()
-    }
-}
+        let json = serde_json::to_string(&Item {
+            id: 1,
+            position: Lexo::first(),
+        })
+        .unwrap();
+        assert_eq!(json, r#"{"id":1,"position":"H"}"#);
 
-impl fmt::Display for Lexo {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.value)
+        let legacy: Item = serde_json::from_str(r#"{"id":1,"position":{"value":"H"}}"#).unwrap();
+        assert_eq!(legacy.position, Lexo::first());
     }
-}
 
-impl FromStr for Lexo {
-    type Err = &'static str;
+    #[test]
+    fn test_compare_agrees_with_sort_order() {
+        // Deterministic pseudo-random ranks (LCG) of varying length
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut ranks: Vec<Lexo> = (0..500)
+            .map(|_| {
+                let mut next = || {
+                    state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    (state >> 33) as usize
+                };
+                let len = 1 + next() % 6;
+                let value: String = (0..len)
+                    .map(|_| operations::BASE62_CHARS[next() % operations::BASE] as char)
+                    .collect();
+                Lexo::from_str(&value).unwrap()
+            })
+            .collect();
+        ranks.sort();
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if !s.is_empty() && !is_valid_base62(s) {
-            return Err("Invalid Lexo: must contain only Base62 characters (0-9, A-Z, a-z)");
+        for pair in ranks.windows(2) {
+            let expected = pair[0].as_str().cmp(pair[1].as_str()) as i32;
+            assert_eq!(lexo_compare(pair[0].clone(), pair[1].clone()), expected);
+            assert_eq!(lexo_compare(pair[1].clone(), pair[0].clone()), -expected);
+            assert!(expected <= 0);
         }
-        Ok(Self {
-            value: s.to_string(),
-        })
+        assert_eq!(lexo_compare(Lexo::first(), Lexo::first()), 0);
     }
-}
 
-impl PartialEq for Lexo {
-    fn eq(&self, other: &Self) -> bool {
-        self.value == other.value
-    }
-}
+    #[test]
+    fn test_check_order_by_expression() {
+        use crate::schema::check_order_by_expression as check;
 
-impl Eq for Lexo {}
+        assert!(check("created_at").is_ok());
+        assert!(check("lower(title)").is_ok());
+        assert!(check("coalesce(\"Due Date\", now())").is_ok());
+        assert!(check("title || 'it''s; fine -- really'").is_ok());
 
-impl PartialOrd for Lexo {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+        assert!(check("").is_err());
+        assert!(check("   ").is_err());
+        assert!(check("id); DROP TABLE items; --").is_err());
+        assert!(check("id) , (SELECT 1").is_err());
+        assert!(check("(id").is_err());
+        assert!(check("id -- comment").is_err());
+        assert!(check("id /* comment */").is_err());
+        assert!(check("$$x$$").is_err());
+        assert!(check("E'\\'' || 1").is_err());
+        assert!(check("'unterminated").is_err());
     }
-}
 
-impl Ord for Lexo {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.value.cmp(&other.value)
-    }
-}
+    #[test]
+    fn test_rebalance_json() {
+        use crate::schema::rebalance_json;
+        use serde_json::json;
 
-impl Hash for Lexo {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.value.hash(state);
-    }
-}
+        let mut doc = json!([
+            {"id": 1, "rank": "Hz", "meta": {"tags": ["a", {"rank": "keep"}]}},
+            {"id": 2, "rank": "H", "children": [{"id": 3, "rank": "0"}]},
+            {"id": 4},
+            {"id": 5, "rank": "not valid!"},
+            {"id": 6, "rank": 7}
+        ]);
+        rebalance_json(&mut doc, "rank").unwrap();
 
-impl InOutFuncs for Lexo {
-    fn input(input: &core::ffi::CStr) -> Self
-    where
-        Self: Sized,
-    {
-        let s = input.to_str().expect("Invalid UTF-8 in Lexo input");
-        Self::from_str(s).expect("Invalid Lexo value")
-    }
+        let ranks: Vec<&str> = doc
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["rank"].as_str().unwrap())
+            .collect();
+        let balanced = crate::operations::generate_balanced_positions(5);
+        assert_eq!(
+            ranks,
+            [
+                &balanced[1],
+                &balanced[0],
+                &balanced[2],
+                &balanced[3],
+                &balanced[4]
+            ]
+        );
+        assert_eq!(doc[0]["meta"], json!({"tags": ["a", {"rank": "keep"}]}));
+        assert_eq!(doc[1]["children"], json!([{"id": 3, "rank": "0"}]));
+        assert_eq!(doc[4]["id"], 6);
 
-    fn output(&self, buffer: &mut pgrx::StringInfo) {
-        buffer.push_str(&self.value);
-    }
-}
+        // Rebalancing again keeps the order and the ranks
+        let again = {
+            let mut copy = doc.clone();
+            rebalance_json(&mut copy, "rank").unwrap();
+            copy
+        };
+        assert_eq!(again, doc);
 
-impl From<String> for Lexo {
-    fn from(value: String) -> Self {
-        Self::new(value)
-    }
-}
+        let mut custom = json!([{"pos": "I"}, {"pos": "H", "rank": "zz"}]);
+        rebalance_json(&mut custom, "pos").unwrap();
+        assert!(custom[1]["pos"].as_str() < custom[0]["pos"].as_str());
+        assert_eq!(custom[1]["rank"], "zz");
 
-impl From<&str> for Lexo {
-    fn from(value: &str) -> Self {
-        Self::from_str_ref(value)
-    }
-}
+        let mut empty = json!([]);
+        rebalance_json(&mut empty, "rank").unwrap();
+        assert_eq!(empty, json!([]));
 
-impl From<Lexo> for String {
-    fn from(rank: Lexo) -> Self {
-        rank.into_inner()
+        assert!(rebalance_json(&mut json!({"rank": "H"}), "rank").is_err());
+        assert!(rebalance_json(&mut json!([{"rank": "H"}, "H"]), "rank").is_err());
     }
-}
 
-impl AsRef<str> for Lexo {
-    fn as_ref(&self) -> &str {
-        &self.value
+    #[test]
+    fn test_split_table_name() {
+        use crate::schema::split_table_name as split;
+
+        assert_eq!(split("items").unwrap(), ["items"]);
+        assert_eq!(split("app.items").unwrap(), ["app", "items"]);
+        assert_eq!(split("MyTable").unwrap(), ["mytable"]);
+        assert_eq!(split("\"MyTable\"").unwrap(), ["MyTable"]);
+        assert_eq!(split("\"weird.name\"").unwrap(), ["weird.name"]);
+        assert_eq!(
+            split("\"my.schema\".items").unwrap(),
+            ["my.schema", "items"]
+        );
+        assert_eq!(
+            split("\"my.schema\".\"a.b\"").unwrap(),
+            ["my.schema", "a.b"]
+        );
+        assert_eq!(split("\"say \"\"hi\"\"\"").unwrap(), ["say \"hi\""]);
+        assert_eq!(split("Mixed.\"Case\"").unwrap(), ["mixed", "Case"]);
+        assert_eq!(split("db.App.items").unwrap(), ["db", "app", "items"]);
+
+        assert!(split("").is_err());
+        assert!(split("\"\"").is_err());
+        assert!(split("app.").is_err());
+        assert!(split(".items").is_err());
+        assert!(split("a.b.c.d").is_err());
+        assert!(split("\"unterminated").is_err());
     }
 }
 
 /// This module is required by `cargo pgrx test` invocations.
-#[cfg(test)]
+#[cfg(all(test, feature = "pg"))]
 pub mod pg_test {
     pub fn setup(_options: Vec<&str>) {
         // perform one-off initialization when the pg_test framework starts
@@ -0,0 +1,87 @@
+//! Per-transaction memory of the positions `lexo_next()` has handed out.
+//!
+//! Every call in a statement reads the table through the same snapshot, so
+//! without this an `INSERT ... SELECT lexo_next(...)` would give every row the
+//! position after the same maximum. The last position returned for each list is
+//! kept per backend and forgotten when the transaction commits, aborts or is
+//! prepared. Positions handed out inside a subtransaction are forgotten when it
+//! aborts, e.g. on `ROLLBACK TO SAVEPOINT`, so the list continues after what is
+//! left. Disabled by setting `lexo.next_cache` to off.
+
+use pgrx::prelude::*;
+use pgrx::{
+    PgSubXactCallbackEvent, PgXactCallbackEvent, register_subxact_callback, register_xact_callback,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+/// A list as `lexo_next()` addresses it: table, position column and filter.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ListKey {
+    pub relation: pg_sys::Oid,
+    pub column: String,
+    pub filter_column: Option<String>,
+    pub filter_value: Option<String>,
+    pub only: bool,
+}
+
+thread_local! {
+    // For each list, the last position handed out in each subtransaction that
+    // handed one out, oldest first. Subtransaction ids grow, so the ones an
+    // aborting subtransaction owns are the entries at or above its id.
+    static HANDED_OUT: RefCell<HashMap<ListKey, Vec<(pg_sys::SubTransactionId, String)>>> =
+        RefCell::new(HashMap::new());
+    static CLEAR_REGISTERED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Returns the last position handed out for `key` in this transaction.
+pub fn last(key: &ListKey) -> Option<String> {
+    HANDED_OUT.with(|cache| {
+        cache
+            .borrow()
+            .get(key)
+            .and_then(|handed_out| handed_out.last())
+            .map(|(_, position)| position.clone())
+    })
+}
+
+/// Records `position` as the last one handed out for `key`.
+pub fn remember(key: ListKey, position: String) {
+    if !CLEAR_REGISTERED.replace(true) {
+        for event in [
+            PgXactCallbackEvent::Commit,
+            PgXactCallbackEvent::Abort,
+            PgXactCallbackEvent::Prepare,
+        ] {
+            register_xact_callback(event, clear);
+        }
+        register_subxact_callback(PgSubXactCallbackEvent::AbortSub, |subid, _parent| {
+            forget_since(subid)
+        });
+    }
+
+    let subid = unsafe { pg_sys::GetCurrentSubTransactionId() };
+    HANDED_OUT.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let handed_out = cache.entry(key).or_default();
+        match handed_out.last_mut() {
+            Some((last_subid, last)) if *last_subid == subid => *last = position,
+            _ => handed_out.push((subid, position)),
+        }
+    });
+}
+
+/// Forgets the positions handed out in subtransaction `subid` and its children.
+fn forget_since(subid: pg_sys::SubTransactionId) {
+    HANDED_OUT.with(|cache| {
+        cache.borrow_mut().retain(|_, handed_out| {
+            handed_out.retain(|(owner, _)| *owner < subid);
+            !handed_out.is_empty()
+        })
+    });
+}
+
+fn clear() {
+    HANDED_OUT.with(|cache| cache.borrow_mut().clear());
+    CLEAR_REGISTERED.set(false);
+}
@@ -3,6 +3,8 @@
 //! This module contains all the core logic for generating and manipulating
 //! Base62-encoded lexicographic positions.
 
+use std::collections::VecDeque;
+
 /// Base62 character set: 0-9, A-Z, a-z (62 characters)
 /// Sorted in ASCII/lexicographic order for proper string comparison
 pub const BASE62_CHARS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
@@ -15,10 +17,40 @@ pub const END_CHAR: char = 'z';
 /// The initial character for first position (index 17 = 'H')
 /// Chosen to reduce front spacing while allowing room for prepending
 pub const MID_CHAR: char = 'H';
+/// The index of `MID_CHAR` in `BASE62_CHARS`
+const MID_INDEX: usize = 17;
+/// The initial character for the first position of a descending list
+/// (index 44 = 'i'), the mirror image of `MID_CHAR`: such lists grow downwards
+pub const DESC_MID_CHAR: char = 'i';
+
+/// Revision of the position generation algorithm.
+/// Bumped whenever generated positions change for the same inputs.
+pub const ALGORITHM_VERSION: i32 = 2;
 
 /// Check if a string contains only valid Base62 characters
 pub fn is_valid_base62(s: &str) -> bool {
-    s.chars().all(|c| BASE62_CHARS.contains(&(c as u8)))
+    find_invalid_char(s).is_none()
+}
+
+/// Find the first character that is not Base62, with its byte offset in `s`
+pub fn find_invalid_char(s: &str) -> Option<(usize, char)> {
+    s.char_indices()
+        .find(|&(_, c)| !c.is_ascii() || !BASE62_CHARS.contains(&(c as u8)))
+}
+
+/// Trim the ASCII whitespace around a typed position and validate the rest
+///
+/// Returns the trimmed position, or the first invalid character together with
+/// its byte offset in the untrimmed input. The empty string is accepted.
+pub fn parse_base62_input(s: &str) -> Result<&str, (usize, char)> {
+    let leading = s.len()
+        - s.trim_start_matches(|c: char| c.is_ascii_whitespace())
+            .len();
+    let trimmed = s.trim_matches(|c: char| c.is_ascii_whitespace());
+    match find_invalid_char(trimmed) {
+        Some((offset, character)) => Err((leading + offset, character)),
+        None => Ok(trimmed),
+    }
 }
 
 /// Get the index of a character in the base62 character set
@@ -31,6 +63,130 @@ pub fn index_to_char(idx: usize) -> Option<char> {
     BASE62_CHARS.get(idx).map(|&b| b as char)
 }
 
+/// The character of a digit already known to be below `BASE`
+fn digit_char(digit: usize) -> char {
+    BASE62_CHARS[digit] as char
+}
+
+/// Interpret a Base62 string as a non-negative integer and return it in decimal
+///
+/// This uses integer (place-value) semantics, so leading '0' characters are not
+/// significant: "0A" and "A" both map to 10. That differs from the fractional
+/// interpretation used for positioning, where "A0" sorts before "B" even though
+/// its integer value (620) is larger than 11.
+pub fn base62_to_decimal(s: &str) -> String {
+    // Little-endian decimal digits
+    let mut digits: Vec<u8> = vec![0];
+
+    for c in s.chars() {
+        let mut carry = char_to_index(c).unwrap_or(0) as u32;
+        for d in digits.iter_mut() {
+            let v = *d as u32 * BASE as u32 + carry;
+            *d = (v % 10) as u8;
+            carry = v / 10;
+        }
+        while carry > 0 {
+            digits.push((carry % 10) as u8);
+            carry /= 10;
+        }
+    }
+
+    digits.iter().rev().map(|d| (b'0' + d) as char).collect()
+}
+
+/// Convert a non-negative decimal integer string into its Base62 representation
+///
+/// Returns `None` if the input is not a plain sequence of decimal digits.
+/// Zero maps to "0"; no leading '0' characters are produced otherwise.
+pub fn decimal_to_base62(s: &str) -> Option<String> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    // Big-endian decimal digits, repeatedly divided by 62
+    let mut digits: Vec<u32> = s.bytes().map(|b| (b - b'0') as u32).collect();
+    let mut result: Vec<char> = Vec::new();
+
+    while digits.iter().any(|&d| d != 0) {
+        let mut remainder = 0u32;
+        for d in digits.iter_mut() {
+            let v = remainder * 10 + *d;
+            *d = v / BASE as u32;
+            remainder = v % BASE as u32;
+        }
+        result.push(digit_char(remainder as usize));
+    }
+
+    if result.is_empty() {
+        return Some(START_CHAR.to_string());
+    }
+
+    Some(result.iter().rev().collect())
+}
+
+/// Why `transcode` could not re-encode a rank
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscodeError {
+    /// The alphabet has fewer than two characters
+    AlphabetTooShort(usize),
+    /// The alphabet lists a character more than once
+    DuplicateCharacter(char),
+    /// The rank is the empty string
+    Empty,
+    /// The rank contains a character that is not in the alphabet
+    InvalidCharacter { offset: usize, character: char },
+}
+
+/// Re-encode a rank written in another alphabet as a Base62 position
+///
+/// `alphabet` lists the source digits from lowest to highest, so it may be in
+/// any order, not just ASCII order. Each source digit `i` of a base `B` alphabet
+/// becomes `width` Base62 digits holding `floor(i * 62^width / B)`, where `width`
+/// is the smallest with `62^width >= B`: the digit keeps its fraction of the
+/// keyspace at every place. The encoding is strictly increasing and maps the
+/// lowest digit to all '0's, so two ranks compare exactly as they did under
+/// their own alphabet, including when one is a prefix of the other. Converting
+/// the whole rank as one fraction could not promise that, since a base that
+/// does not divide a power of 62 has no finite Base62 expansion to truncate
+/// without merging neighbours.
+pub fn transcode(rank: &str, alphabet: &str) -> Result<String, TranscodeError> {
+    let mut digits = std::collections::HashMap::new();
+    for (idx, c) in alphabet.chars().enumerate() {
+        if digits.insert(c, idx as u128).is_some() {
+            return Err(TranscodeError::DuplicateCharacter(c));
+        }
+    }
+    let base = digits.len() as u128;
+    if base < 2 {
+        return Err(TranscodeError::AlphabetTooShort(digits.len()));
+    }
+    if rank.is_empty() {
+        return Err(TranscodeError::Empty);
+    }
+
+    let mut width = 1;
+    let mut scale = BASE as u128;
+    while scale < base {
+        width += 1;
+        scale *= BASE as u128;
+    }
+
+    let mut result = String::with_capacity(rank.len() * width);
+    let mut chunk = vec![START_CHAR; width];
+    for (offset, character) in rank.char_indices() {
+        let Some(&digit) = digits.get(&character) else {
+            return Err(TranscodeError::InvalidCharacter { offset, character });
+        };
+        let mut code = digit * scale / base;
+        for slot in chunk.iter_mut().rev() {
+            *slot = BASE62_CHARS[(code % BASE as u128) as usize] as char;
+            code /= BASE as u128;
+        }
+        result.extend(&chunk);
+    }
+    Ok(result)
+}
+
 /// Generate a vector of evenly distributed position strings
 pub fn generate_balanced_positions(count: usize) -> Vec<String> {
     if count == 0 {
@@ -40,15 +196,153 @@ pub fn generate_balanced_positions(count: usize) -> Vec<String> {
         return vec![MID_CHAR.to_string()];
     }
 
-    let mut positions = Vec::with_capacity(count);
+    (0..count)
+        .filter_map(|i| balanced_position(i, count))
+        .collect()
+}
+
+/// Generate balanced positions for `ranks` without reordering them
+///
+/// Element `i` of the result is the balanced position that the rank at index `i`
+/// gets when the whole list is rebalanced in order. Equal ranks keep their
+/// relative order from the input.
+pub fn rebalance_positions<S: AsRef<str>>(ranks: &[S]) -> Vec<String> {
+    let ranks: Vec<Option<&str>> = ranks.iter().map(|rank| Some(rank.as_ref())).collect();
+    rebalance_positions_missing_last(&ranks)
+}
+
+/// Generate balanced positions like [`rebalance_positions`], with `None` ranks last
+///
+/// Elements without a rank are placed after every ranked element, in their
+/// order from the input.
+pub fn rebalance_positions_missing_last<S: AsRef<str>>(ranks: &[Option<S>]) -> Vec<String> {
+    let mut order: Vec<usize> = (0..ranks.len()).collect();
+    order.sort_by_key(|&idx| {
+        let rank = ranks[idx].as_ref().map(AsRef::as_ref);
+        (rank.is_none(), rank)
+    });
+
+    let mut positions = vec![String::new(); ranks.len()];
+    for (position, idx) in generate_balanced_positions(ranks.len())
+        .into_iter()
+        .zip(order)
+    {
+        positions[idx] = position;
+    }
+    positions
+}
+
+/// Get the position at `index` of `count` evenly distributed positions
+///
+/// This is exactly `generate_balanced_positions(count)[index]`, computed without
+/// building the whole vector. Returns `None` if `index` is not less than `count`.
+pub fn balanced_position(index: usize, count: usize) -> Option<String> {
+    if index >= count {
+        return None;
+    }
+    if count == 1 {
+        return Some(MID_CHAR.to_string());
+    }
 
     // Distribute positions evenly using fractional approach
-    for i in 0..count {
-        let fraction = (i as f64 + 0.5) / (count as f64);
-        positions.push(fraction_to_position(fraction));
+    let fraction = (index as f64 + 0.5) / (count as f64);
+    Some(fraction_to_position(fraction))
+}
+
+/// Generate `count` evenly distributed positions that are all exactly `length` characters
+///
+/// Position `i` is the `length`-digit floor of `(i + 0.5) / count`, so the positions
+/// are spread over the whole keyspace of that length. Returns `None` if `count`
+/// exceeds the number of distinct positions of that length.
+pub fn generate_balanced_positions_of_length(count: usize, length: usize) -> Option<Vec<String>> {
+    let mut capacity: u128 = 1;
+    for _ in 0..length {
+        if capacity >= count as u128 {
+            break;
+        }
+        capacity *= BASE as u128;
+    }
+    if length == 0 || capacity < count as u128 {
+        return None;
     }
 
-    positions
+    let denominator = 2 * count as u128;
+    let positions = (0..count)
+        .map(|i| {
+            // Long division of (2i + 1) / 2count, one Base62 digit at a time
+            let mut remainder = 2 * i as u128 + 1;
+            (0..length)
+                .map(|_| {
+                    remainder *= BASE as u128;
+                    let digit = remainder / denominator;
+                    remainder %= denominator;
+                    digit_char(digit as usize)
+                })
+                .collect()
+        })
+        .collect();
+    Some(positions)
+}
+
+/// A spacing plan for a list whose size is known up front, see `plan_for_size`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizePlan {
+    /// The position of the first entry
+    pub first: String,
+    /// The length of every planned position
+    pub length: usize,
+    /// The distance between consecutive planned positions, in units of the last digit
+    pub step: u128,
+}
+
+/// Plan fixed-length positions for a list that will hold `expected_count` entries
+///
+/// The planned positions cover the middle half of the keyspace of the chosen
+/// length, leaving a quarter free at each end for prepends and appends. The
+/// length is the shortest for which consecutive positions are at least 62
+/// apart, so every gap can take inserts without making positions longer.
+/// Position `i` of the plan is `planned_position(&plan.first, plan.step, i)`.
+pub fn plan_for_size(expected_count: u64) -> SizePlan {
+    let count = expected_count.max(1) as u128;
+    let mut length = 1;
+    let mut capacity = BASE as u128;
+    while capacity / 2 / count < BASE as u128 {
+        length += 1;
+        capacity *= BASE as u128;
+    }
+    SizePlan {
+        first: low_digits(capacity / 4, length),
+        length,
+        step: capacity / 2 / count,
+    }
+}
+
+/// Get the position `index` steps of `step` after `first`, keeping the length of `first`
+///
+/// Returns `None` if the result does not fit in that many characters, or if
+/// `first` is not valid Base62.
+pub fn planned_position(first: &str, step: u128, index: u64) -> Option<String> {
+    let value = first.chars().try_fold(0u128, |v, c| {
+        v.checked_mul(BASE as u128)?
+            .checked_add(char_to_index(c)? as u128)
+    })?;
+    let value = value.checked_add(step.checked_mul(index as u128)?)?;
+    let length = first.chars().count();
+    // Past 21 digits the keyspace is larger than any `u128`
+    let fits = (BASE as u128)
+        .checked_pow(length as u32)
+        .is_none_or(|capacity| value < capacity);
+    fits.then(|| low_digits(value, length))
+}
+
+/// Write the last `length` Base62 digits of `value` as a position
+fn low_digits(mut value: u128, length: usize) -> String {
+    let mut digits = vec![0; length];
+    for d in digits.iter_mut().rev() {
+        *d = (value % BASE as u128) as usize;
+        value /= BASE as u128;
+    }
+    digits_to_string(&digits)
 }
 
 /// Convert a fraction (0.0 to 1.0) to a position string with minimal length
@@ -90,6 +384,88 @@ pub fn fraction_to_position(fraction: f64) -> String {
     result
 }
 
+/// Convert a position string to its fractional value in [0.0, 1.0)
+///
+/// This is the inverse of `fraction_to_position`: each character contributes
+/// its Base62 index scaled by 62^-(i+1). Precision is limited by `f64`, so very
+/// long positions that differ only after ~9 characters may map to the same value.
+pub fn position_to_fraction(s: &str) -> f64 {
+    let base = BASE as f64;
+    let mut fraction = 0.0;
+    let mut scale = 1.0 / base;
+
+    for c in s.chars() {
+        fraction += char_to_index(c).unwrap_or(0) as f64 * scale;
+        scale /= base;
+    }
+
+    fraction
+}
+
+/// Zero-based index of the value at percentile `pct` of `count` sorted values
+///
+/// Follows `percentile_disc`: the first value whose cumulative share of the
+/// values reaches `pct`. Returns `None` when there are no values.
+pub fn percentile_index(count: usize, pct: f64) -> Option<usize> {
+    if count == 0 {
+        return None;
+    }
+    let reached = (pct.clamp(0.0, 1.0) * count as f64).ceil() as usize;
+    Some(reached.clamp(1, count) - 1)
+}
+
+/// How a position decomposes into Base62 digits, as reported by `lexo_explain()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    /// Each character with its Base62 index, most significant first
+    pub digits: Vec<(char, usize)>,
+    /// The position read as a fraction in [0.0, 1.0), see `position_to_fraction`
+    pub fraction: f64,
+    /// Number of characters
+    pub length: usize,
+    /// Every character is '0', so nothing can be placed before it
+    pub is_minimum: bool,
+    /// Every character is 'z', so anything after it must be longer
+    pub is_maximum: bool,
+    /// The last character is '0', so nothing fits between it and its prefix
+    pub ends_with_min_char: bool,
+}
+
+/// Decompose a position into its digits, fraction and boundary flags
+///
+/// Characters outside the Base62 alphabet are reported with index 0, the same
+/// way `position_to_fraction` treats them.
+pub fn explain(s: &str) -> Explanation {
+    let digits: Vec<(char, usize)> = s
+        .chars()
+        .map(|c| (c, char_to_index(c).unwrap_or(0)))
+        .collect();
+    let non_empty = !digits.is_empty();
+
+    Explanation {
+        fraction: position_to_fraction(s),
+        length: digits.len(),
+        is_minimum: non_empty && s.chars().all(|c| c == START_CHAR),
+        is_maximum: non_empty && s.chars().all(|c| c == END_CHAR),
+        ends_with_min_char: s.ends_with(START_CHAR),
+        digits,
+    }
+}
+
+/// Get the leading characters shared by two positions
+///
+/// Long common prefixes between neighbours point at a region that has been
+/// bisected many times and is due for a rebalance.
+pub fn common_prefix<'a>(a: &'a str, b: &str) -> &'a str {
+    let end = a
+        .char_indices()
+        .zip(b.chars())
+        .find(|&((_, x), y)| x != y)
+        .map(|((i, _), _)| i)
+        .unwrap_or_else(|| a.len().min(b.len()));
+    &a[..end]
+}
+
 /// Generate a position string after the given string with minimal spacing
 pub fn generate_after(s: &str) -> String {
     if s.is_empty() {
@@ -105,7 +481,7 @@ pub fn generate_after(s: &str) -> String {
         {
             // Can increment this character
             let mut result: String = chars[..i].iter().collect();
-            result.push(index_to_char(idx + 1).unwrap());
+            result.push(digit_char(idx + 1));
             return result;
         }
         // This char is 'z', continue to previous position
@@ -118,12 +494,11 @@ pub fn generate_after(s: &str) -> String {
 
 /// Generate a position string before the given string with minimal spacing
 ///
-/// # Panics
-/// This function will panic if called with a string consisting entirely of '0' characters,
-/// as there is no valid position before the minimum in lexicographic ordering.
-pub fn generate_before(s: &str) -> String {
+/// Returns `None` if `s` consists entirely of '0' characters, as there is no
+/// valid position before the minimum in lexicographic ordering.
+pub fn generate_before(s: &str) -> Option<String> {
     if s.is_empty() {
-        return MID_CHAR.to_string();
+        return Some(MID_CHAR.to_string());
     }
 
     let chars: Vec<char> = s.chars().collect();
@@ -139,39 +514,300 @@ pub fn generate_before(s: &str) -> String {
             // If this is the last character and we can decrement by more than 1
             // just decrement by 1 for minimal spacing
             if i == chars.len() - 1 && idx > 1 {
-                result.push(index_to_char(idx - 1).unwrap());
-                return result;
+                result.push(digit_char(idx - 1));
+                return Some(result);
             }
 
             // Otherwise, decrement and add a high character to ensure proper ordering
-            result.push(index_to_char(idx - 1).unwrap());
+            result.push(digit_char(idx - 1));
             result.push(END_CHAR);
-            return result;
+            return Some(result);
         }
         // This char is '0', continue to previous position
     }
 
     // All characters are '0' - this is the minimum possible position
-    panic!(
-        "Cannot generate a position before '{}': this is the minimum possible position",
-        s
-    );
+    None
+}
+
+/// Generate a position at least `gap` single steps after the given string
+///
+/// The result is `s` plus `gap` in its last digit, so `gap - 1` positions of
+/// the result's length stay free between the two. When that does not fit in
+/// the current length, a digit is added until it does, and a result that
+/// would end in '0' takes one more step. A `gap` of 1 is exactly
+/// `generate_after`.
+pub fn generate_after_gap(s: &str, gap: usize) -> String {
+    if s.is_empty() || gap <= 1 {
+        return generate_after(s);
+    }
+
+    step_by_gap(s, gap, |digits, amount| {
+        let mut carry = amount;
+        for d in digits.iter_mut().rev() {
+            let v = *d + carry;
+            *d = v % BASE;
+            carry = v / BASE;
+        }
+        carry == 0
+    })
+}
+
+/// Generate a position at least `gap` single steps before the given string
+///
+/// The counterpart of `generate_after_gap`: `s` minus `gap` in its last digit,
+/// adding digits until that stays above zero. A `gap` of 1 is exactly
+/// `generate_before`, and like it this returns `None` if `s` consists
+/// entirely of '0' characters.
+pub fn generate_before_gap(s: &str, gap: usize) -> Option<String> {
+    if s.is_empty() || gap <= 1 || s.chars().all(|c| c == START_CHAR) {
+        return generate_before(s);
+    }
+
+    Some(step_by_gap(s, gap, |digits, amount| {
+        let mut borrow = amount;
+        for d in digits.iter_mut().rev() {
+            let take = borrow % BASE;
+            borrow /= BASE;
+            if *d >= take {
+                *d -= take;
+            } else {
+                *d += BASE - take;
+                borrow += 1;
+            }
+        }
+        borrow == 0
+    }))
+}
+
+/// Why `advance` or `retreat` could not produce a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepError {
+    /// `retreat` was asked to step below a position made only of '0's
+    Minimum,
+    /// The result would be longer than `MAX_TYPMOD_LENGTH` characters
+    TooLong,
+}
+
+/// The position `steps` calls of `generate_after` away from `s`, computed
+/// without generating the ones in between
+///
+/// Each call increments the rightmost character below 'z' and drops the ones
+/// after it, or appends a '0' when every character is 'z'. So the last
+/// character climbs to 'z' and folds into the one before it, and once all
+/// of them are 'z' every 62 steps add one character.
+pub fn advance(s: &str, steps: u64) -> Result<String, StepError> {
+    if steps == 0 {
+        return Ok(s.to_string());
+    }
+    if s.is_empty() {
+        return advance(&MID_CHAR.to_string(), steps - 1);
+    }
+
+    let mut digits: Vec<usize> = s.chars().map(|c| char_to_index(c).unwrap_or(0)).collect();
+    let mut steps = steps;
+    while let Some(i) = digits.iter().rposition(|&d| d < BASE - 1) {
+        digits.truncate(i + 1);
+        let room = (BASE - 1 - digits[i]) as u64;
+        if steps <= room {
+            digits[i] += steps as usize;
+            return Ok(digits_to_string(&digits));
+        }
+        steps -= room;
+        digits[i] = BASE - 1;
+    }
+
+    // All 'z': append '0' and climb it, one more character per 62 steps
+    let levels = (steps - 1) / BASE as u64;
+    if digits.len() as u64 + levels + 1 > MAX_TYPMOD_LENGTH as u64 {
+        return Err(StepError::TooLong);
+    }
+    digits.extend(std::iter::repeat_n(BASE - 1, levels as usize));
+    digits.push(((steps - 1) % BASE as u64) as usize);
+    Ok(digits_to_string(&digits))
+}
+
+/// The position `steps` calls of `generate_before` away from `s`, computed
+/// without generating the ones in between
+///
+/// The mirror image of `advance`: the last character counts down to '1',
+/// then turns into "0z", so from a position ending in '1' every 61 steps
+/// insert one '0' before it. Only a position made only of '0's has nothing
+/// below it.
+pub fn retreat(s: &str, steps: u64) -> Result<String, StepError> {
+    if steps == 0 {
+        return Ok(s.to_string());
+    }
+    if s.is_empty() {
+        return retreat(&MID_CHAR.to_string(), steps - 1);
+    }
+
+    let mut digits: Vec<usize> = s.chars().map(|c| char_to_index(c).unwrap_or(0)).collect();
+    let mut steps = steps;
+    loop {
+        let Some(i) = digits.iter().rposition(|&d| d > 0) else {
+            return Err(StepError::Minimum);
+        };
+        let last = digits.len() - 1;
+        if i == last && digits[i] > 1 {
+            let room = (digits[i] - 1) as u64;
+            if steps <= room {
+                digits[i] -= steps as usize;
+                return Ok(digits_to_string(&digits));
+            }
+            steps -= room;
+            digits[i] = 1;
+            continue;
+        }
+        if i == last {
+            // Ends in '1': 61 steps later it ends in "01"
+            let levels = steps / (BASE as u64 - 1);
+            if digits.len() as u64 + levels > MAX_TYPMOD_LENGTH as u64 {
+                return Err(StepError::TooLong);
+            }
+            digits.splice(last..last, std::iter::repeat_n(0, levels as usize));
+            steps %= BASE as u64 - 1;
+            if steps == 0 {
+                return Ok(digits_to_string(&digits));
+            }
+        }
+        let Some(i) = digits.iter().rposition(|&d| d > 0) else {
+            return Err(StepError::Minimum);
+        };
+        digits.truncate(i + 1);
+        digits[i] -= 1;
+        digits.push(BASE - 1);
+        steps -= 1;
+        if steps == 0 {
+            return Ok(digits_to_string(&digits));
+        }
+    }
+}
+
+/// Apply `step` to the digits of `s` with `gap` (or `gap + 1` to avoid a
+/// trailing '0'), lengthening `s` with '0's until it succeeds
+fn step_by_gap(s: &str, gap: usize, step: impl Fn(&mut [usize], usize) -> bool) -> String {
+    let mut digits: Vec<usize> = s.chars().map(|c| char_to_index(c).unwrap_or(0)).collect();
+    loop {
+        for amount in [gap, gap + 1] {
+            let mut stepped = digits.clone();
+            if step(&mut stepped, amount) && stepped.last() != Some(&0) {
+                return digits_to_string(&stepped);
+            }
+        }
+        digits.push(0);
+    }
+}
+
+/// Message for an empty position passed to a generator
+pub const EMPTY_POSITION: &str = "position must not be empty";
+
+/// Turn an optional bound into the form the generators take, where `""` is
+/// an open end
+///
+/// Only `None` (SQL NULL) opens a bound. An empty position is rejected: it
+/// would sort before every real position yet be read as an open end.
+pub fn bound_position(position: Option<&str>) -> Result<&str, String> {
+    match position {
+        None => Ok(""),
+        Some(p) => required_position(p),
+    }
+}
+
+/// Reject an empty position where one is required
+pub fn required_position(position: &str) -> Result<&str, String> {
+    if position.is_empty() {
+        Err(EMPTY_POSITION.to_string())
+    } else {
+        Ok(position)
+    }
 }
 
 /// Generate a position string between two strings with minimal spacing
-pub fn generate_between(before: &str, after: &str) -> String {
+///
+/// Returns `None` when nothing sorts strictly between the bounds: when `before`
+/// is empty and `after` consists entirely of '0' characters, or when `after` is
+/// `before` followed only by '0' characters (e.g. "H" and "H0").
+pub fn generate_between(before: &str, after: &str) -> Option<String> {
+    generate_between_step(before, after).map(|(position, _)| position)
+}
+
+/// The branch `generate_between` took, for tracing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BetweenStep {
+    /// Both bounds were empty, so the result is the first position
+    OpenBounds,
+    /// Only `after` was given
+    OpenBefore,
+    /// Only `before` was given
+    OpenAfter,
+    /// `before` did not sort below `after`, so the result follows `before`
+    OutOfOrder,
+    /// The characters at `index` left room for one between them
+    Gap { index: usize },
+    /// The characters at `index` were adjacent; the rest of `before` was bisected
+    /// against the end of the keyspace
+    AdjacentBisect { index: usize },
+    /// The characters at `index` were adjacent and `before` ended there
+    AdjacentExtend { index: usize },
+}
+
+impl std::fmt::Display for BetweenStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BetweenStep::OpenBounds => write!(f, "no bounds, first position"),
+            BetweenStep::OpenBefore => write!(f, "no lower bound, stepped before the upper"),
+            BetweenStep::OpenAfter => write!(f, "no upper bound, stepped after the lower"),
+            BetweenStep::OutOfOrder => write!(f, "bounds out of order, stepped after the lower"),
+            BetweenStep::Gap { index } => write!(f, "gap found at index {}", index),
+            BetweenStep::AdjacentBisect { index } => write!(
+                f,
+                "adjacent characters at index {}, bisected above the rest of the lower bound",
+                index
+            ),
+            BetweenStep::AdjacentExtend { index } => write!(
+                f,
+                "adjacent characters at index {}, extended the lower bound",
+                index
+            ),
+        }
+    }
+}
+
+/// Get a short suffix halfway between `rest` and the end of the keyspace
+///
+/// `rest` is read as a fraction below 1.0. The first character that is not 'z'
+/// is raised halfway to the end and the rest dropped; a run of 'z' characters
+/// is extended by the middle character instead.
+fn midpoint_above(rest: &[char]) -> String {
+    let mut result = String::with_capacity(rest.len() + 1);
+    for &c in rest {
+        match char_to_index(c) {
+            Some(idx) if idx < BASE - 1 => {
+                result.push(digit_char((idx + BASE).div_ceil(2)));
+                return result;
+            }
+            _ => result.push(c),
+        }
+    }
+    result.push(MID_CHAR);
+    result
+}
+
+/// `generate_between`, also returning the branch it took
+pub fn generate_between_step(before: &str, after: &str) -> Option<(String, BetweenStep)> {
     if before.is_empty() && after.is_empty() {
-        return MID_CHAR.to_string();
+        return Some((MID_CHAR.to_string(), BetweenStep::OpenBounds));
     }
     if before.is_empty() {
-        return generate_before(after);
+        return Some((generate_before(after)?, BetweenStep::OpenBefore));
     }
     if after.is_empty() {
-        return generate_after(before);
+        return Some((generate_after(before), BetweenStep::OpenAfter));
     }
 
     if before >= after {
-        return generate_after(before);
+        return Some((generate_after(before), BetweenStep::OutOfOrder));
     }
 
     let before_chars: Vec<char> = before.chars().collect();
@@ -187,14 +823,17 @@ pub fn generate_between(before: &str, after: &str) -> String {
         let a_idx = char_to_index(a_char).unwrap_or(BASE - 1);
 
         if b_idx < a_idx {
-            let mut result: String = before_chars.iter().take(i).collect();
+            // Keep the implicit '0' padding when `before` is shorter than the shared prefix
+            let mut result: String = (0..i)
+                .map(|j| before_chars.get(j).copied().unwrap_or(START_CHAR))
+                .collect();
 
             // Check if there's room between the characters
             if a_idx - b_idx > 1 {
                 // There's at least one character between them
                 let mid_idx = (b_idx + a_idx) / 2;
-                result.push(index_to_char(mid_idx).unwrap());
-                return result;
+                result.push(digit_char(mid_idx));
+                return Some((result, BetweenStep::Gap { index: i }));
             }
             // Adjacent characters (e.g., 'A' and 'B')
             // We need to look deeper into the strings
@@ -202,342 +841,2806 @@ pub fn generate_between(before: &str, after: &str) -> String {
 
             // Check if before has more characters at this position
             if i + 1 < before_chars.len() {
-                // before has more chars, try to increment from there
-                let rest: String = before_chars[i + 1..].iter().collect();
-                let after_rest = generate_after(&rest);
-                result.push_str(&after_rest);
-                return result;
+                // Anything above the rest of before fits, so take the midpoint
+                // between it and the end of the keyspace
+                result.push_str(&midpoint_above(&before_chars[i + 1..]));
+                return Some((result, BetweenStep::AdjacentBisect { index: i }));
             }
             // before ends here, after continues or also ends
             // Use the middle character to create a position between
             result.push(MID_CHAR);
-            return result;
+            return Some((result, BetweenStep::AdjacentExtend { index: i }));
         } else if b_idx == a_idx {
             // Characters are the same, continue to next position
             continue;
         }
     }
 
-    // Strings are equal or before is a prefix of after
-    format!("{}{}", before, MID_CHAR)
+    // `after` is `before` padded with '0', so nothing fits between them
+    None
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Check that `tag` can mark the positions of one writer
+///
+/// Any Base62 character but '0' works: nothing fits between a position and the
+/// same position followed by '0'.
+pub fn is_valid_writer_tag(tag: char) -> bool {
+    tag != START_CHAR && is_valid_base62(tag.encode_utf8(&mut [0; 4]))
+}
 
-    #[test]
-    fn test_generate_after_minimal() {
-        assert_eq!(generate_after("A"), "B");
-        assert_eq!(generate_after("0"), "1");
-        assert_eq!(generate_after("Z"), "a");
+/// Generate a position between two strings that ends with a writer's tag
+///
+/// The result is a position strictly between the bounds that depends only on
+/// the bounds, followed by `tag`. Writers with different tags filling the same
+/// gap therefore never produce the same value, and their positions sort by tag.
+/// This costs one extra character per generated position. Returns `None`
+/// where `generate_between` does.
+pub fn generate_between_tagged(before: &str, after: &str, tag: char) -> Option<String> {
+    let mut base = generate_between(before, after)?;
+    if !before.is_empty() && before >= after && !after.is_empty() {
+        // Same fallback as `generate_between` for bounds out of order
+        base.push(tag);
+        return Some(base);
     }
-
-    #[test]
-    fn test_generate_before_minimal() {
-        assert_eq!(generate_before("B"), "A");
-        assert_eq!(generate_before("Z"), "Y");
-        assert_eq!(generate_before("a"), "Z");
-        assert_eq!(generate_before("1"), "0z");
+    // Pick the base so that every tag fits, which keeps it the same for all
+    // writers. This only moves it when `base` is a prefix of `after`; moving it
+    // closer to `after` makes it longer until it no longer is.
+    while !after.is_empty() && format!("{}{}", base, END_CHAR).as_str() >= after {
+        base = generate_between(&base, after)?;
     }
+    base.push(tag);
+    Some(base)
+}
 
-    #[test]
-    fn test_generate_after_overflow() {
-        let result = generate_after("z");
-        assert!(result > "z".to_string());
-        assert_eq!(result, "z0");
+/// Get the smallest string that sorts after every string starting with `prefix`
+///
+/// Every position with the prefix lies in `[prefix, prefix_end(prefix))`.
+/// Returns `None` when there is no such bound, i.e. for an empty prefix or one
+/// made only of 'z' characters.
+pub fn prefix_end(prefix: &str) -> Option<String> {
+    if prefix.chars().all(|c| c == END_CHAR) {
+        return None;
     }
+    Some(generate_after(prefix))
+}
 
-    #[test]
-    fn test_generate_after_basic() {
-        let pos = generate_after("H");
-        assert!(pos > "H".to_string());
-    }
+/// Check whether `candidate` lies strictly between two positions
+///
+/// An empty bound is open-ended, exactly as in `generate_between`. The empty
+/// string itself is never a valid candidate, since no bound can produce it.
+pub fn is_between(candidate: &str, before: &str, after: &str) -> bool {
+    !candidate.is_empty()
+        && (before.is_empty() || candidate > before)
+        && (after.is_empty() || candidate < after)
+}
 
-    #[test]
-    fn test_generate_before_basic() {
-        let pos = generate_before("H");
-        assert!(pos < "H".to_string());
-    }
+/// Minimum number of candidates `generate_random_between` picks from (62^3)
+pub const RANDOM_BETWEEN_CHOICES: u64 = 238_328;
 
-    #[test]
-    fn test_generate_between_basic() {
-        let pos = generate_between("0", "z");
-        assert!(pos > "0".to_string());
-        assert!(pos < "z".to_string());
+/// Generate a random position strictly between two strings
+///
+/// Both bounds are read as digit strings padded with '0' and the shared prefix
+/// is kept. The result then uses just enough extra digits for the gap to hold at
+/// least `RANDOM_BETWEEN_CHOICES` candidates, and one of them is picked uniformly,
+/// so concurrent callers with the same bounds almost never collide. Tight gaps
+/// produce longer positions. An empty string means an open bound.
+///
+/// `random(n)` must return a uniformly distributed value in `0..n`.
+///
+/// Returns `None` when no position exists between the bounds, i.e. when
+/// `after` is not greater than `before` or is `before` followed only by '0's.
+pub fn generate_random_between(
+    before: &str,
+    after: &str,
+    mut random: impl FnMut(u64) -> u64,
+) -> Option<String> {
+    if !after.is_empty() && before >= after {
+        return None;
     }
 
-    #[test]
-    fn test_generate_between_adjacent() {
-        let pos = generate_between("0", "1");
-        assert!(pos > "0".to_string());
-        assert!(pos < "1".to_string());
+    let before_digits: Vec<u64> = before
+        .chars()
+        .map(|c| char_to_index(c).unwrap_or(0) as u64)
+        .collect();
+    // An open upper bound behaves like a digit one past 'z' in the first place
+    let after_digits: Vec<u64> = if after.is_empty() {
+        vec![BASE as u64]
+    } else {
+        after
+            .chars()
+            .map(|c| char_to_index(c).unwrap_or(0) as u64)
+            .collect()
+    };
+    let b = |i: usize| before_digits.get(i).copied().unwrap_or(0);
+    let a = |i: usize| after_digits.get(i).copied().unwrap_or(0);
+
+    // Skip the prefix shared by both padded bounds
+    let width = before_digits.len().max(after_digits.len());
+    let start = (0..width).find(|&i| b(i) != a(i))?;
+
+    // Widen the window one digit at a time until the gap is large enough.
+    // `count` is the number of window values strictly between the bounds.
+    let mut len = 1;
+    let mut count = a(start) - b(start) - 1;
+    while count < RANDOM_BETWEEN_CHOICES {
+        count = (count + 1) * BASE as u64 + a(start + len) - b(start + len) - 1;
+        len += 1;
     }
 
-    #[test]
-    fn test_generate_between_tight() {
-        let result = generate_between("A", "C");
-        assert_eq!(result, "B");
+    let prefix: String = before_digits[..start.min(before_digits.len())]
+        .iter()
+        .chain(std::iter::repeat(&0))
+        .take(start)
+        .map(|&d| digit_char(d as usize))
+        .collect();
+
+    loop {
+        // Window value = lower bound window + 1 + offset, added digit by digit
+        let mut digits: Vec<u64> = (start..start + len).map(b).collect();
+        let mut carry = 1 + random(count);
+        for digit in digits.iter_mut().rev() {
+            let sum = *digit + carry;
+            *digit = sum % BASE as u64;
+            carry = sum / BASE as u64;
+        }
 
-        let result2 = generate_between("Z", "a");
-        assert!(result2 > "Z".to_string());
-        assert!(result2 < "a".to_string());
+        // A trailing '0' would leave no room directly before the new position
+        if digits.last() != Some(&0) {
+            let window: String = digits.iter().map(|&d| digit_char(d as usize)).collect();
+            return Some(format!("{}{}", prefix, window));
+        }
     }
+}
 
-    #[test]
-    fn test_generate_between_adjacent_chars() {
-        let result = generate_between("A", "B");
-        assert!(result > "A".to_string());
-        assert!(result < "B".to_string());
-        assert!(result.len() <= 2);
-    }
+/// Generate a position strictly between two strings, placed by a hash of `seed`
+///
+/// Works like `generate_random_between`, but the candidates are picked with a
+/// generator seeded from a 64-bit FNV-1a hash of `seed`, so the same bounds and
+/// seed always give the same position while different seeds spread across the
+/// gap. Returns `None` when no position exists between the bounds.
+pub fn generate_seeded_between(before: &str, after: &str, seed: &str) -> Option<String> {
+    let mut state = seed.bytes().fold(0xcbf2_9ce4_8422_2325, |hash: u64, b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    // SplitMix64, which mixes well even from the similar states of similar seeds
+    generate_random_between(before, after, |n| {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (z ^ (z >> 31)) % n
+    })
+}
 
-    #[test]
-    fn test_tight_spacing_sequence() {
-        let first = MID_CHAR.to_string();
-        let second = generate_after(&first);
-        let third = generate_after(&second);
+/// Generate the shortest position strictly between two strings
+///
+/// Among strings of a fixed length `len`, lexicographic order is numeric order
+/// of their `len` Base62 digits, so the candidates of that length form one
+/// contiguous range: from `before` (padded with '0') if it is shorter than
+/// `len`, or else one past its first `len` digits, up to `after`'s first `len`
+/// digits (padded with '0'), or one below that unless `after` is longer than
+/// `len`. Lengths are tried in increasing order and the first non-empty range
+/// wins, so no shorter result exists; within it the middle candidate is taken.
+/// An empty string means an open bound, as in `generate_between`.
+///
+/// Returns `None` when no position exists between the bounds, i.e. when
+/// `after` is not greater than `before` or is `before` followed only by '0's.
+pub fn shortest_between(before: &str, after: &str) -> Option<String> {
+    let (lo, hi) = shortest_range(before, after)?;
+    Some(digits_to_string(&midpoint_digits(&lo, &hi)))
+}
 
-        assert!(second.len() <= 2, "Second position too long: {}", second);
-        assert!(third.len() <= 2, "Third position too long: {}", third);
+/// The digits of the first and last position of the shortest length that fits
+/// strictly between two strings, as described for `shortest_between`
+fn shortest_range(before: &str, after: &str) -> Option<(Vec<usize>, Vec<usize>)> {
+    if !after.is_empty() && before >= after {
+        return None;
     }
 
-    #[test]
-    fn test_sequence_maintains_order() {
-        let first = MID_CHAR.to_string();
-        let second = generate_after(&first);
-        let third = generate_after(&second);
-        let fourth = generate_after(&third);
+    let max_len = before.chars().count().max(after.chars().count()) + 1;
+    for len in 1..=max_len {
+        let mut lo = to_digits(before, len);
+        if before.chars().count() >= len && !increment_digits(&mut lo) {
+            continue;
+        }
 
-        assert!(first < second);
+        let mut hi = if after.is_empty() {
+            vec![BASE - 1; len]
+        } else {
+            to_digits(after, len)
+        };
+        if !after.is_empty() && after.chars().count() <= len && !decrement_digits(&mut hi) {
+            continue;
+        }
+
+        if lo <= hi {
+            return Some((lo, hi));
+        }
+    }
+
+    None
+}
+
+/// Generate the shortest position near `current`, keeping its fractional value
+///
+/// The result lies in the half-open cell around `current` that reaches halfway
+/// to its neighbours (or to the ends of the keyspace when a neighbour is
+/// `None`), so positions computed this way for every entry of a sorted list
+/// keep their order. Within the cell, the shortest length that fits is used
+/// and the candidate nearest to `current` is taken, so the gaps between
+/// entries keep roughly their relative sizes.
+///
+/// Returns `None` when the cell is empty, e.g. for duplicate neighbours.
+pub fn proportional_position(
+    before: Option<&str>,
+    current: &str,
+    after: Option<&str>,
+) -> Option<String> {
+    let len = [before.unwrap_or(""), current, after.unwrap_or("")]
+        .iter()
+        .map(|s| s.chars().count())
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let here = to_digits(current, len);
+
+    let lower = match before {
+        Some(b) => midpoint_digits(&to_digits(b, len), &here),
+        None => midpoint_digits(&vec![0; len], &here),
+    };
+    let upper = match after {
+        Some(a) => midpoint_digits(&here, &to_digits(a, len)),
+        None => midpoint_digits(&here, &vec![BASE - 1; len]),
+    };
+
+    let (lo, hi) = shortest_range(&digits_to_string(&lower), &digits_to_string(&upper))?;
+
+    // `current` rounded to the candidates' length, then kept inside the range
+    let mut nearest = to_digits(current, lo.len());
+    if here.get(lo.len()).is_some_and(|&d| d >= BASE / 2) && !increment_digits(&mut nearest) {
+        nearest = hi.clone();
+    }
+    Some(digits_to_string(&nearest.clamp(lo, hi)))
+}
+
+/// The first `len` Base62 digits of a position, padded with '0'
+fn to_digits(s: &str, len: usize) -> Vec<usize> {
+    s.chars()
+        .map(|c| char_to_index(c).unwrap_or(0))
+        .chain(std::iter::repeat(0))
+        .take(len)
+        .collect()
+}
+
+fn digits_to_string(digits: &[usize]) -> String {
+    digits.iter().map(|&d| digit_char(d)).collect()
+}
+
+/// Add one to a big-endian digit vector; false if it overflows
+fn increment_digits(digits: &mut [usize]) -> bool {
+    for d in digits.iter_mut().rev() {
+        if *d < BASE - 1 {
+            *d += 1;
+            return true;
+        }
+        *d = 0;
+    }
+    false
+}
+
+/// Subtract one from a big-endian digit vector; false if it underflows
+fn decrement_digits(digits: &mut [usize]) -> bool {
+    for d in digits.iter_mut().rev() {
+        if *d > 0 {
+            *d -= 1;
+            return true;
+        }
+        *d = BASE - 1;
+    }
+    false
+}
+
+/// Average two big-endian digit vectors of equal length, rounding down
+fn midpoint_digits(lo: &[usize], hi: &[usize]) -> Vec<usize> {
+    // Sum digit by digit, keeping the final carry as an extra leading digit
+    let mut sum = vec![0; lo.len() + 1];
+    let mut carry = 0;
+    for i in (0..lo.len()).rev() {
+        let v = lo[i] + hi[i] + carry;
+        sum[i + 1] = v % BASE;
+        carry = v / BASE;
+    }
+    sum[0] = carry;
+
+    let mut remainder = 0;
+    for d in sum.iter_mut() {
+        let v = remainder * BASE + *d;
+        *d = v / 2;
+        remainder = v % 2;
+    }
+    sum.split_off(1)
+}
+
+/// Estimate how many positions can be appended after `last` before one is
+/// longer than `max_length`
+///
+/// Counts the chain of `generate_after` results, as produced by repeated
+/// `lexo_next()` calls. Results stay at most as long as their input until the
+/// chain reaches an all-'z' position; from there, each extra character allows
+/// 62 more appends.
+pub fn append_capacity(last: &str, max_length: usize) -> u64 {
+    let mut current = last.to_string();
+    let mut count = 0;
+    loop {
+        if !current.is_empty() && current.chars().all(|c| c == END_CHAR) {
+            let len = current.chars().count();
+            return count + BASE as u64 * max_length.saturating_sub(len) as u64;
+        }
+        current = generate_after(&current);
+        if current.chars().count() > max_length {
+            return count;
+        }
+        count += 1;
+    }
+}
+
+/// Estimate how many positions can be prepended before `first` before one is
+/// longer than `max_length`
+///
+/// Counts the chain of `generate_before` results. Once a result ends in 'z',
+/// the chain counts its last character down to '1' and then grows by "0z", so
+/// each extra character allows 61 more prepends.
+pub fn prepend_capacity(first: &str, max_length: usize) -> u64 {
+    let mut current = first.to_string();
+    let mut count = 0;
+    loop {
+        if current.is_empty() {
+            return count;
+        }
+        let Some(next) = generate_before(&current) else {
+            return count;
+        };
+        current = next;
+        let len = current.chars().count();
+        if len > max_length {
+            return count;
+        }
+        count += 1;
+        if current.ends_with(END_CHAR) {
+            return count + (BASE as u64 - 2) + (BASE as u64 - 1) * (max_length - len) as u64;
+        }
+    }
+}
+
+/// Count how many positions can be inserted between two neighbours before one
+/// is longer than `max_length`, when every insert goes right after `before`
+///
+/// This is the worst case for a gap: each `generate_between` call lands
+/// between `before` and the previous insert, so the gap keeps shrinking on the
+/// same side. Returns 0 when the neighbours leave no room.
+pub fn between_capacity(before: &str, after: &str, max_length: usize) -> u64 {
+    // Anything between the two extends their shared prefix
+    if common_prefix(before, after).chars().count() >= max_length {
+        return 0;
+    }
+    let mut upper = after.to_string();
+    let mut count = 0;
+    loop {
+        let Some(position) = generate_between(before, &upper) else {
+            return count;
+        };
+        if position.chars().count() > max_length || position.as_str() <= before || position >= upper
+        {
+            return count;
+        }
+        count += 1;
+        upper = position;
+    }
+}
+
+/// Find a position between two strings that `is_taken` does not reject
+///
+/// Starts from `generate_between(before, after)` and, while the candidate is
+/// taken, bisects again between `before` and the taken candidate. Gives up after
+/// `attempts` candidates and returns `None`.
+pub fn find_free_between(
+    before: &str,
+    after: &str,
+    attempts: usize,
+    mut is_taken: impl FnMut(&str) -> bool,
+) -> Option<String> {
+    let mut upper = after.to_string();
+    for _ in 0..attempts {
+        let candidate = generate_between(before, &upper)?;
+        if !is_taken(&candidate) {
+            return Some(candidate);
+        }
+        upper = candidate;
+    }
+    None
+}
+
+/// Generate a position between two strings that is not in `taken`
+///
+/// Entries of `taken` outside the bounds are ignored. The rest split the gap
+/// into sub-gaps; when the plain `generate_between` result is taken, the
+/// shortest position found in any sub-gap wins, with ties going to the sub-gap
+/// nearest the middle. Returns `None` when no sub-gap has room for a position
+/// of at most `max_length` characters.
+pub fn generate_between_avoiding(
+    before: &str,
+    after: &str,
+    taken: &[&str],
+    max_length: usize,
+) -> Option<String> {
+    let mut inside: Vec<&str> = taken
+        .iter()
+        .copied()
+        .filter(|t| is_between(t, before, after))
+        .collect();
+    inside.sort_unstable();
+    inside.dedup();
+
+    if let Some(plain) =
+        position_in_gap(before, after).filter(|p| inside.binary_search(&p.as_str()).is_err())
+    {
+        return (plain.len() <= max_length).then_some(plain);
+    }
+
+    let mut bounds = Vec::with_capacity(inside.len() + 2);
+    bounds.push(before);
+    bounds.extend(inside);
+    bounds.push(after);
+    let middle = (bounds.len() - 1) / 2;
+    bounds
+        .windows(2)
+        .enumerate()
+        .filter_map(|(i, pair)| position_in_gap(pair[0], pair[1]).map(|p| (p, i)))
+        .min_by_key(|(p, i)| (p.len(), i.abs_diff(middle)))
+        .map(|(p, _)| p)
+        .filter(|p| p.len() <= max_length)
+}
+
+/// `generate_between`, or `None` when nothing fits strictly between the bounds
+fn position_in_gap(before: &str, after: &str) -> Option<String> {
+    generate_between(before, after).filter(|candidate| is_between(candidate, before, after))
+}
+
+/// Compute replacement positions for the invalid entries of a sorted list
+///
+/// `values` must be sorted byte-wise. Each run of values that are not valid
+/// Base62 is given fresh positions between the valid values around it, so the
+/// overall order is kept. Returns `(index, new_position)` pairs.
+pub fn reposition_invalid(values: &[&str]) -> Vec<(usize, String)> {
+    reposition_runs(values, "", "", |i| !is_valid_base62(values[i]))
+}
+
+/// Compute replacement positions for repeated entries of a sorted list
+///
+/// The first entry holding each position keeps it; the entries repeating it
+/// are spread between it and the next distinct position. Returns
+/// `(index, new_position)` pairs, which the caller must check as for
+/// `reposition_runs`.
+pub fn reposition_duplicates(values: &[&str]) -> Vec<(usize, String)> {
+    reposition_runs(values, "", "", |i| i > 0 && values[i] == values[i - 1])
+}
+
+/// Compute fresh positions for the selected entries of a sorted list
+///
+/// Each run of consecutive entries for which `selected(index)` holds is spread
+/// evenly between the unselected entries around it, which keep their values.
+/// A run at the start or end of the list is spread down to `lower` or up to
+/// `upper` instead; an empty edge, or one that does not fit, is an open bound
+/// as in `generate_n_between`. Returns `(index, new_position)` pairs; the caller
+/// must check the result when neighbours leave no room (e.g. "H" and "H0").
+pub fn reposition_runs(
+    values: &[&str],
+    lower: &str,
+    upper: &str,
+    selected: impl Fn(usize) -> bool,
+) -> Vec<(usize, String)> {
+    let mut replacements = Vec::new();
+    let mut i = 0;
+
+    while i < values.len() {
+        if !selected(i) {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < values.len() && selected(i) {
+            i += 1;
+        }
+
+        let mut before = if start > 0 { values[start - 1] } else { "" };
+        let mut after = values.get(i).copied().unwrap_or("");
+        if before.is_empty() && (after.is_empty() || lower < after) {
+            before = lower;
+        }
+        if after.is_empty() && (before.is_empty() || upper > before) {
+            after = upper;
+        }
+        let positions = generate_n_between(before, after, i - start);
+        replacements.extend((start..i).zip(positions));
+    }
+
+    replacements
+}
+
+/// Generate `count` strictly increasing positions between two strings
+///
+/// Positions are placed by recursive bisection, so they are spread across the
+/// gap rather than stepping away from one of the bounds. An empty string means
+/// an open bound, as in `generate_between`.
+pub fn generate_n_between(before: &str, after: &str, count: usize) -> Vec<String> {
+    let mut positions = Vec::with_capacity(count);
+    fill_between(before, after, count, &mut positions);
+    positions
+}
+
+fn fill_between(before: &str, after: &str, count: usize, out: &mut Vec<String>) {
+    if count == 0 {
+        return;
+    }
+
+    // With nothing between the bounds (e.g. below an all-'0' `after`), the bound
+    // itself stands in, so the caller rejects these positions like any other gap
+    // without room
+    let mid = split_point(before, after).unwrap_or_else(|| after.to_string());
+    let left = (count - 1) / 2;
+
+    fill_between(before, &mid, left, out);
+    out.push(mid.clone());
+    fill_between(&mid, after, count - 1 - left, out);
+}
+
+/// The point `fill_between` and `Midpoints` split a gap at, if `generate_between`
+/// finds one
+fn split_point(before: &str, after: &str) -> Option<String> {
+    let mid = generate_between(before, after)?;
+    // A trailing '0' (e.g. "Hz0" between "Hz" and "I") leaves no room below the
+    // midpoint, so step further in before recursing into the lower half
+    if mid.ends_with(START_CHAR) {
+        let stepped = format!("{}{}", mid, MID_CHAR);
+        if after.is_empty() || stepped.as_str() < after {
+            return Some(stepped);
+        }
+    }
+    Some(mid)
+}
+
+/// Yield positions strictly between two strings, bisecting the gap breadth-first
+///
+/// The first item is the midpoint, then the midpoints of the two halves, then
+/// of the four quarters, and so on. Every item lies in a gap that no earlier
+/// item occupies, so all of them are distinct and between the bounds. Items
+/// get longer as the gaps tighten, and the iterator only ends when every
+/// remaining gap is empty. An empty string means an open bound.
+pub fn midpoints(before: &str, after: &str) -> Midpoints {
+    Midpoints {
+        gaps: VecDeque::from([(before.to_string(), after.to_string())]),
+    }
+}
+
+/// The iterator returned by `midpoints`
+pub struct Midpoints {
+    gaps: VecDeque<(String, String)>,
+}
+
+impl Iterator for Midpoints {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        while let Some((before, after)) = self.gaps.pop_front() {
+            let Some(mid) =
+                split_point(&before, &after).filter(|mid| is_between(mid, &before, &after))
+            else {
+                continue;
+            };
+            self.gaps.push_back((before, mid.clone()));
+            self.gaps.push_back((mid.clone(), after));
+            return Some(mid);
+        }
+        None
+    }
+}
+
+/// Narrowest counter `sequence_position` writes, in Base62 digits
+pub const SEQUENCE_MIN_WIDTH: usize = 6;
+
+/// Encode a sequence counter as a position starting with `prefix`
+///
+/// The result is `prefix`, one character giving the counter's width, the
+/// counter as fixed-width Base62, and a trailing `MID_CHAR`. The width starts
+/// at `SEQUENCE_MIN_WIDTH` and grows as the counter does; a wider counter has
+/// a larger width character, so positions keep increasing with the counter.
+/// The trailing character leaves room after each position: `generate_after`
+/// of one stays below the next counter's position.
+pub fn sequence_position(prefix: &str, counter: u64) -> String {
+    let mut digits = Vec::new();
+    let mut value = counter;
+    while value > 0 {
+        digits.push(BASE62_CHARS[(value % BASE as u64) as usize] as char);
+        value /= BASE as u64;
+    }
+    let width = digits.len().max(SEQUENCE_MIN_WIDTH);
+
+    let mut position = String::with_capacity(prefix.len() + width + 2);
+    position.push_str(prefix);
+    position.push(digit_char(width));
+    position.push_str(&START_CHAR.to_string().repeat(width - digits.len()));
+    position.extend(digits.iter().rev());
+    position.push(MID_CHAR);
+    position
+}
+
+/// The smallest counter whose `sequence_position` under `prefix` sorts after
+/// `position`
+///
+/// Used to skip a sequence past positions appended by other means. Returns
+/// `None` when no counter does: `position` sorts after everything starting
+/// with `prefix`, or its counter would not fit in a `u64`.
+pub fn sequence_counter_after(prefix: &str, position: &str) -> Option<u64> {
+    let Some(rest) = position.strip_prefix(prefix) else {
+        return (position < prefix).then_some(0);
+    };
+    let mut chars = rest.chars();
+    let width = match chars.next() {
+        None => return Some(0),
+        Some(c) => char_to_index(c)?,
+    };
+    if width < SEQUENCE_MIN_WIDTH {
+        return Some(0);
+    }
+
+    // The counter `position` was written at, with missing digits as '0'
+    let mut counter: u64 = 0;
+    for c in chars.chain(std::iter::repeat(START_CHAR)).take(width) {
+        counter = counter
+            .checked_mul(BASE as u64)?
+            .checked_add(char_to_index(c)? as u64)?;
+    }
+    // Counters narrower than `width` are written with a smaller width character
+    if width > SEQUENCE_MIN_WIDTH {
+        counter = counter.max((BASE as u64).checked_pow(width as u32 - 1)?);
+    }
+
+    if sequence_position(prefix, counter).as_str() > position {
+        Some(counter)
+    } else {
+        counter.checked_add(1)
+    }
+}
+
+/// The position of the `counter`-th row (from 0) of a list ranked without
+/// knowing its size up front
+///
+/// The result is one character for the counter's width in Base62 digits,
+/// starting at `MID_CHAR` for one digit, followed by the digits. Wider
+/// counters get a larger first character, so positions keep increasing, and
+/// a list of n rows needs positions of about log62(n) + 1 characters.
+pub fn append_rank(counter: u64) -> String {
+    let mut digits = Vec::new();
+    let mut value = counter;
+    loop {
+        digits.push(BASE62_CHARS[(value % BASE as u64) as usize] as char);
+        value /= BASE as u64;
+        if value == 0 {
+            break;
+        }
+    }
+
+    let mut position = String::with_capacity(digits.len() + 1);
+    position.push(digit_char(MID_INDEX + digits.len() - 1));
+    position.extend(digits.iter().rev());
+    position
+}
+
+/// Longest position a `smalllexo` can hold
+pub const SMALL_MAX_LENGTH: usize = 10;
+
+/// Pack a short position into a `u64` whose unsigned order is position order
+///
+/// Each character takes 6 bits from the top down and the length the low 4
+/// bits. Unused characters are zero, like the implicit '0' padding, and the
+/// length then breaks ties, so "A" sorts before "A0". Returns `None` when the
+/// position is longer than `SMALL_MAX_LENGTH` or not Base62.
+pub fn pack_small_position(s: &str) -> Option<u64> {
+    if s.len() > SMALL_MAX_LENGTH {
+        return None;
+    }
+    let mut packed = s.len() as u64;
+    for (i, c) in s.chars().enumerate() {
+        packed |= (char_to_index(c)? as u64) << (58 - 6 * i);
+    }
+    Some(packed)
+}
+
+/// Unpack a position packed by `pack_small_position`
+pub fn unpack_small_position(packed: u64) -> String {
+    let len = ((packed & 0xf) as usize).min(SMALL_MAX_LENGTH);
+    (0..len)
+        .map(|i| index_to_char(((packed >> (58 - 6 * i)) & 0x3f) as usize).unwrap_or(END_CHAR))
+        .collect()
+}
+
+/// Largest length accepted in a `lexo(n)` type modifier (same cap as varchar)
+pub const MAX_TYPMOD_LENGTH: i32 = 10_485_760;
+
+/// Parse the modifiers of a `lexo(n)` declaration into a maximum length
+///
+/// Exactly one positive integer no larger than `MAX_TYPMOD_LENGTH` is accepted.
+pub fn parse_length_typmod(mods: &[&str]) -> Result<i32, String> {
+    let [length] = mods else {
+        return Err("invalid type modifier: lexo takes exactly one length".to_string());
+    };
+
+    match length.trim().parse::<i32>() {
+        Ok(n) if n < 1 => Err(format!(
+            "length for type lexo must be at least 1, got {}",
+            n
+        )),
+        Ok(n) if n > MAX_TYPMOD_LENGTH => Err(format!(
+            "length for type lexo cannot exceed {}, got {}",
+            MAX_TYPMOD_LENGTH, n
+        )),
+        Ok(n) => Ok(n),
+        Err(_) => Err(format!("invalid length for type lexo: '{}'", length)),
+    }
+}
+
+/// Check whether a position fits in a column declared with the given typmod
+///
+/// A negative typmod means the column is unconstrained.
+pub fn fits_typmod(s: &str, typmod: i32) -> bool {
+    typmod < 0 || s.len() <= typmod as usize
+}
+
+/// Pack the first 8 bytes of a position into an integer for abbreviated sorting
+///
+/// Shorter positions are padded with zero bytes, which sort before every Base62
+/// character, so `a < b` always implies `abbreviated_key(a) <= abbreviated_key(b)`.
+/// Equal keys only mean the positions share their first 8 bytes.
+pub fn abbreviated_key(s: &str) -> u64 {
+    let mut bytes = [0u8; 8];
+    let len = s.len().min(8);
+    bytes[..len].copy_from_slice(&s.as_bytes()[..len]);
+    u64::from_be_bytes(bytes)
+}
+
+/// Return the text a stored `lexo` value encodes, without decoding it
+///
+/// `lexo` values are stored as CBOR, and current versions write a bare text
+/// string: a header byte of major type 3 carrying the length, then the UTF-8
+/// bytes. Returns `None` for anything else, such as the `{"value": ...}` map
+/// written by earlier versions, so callers can fall back to a full decode.
+pub fn cbor_text_bytes(encoded: &[u8]) -> Option<&[u8]> {
+    let (&head, rest) = encoded.split_first()?;
+    if head >> 5 != 3 {
+        return None;
+    }
+    let width = match head & 0x1f {
+        0..=23 => 0,
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        _ => return None,
+    };
+    let (len_bytes, body) = rest.split_at_checked(width)?;
+    let len = if width == 0 {
+        (head & 0x1f) as u64
+    } else {
+        len_bytes.iter().fold(0, |len, &b| (len << 8) | b as u64)
+    };
+    (body.len() as u64 == len).then_some(body)
+}
+
+/// Approximate number of distinct values seen, as a HyperLogLog sketch
+///
+/// Sorting uses it to tell when abbreviated keys stop paying off. With 1024
+/// one-byte registers the estimate is typically within a few percent.
+#[derive(Clone)]
+pub struct DistinctCounter {
+    registers: [u8; DistinctCounter::REGISTERS],
+}
+
+impl DistinctCounter {
+    const REGISTER_BITS: u32 = 10;
+    const REGISTERS: usize = 1 << Self::REGISTER_BITS;
+
+    pub const fn new() -> Self {
+        DistinctCounter {
+            registers: [0; Self::REGISTERS],
+        }
+    }
+
+    /// Count an integer value
+    pub fn add(&mut self, value: u64) {
+        // splitmix64 finalizer, so nearby values land in unrelated registers
+        let mut hash = value;
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^= hash >> 31;
+
+        let index = (hash >> (64 - Self::REGISTER_BITS)) as usize;
+        let rest = (hash << Self::REGISTER_BITS) | (1 << (Self::REGISTER_BITS - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    /// Count a byte string, hashed with 64-bit FNV-1a
+    pub fn add_bytes(&mut self, bytes: &[u8]) {
+        self.add(bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash: u64, &b| {
+            (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+        }));
+    }
+
+    /// Estimated number of distinct values added so far
+    pub fn estimate(&self) -> f64 {
+        let m = Self::REGISTERS as f64;
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|&rank| 2f64.powi(-(rank as i32)))
+            .sum();
+        let raw = 0.7213 / (1.0 + 1.079 / m) * m * m / sum;
+        let empty = self.registers.iter().filter(|&&rank| rank == 0).count();
+        // Small counts are estimated better by how many registers are still empty
+        if raw <= 2.5 * m && empty > 0 {
+            m * (m / empty as f64).ln()
+        } else {
+            raw
+        }
+    }
+}
+
+impl Default for DistinctCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Unpadded base64url alphabet used for pagination cursors
+const BASE64URL_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Format version stored in the first byte of every pagination cursor
+const CURSOR_VERSION: u8 = 1;
+
+/// 32-bit FNV-1a hash, used as the cursor checksum
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash: u32, &b| {
+        (hash ^ b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn base64url_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(BASE64URL_CHARS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    out
+}
+
+/// Decodes unpadded base64url, rejecting non-canonical input
+fn base64url_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() / 4 * 3 + 2);
+    for chunk in s.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let value = BASE64URL_CHARS.iter().position(|&x| x == c)? as u32;
+            n |= value << (18 - 6 * i);
+        }
+        let bytes = chunk.len() - 1;
+        // Bits below the last decoded byte must be zero
+        if n & (0x00ff_ffff >> (8 * bytes)) != 0 {
+            return None;
+        }
+        out.extend((0..bytes).map(|i| (n >> (16 - 8 * i)) as u8));
+    }
+    Some(out)
+}
+
+/// Encode a position as an opaque, URL-safe pagination cursor
+///
+/// The token is the unpadded base64url encoding of a version byte, the
+/// position and a checksum of both. The checksum catches truncated or
+/// hand-edited tokens; it is not a signature.
+pub fn encode_cursor(position: &str) -> String {
+    let mut bytes = Vec::with_capacity(position.len() + 5);
+    bytes.push(CURSOR_VERSION);
+    bytes.extend_from_slice(position.as_bytes());
+    let checksum = fnv1a(&bytes);
+    bytes.extend_from_slice(&checksum.to_be_bytes());
+    base64url_encode(&bytes)
+}
+
+/// Decode a cursor made by `encode_cursor`, returning its position
+pub fn decode_cursor(token: &str) -> Result<String, String> {
+    let bytes = base64url_decode(token).ok_or("token is not valid base64url")?;
+    if bytes.len() < 6 {
+        return Err("token is too short".to_string());
+    }
+    let (payload, checksum) = bytes.split_at(bytes.len() - 4);
+    if fnv1a(payload).to_be_bytes() != checksum {
+        return Err("checksum does not match".to_string());
+    }
+    if payload[0] != CURSOR_VERSION {
+        return Err(format!("cursor version {} is not supported", payload[0]));
+    }
+    match std::str::from_utf8(&payload[1..]) {
+        Ok(position) if is_valid_base62(position) => Ok(position.to_string()),
+        _ => Err("token does not hold a valid position".to_string()),
+    }
+}
+
+/// The most steps `simulate_insertions` will run
+pub const MAX_SIMULATION_STEPS: usize = 10_000;
+
+/// Where a simulated insert puts its new position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertPattern {
+    /// After the last position
+    Append,
+    /// Before the first position
+    Prepend,
+    /// Between the two positions in the middle of the list
+    Middle,
+    /// Into a uniformly random gap, including either end
+    Random,
+    /// Always directly after the first position
+    Hotspot,
+}
+
+impl InsertPattern {
+    /// Every pattern with its name, in documentation order
+    pub const ALL: [(&'static str, InsertPattern); 5] = [
+        ("append", InsertPattern::Append),
+        ("prepend", InsertPattern::Prepend),
+        ("middle", InsertPattern::Middle),
+        ("random", InsertPattern::Random),
+        ("hotspot", InsertPattern::Hotspot),
+    ];
+
+    /// Look up a pattern by name, ignoring ASCII case
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name.trim()))
+            .map(|&(_, pattern)| pattern)
+    }
+
+    /// The gap an insert goes into, where gap `i` lies before the `i`th position
+    fn gap(self, len: usize, random: &mut impl FnMut(u64) -> u64) -> usize {
+        match self {
+            InsertPattern::Append => len,
+            InsertPattern::Prepend => 0,
+            InsertPattern::Middle => len / 2,
+            InsertPattern::Random => random(len as u64 + 1) as usize,
+            InsertPattern::Hotspot => len.min(1),
+        }
+    }
+}
+
+/// A sorted list split into blocks, so inserting by index stays cheap
+struct SimulatedList {
+    blocks: Vec<Vec<String>>,
+}
+
+impl SimulatedList {
+    const BLOCK: usize = 256;
+
+    fn locate(&self, mut index: usize) -> (usize, usize) {
+        for (b, block) in self.blocks.iter().enumerate() {
+            if index < block.len() || b + 1 == self.blocks.len() {
+                return (b, index);
+            }
+            index -= block.len();
+        }
+        (0, 0)
+    }
+
+    fn get(&self, index: usize) -> &str {
+        let (b, i) = self.locate(index);
+        &self.blocks[b][i]
+    }
+
+    fn insert(&mut self, index: usize, position: String) {
+        if self.blocks.is_empty() {
+            self.blocks.push(Vec::new());
+        }
+        let (b, i) = self.locate(index);
+        self.blocks[b].insert(i, position);
+        if self.blocks[b].len() > 2 * Self::BLOCK {
+            let tail = self.blocks[b].split_off(Self::BLOCK);
+            self.blocks.insert(b + 1, tail);
+        }
+    }
+}
+
+/// Replay a workload of inserts against an initially empty list
+///
+/// Each step picks a pattern from `mix`, weighted by its count, and generates
+/// the new position with the same functions the extension uses. The picks and
+/// the random gaps come from a generator seeded with `seed`, so a workload is
+/// reproducible. Returns the generated positions in step order.
+pub fn simulate_insertions(steps: usize, mix: &[(InsertPattern, u64)], seed: u64) -> Vec<String> {
+    let total: u64 = mix.iter().map(|&(_, weight)| weight).sum();
+    let mut state = seed;
+    let mut random = |n: u64| {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 33) % n
+    };
+
+    let mut list = SimulatedList { blocks: Vec::new() };
+    let mut generated = Vec::with_capacity(steps);
+    for len in 0..steps {
+        let pattern = if mix.len() == 1 || total == 0 {
+            mix.first().map_or(InsertPattern::Append, |&(p, _)| p)
+        } else {
+            let mut pick = random(total);
+            mix.iter()
+                .find(|&&(_, weight)| {
+                    let hit = pick < weight;
+                    pick = pick.saturating_sub(weight);
+                    hit
+                })
+                .map_or(InsertPattern::Append, |&(p, _)| p)
+        };
+        let gap = pattern.gap(len, &mut random);
+        let before = if gap > 0 { list.get(gap - 1) } else { "" };
+        let after = if gap < len { list.get(gap) } else { "" };
+        let Some(position) = generate_between(before, after) else {
+            break;
+        };
+        list.insert(gap, position.clone());
+        generated.push(position);
+    }
+    generated
+}
+
+#[cfg(test)]
+// The original tests compare against owned strings
+#[allow(clippy::cmp_owned)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_after_minimal() {
+        assert_eq!(generate_after("A"), "B");
+        assert_eq!(generate_after("0"), "1");
+        assert_eq!(generate_after("Z"), "a");
+    }
+
+    #[test]
+    fn test_generate_before_minimal() {
+        assert_eq!(generate_before("B").unwrap(), "A");
+        assert_eq!(generate_before("Z").unwrap(), "Y");
+        assert_eq!(generate_before("a").unwrap(), "Z");
+        assert_eq!(generate_before("1").unwrap(), "0z");
+    }
+
+    #[test]
+    fn test_generate_after_overflow() {
+        let result = generate_after("z");
+        assert!(result > "z".to_string());
+        assert_eq!(result, "z0");
+    }
+
+    #[test]
+    fn test_generate_after_basic() {
+        let pos = generate_after("H");
+        assert!(pos > "H".to_string());
+    }
+
+    #[test]
+    fn test_generate_before_basic() {
+        let pos = generate_before("H").unwrap();
+        assert!(pos < "H".to_string());
+    }
+
+    #[test]
+    fn test_generate_between_basic() {
+        let pos = generate_between("0", "z").unwrap();
+        assert!(pos > "0".to_string());
+        assert!(pos < "z".to_string());
+    }
+
+    #[test]
+    fn test_generate_between_adjacent() {
+        let pos = generate_between("0", "1").unwrap();
+        assert!(pos > "0".to_string());
+        assert!(pos < "1".to_string());
+    }
+
+    #[test]
+    fn test_generate_between_tight() {
+        let result = generate_between("A", "C").unwrap();
+        assert_eq!(result, "B");
+
+        let result2 = generate_between("Z", "a").unwrap();
+        assert!(result2 > "Z".to_string());
+        assert!(result2 < "a".to_string());
+    }
+
+    #[test]
+    fn test_generate_between_adjacent_chars() {
+        let result = generate_between("A", "B").unwrap();
+        assert!(result > "A".to_string());
+        assert!(result < "B".to_string());
+        assert!(result.len() <= 2);
+    }
+
+    #[test]
+    fn test_tight_spacing_sequence() {
+        let first = MID_CHAR.to_string();
+        let second = generate_after(&first);
+        let third = generate_after(&second);
+
+        assert!(second.len() <= 2, "Second position too long: {}", second);
+        assert!(third.len() <= 2, "Third position too long: {}", third);
+    }
+
+    #[test]
+    fn test_sequence_maintains_order() {
+        let first = MID_CHAR.to_string();
+        let second = generate_after(&first);
+        let third = generate_after(&second);
+        let fourth = generate_after(&third);
+
+        assert!(first < second);
         assert!(second < third);
         assert!(third < fourth);
     }
 
     #[test]
-    fn test_insert_between_maintains_order() {
-        let first = MID_CHAR.to_string();
-        let third = generate_after(&first);
-        let second = generate_between(&first, &third);
+    fn test_insert_between_maintains_order() {
+        let first = MID_CHAR.to_string();
+        let third = generate_after(&first);
+        let second = generate_between(&first, &third).unwrap();
+
+        assert!(first < second);
+        assert!(second < third);
+    }
+
+    #[test]
+    fn test_multiple_insertions() {
+        let first = MID_CHAR.to_string();
+        let mut positions: Vec<String> = vec![first];
+
+        for _ in 0..5 {
+            let last = positions.last().unwrap();
+            positions.push(generate_after(last));
+        }
+
+        for i in 0..positions.len() - 1 {
+            assert!(
+                positions[i] < positions[i + 1],
+                "Position {} ({}) should be less than position {} ({})",
+                i,
+                positions[i],
+                i + 1,
+                positions[i + 1]
+            );
+        }
+    }
+
+    #[test]
+    fn test_insert_at_beginning() {
+        let first = MID_CHAR.to_string();
+        let before_first = generate_before(&first).unwrap();
+
+        assert!(before_first < first);
+    }
+
+    #[test]
+    fn test_generate_after_empty_string() {
+        let pos = generate_after("");
+        assert_eq!(pos, "H");
+    }
+
+    #[test]
+    fn test_generate_before_empty_string() {
+        let pos = generate_before("").unwrap();
+        assert_eq!(pos, "H");
+    }
+
+    #[test]
+    fn test_generate_between_empty_strings() {
+        let pos = generate_between("", "").unwrap();
+        assert_eq!(pos, "H");
+    }
+
+    #[test]
+    fn test_generate_between_invalid_order() {
+        let pos = generate_between("z", "0").unwrap();
+        assert!(pos > "z".to_string());
+    }
+
+    #[test]
+    fn test_generate_between_equal_strings() {
+        let pos = generate_between("H", "H").unwrap();
+        assert!(pos > "H".to_string());
+    }
+
+    #[test]
+    fn test_generate_between_without_room() {
+        // Anything longer than "H" that starts with it sorts at or after "H0"
+        assert_eq!(generate_between("H", "H0"), None);
+        assert_eq!(generate_between("H", "H000"), None);
+        assert_eq!(generate_between("Hz", "Hz0"), None);
+    }
+
+    #[test]
+    fn test_base62_char_conversion() {
+        assert_eq!(char_to_index('0'), Some(0));
+        assert_eq!(char_to_index('9'), Some(9));
+        assert_eq!(char_to_index('A'), Some(10));
+        assert_eq!(char_to_index('Z'), Some(35));
+        assert_eq!(char_to_index('a'), Some(36));
+        assert_eq!(char_to_index('z'), Some(61));
+
+        assert_eq!(index_to_char(0), Some('0'));
+        assert_eq!(index_to_char(9), Some('9'));
+        assert_eq!(index_to_char(10), Some('A'));
+        assert_eq!(index_to_char(35), Some('Z'));
+        assert_eq!(index_to_char(36), Some('a'));
+        assert_eq!(index_to_char(61), Some('z'));
+    }
+
+    #[test]
+    fn test_is_valid_base62() {
+        assert!(is_valid_base62("H"));
+        assert!(is_valid_base62("abc123XYZ"));
+        assert!(!is_valid_base62("hello!"));
+        assert!(!is_valid_base62("test-value"));
+        assert!(is_valid_base62(""));
+    }
+
+    #[test]
+    fn test_generate_between_same_prefix() {
+        let pos = generate_between("AB", "AC").unwrap();
+        assert!(pos > "AB".to_string());
+        assert!(pos < "AC".to_string());
+    }
+
+    #[test]
+    fn test_generate_between_adjacent_with_prefix() {
+        let pos = generate_between("A0", "A1").unwrap();
+        assert!(pos > "A0".to_string());
+        assert!(pos < "A1".to_string());
+    }
+
+    #[test]
+    fn test_deep_insertion() {
+        let mut positions = vec!["0".to_string(), "1".to_string()];
+
+        for _ in 0..10 {
+            let mid = generate_between(&positions[0], &positions[1]).unwrap();
+            assert!(
+                mid > positions[0],
+                "mid {} should be > {}",
+                mid,
+                positions[0]
+            );
+            assert!(
+                mid < positions[1],
+                "mid {} should be < {}",
+                mid,
+                positions[1]
+            );
+            positions.insert(1, mid);
+        }
+    }
+
+    #[test]
+    fn test_generate_between_pads_short_lower_bound() {
+        // The lower bound is read as if padded with '0' up to the differing index
+        let pos = generate_between("A", "A02").unwrap();
+        assert_eq!(pos, "A01");
+        assert!(pos > "A".to_string());
+        assert!(pos < "A02".to_string());
+    }
+
+    #[test]
+    fn test_generate_between_different_lengths() {
+        let pos = generate_between("z", "z1").unwrap();
+        assert!(pos > "z".to_string());
+        assert!(pos < "z1".to_string());
+
+        let pos2 = generate_between("A", "AA").unwrap();
+        assert!(pos2 > "A".to_string());
+        assert!(pos2 < "AA".to_string());
+    }
+
+    #[test]
+    fn test_generate_before_minimum_position_is_none() {
+        assert_eq!(generate_before("0"), None);
+    }
+
+    #[test]
+    fn test_generate_before_all_zeros_is_none() {
+        assert_eq!(generate_before("000"), None);
+        assert_eq!(generate_between("", "000"), None);
+        assert_eq!(generate_between_tagged("", "00", 'e'), None);
+    }
+
+    #[test]
+    fn test_generate_between_z_and_z0() {
+        // Every position above "z" sorts at or after "z0"
+        assert_eq!(generate_between("z", "z0"), None);
+    }
+
+    #[test]
+    fn test_generate_before_with_trailing_zeros() {
+        let pos = generate_before("A0").unwrap();
+        assert!(pos < "A0".to_string());
+
+        let pos2 = generate_before("10").unwrap();
+        assert!(pos2 < "10".to_string());
+    }
+
+    /// Number of `len`-character positions strictly between two positions
+    fn free_positions_of_length(before: &str, after: &str, len: usize) -> u128 {
+        let value = |s: &str| {
+            s.chars()
+                .chain(std::iter::repeat(START_CHAR))
+                .take(len)
+                .fold(0u128, |v, c| {
+                    v * BASE as u128 + char_to_index(c).unwrap() as u128
+                })
+        };
+        // A shorter `before` padded with '0's is itself a free position after it
+        let shorter = (before.len() < len) as u128;
+        value(after) - value(before) - 1 + shorter
+    }
+
+    #[test]
+    fn test_generate_after_gap() {
+        assert_eq!(generate_after_gap("H", 1), generate_after("H"));
+        assert_eq!(generate_after_gap("Hz", 1), generate_after("Hz"));
+        assert_eq!(generate_after_gap("H", 5), "M");
+        assert_eq!(generate_after_gap("Hz", 3), "I2");
+        assert_eq!(generate_after_gap("y", 5), "y5");
+
+        for current in ["H", "Hz", "y", "zz", "A0", "H5"] {
+            for gap in [2, 5, 61, 62, 100, 5000] {
+                let next = generate_after_gap(current, gap);
+                assert!(next.as_str() > current, "{} !> {}", next, current);
+                assert!(!next.ends_with(START_CHAR));
+                assert!(
+                    free_positions_of_length(current, &next, next.len()) >= gap as u128 - 1,
+                    "not enough room between {} and {} for gap {}",
+                    current,
+                    next,
+                    gap
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_before_gap() {
+        assert_eq!(
+            generate_before_gap("H", 1).unwrap(),
+            generate_before("H").unwrap()
+        );
+        assert_eq!(generate_before_gap("H", 5).unwrap(), "C");
+        assert_eq!(generate_before_gap("H1", 3).unwrap(), "Gy");
+        assert_eq!(generate_before_gap("1", 5).unwrap(), "0v");
+
+        for current in ["H", "H1", "1", "01", "A0", "zz"] {
+            for gap in [2, 5, 61, 62, 100, 5000] {
+                let prev = generate_before_gap(current, gap).unwrap();
+                assert!(prev.as_str() < current, "{} !< {}", prev, current);
+                assert!(!prev.ends_with(START_CHAR));
+                assert!(
+                    free_positions_of_length(&prev, current, prev.len()) >= gap as u128 - 1,
+                    "not enough room between {} and {} for gap {}",
+                    prev,
+                    current,
+                    gap
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_mid_index_matches_mid_char() {
+        assert_eq!(index_to_char(MID_INDEX), Some(MID_CHAR));
+    }
+
+    #[test]
+    fn test_generate_before_gap_minimum_is_none() {
+        assert_eq!(generate_before_gap("00", 3), None);
+    }
+
+    #[test]
+    fn test_generate_balanced_positions_empty() {
+        let positions = generate_balanced_positions(0);
+        assert!(positions.is_empty());
+    }
+
+    #[test]
+    fn test_generate_balanced_positions_single() {
+        let positions = generate_balanced_positions(1);
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0], "H");
+    }
+
+    #[test]
+    fn test_generate_balanced_positions_multiple() {
+        let positions = generate_balanced_positions(5);
+        assert_eq!(positions.len(), 5);
+
+        for i in 0..positions.len() - 1 {
+            assert!(
+                positions[i] < positions[i + 1],
+                "Position {} ({}) should be < position {} ({})",
+                i,
+                positions[i],
+                i + 1,
+                positions[i + 1]
+            );
+        }
+    }
+
+    #[test]
+    fn test_generate_balanced_positions_large() {
+        let positions = generate_balanced_positions(100);
+        assert_eq!(positions.len(), 100);
+
+        for i in 0..positions.len() - 1 {
+            assert!(
+                positions[i] < positions[i + 1],
+                "Position {} ({}) should be < position {} ({})",
+                i,
+                positions[i],
+                i + 1,
+                positions[i + 1]
+            );
+        }
+    }
+
+    #[test]
+    fn test_fraction_to_position() {
+        let pos_0 = fraction_to_position(0.0);
+        let pos_half = fraction_to_position(0.5);
+        let pos_1 = fraction_to_position(0.999);
+
+        assert!(pos_0 < pos_half);
+        assert!(pos_half < pos_1);
+    }
+
+    #[test]
+    fn test_base62_to_decimal() {
+        assert_eq!(base62_to_decimal(""), "0");
+        assert_eq!(base62_to_decimal("0"), "0");
+        assert_eq!(base62_to_decimal("A"), "10");
+        assert_eq!(base62_to_decimal("z"), "61");
+        assert_eq!(base62_to_decimal("10"), "62");
+        assert_eq!(base62_to_decimal("A0"), "620");
+        assert_eq!(base62_to_decimal("0A"), "10");
+        // 62^12 - 1 does not fit in a u64, precision must be kept
+        assert_eq!(base62_to_decimal("zzzzzzzzzzzz"), "3226266762397899821055");
+    }
+
+    #[test]
+    fn test_decimal_to_base62() {
+        assert_eq!(decimal_to_base62("0").as_deref(), Some("0"));
+        assert_eq!(decimal_to_base62("10").as_deref(), Some("A"));
+        assert_eq!(decimal_to_base62("62").as_deref(), Some("10"));
+        assert_eq!(decimal_to_base62("620").as_deref(), Some("A0"));
+        assert_eq!(decimal_to_base62("0010").as_deref(), Some("A"));
+        assert_eq!(decimal_to_base62(""), None);
+        assert_eq!(decimal_to_base62("-1"), None);
+        assert_eq!(decimal_to_base62("1.5"), None);
+    }
+
+    #[test]
+    fn test_numeric_round_trip() {
+        for s in ["H", "Hz", "A0", "zzzzzzzzzzzz", "123abcXYZ"] {
+            let decimal = base62_to_decimal(s);
+            assert_eq!(decimal_to_base62(&decimal).unwrap(), s);
+        }
+    }
+
+    #[test]
+    fn test_numeric_order_differs_from_lexicographic() {
+        // Lexicographically 'A0' < 'B', but as integers 620 > 11
+        assert!("A0" < "B");
+        let a0: u64 = base62_to_decimal("A0").parse().unwrap();
+        let b: u64 = base62_to_decimal("B").parse().unwrap();
+        assert!(a0 > b);
+    }
+
+    #[test]
+    fn test_balanced_position_matches_balanced_positions() {
+        for count in [1, 2, 5, 62, 1000] {
+            let positions = generate_balanced_positions(count);
+            for (i, expected) in positions.iter().enumerate() {
+                assert_eq!(&balanced_position(i, count).unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_balanced_positions_of_length() {
+        assert_eq!(
+            generate_balanced_positions_of_length(2, 1).unwrap(),
+            vec!["F", "k"]
+        );
+        for (count, length) in [(1, 4), (62, 1), (1000, 3), (5000, 25)] {
+            let positions = generate_balanced_positions_of_length(count, length).unwrap();
+            assert_eq!(positions.len(), count);
+            assert!(positions.iter().all(|p| p.len() == length));
+            assert!(positions.windows(2).all(|w| w[0] < w[1]));
+        }
+        assert!(generate_balanced_positions_of_length(63, 1).is_none());
+        assert!(generate_balanced_positions_of_length(3844, 2).is_some());
+        assert!(generate_balanced_positions_of_length(3845, 2).is_none());
+        assert!(generate_balanced_positions_of_length(1000, 2).unwrap()[0].as_str() > "00");
+        assert!(generate_balanced_positions_of_length(1, 0).is_none());
+    }
+
+    #[test]
+    fn test_append_and_prepend_capacity() {
+        for max_length in 1..=4 {
+            for start in [
+                "H", "z", "zz", "Hz", "H0", "A1", "1", "zzzz", "00H", "Hzzzz",
+            ] {
+                let mut appended = 0;
+                let mut current = start.to_string();
+                loop {
+                    current = generate_after(&current);
+                    if current.len() > max_length {
+                        break;
+                    }
+                    appended += 1;
+                }
+                assert_eq!(
+                    append_capacity(start, max_length),
+                    appended,
+                    "after {}",
+                    start
+                );
+
+                let mut prepended = 0;
+                let mut current = start.to_string();
+                while !current.chars().all(|c| c == START_CHAR) {
+                    current = generate_before(&current).unwrap();
+                    if current.len() > max_length {
+                        break;
+                    }
+                    prepended += 1;
+                }
+                assert_eq!(
+                    prepend_capacity(start, max_length),
+                    prepended,
+                    "before {}",
+                    start
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_between_capacity() {
+        assert_eq!(between_capacity("A", "B", 1), 0);
+        // "AH", "A8", "A4", "A2", "A1"
+        assert_eq!(between_capacity("A", "B", 2), 5);
+        assert_eq!(between_capacity("A", "B", 3), 10);
+        assert_eq!(between_capacity("B", "A", 3), 0);
+        assert_eq!(between_capacity("H", "H0", 6), 0);
+        assert_eq!(between_capacity("Hzzz", "I", 3), 0);
+        assert_eq!(between_capacity("HzzzA", "HzzzB", 4), 0);
+        assert!(between_capacity("0", "z", 12) > between_capacity("Hz", "I", 12));
+    }
+
+    #[test]
+    fn test_plan_for_size() {
+        let plan = plan_for_size(50_000);
+        assert_eq!(plan.length, 4);
+        assert!(plan.step >= BASE as u128);
+
+        let last = planned_position(&plan.first, plan.step, 49_999).unwrap();
+        assert_eq!(last.len(), 4);
+        assert!(plan.first.as_str() > "F");
+        assert!(last.as_str() < "l");
+        let mut previous = plan.first.clone();
+        for i in [1, 2, 1000, 49_999] {
+            let position = planned_position(&plan.first, plan.step, i).unwrap();
+            assert!(position > previous);
+            previous = position;
+        }
+
+        assert_eq!(plan_for_size(0), plan_for_size(1));
+        assert_eq!(plan_for_size(1).length, 2);
+        assert!(plan_for_size(u64::MAX).length <= 13);
+        assert_eq!(planned_position("zy", 1, 1).as_deref(), Some("zz"));
+        assert_eq!(planned_position("zy", 1, 2), None);
+        assert_eq!(planned_position("H!", 1, 0), None);
+    }
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end("Hz").as_deref(), Some("I"));
+        assert_eq!(prefix_end("H").as_deref(), Some("I"));
+        assert_eq!(prefix_end("Hz3").as_deref(), Some("Hz4"));
+        assert_eq!(prefix_end("zz"), None);
+        assert_eq!(prefix_end(""), None);
+        for position in ["Hz", "Hz0", "Hzzz", "Hzy9"] {
+            assert!(position >= "Hz" && position < prefix_end("Hz").unwrap().as_str());
+        }
+        assert!("I" >= prefix_end("Hz").unwrap().as_str());
+    }
+
+    #[test]
+    fn test_parse_base62_input() {
+        assert_eq!(parse_base62_input("Hz"), Ok("Hz"));
+        assert_eq!(parse_base62_input(" \tHz\r\n"), Ok("Hz"));
+        assert_eq!(parse_base62_input(""), Ok(""));
+        assert_eq!(parse_base62_input(" \n\t "), Ok(""));
+        // Inner whitespace is not trimmed, and offsets count the leading spaces
+        assert_eq!(parse_base62_input("H I"), Err((1, ' ')));
+        assert_eq!(parse_base62_input("  H\tI "), Err((3, '\t')));
+        // Offsets are in bytes, not characters
+        assert_eq!(parse_base62_input("Hé!"), Err((1, 'é')));
+        assert_eq!(parse_base62_input("éH"), Err((0, 'é')));
+        assert_eq!(find_invalid_char("AéB!"), Some((1, 'é')));
+        // Non-ASCII whitespace is an invalid character rather than trimmed
+        assert_eq!(parse_base62_input("\u{a0}H"), Err((0, '\u{a0}')));
+    }
 
-        assert!(first < second);
-        assert!(second < third);
+    #[test]
+    fn test_is_valid_base62_rejects_non_ascii() {
+        // 'ű' is U+0171, whose low byte is 'q'
+        assert!(!is_valid_base62("Hű"));
+        assert!(is_valid_base62("Hq"));
     }
 
     #[test]
-    fn test_multiple_insertions() {
-        let first = MID_CHAR.to_string();
-        let mut positions: Vec<String> = vec![first];
+    fn test_desc_mid_char_mirrors_mid_char() {
+        let asc = char_to_index(MID_CHAR).unwrap();
+        let desc = char_to_index(DESC_MID_CHAR).unwrap();
+        assert_eq!(asc + desc, BASE - 1);
+        // A descending list starts with most of the keyspace below it
+        assert!(
+            prepend_capacity(&DESC_MID_CHAR.to_string(), 3)
+                > prepend_capacity(&MID_CHAR.to_string(), 3)
+        );
+    }
 
-        for _ in 0..5 {
-            let last = positions.last().unwrap();
-            positions.push(generate_after(last));
+    #[test]
+    fn test_cursor_round_trip() {
+        for position in ["H", "0", "zzzz", "Hzz0aB9", "0000000000001"] {
+            let token = encode_cursor(position);
+            assert!(
+                token
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'),
+                "{} is not URL-safe",
+                token
+            );
+            assert_eq!(decode_cursor(&token).as_deref(), Ok(position));
         }
+        assert_ne!(encode_cursor("H"), encode_cursor("I"));
+    }
 
-        for i in 0..positions.len() - 1 {
+    #[test]
+    fn test_base64url() {
+        for len in 0..10usize {
+            let bytes: Vec<u8> = (0..len).map(|i| (i * 97 + 200) as u8).collect();
+            let encoded = base64url_encode(&bytes);
+            assert_eq!(encoded.len(), (len * 4).div_ceil(3));
+            assert_eq!(base64url_decode(&encoded), Some(bytes));
+        }
+        assert_eq!(base64url_encode(b"\xfb\xff"), "-_8");
+        // Padding, the standard alphabet and non-canonical trailing bits are rejected
+        assert_eq!(base64url_decode("-_8="), None);
+        assert_eq!(base64url_decode("+/8"), None);
+        assert_eq!(base64url_decode("-_9"), None);
+        assert_eq!(base64url_decode("A"), None);
+    }
+
+    #[test]
+    fn test_decode_cursor_rejects_garbage() {
+        for token in ["", "A", "not a token", "%%%%", "SGVsbG8", "AUgAAAAA"] {
+            assert!(decode_cursor(token).is_err(), "{:?} was accepted", token);
+        }
+        // Any single edited character breaks the checksum
+        let token = encode_cursor("Hzz");
+        for i in 0..token.len() {
+            let mut edited = token.clone().into_bytes();
+            edited[i] = if edited[i] == b'A' { b'B' } else { b'A' };
+            let edited = String::from_utf8(edited).unwrap();
+            assert!(decode_cursor(&edited).is_err(), "{} was accepted", edited);
+        }
+        // A well-formed token of a position that is not Base62
+        let mut bytes = vec![CURSOR_VERSION, b'H', b'!'];
+        bytes.extend_from_slice(&fnv1a(&bytes).to_be_bytes());
+        assert_eq!(
+            decode_cursor(&base64url_encode(&bytes)),
+            Err("token does not hold a valid position".to_string())
+        );
+    }
+
+    #[test]
+    fn test_generate_between_tagged_never_collides() {
+        let tags: Vec<char> = BASE62_CHARS[1..].iter().map(|&b| b as char).collect();
+        let mut state: u64 = 7;
+        let mut random_position = || {
+            let mut pos = String::new();
+            loop {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                pos.push(BASE62_CHARS[((state >> 33) % 62) as usize] as char);
+                if (state >> 20).is_multiple_of(3) && !pos.ends_with(START_CHAR) {
+                    return pos;
+                }
+            }
+        };
+
+        let mut gaps = vec![
+            (String::new(), String::new()),
+            ("H".into(), String::new()),
+            (String::new(), "H".into()),
+            ("A".into(), "B".into()),
+            ("Az".into(), "B".into()),
+            ("B".into(), "BH".into()),
+            ("H".into(), "H1".into()),
+            ("H".into(), "H01".into()),
+        ];
+        for _ in 0..500 {
+            let (a, b) = (random_position(), random_position());
+            if a != b {
+                gaps.push((a.clone().min(b.clone()), a.max(b)));
+            }
+        }
+
+        for (before, after) in &gaps {
+            let positions: Vec<String> = tags
+                .iter()
+                .map(|&tag| generate_between_tagged(before, after, tag).unwrap())
+                .collect();
+            for (pos, &tag) in positions.iter().zip(&tags) {
+                assert!(
+                    is_between(pos, before, after),
+                    "{} not in ({}, {})",
+                    pos,
+                    before,
+                    after
+                );
+                assert!(pos.ends_with(tag));
+            }
+            // Distinct tags give distinct positions, ordered by tag
             assert!(
-                positions[i] < positions[i + 1],
-                "Position {} ({}) should be less than position {} ({})",
-                i,
-                positions[i],
-                i + 1,
-                positions[i + 1]
+                positions.windows(2).all(|w| w[0] < w[1]),
+                "tagged positions for ({}, {}) collide or are out of tag order",
+                before,
+                after
+            );
+            // Deterministic for a given writer
+            assert_eq!(
+                positions[0],
+                generate_between_tagged(before, after, tags[0]).unwrap()
             );
         }
     }
 
     #[test]
-    fn test_insert_at_beginning() {
-        let first = MID_CHAR.to_string();
-        let before_first = generate_before(&first);
+    fn test_is_valid_writer_tag() {
+        assert!(is_valid_writer_tag('1') && is_valid_writer_tag('z') && is_valid_writer_tag('A'));
+        assert!(!is_valid_writer_tag('0'));
+        assert!(!is_valid_writer_tag('-') && !is_valid_writer_tag('é'));
+    }
 
-        assert!(before_first < first);
+    #[test]
+    fn test_simulate_insertions_patterns() {
+        let appended = simulate_insertions(100, &[(InsertPattern::Append, 1)], 0);
+        assert_eq!(appended[0], "H");
+        assert!(appended.windows(2).all(|w| w[1] == generate_after(&w[0])));
+
+        let prepended = simulate_insertions(100, &[(InsertPattern::Prepend, 1)], 0);
+        assert!(
+            prepended
+                .windows(2)
+                .all(|w| w[1] == generate_before(&w[0]).unwrap())
+        );
+
+        let hotspot = simulate_insertions(50, &[(InsertPattern::Hotspot, 1)], 0);
+        assert_eq!(hotspot[1], generate_after("H"));
+        assert!(hotspot[2..].iter().all(|p| p.as_str() > "H"));
+        assert!(hotspot[2..].windows(2).all(|w| w[1] < w[0]));
+
+        for (_, pattern) in InsertPattern::ALL {
+            let mut positions = simulate_insertions(500, &[(pattern, 1)], 7);
+            positions.sort();
+            positions.dedup();
+            assert_eq!(positions.len(), 500, "{:?} generated a duplicate", pattern);
+        }
     }
 
     #[test]
-    fn test_generate_after_empty_string() {
-        let pos = generate_after("");
-        assert_eq!(pos, "H");
+    fn test_simulate_insertions_mix() {
+        let mix = [(InsertPattern::Append, 3), (InsertPattern::Random, 1)];
+        assert_eq!(
+            simulate_insertions(300, &mix, 42),
+            simulate_insertions(300, &mix, 42)
+        );
+        assert_ne!(
+            simulate_insertions(300, &mix, 42),
+            simulate_insertions(300, &mix, 43)
+        );
+
+        let zero_weight = [(InsertPattern::Prepend, 0), (InsertPattern::Append, 1)];
+        assert_eq!(
+            simulate_insertions(100, &zero_weight, 5),
+            simulate_insertions(100, &[(InsertPattern::Append, 1)], 5)
+        );
+        assert_eq!(
+            InsertPattern::parse(" Hotspot"),
+            Some(InsertPattern::Hotspot)
+        );
+        assert_eq!(InsertPattern::parse("sideways"), None);
     }
 
     #[test]
-    fn test_generate_before_empty_string() {
-        let pos = generate_before("");
-        assert_eq!(pos, "H");
+    fn test_generate_between_avoiding() {
+        // Nothing taken, or only positions outside the bounds: same as generate_between
+        assert_eq!(
+            generate_between_avoiding("A", "C", &[], 64),
+            Some("B".into())
+        );
+        assert_eq!(
+            generate_between_avoiding("A", "C", &["0", "B0", "Z", "A"], 64),
+            Some("B".into())
+        );
+        assert_eq!(
+            generate_between_avoiding("", "", &["A"], 64),
+            Some("H".into())
+        );
+
+        let taken = ["B"];
+        let p = generate_between_avoiding("A", "C", &taken, 64).unwrap();
+        assert!(is_between(&p, "A", "C") && p != "B");
+
+        // Fill a gap repeatedly; every result must be new and inside the bounds
+        let mut taken: Vec<String> = Vec::new();
+        for _ in 0..300 {
+            let refs: Vec<&str> = taken.iter().map(String::as_str).collect();
+            let p = generate_between_avoiding("A", "B", &refs, 64).unwrap();
+            assert!(is_between(&p, "A", "B"));
+            assert!(!taken.contains(&p));
+            taken.push(p);
+        }
+        assert!(taken.iter().all(|p| p.len() <= 3));
+
+        // No room at all, or none short enough
+        assert_eq!(generate_between_avoiding("A", "A0", &[], 64), None);
+        assert_eq!(generate_between_avoiding("", "00", &[], 64), None);
+        assert_eq!(
+            generate_between_avoiding("A", "B", &["AH"], 2),
+            Some("Ae".into())
+        );
+        let all_two: Vec<String> = (0..BASE)
+            .map(|i| format!("A{}", index_to_char(i).unwrap()))
+            .collect();
+        let refs: Vec<&str> = all_two.iter().map(String::as_str).collect();
+        assert_eq!(generate_between_avoiding("A", "B", &refs, 2), None);
+        assert!(generate_between_avoiding("A", "B", &refs, 3).is_some());
     }
 
     #[test]
-    fn test_generate_between_empty_strings() {
-        let pos = generate_between("", "");
-        assert_eq!(pos, "H");
+    fn test_generate_seeded_between() {
+        let first = generate_seeded_between("A", "B", "request-1").unwrap();
+        assert_eq!(
+            generate_seeded_between("A", "B", "request-1"),
+            Some(first.clone())
+        );
+        assert!(is_between(&first, "A", "B"));
+
+        // Different seeds spread over the whole gap
+        let positions: Vec<String> = (0..1000)
+            .map(|i| generate_seeded_between("A", "B", &format!("request-{}", i)).unwrap())
+            .collect();
+        let mut distinct = positions.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert!(distinct.len() >= 995);
+        let lower_half = positions.iter().filter(|p| p.as_str() < "AV").count();
+        assert!((400..600).contains(&lower_half), "{}", lower_half);
+
+        // Adjacent and open bounds still give a position strictly inside
+        for (before, after) in [("A", "A01"), ("Hzzz", "I"), ("", "01"), ("z", ""), ("", "")] {
+            let p = generate_seeded_between(before, after, "key").unwrap();
+            assert!(is_between(&p, before, after), "{} {} {}", before, after, p);
+        }
+        assert_eq!(generate_seeded_between("A", "A0", "key"), None);
+        assert_eq!(generate_seeded_between("B", "A", "key"), None);
     }
 
     #[test]
-    fn test_generate_between_invalid_order() {
-        let pos = generate_between("z", "0");
-        assert!(pos > "z".to_string());
+    fn test_midpoints() {
+        let first: Vec<String> = midpoints("A", "B").take(3).collect();
+        assert_eq!(first[0], generate_between("A", "B").unwrap());
+        assert!(first[1] < first[0] && first[0] < first[2]);
+
+        for (before, after) in [("A", "B"), ("Hz", "I"), ("", ""), ("", "01"), ("A", "A01")] {
+            let mut items: Vec<String> = midpoints(before, after).take(2000).collect();
+            assert_eq!(items.len(), 2000);
+            assert!(items.iter().all(|p| is_between(p, before, after)));
+            items.sort();
+            items.dedup();
+            assert_eq!(
+                items.len(),
+                2000,
+                "duplicate between {:?} and {:?}",
+                before,
+                after
+            );
+        }
+
+        assert_eq!(midpoints("A", "A0").next(), None);
+        assert_eq!(midpoints("", "00").next(), None);
+        assert_eq!(midpoints("B", "A").next(), None);
     }
 
     #[test]
-    fn test_generate_between_equal_strings() {
-        let pos = generate_between("H", "H");
-        assert!(pos > "H".to_string());
+    fn test_sequence_position() {
+        assert_eq!(sequence_position("I", 0), "I6000000H");
+        assert_eq!(sequence_position("I", 63), "I6000011H");
+
+        let wide = 62u64.pow(SEQUENCE_MIN_WIDTH as u32);
+        let counters = [0, 1, 61, 62, wide - 1, wide, wide + 1, u64::MAX];
+        let positions: Vec<String> = counters
+            .iter()
+            .map(|&c| sequence_position("I", c))
+            .collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]), "{:?}", positions);
+        assert!(
+            positions
+                .iter()
+                .all(|p| is_valid_base62(p) && p.starts_with('I'))
+        );
+        assert_eq!(positions[5], "I71000000H");
+
+        // Appending after a sequence position stays below the next one
+        for &c in &counters[..counters.len() - 1] {
+            let after = generate_after(&sequence_position("I", c));
+            assert!(after < sequence_position("I", c + 1));
+        }
     }
 
     #[test]
-    fn test_base62_char_conversion() {
-        assert_eq!(char_to_index('0'), Some(0));
-        assert_eq!(char_to_index('9'), Some(9));
-        assert_eq!(char_to_index('A'), Some(10));
-        assert_eq!(char_to_index('Z'), Some(35));
-        assert_eq!(char_to_index('a'), Some(36));
-        assert_eq!(char_to_index('z'), Some(61));
+    fn test_sequence_counter_after() {
+        assert_eq!(sequence_counter_after("I", "H"), Some(0));
+        assert_eq!(sequence_counter_after("I", "I5zz"), Some(0));
+        assert_eq!(sequence_counter_after("I", "I6000000H"), Some(1));
+        assert_eq!(sequence_counter_after("I", "I6000001"), Some(1));
+        assert_eq!(sequence_counter_after("I", "I6000002"), Some(2));
+        assert_eq!(sequence_counter_after("I", "I7"), Some(62u64.pow(6)));
+        assert_eq!(sequence_counter_after("I", "J"), None);
+        assert_eq!(sequence_counter_after("I", "Iz"), None);
+
+        // `lexo_after()` steps from a sequence position never reach past the result
+        let mut position = sequence_position("I", 5);
+        for _ in 0..100 {
+            position = generate_after(&position);
+            let counter = sequence_counter_after("I", &position).unwrap();
+            assert!(sequence_position("I", counter) > position, "{}", position);
+            assert!(
+                sequence_position("I", counter - 1) <= position,
+                "{}",
+                position
+            );
+        }
+    }
 
-        assert_eq!(index_to_char(0), Some('0'));
-        assert_eq!(index_to_char(9), Some('9'));
-        assert_eq!(index_to_char(10), Some('A'));
-        assert_eq!(index_to_char(35), Some('Z'));
-        assert_eq!(index_to_char(36), Some('a'));
-        assert_eq!(index_to_char(61), Some('z'));
+    #[test]
+    fn test_generate_between_step() {
+        assert_eq!(
+            generate_between_step("A", "Z").unwrap(),
+            ("M".to_string(), BetweenStep::Gap { index: 0 })
+        );
+        assert_eq!(
+            generate_between_step("A", "B").unwrap(),
+            ("AH".to_string(), BetweenStep::AdjacentExtend { index: 0 })
+        );
+        assert_eq!(
+            generate_between_step("HA", "I").unwrap(),
+            ("Ha".to_string(), BetweenStep::AdjacentBisect { index: 0 })
+        );
+        assert_eq!(
+            generate_between_step("", "").unwrap().1,
+            BetweenStep::OpenBounds
+        );
+        assert_eq!(
+            generate_between_step("B", "A").unwrap().1,
+            BetweenStep::OutOfOrder
+        );
+        assert_eq!(
+            BetweenStep::Gap { index: 2 }.to_string(),
+            "gap found at index 2"
+        );
     }
 
     #[test]
-    fn test_is_valid_base62() {
-        assert!(is_valid_base62("H"));
-        assert!(is_valid_base62("abc123XYZ"));
-        assert!(!is_valid_base62("hello!"));
-        assert!(!is_valid_base62("test-value"));
-        assert!(is_valid_base62(""));
+    fn test_between_after_z_runs() {
+        for (before, after) in [("Az", "B"), ("Azz", "B"), ("Hzz", "I")] {
+            let result = generate_between(before, after).unwrap();
+            assert!(
+                result.as_str() > before && result.as_str() < after,
+                "{} not between {} and {}",
+                result,
+                before,
+                after
+            );
+            assert!(result.len() <= before.len() + 1, "{} is too long", result);
+        }
+        assert_eq!(generate_between("Az", "B").unwrap(), "AzH");
+        assert_eq!(generate_between("Azy5", "B").unwrap(), "Azz");
     }
 
     #[test]
-    fn test_generate_between_same_prefix() {
-        let pos = generate_between("AB", "AC");
-        assert!(pos > "AB".to_string());
-        assert!(pos < "AC".to_string());
+    fn test_bound_matrix() {
+        let bounds = [None, Some(""), Some("A"), Some("Z")];
+        for before in bounds {
+            for after in bounds {
+                let result =
+                    bound_position(before).and_then(|b| bound_position(after).map(|a| (b, a)));
+                if before == Some("") || after == Some("") {
+                    assert_eq!(result, Err(EMPTY_POSITION.to_string()));
+                    continue;
+                }
+                let (b, a) = result.unwrap();
+                if !b.is_empty() && !a.is_empty() && b >= a {
+                    continue;
+                }
+                let position = generate_between(b, a).unwrap();
+                assert!(!position.is_empty());
+                assert!(
+                    b.is_empty() || position.as_str() > b,
+                    "{:?} {:?}",
+                    before,
+                    after
+                );
+                assert!(
+                    a.is_empty() || position.as_str() < a,
+                    "{:?} {:?}",
+                    before,
+                    after
+                );
+            }
+        }
+        assert_eq!(bound_position(None), Ok(""));
+        assert_eq!(required_position("H"), Ok("H"));
+        assert!(required_position("").is_err());
     }
 
     #[test]
-    fn test_generate_between_adjacent_with_prefix() {
-        let pos = generate_between("A0", "A1");
-        assert!(pos > "A0".to_string());
-        assert!(pos < "A1".to_string());
+    fn test_advance_and_retreat_match_repeated_steps() {
+        for start in [
+            "H", "Hz", "Hy", "y", "z", "zz", "A0", "01", "1", "H00", "0z", "z0",
+        ] {
+            let mut after = start.to_string();
+            let mut before = start.to_string();
+            for steps in 0..400u64 {
+                assert_eq!(
+                    advance(start, steps).unwrap(),
+                    after,
+                    "{} + {}",
+                    start,
+                    steps
+                );
+                assert_eq!(
+                    retreat(start, steps).unwrap(),
+                    before,
+                    "{} - {}",
+                    start,
+                    steps
+                );
+                after = generate_after(&after);
+                before = generate_before(&before).unwrap();
+            }
+        }
+
+        assert_eq!(advance("H", 5).unwrap(), "M");
+        assert_eq!(advance("z", 63).unwrap(), "zz0");
+        assert_eq!(retreat("H", 5).unwrap(), "C");
+        assert_eq!(retreat("1", 62).unwrap(), "00z");
+        assert_eq!(retreat("00", 1), Err(StepError::Minimum));
+        assert_eq!(retreat("00", 0).unwrap(), "00");
+        assert_eq!(advance("H", u64::MAX), Err(StepError::TooLong));
+        assert_eq!(retreat("H", u64::MAX), Err(StepError::TooLong));
     }
 
     #[test]
-    fn test_deep_insertion() {
-        let mut positions = vec!["0".to_string(), "1".to_string()];
+    fn test_append_rank() {
+        assert_eq!(append_rank(0), "H0");
+        assert_eq!(append_rank(61), "Hz");
+        assert_eq!(append_rank(62), "I10");
+        assert_eq!(append_rank(u64::MAX).len(), 12);
+
+        let counters: Vec<u64> = (0..5000)
+            .chain([238327, 238328, u64::MAX - 1, u64::MAX])
+            .collect();
+        let positions: Vec<String> = counters.iter().map(|&c| append_rank(c)).collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+        assert!(positions.iter().all(|p| is_valid_base62(p)));
+    }
 
-        for _ in 0..10 {
-            let mid = generate_between(&positions[0], &positions[1]);
-            assert!(
-                mid > positions[0],
-                "mid {} should be > {}",
-                mid,
-                positions[0]
-            );
+    #[test]
+    fn test_rebalance_positions() {
+        assert!(rebalance_positions::<&str>(&[]).is_empty());
+        assert_eq!(rebalance_positions(&["zzz"]), vec!["H".to_string()]);
+
+        let ranks = ["I", "0001", "H", "zz", "H"];
+        let balanced = generate_balanced_positions(ranks.len());
+        // Sorted order is 0001, H (index 2), H (index 4), I, zz
+        assert_eq!(
+            rebalance_positions(&ranks),
+            vec![
+                balanced[3].clone(),
+                balanced[0].clone(),
+                balanced[1].clone(),
+                balanced[4].clone(),
+                balanced[2].clone(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rebalance_positions_missing_last() {
+        let ranks = [None, Some("I"), None, Some("H")];
+        let balanced = generate_balanced_positions(ranks.len());
+        assert_eq!(
+            rebalance_positions_missing_last(&ranks),
+            vec![
+                balanced[2].clone(),
+                balanced[1].clone(),
+                balanced[3].clone(),
+                balanced[0].clone(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transcode_preserves_order() {
+        // Every rank of up to `depth` characters, listed in the source alphabet's order
+        fn all_ranks(alphabet: &[char], depth: usize) -> Vec<String> {
+            let mut ranks = Vec::new();
+            let mut stack = vec![String::new()];
+            while let Some(prefix) = stack.pop() {
+                if !prefix.is_empty() {
+                    ranks.push(prefix.clone());
+                }
+                if prefix.chars().count() < depth {
+                    for &c in alphabet.iter().rev() {
+                        stack.push(format!("{}{}", prefix, c));
+                    }
+                }
+            }
+            ranks
+        }
+
+        let sortable64 = "-0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ_abcdefghijklmnopqrstuvwxyz";
+        let reversed: String = sortable64.chars().rev().collect();
+        for alphabet in [
+            sortable64,
+            reversed.as_str(),
+            "01",
+            "abc",
+            "\u{3b1}\u{3b2}\u{3b3}\u{3b4}",
+        ] {
+            let chars: Vec<char> = alphabet.chars().collect();
+            let depth = if chars.len() > 8 { 2 } else { 5 };
+            let transcoded: Vec<String> = all_ranks(&chars, depth)
+                .iter()
+                .map(|rank| transcode(rank, alphabet).unwrap())
+                .collect();
             assert!(
-                mid < positions[1],
-                "mid {} should be < {}",
-                mid,
-                positions[1]
+                transcoded.windows(2).all(|w| w[0] < w[1]),
+                "transcoding from {:?} does not preserve order",
+                alphabet
             );
-            positions.insert(1, mid);
+            assert!(transcoded.iter().all(|rank| is_valid_base62(rank)));
         }
+
+        let base62 = std::str::from_utf8(BASE62_CHARS).unwrap();
+        assert_eq!(transcode("Hz0", base62).unwrap(), "Hz0");
+        assert_eq!(transcode("-", sortable64).unwrap(), "00");
+        assert_eq!(transcode("z", sortable64).unwrap(), "z1");
+        assert_eq!(transcode("10", "01").unwrap(), "V0");
     }
 
     #[test]
-    fn test_generate_between_different_lengths() {
-        let pos = generate_between("z", "z1");
-        assert!(pos > "z".to_string());
-        assert!(pos < "z1".to_string());
+    fn test_transcode_errors() {
+        assert_eq!(
+            transcode("a", "a"),
+            Err(TranscodeError::AlphabetTooShort(1))
+        );
+        assert_eq!(
+            transcode("a", "abca"),
+            Err(TranscodeError::DuplicateCharacter('a'))
+        );
+        assert_eq!(transcode("", "ab"), Err(TranscodeError::Empty));
+        assert_eq!(
+            transcode("abz", "ab"),
+            Err(TranscodeError::InvalidCharacter {
+                offset: 2,
+                character: 'z'
+            })
+        );
+    }
 
-        let pos2 = generate_between("A", "AA");
-        assert!(pos2 > "A".to_string());
-        assert!(pos2 < "AA".to_string());
+    #[test]
+    fn test_percentile_index() {
+        assert_eq!(percentile_index(0, 0.5), None);
+        assert_eq!(percentile_index(1, 0.0), Some(0));
+        assert_eq!(percentile_index(1, 1.0), Some(0));
+        // Matches percentile_disc over 1..=4: 0.25 -> 1, 0.5 -> 2, 0.75 -> 3
+        assert_eq!(percentile_index(4, 0.0), Some(0));
+        assert_eq!(percentile_index(4, 0.25), Some(0));
+        assert_eq!(percentile_index(4, 0.26), Some(1));
+        assert_eq!(percentile_index(4, 0.5), Some(1));
+        assert_eq!(percentile_index(4, 0.75), Some(2));
+        assert_eq!(percentile_index(4, 1.0), Some(3));
+        assert_eq!(percentile_index(101, 0.5), Some(50));
     }
 
     #[test]
-    #[should_panic(expected = "Cannot generate a position before")]
-    fn test_generate_before_minimum_position_panics() {
-        let _ = generate_before("0");
+    fn test_pack_small_position() {
+        for s in ["", "0", "H", "A0", "zzzzzzzzzz", "0000000001", "Hz", "aB3"] {
+            assert_eq!(unpack_small_position(pack_small_position(s).unwrap()), s);
+        }
+        assert_eq!(pack_small_position("zzzzzzzzzzz"), None);
+        assert_eq!(pack_small_position("H!"), None);
+
+        // Packed order is byte-wise order, including prefixes and '0' padding
+        let mut state = 12345u64;
+        let mut positions: Vec<String> = vec!["".into(), "0".into(), "00".into(), "A".into()];
+        for _ in 0..2000 {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let len = 1 + (state >> 60) as usize % SMALL_MAX_LENGTH;
+            let s: String = (0..len)
+                .map(|i| {
+                    let digit = (state >> (6 * i)) as usize % 62;
+                    // Favour '0' so trailing zeros and shared prefixes come up often
+                    index_to_char(if digit < 20 { 0 } else { digit }).unwrap()
+                })
+                .collect();
+            positions.push(s);
+        }
+        for a in &positions {
+            for b in positions.iter().step_by(7) {
+                let (pa, pb) = (pack_small_position(a), pack_small_position(b));
+                assert_eq!(pa.cmp(&pb), a.cmp(b), "{:?} vs {:?}", a, b);
+            }
+        }
     }
 
     #[test]
-    #[should_panic(expected = "Cannot generate a position before")]
-    fn test_generate_before_all_zeros_panics() {
-        let _ = generate_before("000");
+    fn test_balanced_position_out_of_range_is_none() {
+        assert_eq!(balanced_position(5, 5), None);
+        assert_eq!(balanced_position(0, 0), None);
     }
 
     #[test]
-    fn test_generate_between_z_and_z0() {
-        let result = generate_between("z", "z0");
-        assert!(result > "z".to_string());
+    fn test_generate_n_between_bounded() {
+        let positions = generate_n_between("A", "B", 3000);
+        assert_eq!(positions.len(), 3000);
+        assert!(positions[0].as_str() > "A");
+        assert!(positions[positions.len() - 1].as_str() < "B");
+        for pair in positions.windows(2) {
+            assert!(pair[0] < pair[1], "{} should be < {}", pair[0], pair[1]);
+        }
     }
 
     #[test]
-    fn test_generate_before_with_trailing_zeros() {
-        let pos = generate_before("A0");
-        assert!(pos < "A0".to_string());
+    fn test_generate_n_between_open_bounds() {
+        let positions = generate_n_between("", "", 100);
+        assert_eq!(positions.len(), 100);
+        for pair in positions.windows(2) {
+            assert!(pair[0] < pair[1], "{} should be < {}", pair[0], pair[1]);
+        }
+    }
 
-        let pos2 = generate_before("10");
-        assert!(pos2 < "10".to_string());
+    #[test]
+    fn test_generate_n_between_zero() {
+        assert!(generate_n_between("A", "B", 0).is_empty());
     }
 
     #[test]
-    fn test_generate_balanced_positions_empty() {
-        let positions = generate_balanced_positions(0);
-        assert!(positions.is_empty());
+    fn test_position_to_fraction() {
+        assert_eq!(position_to_fraction(""), 0.0);
+        assert_eq!(position_to_fraction("0"), 0.0);
+        assert!((position_to_fraction("V") - 31.0 / 62.0).abs() < 1e-12);
+        assert!(position_to_fraction("A") < position_to_fraction("A0z"));
+        assert!(position_to_fraction("A0z") < position_to_fraction("B"));
+        assert!(position_to_fraction("zzzzzz") < 1.0);
     }
 
     #[test]
-    fn test_generate_balanced_positions_single() {
-        let positions = generate_balanced_positions(1);
-        assert_eq!(positions.len(), 1);
-        assert_eq!(positions[0], "H");
+    fn test_common_prefix() {
+        assert_eq!(common_prefix("HzzA", "HzzB"), "Hzz");
+        assert_eq!(common_prefix("H", "Hz"), "H");
+        assert_eq!(common_prefix("Hz", "H"), "H");
+        assert_eq!(common_prefix("A", "B"), "");
+        assert_eq!(common_prefix("", "H"), "");
+        assert_eq!(common_prefix("Hé", "Hè"), "H");
     }
 
     #[test]
-    fn test_generate_balanced_positions_multiple() {
-        let positions = generate_balanced_positions(5);
-        assert_eq!(positions.len(), 5);
+    fn test_explain() {
+        let e = explain("Az0");
+        assert_eq!(e.digits, vec![('A', 10), ('z', 61), ('0', 0)]);
+        assert_eq!(e.length, 3);
+        assert!((e.fraction - position_to_fraction("Az0")).abs() < f64::EPSILON);
+        assert!(!e.is_minimum && !e.is_maximum && e.ends_with_min_char);
+
+        assert!(explain("00").is_minimum);
+        assert!(explain("zz").is_maximum);
+        assert!(!explain("H").ends_with_min_char);
+    }
 
-        for i in 0..positions.len() - 1 {
+    #[test]
+    fn test_position_to_fraction_inverts_fraction_to_position() {
+        for fraction in [0.1, 0.25, 0.5, 0.75, 0.9] {
+            let position = fraction_to_position(fraction);
+            assert!((position_to_fraction(&position) - fraction).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_parse_length_typmod() {
+        assert_eq!(parse_length_typmod(&["16"]), Ok(16));
+        assert_eq!(parse_length_typmod(&["1"]), Ok(1));
+        assert!(parse_length_typmod(&["0"]).is_err());
+        assert!(parse_length_typmod(&["-3"]).is_err());
+        assert!(parse_length_typmod(&["abc"]).is_err());
+        assert!(parse_length_typmod(&["10485761"]).is_err());
+        assert!(parse_length_typmod(&[]).is_err());
+        assert!(parse_length_typmod(&["8", "4"]).is_err());
+    }
+
+    #[test]
+    fn test_fits_typmod() {
+        assert!(fits_typmod("H", 1));
+        assert!(!fits_typmod("HV", 1));
+        assert!(fits_typmod("HV", 16));
+        assert!(fits_typmod(&"z".repeat(1000), -1));
+    }
+
+    #[test]
+    fn test_cbor_text_bytes() {
+        assert_eq!(cbor_text_bytes(b"\x61H"), Some(&b"H"[..]));
+        assert_eq!(cbor_text_bytes(b"\x60"), Some(&b""[..]));
+        let long = "Hz".repeat(20);
+        let mut encoded = vec![0x78, 40];
+        encoded.extend_from_slice(long.as_bytes());
+        assert_eq!(cbor_text_bytes(&encoded), Some(long.as_bytes()));
+        let mut encoded = vec![0x79, 0, 40];
+        encoded.extend_from_slice(long.as_bytes());
+        assert_eq!(cbor_text_bytes(&encoded), Some(long.as_bytes()));
+
+        // The {"value": "H"} map earlier versions wrote
+        assert_eq!(cbor_text_bytes(b"\xa1\x65value\x61H"), None);
+        // Truncated or padded values
+        assert_eq!(cbor_text_bytes(b"\x62H"), None);
+        assert_eq!(cbor_text_bytes(b"\x61Hz"), None);
+        assert_eq!(cbor_text_bytes(b"\x78"), None);
+        assert_eq!(cbor_text_bytes(b""), None);
+    }
+
+    #[test]
+    fn test_distinct_counter() {
+        let mut counter = DistinctCounter::new();
+        assert_eq!(counter.estimate(), 0.0);
+        for _ in 0..1000 {
+            counter.add(42);
+        }
+        assert!((counter.estimate() - 1.0).abs() < 0.01);
+
+        for expected in [100u64, 10_000, 1_000_000] {
+            let mut counter = DistinctCounter::new();
+            for value in 0..expected {
+                counter.add(value);
+            }
+            let error = (counter.estimate() - expected as f64).abs() / expected as f64;
             assert!(
-                positions[i] < positions[i + 1],
-                "Position {} ({}) should be < position {} ({})",
-                i,
-                positions[i],
-                i + 1,
-                positions[i + 1]
+                error < 0.1,
+                "{} values estimated as {}",
+                expected,
+                counter.estimate()
             );
         }
+
+        let mut counter = DistinctCounter::new();
+        for i in 0..5000 {
+            counter.add_bytes(format!("H{}", i % 500).as_bytes());
+        }
+        let error = (counter.estimate() - 500.0).abs() / 500.0;
+        assert!(error < 0.1, "{}", counter.estimate());
     }
 
     #[test]
-    fn test_generate_balanced_positions_large() {
-        let positions = generate_balanced_positions(100);
-        assert_eq!(positions.len(), 100);
+    fn test_abbreviated_key_preserves_order() {
+        assert_eq!(abbreviated_key(""), 0);
+        assert!(abbreviated_key("A") < abbreviated_key("A0"));
+        assert!(abbreviated_key("Hz") < abbreviated_key("I"));
+        assert_eq!(abbreviated_key("HHHHHHHHa"), abbreviated_key("HHHHHHHHb"));
+
+        let mut ranks = generate_n_between("", "", 2000);
+        ranks.extend(generate_n_between("HHHHHHHH", "HHHHHHHI", 500));
+        ranks.reverse();
+
+        let mut expected = ranks.clone();
+        expected.sort();
+
+        let mut abbreviated = ranks;
+        abbreviated.sort_by(|a, b| {
+            abbreviated_key(a)
+                .cmp(&abbreviated_key(b))
+                .then_with(|| a.cmp(b))
+        });
+
+        assert_eq!(abbreviated, expected);
+    }
 
-        for i in 0..positions.len() - 1 {
+    #[test]
+    fn test_is_between() {
+        assert!(is_between("M", "A", "Z"));
+        assert!(!is_between("A", "A", "Z"));
+        assert!(!is_between("Z", "A", "Z"));
+        assert!(!is_between("a", "A", "Z"));
+        assert!(is_between("A", "", "B"));
+        assert!(is_between("z", "y", ""));
+        assert!(is_between("H", "", ""));
+        assert!(!is_between("", "", ""));
+
+        for (before, after) in [("", ""), ("A", ""), ("", "A"), ("A", "B"), ("Az", "B")] {
+            assert!(is_between(
+                &generate_between(before, after).unwrap(),
+                before,
+                after
+            ));
+        }
+    }
+
+    #[test]
+    fn test_generate_random_between_is_strictly_between_and_unique() {
+        let mut state: u64 = 42;
+        let mut random = |n: u64| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 11) % n
+        };
+
+        for (before, after) in [
+            ("", ""),
+            ("H", ""),
+            ("", "H"),
+            ("A", "B"),
+            ("Az", "B"),
+            ("Azzz", "B"),
+        ] {
+            let mut seen = std::collections::HashSet::new();
+            for _ in 0..3000 {
+                let pos = generate_random_between(before, after, &mut random).unwrap();
+                assert!(
+                    is_between(&pos, before, after),
+                    "{} not in ({}, {})",
+                    pos,
+                    before,
+                    after
+                );
+                assert!(!pos.ends_with(START_CHAR));
+                assert!(is_valid_base62(&pos));
+                seen.insert(pos);
+            }
             assert!(
-                positions[i] < positions[i + 1],
-                "Position {} ({}) should be < position {} ({})",
-                i,
-                positions[i],
-                i + 1,
-                positions[i + 1]
+                seen.len() > 2950,
+                "too many collisions between {} and {}",
+                before,
+                after
             );
         }
     }
 
     #[test]
-    fn test_fraction_to_position() {
-        let pos_0 = fraction_to_position(0.0);
-        let pos_half = fraction_to_position(0.5);
-        let pos_1 = fraction_to_position(0.999);
+    fn test_generate_random_between_without_room() {
+        let random = |_: u64| 0;
+        assert_eq!(generate_random_between("B", "A", random), None);
+        assert_eq!(generate_random_between("A", "A", random), None);
+        assert_eq!(generate_random_between("A", "A00", random), None);
+    }
 
-        assert!(pos_0 < pos_half);
-        assert!(pos_half < pos_1);
+    #[test]
+    fn test_shortest_between_examples() {
+        assert_eq!(shortest_between("A", "C").as_deref(), Some("B"));
+        assert_eq!(shortest_between("A", "B").as_deref(), Some("AU"));
+        // generate_between gives "AzH" here, which is as short
+        assert_eq!(shortest_between("Az", "B").as_deref(), Some("AzU"));
+        assert_eq!(shortest_between("", "").as_deref(), Some("U"));
+        assert_eq!(shortest_between("z", "").as_deref(), Some("zU"));
+        assert_eq!(shortest_between("", "1").as_deref(), Some("0"));
+        assert_eq!(shortest_between("H", "H01").as_deref(), Some("H0"));
+        assert_eq!(shortest_between("H", "H0"), None);
+        assert_eq!(shortest_between("", "0"), None);
+        assert_eq!(shortest_between("B", "A"), None);
+        assert_eq!(shortest_between("A", "A"), None);
+    }
+
+    #[test]
+    fn test_proportional_position_keeps_gap_ratios() {
+        // Long positions at fractions 0.1, 0.2, 0.5 and 0.6
+        let values: Vec<String> = [0.1, 0.2, 0.5, 0.6]
+            .iter()
+            .map(|&f| {
+                let mut p = fraction_to_position(f);
+                p.push_str("Xq3z");
+                p
+            })
+            .collect();
+
+        let shortened: Vec<String> = (0..values.len())
+            .map(|i| {
+                proportional_position(
+                    i.checked_sub(1).map(|j| values[j].as_str()),
+                    &values[i],
+                    values.get(i + 1).map(String::as_str),
+                )
+                .unwrap()
+            })
+            .collect();
+
+        assert!(shortened.windows(2).all(|w| w[0] < w[1]), "{:?}", shortened);
+        let longest = |v: &[String]| v.iter().map(String::len).max().unwrap();
+        assert!(longest(&shortened) < longest(&values), "{:?}", shortened);
+
+        let gaps = |v: &[String]| -> Vec<f64> {
+            v.windows(2)
+                .map(|w| position_to_fraction(&w[1]) - position_to_fraction(&w[0]))
+                .collect()
+        };
+        let (before, after) = (gaps(&values), gaps(&shortened));
+        for k in 1..before.len() {
+            let ratio_before = before[k] / before[k - 1];
+            let ratio_after = after[k] / after[k - 1];
+            assert!(
+                (ratio_after / ratio_before - 1.0).abs() < 0.2,
+                "gap ratio {} became {} ({:?})",
+                ratio_before,
+                ratio_after,
+                shortened
+            );
+        }
+    }
+
+    #[test]
+    fn test_proportional_position_tight_neighbours() {
+        assert_eq!(
+            proportional_position(Some("HzzzA"), "HzzzB", Some("HzzzC")).as_deref(),
+            Some("HzzzB")
+        );
+        assert_eq!(proportional_position(None, "H", None).as_deref(), Some("H"));
+        assert_eq!(proportional_position(Some("H"), "H", Some("H")), None);
+    }
+
+    /// Smallest string of exactly `len` characters that sorts after `before`
+    fn smallest_of_length_after(before: &str, len: usize) -> Option<String> {
+        let mut chars: Vec<char> = before
+            .chars()
+            .chain(std::iter::repeat(START_CHAR))
+            .take(len)
+            .collect();
+        if chars.iter().collect::<String>().as_str() > before {
+            return Some(chars.into_iter().collect());
+        }
+        for i in (0..len).rev() {
+            if chars[i] != END_CHAR {
+                chars[i] = index_to_char(char_to_index(chars[i]).unwrap() + 1).unwrap();
+                return Some(chars.into_iter().collect());
+            }
+            chars[i] = START_CHAR;
+        }
+        None
+    }
+
+    #[test]
+    fn test_shortest_between_is_minimal() {
+        let mut state: u64 = 7;
+        let mut random = |n: u64| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 11) % n
+        };
+        let random_rank = |random: &mut dyn FnMut(u64) -> u64| -> String {
+            let len = 1 + random(6) as usize;
+            (0..len)
+                .map(|_| {
+                    // Favour the extreme characters, where the edge cases are
+                    match random(4) {
+                        0 => START_CHAR,
+                        1 => END_CHAR,
+                        _ => index_to_char(random(BASE as u64) as usize).unwrap(),
+                    }
+                })
+                .collect()
+        };
+
+        let short: Vec<String> = BASE62_CHARS
+            .iter()
+            .map(|&c| (c as char).to_string())
+            .chain(BASE62_CHARS.iter().flat_map(|&x| {
+                BASE62_CHARS
+                    .iter()
+                    .map(move |&y| format!("{}{}", x as char, y as char))
+            }))
+            .collect();
+
+        for _ in 0..2000 {
+            let x = random_rank(&mut random);
+            let y = if random(2) == 0 {
+                random_rank(&mut random)
+            } else {
+                // Close pairs force long results
+                let keep = random(x.len() as u64 + 1) as usize;
+                format!("{}{}", &x[..keep], random_rank(&mut random))
+            };
+            let (before, after) = if x < y { (x, y) } else { (y, x) };
+
+            let Some(result) = shortest_between(&before, &after) else {
+                assert!(
+                    after
+                        .strip_prefix(before.as_str())
+                        .is_some_and(|rest| rest.chars().all(|c| c == START_CHAR)),
+                    "no result between {} and {}",
+                    before,
+                    after
+                );
+                continue;
+            };
+            assert!(
+                is_between(&result, &before, &after),
+                "{} not in ({}, {})",
+                result,
+                before,
+                after
+            );
+
+            for len in 1..result.len() {
+                if let Some(candidate) = smallest_of_length_after(&before, len) {
+                    assert!(
+                        candidate >= after,
+                        "{} is shorter than {} and lies in ({}, {})",
+                        candidate,
+                        result,
+                        before,
+                        after
+                    );
+                }
+            }
+            if result.len() > 2 {
+                assert!(!short.iter().any(|s| is_between(s, &before, &after)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_free_between_skips_taken_positions() {
+        let first = generate_between("A", "B").unwrap();
+        let second = generate_between("A", &first).unwrap();
+        let taken = [first.clone(), second.clone()];
+
+        let free = find_free_between("A", "B", 8, |c| taken.iter().any(|t| t == c)).unwrap();
+        assert!(!taken.contains(&free));
+        assert!(free.as_str() > "A" && free < second);
+
+        assert_eq!(find_free_between("A", "B", 1, |_| false), Some(first));
+        assert_eq!(find_free_between("A", "B", 8, |_| true), None);
+    }
+
+    #[test]
+    fn test_generate_n_between_open_end_after_max() {
+        let block = generate_n_between("Hz", "", 100);
+        assert_eq!(block.len(), 100);
+        assert!(block[0].as_str() > "Hz");
+        assert!(block.windows(2).all(|w| w[0] < w[1]));
+
+        let next_block = generate_n_between(block.last().unwrap(), "", 100);
+        assert!(next_block[0] > *block.last().unwrap());
+
+        let tight = generate_n_between("Hz", "I", 20);
+        assert!(tight.iter().all(|p| p.as_str() > "Hz" && p.as_str() < "I"));
+        assert!(tight.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_reposition_duplicates() {
+        let values = ["A", "B", "B", "B", "C", "C"];
+        let replacements = reposition_duplicates(&values);
+        assert_eq!(
+            replacements.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            vec![2, 3, 5]
+        );
+        let mut result: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        for (i, position) in replacements {
+            result[i] = position;
+        }
+        assert!(result.windows(2).all(|w| w[0] < w[1]), "{:?}", result);
+        assert!(reposition_duplicates(&["A", "B"]).is_empty());
+    }
+
+    #[test]
+    fn test_reposition_runs_keeps_unselected() {
+        let values = ["A", "A1", "A2", "M", "Mz", "N", "z"];
+        let selected = [true, true, false, true, true, false, true];
+        let replacements = reposition_runs(&values, "", "", |i| selected[i]);
+        assert_eq!(
+            replacements.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            vec![0, 1, 3, 4, 6]
+        );
+
+        let mut result: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        for (i, position) in replacements {
+            result[i] = position;
+        }
+        assert_eq!(result[2], "A2");
+        assert_eq!(result[5], "N");
+        assert!(result.windows(2).all(|w| w[0] < w[1]), "{:?}", result);
+
+        assert!(reposition_runs(&values, "", "", |_| false).is_empty());
+
+        // Runs at the ends spread towards the edges instead of hugging their neighbour
+        let edges = reposition_runs(&["A", "B", "C", "V"], "0", "z", |i| i < 3);
+        assert!(
+            edges
+                .iter()
+                .all(|(_, p)| p.as_str() > "0" && p.as_str() < "V")
+        );
+        assert!(edges[0].1.as_str() < "A", "{:?}", edges);
+        let all = reposition_runs(&["x", "y"], "0", "z", |_| true);
+        assert!(
+            all.iter()
+                .all(|(_, p)| p.as_str() > "0" && p.as_str() < "z")
+        );
+    }
+
+    #[test]
+    fn test_reposition_invalid() {
+        assert!(reposition_invalid(&["A", "B", "C"]).is_empty());
+
+        let values = ["A", "A-1", "A_2", "B", "c!"];
+        let replacements = reposition_invalid(&values);
+        assert_eq!(
+            replacements.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            vec![1, 2, 4]
+        );
+
+        let mut repaired: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        for (i, position) in replacements {
+            repaired[i] = position;
+        }
+        assert!(repaired.iter().all(|p| is_valid_base62(p)));
+        assert!(repaired.windows(2).all(|w| w[0] < w[1]));
     }
 }
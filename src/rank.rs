@@ -0,0 +1,250 @@
+//! A validated position type that works without PostgreSQL.
+//!
+//! `Rank` holds the same Base62 positions as the `lexo` SQL type, but every
+//! operation that can fail returns a [`RankError`] instead of raising a
+//! PostgreSQL error. It is available with the `pg` feature disabled, which lets
+//! a Rust service compute positions before writing them to the database.
+//!
+//! ```
+//! use pg_lexo::rank::Rank;
+//!
+//! let first = Rank::first();
+//! let second = first.after();
+//! let middle = Rank::between(Some(&first), Some(&second)).unwrap();
+//! assert!(first < middle && middle < second);
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::operations::{
+    MID_CHAR, find_invalid_char, generate_after, generate_balanced_positions, generate_before,
+    generate_between,
+};
+
+/// Why a position could not be parsed or generated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RankError {
+    /// The position is the empty string.
+    Empty,
+    /// The position contains a character outside the Base62 alphabet.
+    InvalidCharacter { offset: usize, character: char },
+    /// There is no position before the minimum (all '0' characters).
+    NoPositionBefore(String),
+    /// The lower bound is not strictly below the upper bound.
+    OutOfOrder { before: String, after: String },
+    /// Nothing sorts strictly between the bounds, as with "H" and "H0".
+    NoPositionBetween { before: String, after: String },
+}
+
+impl fmt::Display for RankError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RankError::Empty => write!(f, "position is empty"),
+            RankError::InvalidCharacter { offset, character } => write!(
+                f,
+                "invalid character {:?} at byte offset {}: positions may only contain Base62 characters (0-9, A-Z, a-z)",
+                character, offset
+            ),
+            RankError::NoPositionBefore(rank) => write!(
+                f,
+                "cannot generate a position before '{}': this is the minimum possible position",
+                rank
+            ),
+            RankError::OutOfOrder { before, after } => write!(
+                f,
+                "'{}' must sort before '{}' to generate a position between them",
+                before, after
+            ),
+            RankError::NoPositionBetween { before, after } => write!(
+                f,
+                "cannot generate a position between '{}' and '{}': no position sorts between them",
+                before, after
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RankError {}
+
+/// Checks that `s` is a non-empty Base62 position.
+pub fn validate(s: &str) -> Result<(), RankError> {
+    if s.is_empty() {
+        return Err(RankError::Empty);
+    }
+    match find_invalid_char(s) {
+        Some((offset, character)) => Err(RankError::InvalidCharacter { offset, character }),
+        None => Ok(()),
+    }
+}
+
+/// A non-empty, valid Base62 position.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rank(String);
+
+impl Rank {
+    /// Returns the first position for a new list ('H').
+    pub fn first() -> Self {
+        Rank(MID_CHAR.to_string())
+    }
+
+    /// Parses a position, checking that it is non-empty and valid Base62.
+    pub fn parse(s: &str) -> Result<Self, RankError> {
+        validate(s)?;
+        Ok(Rank(s.to_string()))
+    }
+
+    /// Returns the position as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Consumes the rank and returns the position string.
+    pub fn into_string(self) -> String {
+        self.0
+    }
+
+    /// Returns a position after this one.
+    pub fn after(&self) -> Self {
+        Rank(generate_after(&self.0))
+    }
+
+    /// Returns a position before this one, unless this is the minimum.
+    pub fn before(&self) -> Result<Self, RankError> {
+        generate_before(&self.0)
+            .map(Rank)
+            .ok_or_else(|| RankError::NoPositionBefore(self.0.clone()))
+    }
+
+    /// Returns a position between two bounds; `None` leaves that side open.
+    pub fn between(before: Option<&Rank>, after: Option<&Rank>) -> Result<Self, RankError> {
+        match (before, after) {
+            (None, None) => Ok(Rank::first()),
+            (Some(before), None) => Ok(before.after()),
+            (None, Some(after)) => after.before(),
+            (Some(before), Some(after)) => {
+                if before >= after {
+                    return Err(RankError::OutOfOrder {
+                        before: before.0.clone(),
+                        after: after.0.clone(),
+                    });
+                }
+                generate_between(&before.0, &after.0)
+                    .map(Rank)
+                    .ok_or_else(|| RankError::NoPositionBetween {
+                        before: before.0.clone(),
+                        after: after.0.clone(),
+                    })
+            }
+        }
+    }
+
+    /// Returns `count` evenly distributed positions, as used by rebalancing.
+    pub fn balanced(count: usize) -> Vec<Self> {
+        generate_balanced_positions(count)
+            .into_iter()
+            .map(Rank)
+            .collect()
+    }
+}
+
+impl Default for Rank {
+    fn default() -> Self {
+        Self::first()
+    }
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for Rank {
+    type Err = RankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Rank::parse(s)
+    }
+}
+
+impl TryFrom<String> for Rank {
+    type Error = RankError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        validate(&value)?;
+        Ok(Rank(value))
+    }
+}
+
+impl From<Rank> for String {
+    fn from(rank: Rank) -> Self {
+        rank.0
+    }
+}
+
+impl AsRef<str> for Rank {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Rank::parse("H").unwrap().as_str(), "H");
+        assert_eq!(Rank::parse(""), Err(RankError::Empty));
+        assert_eq!(
+            Rank::parse("H!"),
+            Err(RankError::InvalidCharacter {
+                offset: 1,
+                character: '!'
+            })
+        );
+        assert_eq!(
+            "Hé".parse::<Rank>(),
+            Err(RankError::InvalidCharacter {
+                offset: 1,
+                character: 'é'
+            })
+        );
+    }
+
+    #[test]
+    fn test_before_minimum() {
+        let min = Rank::parse("00").unwrap();
+        assert_eq!(min.before(), Err(RankError::NoPositionBefore("00".into())));
+        assert!(Rank::between(None, Some(&min)).is_err());
+    }
+
+    #[test]
+    fn test_between() {
+        let a = Rank::parse("A").unwrap();
+        let b = Rank::parse("B").unwrap();
+        let mid = Rank::between(Some(&a), Some(&b)).unwrap();
+        assert!(a < mid && mid < b);
+        assert_eq!(Rank::between(None, None).unwrap(), Rank::first());
+        assert!(matches!(
+            Rank::between(Some(&b), Some(&a)),
+            Err(RankError::OutOfOrder { .. })
+        ));
+        assert!(Rank::between(Some(&a), Some(&a)).is_err());
+        assert!(matches!(
+            Rank::between(
+                Some(&Rank::parse("H").unwrap()),
+                Some(&Rank::parse("H0").unwrap())
+            ),
+            Err(RankError::NoPositionBetween { .. })
+        ));
+    }
+
+    #[test]
+    fn test_balanced() {
+        let ranks = Rank::balanced(10);
+        assert_eq!(ranks.len(), 10);
+        assert!(ranks.windows(2).all(|w| w[0] < w[1]));
+    }
+}
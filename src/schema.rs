@@ -5,12 +5,31 @@
 
 use pgrx::prelude::*;
 use pgrx::spi::{Spi, quote_identifier, quote_literal};
+use serde_json::{Value, json};
+use std::cell::Cell;
+use std::ffi::{CStr, CString, c_int};
 
 use crate::Lexo;
+use crate::LexoText;
+use crate::SmallLexo;
+use crate::next_cache;
 use crate::operations::{
-    MID_CHAR, generate_after, generate_balanced_positions, generate_before,
-    generate_between as gen_between, is_valid_base62,
+    ALGORITHM_VERSION, BASE, BASE62_CHARS, DESC_MID_CHAR, DistinctCounter, EMPTY_POSITION,
+    END_CHAR, InsertPattern, MAX_SIMULATION_STEPS, MAX_TYPMOD_LENGTH, MID_CHAR, START_CHAR,
+    StepError, TranscodeError, abbreviated_key, advance, append_capacity, append_rank,
+    balanced_position, base62_to_decimal, between_capacity, bound_position, cbor_text_bytes,
+    common_prefix, decimal_to_base62, decode_cursor, encode_cursor, explain, find_free_between,
+    fits_typmod, generate_after, generate_after_gap, generate_balanced_positions,
+    generate_balanced_positions_of_length, generate_before, generate_before_gap,
+    generate_between_avoiding, generate_between_step, generate_between_tagged, generate_n_between,
+    generate_random_between, generate_seeded_between, is_between, is_valid_base62,
+    is_valid_writer_tag, midpoints, parse_length_typmod, percentile_index, plan_for_size,
+    planned_position, position_to_fraction, prefix_end, prepend_capacity, proportional_position,
+    rebalance_positions, rebalance_positions_missing_last, reposition_duplicates,
+    reposition_invalid, reposition_runs, required_position, retreat, sequence_counter_after,
+    sequence_position, shortest_between, simulate_insertions, transcode,
 };
+use crate::stats::{self, Operation};
 
 /// Returns the first position for a new ordered list.
 ///
@@ -22,11 +41,29 @@ use crate::operations::{
 /// SELECT lexo_first();  -- Returns 'H'
 /// INSERT INTO items (position) VALUES (lexo_first());
 /// ```
-#[pg_extern]
+#[pg_extern(immutable, parallel_safe)]
 pub fn lexo_first() -> Lexo {
     Lexo::first()
 }
 
+/// Returns the first position for a new descending list.
+///
+/// Lists displayed with `ORDER BY position DESC` grow downwards, so they start
+/// high and leave most of the keyspace below the first position. Add to such
+/// a list with `lexo_next_desc()`.
+///
+/// # Returns
+/// The initial descending Lexo position ('i')
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_first_desc();  -- Returns 'i'
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_first_desc() -> Lexo {
+    Lexo::new(DESC_MID_CHAR.to_string())
+}
+
 /// Returns a position after the given position.
 ///
 /// # Arguments
@@ -40,9 +77,12 @@ pub fn lexo_first() -> Lexo {
 /// SELECT lexo_after('H');  -- Returns a position after 'H'
 /// SELECT lexo_after(lexo_first());
 /// ```
-#[pg_extern]
+#[pg_extern(immutable, parallel_safe)]
 pub fn lexo_after(current: Lexo) -> Lexo {
-    let result = generate_after(current.as_str());
+    let result = generate_after(position_str(&current));
+    if crate::gucs::DEBUG.get() {
+        pgrx::log!("lexo_after('{}') -> '{}'", current.as_str(), result);
+    }
     Lexo::new(result)
 }
 
@@ -59,12 +99,198 @@ pub fn lexo_after(current: Lexo) -> Lexo {
 /// SELECT lexo_before('H');  -- Returns a position before 'H'
 /// SELECT lexo_before(lexo_first());
 /// ```
-#[pg_extern]
+#[pg_extern(immutable, parallel_safe)]
 pub fn lexo_before(current: Lexo) -> Lexo {
-    let result = generate_before(current.as_str());
+    let result = generate_before(position_str(&current))
+        .unwrap_or_else(|| report_no_position_before(current.as_str()));
+    if crate::gucs::DEBUG.get() {
+        pgrx::log!("lexo_before('{}') -> '{}'", current.as_str(), result);
+    }
+    Lexo::new(result)
+}
+
+/// Returns a position after the given position, or the first position when it is NULL.
+///
+/// `lexo_after()` is strict and returns NULL for a NULL argument. This variant
+/// treats NULL as an empty list instead, so appending after
+/// `(SELECT max(position) ...)` works whether or not the list has rows yet.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_after_or_first(NULL);  -- Returns 'H'
+/// INSERT INTO items (position)
+/// SELECT lexo_after_or_first(max(position)) FROM items WHERE list_id = 7;
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_after_or_first(current: Option<Lexo>) -> Lexo {
+    current.map(lexo_after).unwrap_or_else(Lexo::first)
+}
+
+/// Returns a position before the given position, or the first position when it is NULL.
+///
+/// The counterpart of `lexo_after_or_first()` for prepending, e.g. before
+/// `(SELECT min(position) ...)`.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_before_or_first(NULL);  -- Returns 'H'
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_before_or_first(current: Option<Lexo>) -> Lexo {
+    current.map(lexo_before).unwrap_or_else(Lexo::first)
+}
+
+/// Returns a position after the given position, leaving room for later inserts.
+///
+/// The result is `gap` single steps after `current`, so `gap - 1` positions of
+/// the same length stay free between them. Use it when appending an item that
+/// others will soon be inserted after, such as a section header. A `gap` of 1
+/// is the same as `lexo_after(current)`.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_after('H', 5);   -- Returns 'M'
+/// SELECT lexo_after('y', 5);   -- Returns 'y5'
+/// ```
+#[pg_extern(immutable, parallel_safe, name = "lexo_after")]
+pub fn lexo_after_gap(current: Lexo, gap: i32) -> Lexo {
+    let result = generate_after_gap(position_str(&current), check_gap(gap));
+    Lexo::new(result)
+}
+
+/// Returns a position before the given position, leaving room for later inserts.
+///
+/// The counterpart of `lexo_after(current, gap)`. A `gap` of 1 is the same as
+/// `lexo_before(current)`.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_before('H', 5);  -- Returns 'C'
+/// SELECT lexo_before('1', 5);  -- Returns '0v'
+/// ```
+#[pg_extern(immutable, parallel_safe, name = "lexo_before")]
+pub fn lexo_before_gap(current: Lexo, gap: i32) -> Lexo {
+    let result = generate_before_gap(position_str(&current), check_gap(gap))
+        .unwrap_or_else(|| report_no_position_before(current.as_str()));
+    Lexo::new(result)
+}
+
+/// Returns the position `steps` calls of `lexo_after()` after `rank`.
+///
+/// The result is computed directly rather than by generating the positions in
+/// between, so it is cheap for any step count. It is exactly what repeated
+/// `lexo_after()` calls would return, including their growth: once every
+/// character is 'z', each further 62 steps add a character. `steps` of 0
+/// returns `rank`.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_advance('H', 5);   -- Returns 'M'
+/// SELECT lexo_advance('Hz', 1);  -- Returns 'I', like lexo_after('Hz')
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_advance(rank: Lexo, steps: i64) -> Lexo {
+    let result = advance(position_str(&rank), check_steps(steps))
+        .unwrap_or_else(|error| report_step_error(error, "advancing", &rank, steps));
+    Lexo::new(result)
+}
+
+/// Returns the position `steps` calls of `lexo_before()` before `rank`.
+///
+/// The mirror image of `lexo_advance()`. Only a position made only of '0'
+/// characters has nothing before it; retreating from one raises an error.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_retreat('H', 5);  -- Returns 'C'
+/// SELECT lexo_retreat('1', 1);  -- Returns '0z', like lexo_before('1')
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_retreat(rank: Lexo, steps: i64) -> Lexo {
+    let result = retreat(position_str(&rank), check_steps(steps))
+        .unwrap_or_else(|error| report_step_error(error, "retreating", &rank, steps));
     Lexo::new(result)
 }
 
+fn check_steps(steps: i64) -> u64 {
+    if steps < 0 {
+        pgrx::error!("steps must not be negative, got {}", steps);
+    }
+    steps as u64
+}
+
+fn report_step_error(error: StepError, verb: &str, rank: &Lexo, steps: i64) -> ! {
+    let (code, message, hint) = match error {
+        StepError::Minimum => report_no_position_before(rank.as_str()),
+        StepError::TooLong => (
+            PgSqlErrorCode::ERRCODE_PROGRAM_LIMIT_EXCEEDED,
+            format!(
+                "{} '{}' by {} steps gives a position longer than {} characters",
+                verb, rank, steps, MAX_TYPMOD_LENGTH
+            ),
+            "Run lexo_rebalance() on the list, or leave room with lexo_after(rank, gap).",
+        ),
+    };
+    raise_error!(code, message, hint = hint)
+}
+
+/// Raises `invalid_parameter_value` for a request to go below `rank`, a
+/// position made only of '0's.
+fn report_no_position_before(rank: &str) -> ! {
+    raise_error!(
+        PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+        format!(
+            "cannot generate a position before '{}': this is the minimum possible position",
+            rank
+        ),
+        hint = "Run lexo_rebalance() on the list to make room before its first row.",
+    )
+}
+
+/// Raises `invalid_parameter_value` when nothing fits between `before` and
+/// `after`, either below an all-'0' `after` or because `after` is `before`
+/// followed only by '0's.
+fn report_no_position_between(before: &str, after: &str) -> ! {
+    if before.is_empty() {
+        report_no_position_before(after)
+    }
+    raise_error!(
+        PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+        format!(
+            "cannot generate a position between '{}' and '{}': no position sorts between them",
+            before, after
+        ),
+        hint = "Run lexo_rebalance() on the list to make room between the two rows.",
+    )
+}
+
+/// Returns an optional bound in the form the generators take, raising
+/// `invalid_parameter_value` for an empty position: only NULL opens a bound.
+fn bound_str(position: &Option<Lexo>) -> &str {
+    bound_position(position.as_ref().map(Lexo::as_str))
+        .unwrap_or_else(|message| report_empty(&message))
+}
+
+/// Returns a required position, raising `invalid_parameter_value` if it is empty.
+fn position_str(position: &Lexo) -> &str {
+    required_position(position.as_str()).unwrap_or_else(|message| report_empty(&message))
+}
+
+fn report_empty(message: &str) -> ! {
+    raise_error!(
+        PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+        message,
+        hint = "Use NULL for an open bound, or lexo_after_or_first(NULL) for an empty list.",
+    )
+}
+
+fn check_gap(gap: i32) -> usize {
+    if gap < 1 {
+        pgrx::error!("gap must be at least 1, got {}", gap);
+    }
+    gap as usize
+}
+
 /// Returns a position between two existing positions.
 ///
 /// # Arguments
@@ -81,224 +307,11757 @@ pub fn lexo_before(current: Lexo) -> Lexo {
 /// SELECT lexo_between(NULL, 'H');        -- Returns position before 'H'
 /// SELECT lexo_between('A', 'Z');         -- Returns midpoint between 'A' and 'Z'
 /// ```
-#[pg_extern]
+#[pg_extern(immutable, parallel_safe)]
 pub fn lexo_between(before_pos: Option<Lexo>, after_pos: Option<Lexo>) -> Lexo {
-    let before_str = before_pos.as_ref().map(|r| r.as_str()).unwrap_or("");
-    let after_str = after_pos.as_ref().map(|r| r.as_str()).unwrap_or("");
+    let before_str = bound_str(&before_pos);
+    let after_str = bound_str(&after_pos);
 
-    match (before_str.is_empty(), after_str.is_empty()) {
-        (true, true) => Lexo::first(),
-        (false, true) => Lexo::new(generate_after(before_str)),
-        (true, false) => Lexo::new(generate_before(after_str)),
-        (false, false) => Lexo::new(gen_between(before_str, after_str)),
+    let (result, step) = generate_between_step(before_str, after_str)
+        .unwrap_or_else(|| report_no_position_between(before_str, after_str));
+    if crate::gucs::DEBUG.get() {
+        pgrx::log!(
+            "lexo_between('{}', '{}'): {} -> '{}'",
+            before_str,
+            after_str,
+            step,
+            result
+        );
     }
+    Lexo::new(result)
 }
 
-/// Returns the next position after the maximum in a table column.
+/// Returns a position after the given text position.
 ///
-/// This function queries the specified table to find the maximum position value
-/// in the given column, then returns a position that comes after it.
+/// Overload of `lexo_after(lexo)` for text columns and expressions, which are
+/// validated like a cast to `lexo`. Untyped literals resolve to this overload.
 ///
-/// # Arguments
-/// * `table_name` - The name of the table (can be schema-qualified)
-/// * `lexo_column_name` - The name of the column containing position values
-/// * `identifier_column_name` - Optional: column to filter by (e.g., 'collection_id')
-/// * `identifier_value` - Optional: value to filter by
+/// # Example
+/// ```sql
+/// SELECT lexo_after(sort_key) FROM legacy_items;  -- sort_key is text
+/// ```
+#[pg_extern(immutable, parallel_safe, name = "lexo_after")]
+pub fn lexo_after_from_text(current: &str) -> Lexo {
+    lexo_after(Lexo::from(current))
+}
+
+/// Returns a position before the given text position.
 ///
-/// # Returns
-/// A new Lexo after the maximum, or 'H' if table is empty
+/// Overload of `lexo_before(lexo)` for text columns and expressions.
 ///
 /// # Example
 /// ```sql
-/// -- Get next position for entire table
-/// SELECT lexo_next('items', 'position', NULL, NULL);
-///
-/// -- Get next position for a specific collection
-/// SELECT lexo_next('collection_songs', 'position', 'collection_id', 'abc-123');
+/// SELECT lexo_before(sort_key) FROM legacy_items;  -- sort_key is text
 /// ```
-#[pg_extern]
-pub fn lexo_next(
-    table_name: &str,
-    lexo_column_name: &str,
-    identifier_column_name: Option<&str>,
-    identifier_value: Option<&str>,
-) -> Lexo {
-    let quoted_lexo_column = quote_identifier(lexo_column_name);
+#[pg_extern(immutable, parallel_safe, name = "lexo_before")]
+pub fn lexo_before_from_text(current: &str) -> Lexo {
+    lexo_before(Lexo::from(current))
+}
 
-    let quoted_table = if let Some((schema, table)) = table_name.split_once('.') {
-        format!("{}.{}", quote_identifier(schema), quote_identifier(table))
-    } else {
-        quote_identifier(table_name)
-    };
+/// Returns a position between two text positions.
+///
+/// Overload of `lexo_between(lexo, lexo)` for text arguments. Either bound can
+/// be NULL.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_between('A', 'Z');  -- Returns 'M'
+/// ```
+#[pg_extern(immutable, parallel_safe, name = "lexo_between")]
+pub fn lexo_between_from_text(before_pos: Option<&str>, after_pos: Option<&str>) -> Lexo {
+    lexo_between(before_pos.map(Lexo::from), after_pos.map(Lexo::from))
+}
 
-    let query = match (identifier_column_name, identifier_value) {
-        (Some(id_col), Some(id_val)) => {
-            let quoted_id_column = quote_identifier(id_col);
-            let quoted_id_value = quote_literal(id_val);
-            format!(
-                "SELECT MAX({})::text FROM {} WHERE {} = {}",
-                quoted_lexo_column, quoted_table, quoted_id_column, quoted_id_value
-            )
-        }
-        _ => {
-            format!(
-                "SELECT MAX({})::text FROM {}",
-                quoted_lexo_column, quoted_table
-            )
-        }
-    };
+/// Returns a position between a text position and a `lexo` position.
+///
+/// Overload of `lexo_between(lexo, lexo)` for mixing a text column with a
+/// `lexo` one. Either bound can be NULL.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_between(imported.sort_key, items.position) FROM ...;
+/// ```
+#[pg_extern(immutable, parallel_safe, name = "lexo_between")]
+pub fn lexo_between_text_lexo(before_pos: Option<&str>, after_pos: Option<Lexo>) -> Lexo {
+    lexo_between(before_pos.map(Lexo::from), after_pos)
+}
 
-    let max_position: Option<String> =
-        Spi::get_one(&query).expect("Failed to query table for maximum position");
+/// Returns a position between a `lexo` position and a text position.
+///
+/// The mirror image of `lexo_between(text, lexo)`.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_between(items.position, imported.sort_key) FROM ...;
+/// ```
+#[pg_extern(immutable, parallel_safe, name = "lexo_between")]
+pub fn lexo_between_lexo_text(before_pos: Option<Lexo>, after_pos: Option<&str>) -> Lexo {
+    lexo_between(before_pos, after_pos.map(Lexo::from))
+}
 
-    match max_position {
-        Some(pos) => Lexo::new(generate_after(&pos)),
-        None => Lexo::first(),
-    }
+/// Returns a `smalllexo` position after the given one.
+///
+/// Overload of `lexo_after(lexo)` for `smalllexo` columns. Raises an error
+/// when the result would not fit in a `smalllexo`.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_after('H'::smalllexo);  -- Returns 'I'
+/// ```
+#[pg_extern(immutable, parallel_safe, name = "lexo_after", requires = ["smalllexo_type"])]
+pub fn lexo_after_small(current: SmallLexo) -> SmallLexo {
+    SmallLexo::from_position(lexo_after(Lexo::new(current.to_position())).as_str())
 }
 
-/// Adds a lexo position column to an existing table.
+/// Returns a `smalllexo` position before the given one.
 ///
-/// The column will be of type `lexo` to ensure proper
-/// lexicographic ordering with the custom type.
+/// Overload of `lexo_before(lexo)` for `smalllexo` columns. Raises an error
+/// when the result would not fit in a `smalllexo`.
 ///
-/// # Arguments
-/// * `table_name` - The name of the table (can be schema-qualified)
-/// * `column_name` - The name of the new column to add
+/// # Example
+/// ```sql
+/// SELECT lexo_before('H'::smalllexo);  -- Returns 'G'
+/// ```
+#[pg_extern(immutable, parallel_safe, name = "lexo_before", requires = ["smalllexo_type"])]
+pub fn lexo_before_small(current: SmallLexo) -> SmallLexo {
+    SmallLexo::from_position(lexo_before(Lexo::new(current.to_position())).as_str())
+}
+
+/// Returns a `smalllexo` position between two others.
+///
+/// Overload of `lexo_between(lexo, lexo)` for `smalllexo` columns. Either
+/// bound can be NULL. Raises an error when the result would not fit in a
+/// `smalllexo`.
 ///
 /// # Example
 /// ```sql
-/// -- Add a 'position' column to 'items' table
-/// SELECT lexo_add_column('items', 'position');
+/// SELECT lexo_between('A'::smalllexo, 'B'::smalllexo);  -- Returns 'AH'
+/// ```
+#[pg_extern(immutable, parallel_safe, name = "lexo_between", requires = ["smalllexo_type"])]
+pub fn lexo_between_small(
+    before_pos: Option<SmallLexo>,
+    after_pos: Option<SmallLexo>,
+) -> SmallLexo {
+    let widen = |rank: SmallLexo| Lexo::new(rank.to_position());
+    SmallLexo::from_position(lexo_between(before_pos.map(widen), after_pos.map(widen)).as_str())
+}
+
+/// Returns a position between two positions, tagged with this writer's id.
 ///
-/// -- The column is created as:
-/// -- ALTER TABLE items ADD COLUMN position lexo;
+/// For lists written from several places at once, e.g. regions replicating
+/// into each other. The result is the position strictly between the bounds
+/// that depends only on the bounds, followed by `lexo.writer_id`. Writers
+/// with different ids filling the same gap therefore never produce the same
+/// position, and their positions sort by writer id. Each position costs one
+/// extra character. Either bound can be NULL.
+///
+/// # Example
+/// ```sql
+/// SET lexo.writer_id = 'e';
+/// SELECT lexo_between_tagged('A', 'Z');  -- Returns 'Me'
 /// ```
-#[pg_extern]
-pub fn lexo_add_column(table_name: &str, column_name: &str) {
-    let quoted_table = if let Some((schema, table)) = table_name.split_once('.') {
-        format!("{}.{}", quote_identifier(schema), quote_identifier(table))
-    } else {
-        quote_identifier(table_name)
-    };
+#[pg_extern(stable, parallel_safe)]
+pub fn lexo_between_tagged(before_pos: Option<Lexo>, after_pos: Option<Lexo>) -> Lexo {
+    let tag = writer_tag();
+    let before_str = bound_str(&before_pos);
+    let after_str = bound_str(&after_pos);
 
-    let quoted_column = quote_identifier(column_name);
+    Lexo::new(
+        generate_between_tagged(before_str, after_str, tag)
+            .unwrap_or_else(|| report_no_position_between(before_str, after_str)),
+    )
+}
 
-    let query = format!(
-        "ALTER TABLE {} ADD COLUMN {} lexo",
-        quoted_table, quoted_column
-    );
+/// Reads and checks `lexo.writer_id`.
+fn writer_tag() -> char {
+    let setting = writer_id_setting();
+    if setting.is_empty() {
+        raise_error!(
+            PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            "lexo.writer_id is not set",
+            hint = "Give every writer its own id, e.g. SET lexo.writer_id = 'a'.",
+        )
+    }
+    parse_writer_id(&setting).unwrap_or_else(|| {
+        pgrx::error!(
+            "invalid lexo.writer_id '{}': must be a single Base62 character other than '0'",
+            setting
+        )
+    })
+}
 
-    Spi::run(&query).expect("Failed to add lexo column to table");
+fn writer_id_setting() -> String {
+    crate::gucs::WRITER_ID
+        .get()
+        .map(|id| id.to_string_lossy().into_owned())
+        .unwrap_or_default()
 }
 
-/// Rebalances lexicographic position values in a table.
-///
-/// This function recalculates all position values to be evenly distributed,
-/// which is useful when positions have become too long due to many insertions
-/// or when you want to "clean up" the ordering.
+fn parse_writer_id(setting: &str) -> Option<char> {
+    let mut chars = setting.chars();
+    match (chars.next(), chars.next()) {
+        (Some(tag), None) if is_valid_writer_tag(tag) => Some(tag),
+        _ => None,
+    }
+}
+
+/// Returns a random position between two existing positions.
 ///
-/// The function preserves the current order of rows while assigning new,
-/// optimally distributed position values.
+/// Like `lexo_between()`, but picks uniformly among a large number of candidates
+/// in the gap, so concurrent inserts between the same neighbours practically
+/// never produce the same position. Tight gaps yield longer positions.
 ///
 /// # Arguments
-/// * `table_name` - The name of the table (can be schema-qualified)
-/// * `lexo_column_name` - The name of the column containing position values
-/// * `key_column_name` - Optional: column to group by (e.g., 'playlist_id')
-/// * `key_value` - Optional: value to filter by (rebalance only rows with this key)
-///
-/// # Returns
-/// The number of rows that were rebalanced
+/// * `before_pos` - The position before the new position (can be NULL for beginning)
+/// * `after_pos` - The position after the new position (can be NULL for end)
 ///
 /// # Example
 /// ```sql
-/// -- Rebalance all positions in a table
-/// SELECT lexo_rebalance('items', 'position', NULL, NULL);
-///
-/// -- Rebalance positions for a specific playlist
-/// SELECT lexo_rebalance('playlist_songs', 'position', 'playlist_id', 'abc-123');
+/// SELECT lexo_random_between('A', 'B');  -- Returns e.g. 'A7Kq'
 /// ```
-#[pg_extern]
-pub fn lexo_rebalance(
-    table_name: &str,
-    lexo_column_name: &str,
-    key_column_name: Option<&str>,
-    key_value: Option<&str>,
-) -> i64 {
-    let quoted_lexo_column = quote_identifier(lexo_column_name);
+#[pg_extern(volatile, parallel_restricted)]
+pub fn lexo_random_between(before_pos: Option<Lexo>, after_pos: Option<Lexo>) -> Lexo {
+    let before_str = bound_str(&before_pos);
+    let after_str = bound_str(&after_pos);
 
-    let quoted_table = if let Some((schema, table)) = table_name.split_once('.') {
-        format!("{}.{}", quote_identifier(schema), quote_identifier(table))
-    } else {
-        quote_identifier(table_name)
+    let random = |n: u64| unsafe {
+        pg_sys::pg_prng_uint64_range(&raw mut pg_sys::pg_global_prng_state, 0, n - 1)
     };
 
-    // Build the query to get row count
-    let count_query = match (&key_column_name, &key_value) {
-        (Some(key_col), Some(key_val)) => {
-            let quoted_key_column = quote_identifier(key_col);
-            let quoted_key_value = quote_literal(key_val);
-            format!(
-                "SELECT COUNT(*) FROM {} WHERE {} = {}",
-                quoted_table, quoted_key_column, quoted_key_value
-            )
-        }
-        _ => format!("SELECT COUNT(*) FROM {}", quoted_table),
+    let result = match generate_random_between(before_str, after_str, random) {
+        Some(position) => Lexo::new(position),
+        None => pgrx::error!(
+            "no position exists between '{}' and '{}'",
+            before_str,
+            after_str
+        ),
     };
+    stats::count_call(
+        Operation::placing(before_pos.is_some(), after_pos.is_some()),
+        result.as_str(),
+    );
+    result
+}
 
-    let count: Option<i64> = Spi::get_one(&count_query).expect("Failed to count rows in table");
-    let row_count = count.unwrap_or(0);
+/// Returns a position between two positions that depends only on its arguments.
+///
+/// Like `lexo_random_between()`, but the placement within the gap is derived
+/// from a hash of `seed`, for example an idempotency key: a retried request
+/// gets the same position as the first attempt, while different seeds are
+/// spread across the gap. Tight gaps yield longer positions.
+///
+/// # Arguments
+/// * `before_pos` - The position before the new position (can be NULL for beginning)
+/// * `after_pos` - The position after the new position (can be NULL for end)
+/// * `seed` - Any text identifying the insert
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_between_seeded('A', 'B', 'order-42');  -- Always returns 'AVwKt'
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_between_seeded(before_pos: Option<Lexo>, after_pos: Option<Lexo>, seed: &str) -> Lexo {
+    let before_str = bound_str(&before_pos);
+    let after_str = bound_str(&after_pos);
 
-    if row_count == 0 {
-        return 0;
+    match generate_seeded_between(before_str, after_str, seed) {
+        Some(position) => Lexo::new(position),
+        None => pgrx::error!(
+            "no position exists between '{}' and '{}'",
+            before_str,
+            after_str
+        ),
     }
+}
 
-    // Generate evenly distributed positions for all rows
-    let positions = generate_balanced_positions(row_count as usize);
+/// Returns the shortest position between two existing positions.
+///
+/// Unlike `lexo_between()`, which favours speed and sometimes returns a longer
+/// position than necessary, this always returns a position of the minimum
+/// possible length, choosing the one closest to the middle of the gap.
+///
+/// # Arguments
+/// * `before_pos` - The position before the new position (can be NULL for beginning)
+/// * `after_pos` - The position after the new position (can be NULL for end)
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_between('A', 'A01');           -- Returns 'A00H'
+/// SELECT lexo_shortest_between('A', 'A01');  -- Returns 'A0'
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_shortest_between(before_pos: Option<Lexo>, after_pos: Option<Lexo>) -> Lexo {
+    let before_str = bound_str(&before_pos);
+    let after_str = bound_str(&after_pos);
 
-    // Build query to get all rows ordered by current position, using ctid as text
-    let select_query = match (&key_column_name, &key_value) {
-        (Some(key_col), Some(key_val)) => {
-            let quoted_key_column = quote_identifier(key_col);
-            let quoted_key_value = quote_literal(key_val);
-            format!(
-                "SELECT ctid::text FROM {} WHERE {} = {} ORDER BY {}::text",
-                quoted_table, quoted_key_column, quoted_key_value, quoted_lexo_column
-            )
-        }
-        _ => format!(
-            "SELECT ctid::text FROM {} ORDER BY {}::text",
-            quoted_table, quoted_lexo_column
+    match shortest_between(before_str, after_str) {
+        Some(position) => Lexo::new(position),
+        None => pgrx::error!(
+            "no position exists between '{}' and '{}'",
+            before_str,
+            after_str
         ),
-    };
-
-    // Update each row with its new position
-    Spi::connect_mut(|client| {
-        let rows = client
-            .select(&select_query, None, &[])
-            .expect("Failed to select rows for rebalancing");
-
-        for (idx, row) in rows.enumerate() {
-            let ctid_str: String = row
-                .get(1)
-                .expect("Failed to get ctid")
-                .expect("ctid was NULL");
-
-            let new_position = &positions[idx];
-            let quoted_new_position = quote_literal(new_position);
+    }
+}
 
-            // Use quote_literal to safely escape the ctid string
-            let quoted_ctid = quote_literal(&ctid_str);
-            let update_query = format!(
-                "UPDATE {} SET {} = {} WHERE ctid = {}::tid",
-                quoted_table, quoted_lexo_column, quoted_new_position, quoted_ctid
+/// Returns a position between two positions that is not in a set of taken ones.
+///
+/// Meant for optimistic flows that already hold positions not yet committed:
+/// the result is strictly between the bounds and differs from every element of
+/// `taken`. Elements outside the bounds, and NULLs, are ignored. When the
+/// `lexo_between()` result is taken, the shortest free position in the gaps
+/// between taken elements is used instead.
+///
+/// # Arguments
+/// * `before_pos` - The position before the new position (can be NULL for beginning)
+/// * `after_pos` - The position after the new position (can be NULL for end)
+/// * `taken` - Positions the result must not equal
+/// * `max_length` - The longest acceptable position (default 64)
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_between_avoiding('A', 'C', ARRAY['B']::lexo[]);  -- Returns 'BH'
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_between_avoiding(
+    before_pos: Option<Lexo>,
+    after_pos: Option<Lexo>,
+    taken: Option<Array<'_, Lexo>>,
+    max_length: default!(i32, 64),
+) -> Lexo {
+    let before_str = bound_str(&before_pos);
+    let after_str = bound_str(&after_pos);
+    if !before_str.is_empty() && !after_str.is_empty() && before_str >= after_str {
+        pgrx::error!(
+            "no position exists between '{}' and '{}'",
+            before_str,
+            after_str
+        );
+    }
+    if max_length < 1 {
+        pgrx::error!("max_length must be at least 1, got {}", max_length);
+    }
+
+    let taken: Vec<Lexo> = taken
+        .map(|a| a.iter().flatten().collect())
+        .unwrap_or_default();
+    let taken: Vec<&str> = taken.iter().map(|r| r.as_str()).collect();
+
+    match generate_between_avoiding(before_str, after_str, &taken, max_length as usize) {
+        Some(position) => Lexo::new(position),
+        None => {
+            raise_error!(
+                PgSqlErrorCode::ERRCODE_PROGRAM_LIMIT_EXCEEDED,
+                format!(
+                    "no free position of at most {} characters between '{}' and '{}'",
+                    max_length, before_str, after_str
+                ),
+                detail = format!(
+                    "{} of the taken positions lie inside the gap.",
+                    taken
+                        .iter()
+                        .filter(|t| is_between(t, before_str, after_str))
+                        .count()
+                ),
+                hint = "Pass a larger max_length, or rebalance the list to widen the gap.",
+            )
+        }
+    }
+}
+
+/// Returns a position between two positions while holding a per-list lock.
+///
+/// Takes a transaction-scoped advisory lock on a hash of `list_key` before
+/// computing the position, so concurrent inserters into the same list queue up
+/// instead of generating the same position. The lock is released when the
+/// transaction ends; call this in the same transaction as the INSERT.
+///
+/// # Arguments
+/// * `list_key` - Identifies the list (e.g. `'playlist:42'`)
+/// * `before_pos` - The position before the new position (can be NULL for beginning)
+/// * `after_pos` - The position after the new position (can be NULL for end)
+///
+/// # Example
+/// ```sql
+/// BEGIN;
+/// INSERT INTO items (list_id, position)
+/// VALUES (42, lexo_between_locked('items:42', 'A', 'B'));
+/// COMMIT;
+/// ```
+#[pg_extern(cost = 100)]
+pub fn lexo_between_locked(
+    list_key: &str,
+    before_pos: Option<Lexo>,
+    after_pos: Option<Lexo>,
+) -> Lexo {
+    with_pinned_search_path(|| {
+        lock_list(&quote_literal(list_key));
+        counted_between(before_pos, after_pos)
+    })
+}
+
+/// `lexo_between()` for the functions that place a position in a list, counted
+/// in the usage statistics as an after, before or between call depending on
+/// which neighbours the list has.
+fn counted_between(before: Option<Lexo>, after: Option<Lexo>) -> Lexo {
+    let operation = Operation::placing(before.is_some(), after.is_some());
+    let result = lexo_between(before, after);
+    stats::count_call(operation, result.as_str());
+    result
+}
+
+/// Takes a transaction-scoped advisory lock keyed on a hash of `key_sql`, an SQL
+/// expression evaluating to text.
+fn lock_list(key_sql: &str) {
+    let lock_query = format!(
+        "SELECT pg_catalog.pg_advisory_xact_lock(pg_catalog.hashtextextended({}, 0))",
+        key_sql
+    );
+    Spi::run(&lock_query).expect("Failed to acquire list lock");
+}
+
+/// Builds the advisory lock key for a list stored in a table, so every caller
+/// appending to the same (table, column, scope) contends on the same lock.
+///
+/// Each name and value is prefixed with its length, so no two different lists
+/// share a key, whatever characters their names and values contain.
+fn table_list_lock_key(
+    table_name: &str,
+    lexo_column_name: &str,
+    identifier_column_name: Option<&str>,
+    identifier_value: Option<&str>,
+) -> String {
+    let part = |s: &str| format!("{}:{}", s.len(), s);
+    let mut key = format!("/{}", part(lexo_column_name));
+    if let (Some(id_col), Some(id_val)) = (identifier_column_name, identifier_value) {
+        key.push_str(&format!("/{}/{}", part(id_col), part(id_val)));
+    }
+    format!(
+        "{}::pg_catalog.regclass::pg_catalog.oid::pg_catalog.text OPERATOR(pg_catalog.||) {}",
+        quote_literal(quote_table_name(table_name)),
+        quote_literal(&key)
+    )
+}
+
+/// Maximum number of candidates `lexo_between_unique()` tries before giving up.
+const BETWEEN_UNIQUE_ATTEMPTS: usize = 16;
+
+/// Returns a position between two positions that is not already used in a table.
+///
+/// Generates a candidate like `lexo_between()` and checks it against the
+/// (optionally filtered) table. If the candidate already exists, typically
+/// because the neighbours passed in were stale, it bisects again between
+/// `before_pos` and the taken candidate, up to 16 attempts.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `before_pos` - The position before the new position (can be NULL for beginning)
+/// * `after_pos` - The position after the new position (can be NULL for end)
+/// * `identifier_column_name` - Optional: column to filter by (e.g., 'collection_id')
+/// * `identifier_value` - Optional: value to filter by
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_between_unique('items', 'position', 'A', 'B', 'collection_id', '42');
+/// ```
+#[pg_extern(cost = 100)]
+pub fn lexo_between_unique(
+    table_name: &str,
+    lexo_column_name: &str,
+    before_pos: Option<Lexo>,
+    after_pos: Option<Lexo>,
+    identifier_column_name: Option<&str>,
+    identifier_value: Option<&str>,
+) -> Lexo {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[Some(lexo_column_name), identifier_column_name],
+        );
+        let before_str = bound_str(&before_pos);
+        let after_str = bound_str(&after_pos);
+
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+
+        let quoted_table = quote_table_name(table_name);
+
+        let filter = match (identifier_column_name, identifier_value) {
+            (Some(id_col), Some(id_val)) => format!(
+                " AND {} = {}",
+                quote_identifier(id_col),
+                quote_literal(id_val)
+            ),
+            _ => String::new(),
+        };
+
+        let is_taken = |candidate: &str| {
+            let query = format!(
+                "SELECT EXISTS (SELECT 1 FROM {} WHERE {}::{ext}.lexo = {}::{ext}.lexo{})",
+                quoted_table,
+                quoted_lexo_column,
+                quote_literal(candidate),
+                filter,
+                ext = extension_schema()
+            );
+            Spi::get_one::<bool>(&query)
+                .expect("Failed to check for an existing position")
+                .unwrap_or(false)
+        };
+
+        let Some(position) =
+            find_free_between(before_str, after_str, BETWEEN_UNIQUE_ATTEMPTS, is_taken)
+        else {
+            raise_error!(
+                PgSqlErrorCode::ERRCODE_UNIQUE_VIOLATION,
+                format!(
+                    "could not find a free position between '{}' and '{}' after {} attempts",
+                    before_str, after_str, BETWEEN_UNIQUE_ATTEMPTS
+                ),
+                hint = "Run lexo_rebalance() on the list to spread its positions out.",
+            );
+        };
+
+        let result = Lexo::new(position);
+        stats::count_call(
+            Operation::placing(before_pos.is_some(), after_pos.is_some()),
+            result.as_str(),
+        );
+        result
+    })
+}
+
+// Deferred constraint trigger behind lexo_enforce_unique(). It runs after the
+// statement's rows are written, so a UNIQUE index's opaque duplicate-key error
+// becomes one that names the position and the list.
+extension_sql!(
+    r#"
+CREATE FUNCTION lexo_unique_check() RETURNS trigger
+LANGUAGE plpgsql
+SET search_path = pg_catalog, @extschema@, pg_temp AS $$
+DECLARE
+    rank_column text := TG_ARGV[0];
+    key_column text := TG_ARGV[1];
+    rank text;
+    list_key text;
+    matches bigint;
+BEGIN
+    EXECUTE format('SELECT ($1).%I::text', rank_column) INTO rank USING NEW;
+    IF rank IS NULL THEN
+        RETURN NULL;
+    END IF;
+
+    IF key_column IS NULL THEN
+        EXECUTE format('SELECT count(*) FROM %I.%I WHERE %I = ($1).%I',
+                       TG_TABLE_SCHEMA, TG_TABLE_NAME, rank_column, rank_column)
+            INTO matches USING NEW;
+    ELSE
+        EXECUTE format('SELECT ($1).%I::text', key_column) INTO list_key USING NEW;
+        EXECUTE format('SELECT count(*) FROM %I.%I WHERE %I = ($1).%I AND %I IS NOT DISTINCT FROM ($1).%I',
+                       TG_TABLE_SCHEMA, TG_TABLE_NAME, rank_column, rank_column, key_column, key_column)
+            INTO matches USING NEW;
+    END IF;
+
+    IF matches > 1 THEN
+        RAISE EXCEPTION USING
+            ERRCODE = 'unique_violation',
+            MESSAGE = format('duplicate position %L in %s.%s', rank, TG_TABLE_NAME, rank_column),
+            DETAIL = CASE WHEN key_column IS NULL
+                THEN format('%s rows share position %L.', matches, rank)
+                ELSE format('%s rows with %I = %L share position %L.', matches, key_column, list_key, rank)
+            END,
+            HINT = 'Re-read the neighbouring positions and retry, or generate the position with lexo_between_unique().';
+    END IF;
+    RETURN NULL;
+END
+$$;
+"#,
+    name = "lexo_unique_check"
+);
+
+/// Rejects duplicate positions within each list of a table.
+///
+/// Installs a deferrable constraint trigger that, at commit, raises a
+/// `unique_violation` naming the duplicated position and list key, with a hint
+/// to retry with fresh neighbours or `lexo_between_unique()`. Because the check
+/// is deferred, a transaction may hold duplicates temporarily, e.g. while
+/// swapping two rows. Rows with a NULL key form one list of their own.
+///
+/// Unlike a UNIQUE index, the check does not see rows written by transactions
+/// that have not committed yet, so concurrent inserters should still serialize
+/// with `lexo_between_locked()` or `lexo_claim_next()`.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `key_column_name` - Optional: column identifying the list (e.g., 'playlist_id')
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_enforce_unique('playlist_songs', 'position', 'playlist_id');
+///
+/// BEGIN;  -- swapping two rows passes, since the check runs at commit
+/// UPDATE playlist_songs SET position = 'I' WHERE id = 1;
+/// UPDATE playlist_songs SET position = 'H' WHERE id = 2;
+/// COMMIT;
+/// ```
+#[pg_extern]
+pub fn lexo_enforce_unique(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: default!(Option<&str>, "NULL"),
+) {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(lexo_column_name), key_column_name]);
+        let quoted_table = quote_table_name(table_name);
+
+        let (columns, args) = match key_column_name {
+            Some(key_col) => (
+                format!(
+                    "{}, {}",
+                    quote_identifier(lexo_column_name),
+                    quote_identifier(key_col)
+                ),
+                format!(
+                    "{}, {}",
+                    quote_literal(lexo_column_name),
+                    quote_literal(key_col)
+                ),
+            ),
+            None => (
+                quote_identifier(lexo_column_name),
+                quote_literal(lexo_column_name),
+            ),
+        };
+
+        let query = format!(
+            "CREATE CONSTRAINT TRIGGER {} AFTER INSERT OR UPDATE OF {} ON {} \
+             DEFERRABLE INITIALLY DEFERRED FOR EACH ROW EXECUTE FUNCTION {ext}.lexo_unique_check({})",
+            unique_trigger_name(lexo_column_name),
+            columns,
+            quoted_table,
+            args,
+            ext = extension_schema()
+        );
+
+        Spi::run(&query).expect("Failed to create unique position trigger");
+    })
+}
+
+/// Removes the trigger installed by `lexo_enforce_unique()`.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_drop_unique('playlist_songs', 'position');
+/// ```
+#[pg_extern]
+pub fn lexo_drop_unique(table_name: &str, lexo_column_name: &str) {
+    with_pinned_search_path(|| {
+        let quoted_table = quote_table_name(table_name);
+
+        let query = format!(
+            "DROP TRIGGER {} ON {}",
+            unique_trigger_name(lexo_column_name),
+            quoted_table
+        );
+
+        Spi::run(&query).expect("Failed to drop unique position trigger");
+    })
+}
+
+/// Adds a UNIQUE index on `(key_column, position)`, after checking the table
+/// for positions that would make it fail.
+///
+/// Duplicates within a list are returned one row per repeated position, with
+/// how many rows share it. Without `fix`, no index is created when there are
+/// any. With `fix`, the first row holding each repeated position keeps it and
+/// the others are moved between it and the next position in the list, then
+/// the index is created; the rows returned are the duplicates that were
+/// resolved. The index is built by `lexo_create_index()`, so it is not built
+/// concurrently, and an existing equivalent index is reused.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `key_column_name` - Column identifying the list (e.g., 'playlist_id')
+/// * `fix` - Move duplicates apart before creating the index (default false)
+///
+/// # Example
+/// ```sql
+/// SELECT * FROM lexo_enforce_unique_per_key('playlist_songs', 'position', 'playlist_id');
+/// SELECT * FROM lexo_enforce_unique_per_key('playlist_songs', 'position', 'playlist_id', fix => true);
+/// ```
+#[pg_extern]
+pub fn lexo_enforce_unique_per_key(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: &str,
+    fix: default!(bool, false),
+) -> TableIterator<
+    'static,
+    (
+        name!(key_value, String),
+        name!(position, String),
+        name!(duplicates, i64),
+        name!(fixed, bool),
+    ),
+> {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(lexo_column_name), Some(key_column_name)]);
+        let quoted_table = quote_table_name(table_name);
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+        let quoted_key_column = quote_identifier(key_column_name);
+
+        // A UNIQUE index treats NULLs as distinct, so only non-NULL pairs can clash
+        let violations_query = format!(
+            "SELECT {key}::text, {col}::text, count(*) FROM {} \
+             WHERE {key} IS NOT NULL AND {col} IS NOT NULL \
+             GROUP BY {key}, {col} HAVING count(*) > 1 ORDER BY 1, 2",
+            quoted_table,
+            key = quoted_key_column,
+            col = quoted_lexo_column
+        );
+        let violations: Vec<(String, String, i64)> = Spi::connect(|client| {
+            client
+                .select(&violations_query, None, &[])
+                .expect("Failed to look for duplicate positions")
+                .map(|row| {
+                    (
+                        row.get::<String>(1)
+                            .expect("Failed to get key value")
+                            .expect("key value was NULL"),
+                        row.get::<String>(2)
+                            .expect("Failed to get position")
+                            .expect("position was NULL"),
+                        row.get::<i64>(3)
+                            .expect("Failed to get count")
+                            .expect("count was NULL"),
+                    )
+                })
+                .collect()
+        });
+
+        if !violations.is_empty() && !fix {
+            pgrx::notice!(
+                "{} positions are repeated within a list, no index created; pass fix => true to move the duplicates",
+                violations.len()
+            );
+        } else {
+            let mut key_values: Vec<&str> =
+                violations.iter().map(|(key, _, _)| key.as_str()).collect();
+            key_values.dedup();
+            for key_value in key_values {
+                separate_duplicates(
+                    &quoted_table,
+                    &quoted_lexo_column,
+                    key_column_name,
+                    key_value,
+                );
+            }
+            lexo_create_index(
+                table_name,
+                lexo_column_name,
+                vec![key_column_name.to_string()],
+                true,
+                None,
+            );
+        }
+
+        TableIterator::new(
+            violations
+                .into_iter()
+                .map(move |(key, position, count)| (key, position, count, fix)),
+        )
+    })
+}
+
+/// Moves apart the rows of one list that share a position, keeping the order.
+fn separate_duplicates(
+    quoted_table: &str,
+    quoted_lexo_column: &str,
+    key_column_name: &str,
+    key_value: &str,
+) {
+    // The key is compared as its own type, so an index on it can be used
+    let select_query = format!(
+        "SELECT tableoid::text, ctid::text, {col}::text FROM {}{} \
+         ORDER BY {col}::{ext}.lexo, tableoid, ctid",
+        quoted_table,
+        filter_and(
+            &list_filter(Some(key_column_name), Some(key_value)),
+            &format!("{} IS NOT NULL", quoted_lexo_column)
+        ),
+        col = quoted_lexo_column,
+        ext = extension_schema()
+    );
+
+    Spi::connect_mut(|client| {
+        let mut locators = Vec::new();
+        let mut values = Vec::new();
+        for row in client
+            .select(&select_query, None, &[])
+            .expect("Failed to select rows for deduplication")
+        {
+            locators.push(row_locator(&row));
+            values.push(
+                row.get::<String>(3)
+                    .expect("Failed to get position")
+                    .expect("position was NULL"),
             );
+        }
 
+        let value_refs: Vec<&str> = values.iter().map(String::as_str).collect();
+        let replacements = reposition_duplicates(&value_refs);
+
+        // Neighbours that leave no room (e.g. 'H' and 'H0') would break the order
+        let mut result = values.clone();
+        for (i, position) in &replacements {
+            result[*i] = position.clone();
+        }
+        if let Some(k) = (1..result.len()).find(|&k| result[k - 1] >= result[k]) {
+            raise_error!(
+                PgSqlErrorCode::ERRCODE_UNIQUE_VIOLATION,
+                format!(
+                    "no room to move duplicate position '{}' apart in the list with key {}",
+                    values[k], key_value
+                ),
+                hint = "Rebalance the list with lexo_rebalance() first.",
+            )
+        }
+
+        for (i, position) in &replacements {
+            let update_query = format!(
+                "UPDATE {} SET {} = {} WHERE {}",
+                quoted_table,
+                quoted_lexo_column,
+                quote_literal(position),
+                locators[*i]
+            );
             client
                 .update(&update_query, None, &[])
                 .expect("Failed to update row position");
         }
     });
+}
 
-    row_count
+fn unique_trigger_name(lexo_column_name: &str) -> String {
+    quote_identifier(format!("lexo_unique_{}", lexo_column_name))
+}
+
+// Row trigger behind lexo_attach_validation(). Its arguments are the position
+// column, then the key column and the bounds, with '' meaning none.
+extension_sql!(
+    r#"
+CREATE FUNCTION lexo_validate_position() RETURNS trigger
+LANGUAGE plpgsql
+SET search_path = pg_catalog, @extschema@, pg_temp AS $$
+DECLARE
+    rank_column text := TG_ARGV[0];
+    key_column text := nullif(TG_ARGV[1], '');
+    min_rank lexo := nullif(TG_ARGV[2], '');
+    max_rank lexo := nullif(TG_ARGV[3], '');
+    rank lexo;
+    list_key text;
+    changed boolean := true;
+    taken boolean;
+BEGIN
+    EXECUTE format('SELECT ($1).%I::lexo', rank_column) INTO rank USING NEW;
+    IF rank IS NULL THEN
+        RETURN NEW;
+    END IF;
+
+    IF rank::text = '' THEN
+        RAISE EXCEPTION USING
+            ERRCODE = 'check_violation',
+            MESSAGE = format('empty position in %s.%s', TG_TABLE_NAME, rank_column),
+            HINT = 'Generate positions with lexo_first(), lexo_after() or lexo_between().';
+    END IF;
+
+    IF rank < min_rank OR rank > max_rank THEN
+        RAISE EXCEPTION USING
+            ERRCODE = 'check_violation',
+            MESSAGE = format('position %L in %s.%s is outside the allowed range', rank, TG_TABLE_NAME, rank_column),
+            DETAIL = CASE
+                WHEN min_rank IS NULL THEN format('Positions must be at most %L.', max_rank)
+                WHEN max_rank IS NULL THEN format('Positions must be at least %L.', min_rank)
+                ELSE format('Positions must be between %L and %L.', min_rank, max_rank)
+            END;
+    END IF;
+
+    IF key_column IS NULL THEN
+        RETURN NEW;
+    END IF;
+
+    IF TG_OP = 'UPDATE' THEN
+        EXECUTE format('SELECT ($1).%I IS DISTINCT FROM ($2).%I OR ($1).%I IS DISTINCT FROM ($2).%I',
+                       rank_column, rank_column, key_column, key_column)
+            INTO changed USING NEW, OLD;
+    END IF;
+
+    IF changed THEN
+        EXECUTE format('SELECT ($1).%I::text', key_column) INTO list_key USING NEW;
+        EXECUTE format('SELECT EXISTS (SELECT 1 FROM %I.%I WHERE %I::lexo = $2 AND %I IS NOT DISTINCT FROM ($1).%I)',
+                       TG_TABLE_SCHEMA, TG_TABLE_NAME, rank_column, key_column, key_column)
+            INTO taken USING NEW, rank;
+        IF taken THEN
+            RAISE WARNING USING
+                MESSAGE = format('position %L is already used in %s.%s for %s = %L', rank, TG_TABLE_NAME, rank_column, key_column, list_key),
+                HINT = 'Re-read the neighbouring positions, or generate the position with lexo_between_unique().';
+        END IF;
+    END IF;
+    RETURN NEW;
+END
+$$;
+"#,
+    name = "lexo_validate_position",
+    requires = [Lexo]
+);
+
+/// Checks positions written to a column by hand.
+///
+/// Installs a BEFORE INSERT OR UPDATE trigger that rejects empty positions and
+/// positions outside `[min_rank, max_rank]` (either bound can be NULL). With a
+/// key column, it also warns when the new position is already used by another
+/// row of the same list. NULL positions are left to the column's constraints.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `key_column_name` - Optional: column identifying the list, to warn about collisions
+/// * `min_rank` - Optional: the smallest allowed position
+/// * `max_rank` - Optional: the largest allowed position
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_attach_validation('playlist_songs', 'position', 'playlist_id');
+/// SELECT lexo_attach_validation('pinned', 'position', min_rank => '0', max_rank => 'A');
+/// ```
+#[pg_extern]
+pub fn lexo_attach_validation(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: default!(Option<&str>, "NULL"),
+    min_rank: default!(Option<Lexo>, "NULL"),
+    max_rank: default!(Option<Lexo>, "NULL"),
+) {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(lexo_column_name), key_column_name]);
+        if let (Some(min), Some(max)) = (&min_rank, &max_rank)
+            && min > max
+        {
+            pgrx::error!(
+                "min_rank '{}' must not be greater than max_rank '{}'",
+                min.as_str(),
+                max.as_str()
+            );
+        }
+
+        let quoted_table = quote_table_name(table_name);
+
+        let columns = match key_column_name {
+            Some(key_col) => format!(
+                "{}, {}",
+                quote_identifier(lexo_column_name),
+                quote_identifier(key_col)
+            ),
+            None => quote_identifier(lexo_column_name),
+        };
+
+        let args = [
+            lexo_column_name,
+            key_column_name.unwrap_or(""),
+            min_rank.as_ref().map(|r| r.as_str()).unwrap_or(""),
+            max_rank.as_ref().map(|r| r.as_str()).unwrap_or(""),
+        ]
+        .map(quote_literal)
+        .join(", ");
+
+        let query = format!(
+            "CREATE TRIGGER {} BEFORE INSERT OR UPDATE OF {} ON {} \
+             FOR EACH ROW EXECUTE FUNCTION {ext}.lexo_validate_position({})",
+            validation_trigger_name(lexo_column_name),
+            columns,
+            quoted_table,
+            args,
+            ext = extension_schema()
+        );
+
+        Spi::run(&query).expect("Failed to create position validation trigger");
+    })
+}
+
+/// Removes the trigger installed by `lexo_attach_validation()`.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_detach_validation('playlist_songs', 'position');
+/// ```
+#[pg_extern]
+pub fn lexo_detach_validation(table_name: &str, lexo_column_name: &str) {
+    with_pinned_search_path(|| {
+        let quoted_table = quote_table_name(table_name);
+
+        let query = format!(
+            "DROP TRIGGER {} ON {}",
+            validation_trigger_name(lexo_column_name),
+            quoted_table
+        );
+
+        Spi::run(&query).expect("Failed to drop position validation trigger");
+    })
+}
+
+fn validation_trigger_name(lexo_column_name: &str) -> String {
+    quote_identifier(format!("lexo_validate_{}", lexo_column_name))
+}
+
+// Audit trail behind lexo_enable_history(). Rebalances that summarize write one
+// row with a NULL pk and the details in `detail`.
+extension_sql!(
+    r#"
+CREATE TABLE lexo_history (
+    id bigserial PRIMARY KEY,
+    table_name regclass NOT NULL,
+    column_name name NOT NULL,
+    pk text,
+    old_rank lexo,
+    new_rank lexo,
+    changed_at timestamptz NOT NULL DEFAULT now(),
+    changed_by name NOT NULL DEFAULT current_user,
+    detail text
+);
+CREATE INDEX lexo_history_row_idx ON lexo_history (table_name, pk);
+CREATE INDEX lexo_history_changed_at_idx ON lexo_history (changed_at);
+SELECT pg_catalog.pg_extension_config_dump('lexo_history', '');
+SELECT pg_catalog.pg_extension_config_dump('lexo_history_id_seq', '');
+
+CREATE FUNCTION lexo_prune_history(older_than interval) RETURNS bigint
+LANGUAGE sql
+SET search_path = pg_catalog, @extschema@, pg_temp AS $$
+    WITH pruned AS (
+        DELETE FROM lexo_history WHERE changed_at < now() - older_than RETURNING 1
+    )
+    SELECT count(*) FROM pruned
+$$;
+
+-- Its arguments are the position column and the primary key column. A failed
+-- insert into lexo_history only raises a WARNING, so it never fails the UPDATE.
+CREATE FUNCTION lexo_history_record() RETURNS trigger
+LANGUAGE plpgsql
+SET search_path = pg_catalog, @extschema@, pg_temp AS $$
+DECLARE
+    rank_column text := TG_ARGV[0];
+    pk_column text := TG_ARGV[1];
+    pk text;
+    old_rank lexo;
+    new_rank lexo;
+BEGIN
+    IF current_setting('lexo.record_history', true) = 'off' THEN
+        RETURN NULL;
+    END IF;
+    BEGIN
+        EXECUTE format('SELECT ($1).%I::text, ($2).%I::lexo, ($1).%I::lexo',
+                       pk_column, rank_column, rank_column)
+            INTO pk, old_rank, new_rank USING NEW, OLD;
+        INSERT INTO lexo_history (table_name, column_name, pk, old_rank, new_rank)
+        VALUES (TG_RELID, rank_column, pk, old_rank, new_rank);
+    EXCEPTION WHEN OTHERS THEN
+        RAISE WARNING 'could not record the position change of %.% in lexo_history: %',
+            TG_TABLE_NAME, rank_column, SQLERRM;
+    END;
+    RETURN NULL;
+END
+$$;
+"#,
+    name = "lexo_history",
+    requires = [Lexo]
+);
+
+/// Records every change of a position column in `lexo_history`.
+///
+/// Installs an `AFTER UPDATE` row trigger that, whenever the position actually
+/// changes, writes the table, the primary key, the old and new position, the
+/// time and the current user. If writing the history row fails, e.g. because
+/// the user lacks privileges on `lexo_history`, the `UPDATE` still succeeds
+/// and a WARNING is raised instead.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `pk_column_name` - The column identifying the row in the history
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_enable_history('cards', 'position', 'id');
+/// SELECT * FROM lexo_history WHERE table_name = 'cards'::regclass AND pk = '42';
+/// ```
+#[pg_extern]
+pub fn lexo_enable_history(table_name: &str, lexo_column_name: &str, pk_column_name: &str) {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(lexo_column_name), Some(pk_column_name)]);
+        let quoted_table = quote_table_name(table_name);
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+
+        let query = format!(
+            "CREATE TRIGGER {} AFTER UPDATE OF {col} ON {} FOR EACH ROW \
+             WHEN (OLD.{col} IS DISTINCT FROM NEW.{col}) \
+             EXECUTE FUNCTION {ext}.lexo_history_record({}, {})",
+            history_trigger_name(lexo_column_name),
+            quoted_table,
+            quote_literal(lexo_column_name),
+            quote_literal(pk_column_name),
+            col = quoted_lexo_column,
+            ext = extension_schema()
+        );
+
+        Spi::run(&query).expect("Failed to create position history trigger");
+    })
+}
+
+/// Removes the trigger installed by `lexo_enable_history()`.
+///
+/// The rows already recorded in `lexo_history` are kept.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_disable_history('cards', 'position');
+/// ```
+#[pg_extern]
+pub fn lexo_disable_history(table_name: &str, lexo_column_name: &str) {
+    with_pinned_search_path(|| {
+        let quoted_table = quote_table_name(table_name);
+
+        let query = format!(
+            "DROP TRIGGER {} ON {}",
+            history_trigger_name(lexo_column_name),
+            quoted_table
+        );
+
+        Spi::run(&query).expect("Failed to drop position history trigger");
+    })
+}
+
+fn history_trigger_name(lexo_column_name: &str) -> String {
+    quote_identifier(format!("lexo_history_{}", lexo_column_name))
+}
+
+/// Runs a rebalance with per-row history switched off and records a single
+/// summary row instead, when `lexo.history_summarize_rebalance` is on and the
+/// column has history enabled.
+fn with_history_summary(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: Option<&str>,
+    key_value: Option<&str>,
+    rebalance: impl FnOnce() -> i64,
+) -> i64 {
+    let quoted_table = quote_table_name(table_name);
+    let summarize = crate::gucs::HISTORY_SUMMARIZE_REBALANCE.get()
+        && crate::gucs::RECORD_HISTORY.get()
+        && Spi::get_one::<bool>(&format!(
+            "SELECT EXISTS (SELECT 1 FROM pg_catalog.pg_trigger \
+             WHERE tgrelid = {}::regclass AND tgname = {} AND tgenabled <> 'D')",
+            quote_literal(&quoted_table),
+            quote_literal(format!("lexo_history_{}", lexo_column_name))
+        ))
+        .expect("Failed to look up the history trigger")
+            == Some(true);
+    if !summarize {
+        return rebalance();
+    }
+
+    Spi::run("SELECT pg_catalog.set_config('lexo.record_history', 'off', true)")
+        .expect("Failed to pause position history");
+    let rows = rebalance();
+    Spi::run("SELECT pg_catalog.set_config('lexo.record_history', 'on', true)")
+        .expect("Failed to resume position history");
+
+    let detail = match (key_column_name, key_value) {
+        (Some(key_col), Some(key_val)) => format!(
+            "lexo_rebalance rewrote {} rows with {} = {}",
+            rows, key_col, key_val
+        ),
+        _ => format!("lexo_rebalance rewrote {} rows", rows),
+    };
+    Spi::run(&format!(
+        "INSERT INTO {}.lexo_history (table_name, column_name, detail) VALUES ({}::regclass, {}, {})",
+        extension_schema(),
+        quote_literal(&quoted_table),
+        quote_literal(lexo_column_name),
+        quote_literal(&detail)
+    ))
+    .expect("Failed to record the rebalance in lexo_history");
+    rows
+}
+
+/// Returns true if a position lies strictly between two positions.
+///
+/// Intended for validating positions computed by clients before they are stored.
+/// NULL bounds are open-ended, exactly as in `lexo_between()`, and a candidate
+/// equal to either bound is not between them.
+///
+/// # Arguments
+/// * `candidate` - The position to check
+/// * `before_pos` - The lower bound (NULL for no lower bound)
+/// * `after_pos` - The upper bound (NULL for no upper bound)
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_is_between('M', 'A', 'Z');    -- Returns true
+/// SELECT lexo_is_between('A', 'A', 'Z');    -- Returns false
+/// SELECT lexo_is_between('A', NULL, 'H');   -- Returns true
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_is_between(
+    candidate: Option<Lexo>,
+    before_pos: Option<Lexo>,
+    after_pos: Option<Lexo>,
+) -> Option<bool> {
+    let candidate = candidate?;
+    let before_str = bound_str(&before_pos);
+    let after_str = bound_str(&after_pos);
+
+    Some(is_between(candidate.as_str(), before_str, after_str))
+}
+
+/// Returns the smallest position after every position that starts with `prefix`.
+///
+/// NULL when no such bound exists (an empty prefix or one made only of 'z').
+/// Used by `lexo_starts_with()` to turn a prefix test into a range.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_prefix_end('Hz');  -- Returns 'I'
+/// SELECT lexo_prefix_end('zz');  -- Returns NULL
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_prefix_end(prefix: &str) -> Option<Lexo> {
+    if !is_valid_base62(prefix) {
+        pgrx::error!(
+            "invalid prefix '{}': positions may only contain Base62 characters (0-9, A-Z, a-z)",
+            prefix
+        );
+    }
+    prefix_end(prefix).map(Lexo::new)
+}
+
+// A prefix test written as a range over the btree operators. The planner
+// inlines this SQL function, and with a constant prefix both bounds fold to
+// constants, so `position ^@ 'Hz'` becomes an index range scan.
+extension_sql!(
+    r#"
+CREATE FUNCTION lexo_starts_with(rank lexo, prefix text) RETURNS boolean
+LANGUAGE sql IMMUTABLE PARALLEL SAFE
+AS $$
+    SELECT rank >= prefix::lexo
+       AND (lexo_prefix_end(prefix) IS NULL OR rank < lexo_prefix_end(prefix))
+$$;
+CREATE OPERATOR ^@ (
+    LEFTARG = lexo,
+    RIGHTARG = text,
+    FUNCTION = lexo_starts_with
+);
+"#,
+    name = "lexo_starts_with",
+    requires = [lexo_prefix_end, lexo_ge, lexo_lt]
+);
+
+/// Returns `prefix` followed by `suffix`, for ranks namespaced under a parent.
+///
+/// Every result starts with `prefix`, so it sorts after `prefix` and before
+/// `lexo_prefix_end(prefix)`: in a global `ORDER BY` the children of a parent
+/// come right after it, in the order of their suffixes. This holds as long as
+/// no parent rank is itself a prefix of another, e.g. parents 'H' and 'HV',
+/// whose children would interleave. The suffix must not be empty, since the
+/// result would equal the parent.
+///
+/// The `||` operator calls this for `lexo || lexo` and `lexo || text`. An
+/// untyped literal next to a `lexo` is read as a `lexo`, so cast the position
+/// to `text` for plain string concatenation.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_concat('H', 'V');  -- Returns 'HV'
+/// SELECT 'H'::lexo || 'a0';      -- Returns 'Ha0'
+/// SELECT 'H'::lexo || 'a-0';     -- Error: invalid suffix
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_concat(prefix: Lexo, suffix: Lexo) -> Lexo {
+    if suffix.is_empty() {
+        pgrx::error!("suffix must not be empty: the result would equal the prefix");
+    }
+    Lexo::new(format!("{}{}", prefix.as_str(), suffix.as_str()))
+}
+
+/// Returns `prefix` followed by a suffix given as text, which must be a valid
+/// position.
+#[pg_extern(immutable, parallel_safe, name = "lexo_concat")]
+pub fn lexo_concat_text(prefix: Lexo, suffix: &str) -> Lexo {
+    lexo_concat(prefix, Lexo::from_str_ref(suffix))
+}
+
+/// Returns the rest of `rank` after `prefix`, the inverse of `lexo_concat()`.
+///
+/// NULL when `rank` doesn't start with `prefix` or equals it.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_strip_prefix('Ha0', 'H');  -- Returns 'a0'
+/// SELECT lexo_strip_prefix('Ia0', 'H');  -- Returns NULL
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_strip_prefix(rank: Lexo, prefix: Lexo) -> Option<Lexo> {
+    rank.as_str()
+        .strip_prefix(prefix.as_str())
+        .filter(|rest| !rest.is_empty())
+        .map(|rest| Lexo::new(rest.to_string()))
+}
+
+extension_sql!(
+    r#"
+CREATE OPERATOR || (
+    LEFTARG = lexo,
+    RIGHTARG = lexo,
+    FUNCTION = lexo_concat
+);
+CREATE OPERATOR || (
+    LEFTARG = lexo,
+    RIGHTARG = text,
+    FUNCTION = lexo_concat
+);
+"#,
+    name = "lexo_concat_operator",
+    requires = [lexo_concat, lexo_concat_text]
+);
+
+/// Returns true if a text value is a valid position.
+///
+/// Used by the `lexo_text` domain's CHECK constraint. The empty string is not
+/// a position, so it is rejected like any other invalid value.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_is_valid('Hz');   -- Returns true
+/// SELECT lexo_is_valid('H-1');  -- Returns false
+/// SELECT lexo_is_valid('');     -- Returns false
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_is_valid(value: &str) -> bool {
+    !value.is_empty() && is_valid_base62(value)
+}
+
+/// Returns the first position as a `lexo_text` value.
+///
+/// Text variant of `lexo_first()` for `lexo_text` columns.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_first_text();  -- Returns 'H'
+/// ```
+#[pg_extern(immutable, parallel_safe, requires = ["lexo_text_domain"])]
+pub fn lexo_first_text() -> LexoText {
+    LexoText::from(lexo_first())
+}
+
+/// Returns a position after the given text position as a `lexo_text` value.
+///
+/// Text variant of `lexo_after()` for `lexo_text` columns: a thin wrapper over
+/// the `lexo_after(text)` overload that returns the domain instead of `lexo`.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_after_text('H');  -- Returns 'I'
+/// ```
+#[pg_extern(immutable, parallel_safe, requires = ["lexo_text_domain"])]
+pub fn lexo_after_text(current: &str) -> LexoText {
+    LexoText::from(lexo_after_from_text(current))
+}
+
+/// Returns a position before the given text position as a `lexo_text` value.
+///
+/// Text variant of `lexo_before()` for `lexo_text` columns, wrapping the
+/// `lexo_before(text)` overload.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_before_text('H');  -- Returns 'G'
+/// ```
+#[pg_extern(immutable, parallel_safe, requires = ["lexo_text_domain"])]
+pub fn lexo_before_text(current: &str) -> LexoText {
+    LexoText::from(lexo_before_from_text(current))
+}
+
+/// Returns a position between two text positions as a `lexo_text` value.
+///
+/// Text variant of `lexo_between()` for `lexo_text` columns, wrapping the
+/// `lexo_between(text, text)` overload. Either bound can be NULL.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_between_text('A', 'Z');   -- Returns 'M'
+/// SELECT lexo_between_text(NULL, 'H');  -- Returns 'G'
+/// ```
+#[pg_extern(immutable, parallel_safe, requires = ["lexo_text_domain"])]
+pub fn lexo_between_text(before_pos: Option<&str>, after_pos: Option<&str>) -> LexoText {
+    LexoText::from(lexo_between_from_text(before_pos, after_pos))
+}
+
+/// Returns the next position after the maximum in a table column as a `lexo_text` value.
+///
+/// Text variant of `lexo_next()` for `lexo_text` columns, with the same
+/// arguments; `empty_default` is text here.
+///
+/// # Example
+/// ```sql
+/// INSERT INTO cards (position) VALUES (lexo_next_text('cards', 'position', NULL, NULL));
+/// ```
+#[pg_extern(cost = 100, requires = ["lexo_text_domain"])]
+pub fn lexo_next_text(
+    table_name: &str,
+    lexo_column_name: &str,
+    identifier_column_name: Option<&str>,
+    identifier_value: Option<&str>,
+    empty_default: default!(Option<&str>, "NULL"),
+    only: default!(bool, false),
+) -> LexoText {
+    LexoText::from(lexo_next(
+        table_name,
+        lexo_column_name,
+        identifier_column_name,
+        identifier_value,
+        empty_default.map(Lexo::from),
+        only,
+    ))
+}
+
+/// Returns the positions a rebalance would assign to an array of text positions.
+///
+/// Text variant of `lexo_rebalance_array()` for `lexo_text` values. Element
+/// `i` of the result is the new position for input element `i`.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_rebalance_text(ARRAY['Hzzz', 'H', 'Hz']);  -- Returns {pfKfKf,AKfKfK,V}
+/// ```
+#[pg_extern(immutable, parallel_safe, requires = ["lexo_text_domain"])]
+pub fn lexo_rebalance_text(ranks: Array<'_, &str>) -> Vec<LexoText> {
+    let ranks: Vec<Lexo> = ranks
+        .iter()
+        .enumerate()
+        .map(|(idx, rank)| match rank {
+            Some(rank) => Lexo::from(rank),
+            None => pgrx::error!(
+                "lexo array must not contain NULL elements (found at index {})",
+                idx + 1
+            ),
+        })
+        .collect();
+    rebalance_positions(&ranks)
+        .into_iter()
+        .map(|position| LexoText::from(Lexo::new(position)))
+        .collect()
+}
+
+/// Interprets a position as a Base62 integer.
+///
+/// This uses integer (place-value) semantics, which is distinct from the
+/// fractional interpretation used for ordering: `'A0'` sorts before `'B'`,
+/// but as integers `'A0'` is 620 and `'B'` is 11. Leading `'0'` characters
+/// are not significant, so `'0A'` and `'A'` both convert to 10.
+///
+/// # Arguments
+/// * `rank` - The position to convert
+///
+/// # Returns
+/// The exact integer value of `rank` as a numeric
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_to_numeric('A');   -- Returns 10
+/// SELECT lexo_to_numeric('A0');  -- Returns 620
+/// SELECT lexo_to_numeric('B');   -- Returns 11
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_to_numeric(rank: Lexo) -> AnyNumeric {
+    // numeric holds up to 131072 digits before the decimal point; checking
+    // first saves converting a position that can't fit
+    let significant = rank.as_str().trim_start_matches(START_CHAR).len();
+    let converted = if significant as f64 * (BASE as f64).log10() > 131_072.0 {
+        None
+    } else {
+        AnyNumeric::try_from(base62_to_decimal(rank.as_str()).as_str()).ok()
+    };
+    converted.unwrap_or_else(|| {
+        raise_error!(
+            PgSqlErrorCode::ERRCODE_NUMERIC_VALUE_OUT_OF_RANGE,
+            format!(
+                "position of length {} is out of range for type numeric",
+                rank.as_str().len()
+            ),
+        );
+    })
+}
+
+/// Converts a non-negative integer into a Base62 position.
+///
+/// This is the inverse of `lexo_to_numeric()` and is useful for deriving
+/// positions from external monotonically increasing ids. Note that integer
+/// order only matches position order for values of the same length.
+///
+/// # Arguments
+/// * `value` - A non-negative integer
+///
+/// # Returns
+/// The Base62 representation of `value` as a Lexo
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_from_numeric(10);   -- Returns 'A'
+/// SELECT lexo_from_numeric(620);  -- Returns 'A0'
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_from_numeric(value: AnyNumeric) -> Lexo {
+    let normalized = value.normalize();
+    match decimal_to_base62(normalized) {
+        Some(result) => Lexo::new(result),
+        None => pgrx::error!(
+            "Cannot convert {} to lexo: value must be a non-negative integer",
+            normalized
+        ),
+    }
+}
+
+/// Converts a rank generated with another alphabet into a position.
+///
+/// `from_alphabet` lists the source alphabet's characters from lowest to
+/// highest, so ranks that were ordered under that alphabet stay in the same
+/// order as positions. Each source character is re-encoded at its fraction of
+/// the keyspace: alphabets of up to 62 characters keep one character per
+/// character, larger ones take two (up to 3844 characters) or more.
+///
+/// # Arguments
+/// * `rank` - A rank written in `from_alphabet`
+/// * `from_alphabet` - The source characters in ascending order, each listed once
+///
+/// # Returns
+/// A position that sorts among other transcoded ranks as `rank` did
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_transcode('10', '01');  -- Returns 'V0'
+/// SELECT lexo_transcode('z', '-0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ_abcdefghijklmnopqrstuvwxyz');
+/// -- Returns 'z1'
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_transcode(rank: &str, from_alphabet: &str) -> Lexo {
+    match transcode(rank, from_alphabet) {
+        Ok(result) => Lexo::new(result),
+        Err(error) => {
+            let message = match error {
+                TranscodeError::AlphabetTooShort(length) => format!(
+                    "from_alphabet must have at least 2 characters, got {}",
+                    length
+                ),
+                TranscodeError::DuplicateCharacter(character) => format!(
+                    "from_alphabet lists the character {:?} more than once",
+                    character
+                ),
+                TranscodeError::Empty => EMPTY_POSITION.to_string(),
+                TranscodeError::InvalidCharacter { offset, character } => format!(
+                    "invalid character {:?} at byte offset {} of '{}': it is not in from_alphabet",
+                    character, offset, rank
+                ),
+            };
+            raise_error!(PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE, message);
+        }
+    }
+}
+
+/// Returns the balanced position for the `index`-th of `total` items.
+///
+/// The result is the same position `lexo_rebalance()` would assign to the row
+/// at `index` (zero-based) in a list of `total` rows, so importing an ordered
+/// dataset with this function and rebalancing it later yields the same values.
+///
+/// # Arguments
+/// * `index` - The zero-based index of the item (must be in `[0, total)`)
+/// * `total` - The total number of items in the list
+///
+/// # Returns
+/// The balanced Lexo position for `index`
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_from_int(0, 1);  -- Returns 'H'
+///
+/// INSERT INTO items (name, position)
+/// SELECT name, lexo_from_int(row_number() OVER (ORDER BY name) - 1, count(*) OVER ())
+/// FROM staging;
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_from_int(index: i64, total: i64) -> Lexo {
+    let position = match (usize::try_from(index), usize::try_from(total)) {
+        (Ok(index), Ok(total)) => balanced_position(index, total),
+        _ => None,
+    };
+    let Some(position) = position else {
+        pgrx::error!(
+            "Index {} is out of range: must be between 0 and total - 1 (total = {})",
+            index,
+            total
+        );
+    };
+    Lexo::new(position)
+}
+
+/// Plans positions for a list that will hold about `expected_count` rows.
+///
+/// Instead of starting at 'H' and letting positions grow, a bulk load can give
+/// every row a position of the same `rank_length`, `step` apart, starting at
+/// `first_rank`. The plan covers the middle half of the keyspace of that
+/// length, so a quarter is left free for prepends and a quarter for appends,
+/// and consecutive positions are at least 62 apart, so each gap can take
+/// inserts without positions growing. Generate the positions with
+/// `lexo_nth_planned()`.
+///
+/// # Example
+/// ```sql
+/// SELECT * FROM lexo_start_for_estimated_size(50000);
+/// -- first_rank | rank_length | step
+/// -- FV00       |           4 |  147
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_start_for_estimated_size(
+    expected_count: i64,
+) -> TableIterator<
+    'static,
+    (
+        name!(first_rank, Lexo),
+        name!(rank_length, i32),
+        name!(step, AnyNumeric),
+    ),
+> {
+    if expected_count < 1 {
+        pgrx::error!("expected_count must be at least 1, got {}", expected_count);
+    }
+    let plan = plan_for_size(expected_count as u64);
+    TableIterator::once((
+        Lexo::new(plan.first),
+        plan.length as i32,
+        AnyNumeric::try_from(plan.step.to_string().as_str())
+            .expect("Failed to convert step to numeric"),
+    ))
+}
+
+/// Returns position `i` (zero-based) of a plan from `lexo_start_for_estimated_size()`.
+///
+/// The result is `first_rank` plus `i * step`, with the same length as
+/// `first_rank`. Errors if it no longer fits in that length, i.e. when far more
+/// rows are loaded than the plan was made for.
+///
+/// # Example
+/// ```sql
+/// INSERT INTO catalog (sku, position)
+/// SELECT s.sku, lexo_nth_planned(p.first_rank, p.step, s.n - 1)
+/// FROM lexo_start_for_estimated_size(50000) p,
+///      (SELECT sku, row_number() OVER (ORDER BY sku) AS n FROM staging) s;
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_nth_planned(first_rank: Lexo, step: AnyNumeric, i: i64) -> Lexo {
+    let step_text = step.normalize();
+    let Some(step_value) = step_text.parse::<u128>().ok().filter(|&s| s > 0) else {
+        pgrx::error!("step must be a positive integer, got {}", step_text);
+    };
+    if i < 0 {
+        pgrx::error!("i must not be negative, got {}", i);
+    }
+    if first_rank.is_empty() {
+        pgrx::error!("first_rank must be a non-empty position");
+    }
+    match planned_position(first_rank.as_str(), step_value, i as u64) {
+        Some(position) => Lexo::new(position),
+        None => pgrx::error!(
+            "planned position {} after '{}' does not fit in {} characters: plan for a larger size",
+            i,
+            first_rank.as_str(),
+            first_rank.as_str().chars().count()
+        ),
+    }
+}
+
+/// Describes how a position decomposes, for debugging unexpected orderings.
+///
+/// Reports each character with its Base62 index, the position read as a
+/// fraction in [0, 1), its length, and whether it is the minimum (all `'0'`,
+/// nothing fits before it), all `'z'` (anything after it must be longer) or
+/// ends in `'0'` (nothing fits between it and its prefix).
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_explain('Az0');
+/// -- {"rank": "Az0", "length": 3, "fraction": 0.17715...,
+/// --  "characters": [{"char": "A", "index": 10}, {"char": "z", "index": 61}, {"char": "0", "index": 0}],
+/// --  "is_maximum": false, "is_minimum": false, "ends_with_min_char": true}
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_explain(rank: Lexo) -> pgrx::JsonB {
+    let explanation = explain(rank.as_str());
+    let characters: Vec<Value> = explanation
+        .digits
+        .iter()
+        .map(|&(c, index)| json!({ "char": c.to_string(), "index": index }))
+        .collect();
+
+    pgrx::JsonB(json!({
+        "rank": rank.as_str(),
+        "length": explanation.length,
+        "fraction": explanation.fraction,
+        "characters": characters,
+        "is_minimum": explanation.is_minimum,
+        "is_maximum": explanation.is_maximum,
+        "ends_with_min_char": explanation.ends_with_min_char,
+    }))
+}
+
+/// Returns the number of characters in a position.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_length('Hzz');  -- Returns 3
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_length(rank: Lexo) -> i32 {
+    rank.as_str().chars().count() as i32
+}
+
+/// Returns the leading characters two positions share.
+///
+/// A long common prefix between neighbouring rows means that region of the
+/// list has been split many times and is a candidate for `lexo_rebalance()`.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_common_prefix('HzzA', 'HzzB');  -- Returns 'Hzz'
+/// SELECT lexo_common_prefix('A', 'B');        -- Returns ''
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_common_prefix(a: Lexo, b: Lexo) -> String {
+    common_prefix(a.as_str(), b.as_str()).to_string()
+}
+
+/// Converts a position to a JSON string.
+///
+/// Registered as the `lexo -> json` cast, which `to_json()`, `to_jsonb()`,
+/// `row_to_json()` and `jsonb_build_object()` use for `lexo` values.
+///
+/// # Example
+/// ```sql
+/// SELECT to_jsonb('H'::lexo);                      -- Returns "H"
+/// SELECT jsonb_build_object('pos', lexo_first());  -- Returns {"pos": "H"}
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_to_json(rank: Lexo) -> pgrx::Json {
+    pgrx::Json(Value::String(rank.into_inner()))
+}
+
+/// Converts a position to a JSONB string.
+///
+/// Registered as the `lexo -> jsonb` cast.
+///
+/// # Example
+/// ```sql
+/// SELECT 'H'::lexo::jsonb;  -- Returns "H"
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_to_jsonb(rank: Lexo) -> pgrx::JsonB {
+    pgrx::JsonB(Value::String(rank.into_inner()))
+}
+
+/// Converts a JSON string back to a position.
+///
+/// Registered as the `json -> lexo` cast. Errors if the value is not a JSON
+/// string containing a valid Base62 position.
+///
+/// # Example
+/// ```sql
+/// SELECT '"H"'::json::lexo;  -- Returns 'H'
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_from_json(value: pgrx::Json) -> Lexo {
+    lexo_from_json_value(value.0)
+}
+
+/// Converts a JSONB string back to a position.
+///
+/// Registered as the `jsonb -> lexo` cast. Errors if the value is not a JSON
+/// string containing a valid Base62 position.
+///
+/// # Example
+/// ```sql
+/// SELECT '"H"'::jsonb::lexo;               -- Returns 'H'
+/// SELECT (doc->'pos')::lexo FROM documents;
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_from_jsonb(value: pgrx::JsonB) -> Lexo {
+    lexo_from_json_value(value.0)
+}
+
+fn lexo_from_json_value(value: Value) -> Lexo {
+    match value {
+        Value::String(s) => Lexo::new(s),
+        other => pgrx::error!(
+            "Cannot cast JSON value {} to lexo: expected a string",
+            other
+        ),
+    }
+}
+
+extension_sql!(
+    r#"
+CREATE CAST (lexo AS json) WITH FUNCTION lexo_to_json(lexo);
+CREATE CAST (lexo AS jsonb) WITH FUNCTION lexo_to_jsonb(lexo);
+CREATE CAST (json AS lexo) WITH FUNCTION lexo_from_json(json);
+CREATE CAST (jsonb AS lexo) WITH FUNCTION lexo_from_jsonb(jsonb);
+"#,
+    name = "lexo_json_casts",
+    requires = [lexo_to_json, lexo_to_jsonb, lexo_from_json, lexo_from_jsonb]
+);
+
+/// Compares two positions, returning -1, 0 or 1.
+///
+/// This is the comparison support function of `lexo_btree_ops`, so the result
+/// always agrees with `ORDER BY` and the `<`/`>` operators.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_compare('A', 'B');  -- Returns -1
+/// SELECT lexo_compare('H', 'H');  -- Returns 0
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_compare(a: Lexo, b: Lexo) -> i32 {
+    a.cmp(&b) as i32
+}
+
+/// Returns the smaller of two positions.
+///
+/// State and combine function for the `min(lexo)` aggregate.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_smaller('A', 'B');  -- Returns 'A'
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_smaller(a: Lexo, b: Lexo) -> Lexo {
+    if b < a { b } else { a }
+}
+
+/// Returns the larger of two positions.
+///
+/// State and combine function for the `max(lexo)` aggregate.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_larger('A', 'B');  -- Returns 'B'
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_larger(a: Lexo, b: Lexo) -> Lexo {
+    if b > a { b } else { a }
+}
+
+// The sort operators let the planner answer MIN/MAX with a btree index probe.
+extension_sql!(
+    r#"
+CREATE AGGREGATE min(lexo) (
+    SFUNC = lexo_smaller,
+    STYPE = lexo,
+    COMBINEFUNC = lexo_smaller,
+    SORTOP = <,
+    PARALLEL = SAFE
+);
+CREATE AGGREGATE max(lexo) (
+    SFUNC = lexo_larger,
+    STYPE = lexo,
+    COMBINEFUNC = lexo_larger,
+    SORTOP = >,
+    PARALLEL = SAFE
+);
+"#,
+    name = "lexo_min_max_aggregates",
+    requires = [lexo_smaller, lexo_larger, lexo_lt, lexo_gt]
+);
+
+// The window function API isn't part of pgrx's bindings.
+unsafe extern "C-unwind" {
+    fn WinGetCurrentPosition(winobj: *mut pg_sys::WindowObjectData) -> i64;
+    fn WinSetMarkPosition(winobj: *mut pg_sys::WindowObjectData, markpos: i64);
+}
+
+/// Returns the position of the current row in its window partition.
+///
+/// A window function, so it is called as `lexo_assign_rank() OVER (...)`.
+/// Like `row_number()`, it numbers the rows of each partition in window order
+/// and ignores the frame, so rows that tie in the window's `ORDER BY` still
+/// get distinct, increasing positions. The number is turned into a position
+/// with `append_rank()`.
+///
+/// # Safety
+/// `fcinfo` must be the call info of a window function call, which is how
+/// PostgreSQL calls a `WINDOW` function.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    sql = r#"
+CREATE FUNCTION lexo_assign_rank() RETURNS lexo
+WINDOW IMMUTABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_assign_rank_wrapper';
+"#
+)]
+pub unsafe fn lexo_assign_rank(fcinfo: pg_sys::FunctionCallInfo) -> Lexo {
+    let winobj = unsafe { (*fcinfo).context }.cast::<pg_sys::WindowObjectData>();
+    let row = unsafe { WinGetCurrentPosition(winobj) };
+    // Earlier rows are never read again
+    unsafe { WinSetMarkPosition(winobj, row) };
+    Lexo::new(append_rank(row as u64))
+}
+
+// BRIN minmax support: PostgreSQL's generic minmax procedures look up the
+// comparison operators in the family, so the btree operators are all it needs.
+// Block ranges summarize well when positions follow insertion order, as in
+// append-only tables filled with `lexo_next()`.
+extension_sql!(
+    r#"
+CREATE OPERATOR CLASS lexo_minmax_ops DEFAULT FOR TYPE lexo USING brin AS
+    OPERATOR 1 <,
+    OPERATOR 2 <=,
+    OPERATOR 3 =,
+    OPERATOR 4 >=,
+    OPERATOR 5 >,
+    FUNCTION 1 brin_minmax_opcinfo(internal),
+    FUNCTION 2 brin_minmax_add_value(internal, internal, internal, internal),
+    FUNCTION 3 brin_minmax_consistent(internal, internal, internal),
+    FUNCTION 4 brin_minmax_union(internal, internal, internal);
+"#,
+    name = "lexo_brin_minmax",
+    requires = [lexo_lt, lexo_le, lexo_eq, lexo_ge, lexo_gt]
+);
+
+/// Sorts an array of positions in ascending order.
+///
+/// # Arguments
+/// * `ranks` - The positions to sort (must not contain NULL elements)
+///
+/// # Returns
+/// A new array with the same positions in ascending order
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_array_sort(ARRAY['I', 'H', 'Gz']::lexo[]);  -- Returns {Gz,H,I}
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_array_sort(ranks: Array<'_, Lexo>) -> Vec<Lexo> {
+    let mut ranks = collect_ranks(ranks);
+    ranks.sort();
+    ranks
+}
+
+/// Checks whether an array of positions is in ascending order.
+///
+/// Equal adjacent positions are considered sorted.
+///
+/// # Arguments
+/// * `ranks` - The positions to check (must not contain NULL elements)
+///
+/// # Returns
+/// `true` if every position is less than or equal to the next one
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_array_is_sorted(ARRAY['Gz', 'H', 'I']::lexo[]);  -- Returns true
+/// SELECT lexo_array_is_sorted(ARRAY['I', 'H']::lexo[]);        -- Returns false
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_array_is_sorted(ranks: Array<'_, Lexo>) -> bool {
+    collect_ranks(ranks).is_sorted()
+}
+
+/// Returns the position for a new element at zero-based `index` of a sorted array.
+///
+/// The array itself is not changed; see `lexo_array_with_insert()`. Index 0
+/// (or less) prepends, an index at or past the length appends, and an empty
+/// array gets the first position.
+///
+/// # Arguments
+/// * `ranks` - The positions, in ascending order (must not contain NULL elements)
+/// * `index` - Zero-based index the new element should get
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_array_insert_at(ARRAY['H', 'V']::lexo[], 1);  -- Returns 'O'
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_array_insert_at(ranks: Array<'_, Lexo>, index: i32) -> Lexo {
+    let ranks = collect_ranks(ranks);
+    let index = array_insert_index(&ranks, index);
+    array_insert_position(&ranks, index)
+}
+
+/// Returns a sorted array with a new position spliced in at zero-based `index`.
+///
+/// The new element is the position `lexo_array_insert_at()` returns for the
+/// same arguments, so the result stays sorted.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_array_with_insert(ARRAY['H', 'V']::lexo[], 0);  -- Returns {G,H,V}
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_array_with_insert(ranks: Array<'_, Lexo>, index: i32) -> Vec<Lexo> {
+    let mut ranks = collect_ranks(ranks);
+    let index = array_insert_index(&ranks, index);
+    let position = array_insert_position(&ranks, index);
+    ranks.insert(index, position);
+    ranks
+}
+
+/// Checks that `ranks` is sorted and clamps `index` to an insertion point.
+fn array_insert_index(ranks: &[Lexo], index: i32) -> usize {
+    if let Some(idx) = ranks.windows(2).position(|pair| pair[0] > pair[1]) {
+        pgrx::error!(
+            "lexo array must be sorted in ascending order (element {} sorts after element {})",
+            idx + 1,
+            idx + 2
+        );
+    }
+    (index.max(0) as usize).min(ranks.len())
+}
+
+/// Returns a position between the elements around insertion point `index`.
+fn array_insert_position(ranks: &[Lexo], index: usize) -> Lexo {
+    let before = index.checked_sub(1).map(|i| ranks[i].clone());
+    let after = ranks.get(index).cloned();
+    if before.is_some() && before == after {
+        pgrx::error!(
+            "no position fits between the equal elements {} and {} ('{}')",
+            index,
+            index + 1,
+            ranks[index]
+        );
+    }
+    lexo_between(before, after)
+}
+
+/// Returns the positions a rebalance would assign to an array of positions.
+///
+/// The result has the same length as the input, and element `i` is the new
+/// position for input element `i`, so the input does not need to be sorted.
+/// The new positions are the ones `lexo_rebalance()` hands out for a list in
+/// that order. Equal positions keep their order from the array.
+///
+/// # Arguments
+/// * `ranks` - The positions to rebalance (must not contain NULL elements)
+///
+/// # Returns
+/// A new array of balanced positions in the same relative order as `ranks`
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_rebalance_array(ARRAY['Hzzz', 'H', 'Hz']::lexo[]);  -- Returns {pfKfKf,AKfKfK,V}
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_rebalance_array(ranks: Array<'_, Lexo>) -> Vec<Lexo> {
+    let ranks = collect_ranks(ranks);
+    rebalance_positions(&ranks)
+        .into_iter()
+        .map(Lexo::new)
+        .collect()
+}
+
+/// Rebalances the ranks of a JSON array of objects.
+///
+/// Each object's rank is read from the `rank_key` field. Objects are ordered by
+/// their rank and given fresh balanced ranks, with objects whose rank is
+/// missing or not a valid position placed last in array order. The array keeps
+/// its element order and every other field is left untouched, including nested
+/// values; only the `rank_key` field of each object is rewritten.
+///
+/// # Arguments
+/// * `doc` - A JSON array of objects
+/// * `rank_key` - The field holding each object's rank (defaults to `rank`)
+///
+/// # Returns
+/// The document with a new rank in every object
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_rebalance_jsonb('[{"id": 1, "rank": "Hz"}, {"id": 2, "rank": "H"}, {"id": 3}]');
+/// -- Returns [{"id": 1, "rank": "V"}, {"id": 2, "rank": "AKfKfK"}, {"id": 3, "rank": "pfKfKf"}]
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_rebalance_jsonb(doc: pgrx::JsonB, rank_key: default!(&str, "'rank'")) -> pgrx::JsonB {
+    let mut doc = doc.0;
+    rebalance_json(&mut doc, rank_key).unwrap_or_else(|message| pgrx::error!("{}", message));
+    pgrx::JsonB(doc)
+}
+
+/// Rewrites the `rank_key` field of every object in a JSON array with a balanced rank.
+pub(crate) fn rebalance_json(doc: &mut Value, rank_key: &str) -> Result<(), String> {
+    let items = match doc {
+        Value::Array(items) => items,
+        other => {
+            return Err(format!(
+                "document must be a JSON array of objects, got {}",
+                json_kind(other)
+            ));
+        }
+    };
+
+    let mut ranks = Vec::with_capacity(items.len());
+    for (idx, item) in items.iter().enumerate() {
+        let Value::Object(fields) = item else {
+            return Err(format!(
+                "element {} of the document must be a JSON object, got {}",
+                idx,
+                json_kind(item)
+            ));
+        };
+        ranks.push(match fields.get(rank_key) {
+            Some(Value::String(rank)) if crate::rank::validate(rank).is_ok() => Some(rank.as_str()),
+            _ => None,
+        });
+    }
+
+    let positions = rebalance_positions_missing_last(&ranks);
+    for (item, position) in items.iter_mut().zip(positions) {
+        if let Value::Object(fields) = item {
+            fields.insert(rank_key.to_string(), Value::String(position));
+        }
+    }
+    Ok(())
+}
+
+fn json_kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Returns an array of evenly spread positions between two positions.
+///
+/// # Arguments
+/// * `before_pos` - The lower bound (can be NULL for beginning)
+/// * `after_pos` - The upper bound (can be NULL for end)
+/// * `count` - The number of positions to generate
+///
+/// # Returns
+/// `count` strictly increasing positions between `before_pos` and `after_pos`
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_array_fill_between('A', 'B', 3);     -- Returns {A8,AH,Ae}
+/// SELECT lexo_array_fill_between(NULL, NULL, 3);   -- Returns three positions around 'H'
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_array_fill_between(
+    before_pos: Option<Lexo>,
+    after_pos: Option<Lexo>,
+    count: i32,
+) -> Vec<Lexo> {
+    if count < 0 {
+        pgrx::error!("count must not be negative, got {}", count);
+    }
+
+    let before_str = bound_str(&before_pos);
+    let after_str = bound_str(&after_pos);
+
+    if !before_str.is_empty() && !after_str.is_empty() && before_str >= after_str {
+        pgrx::error!(
+            "before position '{}' must be less than after position '{}'",
+            before_str,
+            after_str
+        );
+    }
+
+    generate_n_between(before_str, after_str, count as usize)
+        .into_iter()
+        .map(Lexo::new)
+        .collect()
+}
+
+/// Returns positions between two positions, bisecting the gap breadth-first.
+///
+/// Yields the midpoint first, then the midpoints of both halves, then of the
+/// four quarters, and so on. Rows are computed as they are fetched, so limit
+/// the set with `LIMIT`; positions grow longer as the gaps tighten, and every
+/// position is distinct and strictly between the bounds. The set ends after
+/// `max_count` rows, or earlier when the gap cannot hold that many positions.
+///
+/// # Arguments
+/// * `before_pos` - The lower bound (can be NULL for beginning)
+/// * `after_pos` - The upper bound (can be NULL for end)
+/// * `max_count` - Hard cap on the rows returned (default: 1000000)
+///
+/// # Example
+/// ```sql
+/// SELECT * FROM lexo_midpoints('A', 'B') LIMIT 3;  -- Returns AH, A8, Ae
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_midpoints(
+    before_pos: Option<Lexo>,
+    after_pos: Option<Lexo>,
+    max_count: default!(i32, 1000000),
+) -> SetOfIterator<'static, Lexo> {
+    if max_count < 0 {
+        pgrx::error!("max_count must not be negative, got {}", max_count);
+    }
+
+    let before_str = bound_str(&before_pos);
+    let after_str = bound_str(&after_pos);
+
+    if !before_str.is_empty() && !after_str.is_empty() && before_str >= after_str {
+        pgrx::error!(
+            "before position '{}' must be less than after position '{}'",
+            before_str,
+            after_str
+        );
+    }
+
+    SetOfIterator::new(
+        midpoints(before_str, after_str)
+            .take(max_count as usize)
+            .map(Lexo::new),
+    )
+}
+
+fn collect_ranks(ranks: Array<'_, Lexo>) -> Vec<Lexo> {
+    ranks
+        .iter()
+        .enumerate()
+        .map(|(idx, rank)| {
+            rank.unwrap_or_else(|| {
+                pgrx::error!(
+                    "lexo array must not contain NULL elements (found at index {})",
+                    idx + 1
+                )
+            })
+        })
+        .collect()
+}
+
+/// Btree sort support for `lexo`, registered as support function 2 of `lexo_btree_ops`.
+///
+/// Sorts and index builds compare the stored bytes directly instead of going
+/// through `lexo_cmp()`, and when PostgreSQL asks for abbreviation the first
+/// 8 bytes of each position are packed into the sort Datum so most comparisons
+/// never touch the stored value at all.
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_sortsupport(ssup: pgrx::Internal) {
+    let ssup = ssup
+        .unwrap()
+        .expect("sortsupport called without SortSupport")
+        .cast_mut_ptr::<pg_sys::SortSupportData>();
+
+    unsafe {
+        (*ssup).comparator = Some(lexo_sort_cmp);
+        if (*ssup).abbreviate {
+            let state = pg_sys::MemoryContextAlloc((*ssup).ssup_cxt, size_of::<AbbrevState>())
+                .cast::<AbbrevState>();
+            state.write(AbbrevState {
+                abbreviated: DistinctCounter::new(),
+                full: DistinctCounter::new(),
+                prop_card: 0.20,
+            });
+            (*ssup).ssup_extra = state.cast();
+            (*ssup).abbrev_full_comparator = Some(lexo_sort_cmp);
+            (*ssup).comparator = Some(lexo_sort_abbrev_cmp);
+            (*ssup).abbrev_converter = Some(lexo_sort_abbrev_convert);
+            (*ssup).abbrev_abort = Some(lexo_sort_abbrev_abort);
+        }
+    }
+}
+
+/// Distinct counts `lexo_sort_abbrev_abort` weighs, kept in `ssup_extra`
+struct AbbrevState {
+    abbreviated: DistinctCounter,
+    full: DistinctCounter,
+    /// Fraction of the full keys' cardinality the abbreviated keys must reach
+    prop_card: f64,
+}
+
+unsafe fn datum_to_lexo(datum: pg_sys::Datum) -> Lexo {
+    unsafe { Lexo::from_datum(datum, false) }.expect("lexo sort key is NULL")
+}
+
+#[pg_guard]
+unsafe extern "C-unwind" fn lexo_sort_cmp(
+    x: pg_sys::Datum,
+    y: pg_sys::Datum,
+    _ssup: pg_sys::SortSupport,
+) -> c_int {
+    unsafe {
+        let (x_ptr, y_ptr) = (x.cast_mut_ptr(), y.cast_mut_ptr());
+        let a = pg_sys::pg_detoast_datum_packed(x_ptr);
+        let b = pg_sys::pg_detoast_datum_packed(y_ptr);
+        let a_text = cbor_text_bytes(pgrx::varlena::varlena_to_byte_slice(a));
+        let b_text = cbor_text_bytes(pgrx::varlena::varlena_to_byte_slice(b));
+        let ordering = match (a_text, b_text) {
+            (Some(a_text), Some(b_text)) => a_text.cmp(b_text),
+            // Values in the map form older versions wrote need a full decode
+            _ => datum_to_lexo(x).cmp(&datum_to_lexo(y)),
+        };
+        if a != x_ptr {
+            pg_sys::pfree(a.cast());
+        }
+        if b != y_ptr {
+            pg_sys::pfree(b.cast());
+        }
+        ordering as c_int
+    }
+}
+
+#[pg_guard]
+unsafe extern "C-unwind" fn lexo_sort_abbrev_cmp(
+    x: pg_sys::Datum,
+    y: pg_sys::Datum,
+    _ssup: pg_sys::SortSupport,
+) -> c_int {
+    x.value().cmp(&y.value()) as c_int
+}
+
+#[pg_guard]
+unsafe extern "C-unwind" fn lexo_sort_abbrev_convert(
+    original: pg_sys::Datum,
+    ssup: pg_sys::SortSupport,
+) -> pg_sys::Datum {
+    let rank = unsafe { datum_to_lexo(original) };
+    let key = abbreviated_key(rank.as_str());
+    let state = unsafe { &mut *(*ssup).ssup_extra.cast::<AbbrevState>() };
+    state.abbreviated.add(key);
+    state.full.add_bytes(rank.as_str().as_bytes());
+    pg_sys::Datum::from(key as usize)
+}
+
+// Same policy as varstr_abbrev_abort() for text: keep abbreviating while the
+// abbreviated keys stay at least `prop_card` as distinct as the full values,
+// relaxing the bar as the sort grows. Below that, most abbreviated comparisons
+// tie and fall through to the full comparator, which then costs more than
+// the abbreviation saves.
+#[pg_guard]
+unsafe extern "C-unwind" fn lexo_sort_abbrev_abort(
+    memtupcount: c_int,
+    ssup: pg_sys::SortSupport,
+) -> bool {
+    if memtupcount < 100 {
+        return false;
+    }
+
+    let state = unsafe { &mut *(*ssup).ssup_extra.cast::<AbbrevState>() };
+    let abbrev_distinct = state.abbreviated.estimate().max(1.0);
+    let key_distinct = state.full.estimate().max(1.0);
+
+    if abbrev_distinct > key_distinct * state.prop_card {
+        if memtupcount > 10000 {
+            state.prop_card *= 0.65;
+        }
+        return false;
+    }
+    true
+}
+
+/// Returns the fractional distance between two positions.
+///
+/// Used as the `subtype_diff` function of the `lexorange` range type, which
+/// GiST indexes rely on to build balanced trees. Each position is read as a
+/// fraction in `[0, 1)` and the result is `a - b`.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_range_diff('V', '0');  -- Returns 0.5
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_range_diff(a: Lexo, b: Lexo) -> f64 {
+    position_to_fraction(a.as_str()) - position_to_fraction(b.as_str())
+}
+
+/// Returns the next position after the maximum in a table column.
+///
+/// This function queries the specified table to find the maximum position value
+/// in the given column, then returns a position that comes after it.
+///
+/// Calls in the same transaction also come after the position the previous call
+/// returned for the same list, even before that row is inserted, so
+/// `INSERT ... SELECT lexo_next(...) FROM ...` gives every row its own position.
+/// Set `lexo.next_cache` to off to derive each result only from the rows the
+/// statement can see.
+///
+/// Under row-level security the maximum is taken over the rows the policies
+/// let the caller see, so the result can sort before, or equal, a hidden
+/// row's position. Filter by the column the policy checks to keep each
+/// visible list separate.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `identifier_column_name` - Optional: column to filter by (e.g., 'collection_id')
+/// * `identifier_value` - Optional: value to filter by
+/// * `empty_default` - Optional: position to return when no rows match (default 'H')
+/// * `only` - Optional: leave out inheritance children and partitions, as
+///   `FROM ONLY` does (default false)
+///
+/// # Returns
+/// A new Lexo after the maximum, or `empty_default` ('H' if NULL) if table is empty
+///
+/// # Example
+/// ```sql
+/// -- Get next position for entire table
+/// SELECT lexo_next('items', 'position', NULL, NULL);
+///
+/// -- Get next position for a specific collection
+/// SELECT lexo_next('collection_songs', 'position', 'collection_id', 'abc-123');
+///
+/// -- Start an append-only log low, keeping the keyspace above free
+/// SELECT lexo_next('events', 'position', NULL, NULL, empty_default => '1');
+/// ```
+#[pg_extern(cost = 100)]
+pub fn lexo_next(
+    table_name: &str,
+    lexo_column_name: &str,
+    identifier_column_name: Option<&str>,
+    identifier_value: Option<&str>,
+    empty_default: default!(Option<Lexo>, "NULL"),
+    only: default!(bool, false),
+) -> Lexo {
+    with_pinned_search_path(|| {
+        let relation = require_columns(
+            table_name,
+            &[Some(lexo_column_name), identifier_column_name],
+        );
+        if empty_default.as_ref().is_some_and(Lexo::is_empty) {
+            pgrx::error!("empty_default must be a non-empty position");
+        }
+
+        let max_position = query_max_position(
+            table_name,
+            lexo_column_name,
+            identifier_column_name,
+            identifier_value,
+            only,
+        )
+        .map(Lexo::into_inner);
+
+        let cache_key = crate::gucs::NEXT_CACHE.get().then(|| next_cache::ListKey {
+            relation,
+            column: lexo_column_name.to_string(),
+            filter_column: identifier_column_name.map(str::to_string),
+            filter_value: identifier_value.map(str::to_string),
+            only,
+        });
+        let handed_out = cache_key.as_ref().and_then(next_cache::last);
+        let latest = max_position.max(handed_out);
+
+        let result = match latest {
+            Some(pos) => Lexo::new(generate_after(&pos)),
+            None => empty_default.unwrap_or_else(Lexo::first),
+        };
+        if let Some(key) = cache_key {
+            next_cache::remember(key, result.as_str().to_string());
+        }
+        stats::count_call(Operation::Next, result.as_str());
+        result
+    })
+}
+
+/// Returns the next position after the maximum, serialized per list.
+///
+/// Like `lexo_next()`, but first takes a transaction-scoped advisory lock on
+/// (table, column, filter), so concurrent transactions appending to the same
+/// list are handed distinct, correctly ordered positions. The lock is held until
+/// the transaction ends; insert the returned position in the same transaction.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `identifier_column_name` - Optional: column to filter by (e.g., 'collection_id')
+/// * `identifier_value` - Optional: value to filter by
+/// * `empty_default` - Optional: position to return when no rows match (default 'H')
+///
+/// # Example
+/// ```sql
+/// BEGIN;
+/// INSERT INTO items (collection_id, position)
+/// VALUES (42, lexo_claim_next('items', 'position', 'collection_id', '42'));
+/// COMMIT;
+/// ```
+#[pg_extern(cost = 100)]
+pub fn lexo_claim_next(
+    table_name: &str,
+    lexo_column_name: &str,
+    identifier_column_name: Option<&str>,
+    identifier_value: Option<&str>,
+    empty_default: default!(Option<Lexo>, "NULL"),
+) -> Lexo {
+    with_pinned_search_path(|| {
+        lock_list(&table_list_lock_key(
+            table_name,
+            lexo_column_name,
+            identifier_column_name,
+            identifier_value,
+        ));
+        lexo_next(
+            table_name,
+            lexo_column_name,
+            identifier_column_name,
+            identifier_value,
+            empty_default,
+            false,
+        )
+    })
+}
+
+// Columns appended to with lexo_seq_next(): the prefix every sequence position
+// starts with, the sequence counting them, and the key column of per-list
+// sequences. With a key column, each list gets its own prefix and sequence in
+// lexo_sequence_keys the first time it is appended to, and the column's own
+// sequence only numbers those.
+extension_sql!(
+    r#"
+CREATE TABLE lexo_sequence_appends (
+    table_name regclass NOT NULL,
+    column_name name NOT NULL,
+    prefix text NOT NULL,
+    sequence_name regclass NOT NULL,
+    key_column name,
+    PRIMARY KEY (table_name, column_name)
+);
+SELECT pg_catalog.pg_extension_config_dump('lexo_sequence_appends', '');
+
+CREATE TABLE lexo_sequence_keys (
+    table_name regclass NOT NULL,
+    column_name name NOT NULL,
+    key_value text NOT NULL,
+    prefix text NOT NULL,
+    sequence_name regclass NOT NULL,
+    PRIMARY KEY (table_name, column_name, key_value)
+);
+SELECT pg_catalog.pg_extension_config_dump('lexo_sequence_keys', '');
+"#,
+    name = "lexo_sequence_appends"
+);
+
+/// Most sequence values `lexo_seq_next()` skips to get past a position
+/// appended by other means, before it gives up.
+const SEQUENCE_SKIP_LIMIT: u64 = 1000;
+
+/// Sets up `lexo_seq_next()` for a column.
+///
+/// Creates a sequence owned by the column, named `<table>_<column>_lexo_seq`,
+/// and records a prefix that sorts after every position the column holds
+/// now. From then on every `lexo_seq_next()` position is greater than the
+/// existing ones and than every earlier `lexo_seq_next()` position. Without
+/// a key column, one sequence serves all lists of the table: a counter that
+/// increases across the table also increases within each list. With one,
+/// each list gets its own sequence and a prefix after its own positions,
+/// created the first time `lexo_seq_next()` appends to it.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `key_column_name` - Optional: column identifying the list, for a sequence per list
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_enable_sequence_append('messages', 'position', 'channel_id');
+/// ```
+#[pg_extern]
+pub fn lexo_enable_sequence_append(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: default!(Option<&str>, "NULL"),
+) {
+    with_pinned_search_path(|| {
+        let quoted_table = quote_table_name(table_name);
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+        let (_, sort_key, _) = position_sort_key(table_name, &quoted_table, lexo_column_name);
+        if let Some(key_col) = key_column_name {
+            column_info(table_name, &quoted_table, key_col);
+        }
+
+        let enabled = Spi::get_one::<bool>(&format!(
+            "SELECT EXISTS (SELECT 1 FROM {}.lexo_sequence_appends \
+             WHERE table_name = {}::regclass AND column_name = {})",
+            extension_schema(),
+            quote_literal(&quoted_table),
+            quote_literal(lexo_column_name)
+        ))
+        .expect("Failed to look up lexo_sequence_appends");
+        if enabled == Some(true) {
+            pgrx::error!(
+                "sequence append is already enabled for {}.{}",
+                table_name,
+                lexo_column_name
+            );
+        }
+
+        let max_position = Spi::get_one::<String>(&format!(
+            "SELECT max({})::text FROM {}",
+            sort_key, quoted_table
+        ))
+        .expect("Failed to find the maximum position");
+        let prefix = generate_after(max_position.as_deref().unwrap_or(""));
+
+        let quoted_sequence =
+            create_append_sequence(&quoted_table, &quoted_lexo_column, lexo_column_name, "");
+
+        Spi::run(&format!(
+            "INSERT INTO {}.lexo_sequence_appends \
+             (table_name, column_name, prefix, sequence_name, key_column) \
+             VALUES ({}::regclass, {}, {}, {}::regclass, {})",
+            extension_schema(),
+            quote_literal(&quoted_table),
+            quote_literal(lexo_column_name),
+            quote_literal(&prefix),
+            quote_literal(&quoted_sequence),
+            key_column_name
+                .map(quote_literal)
+                .unwrap_or_else(|| "NULL".to_string())
+        ))
+        .expect("Failed to record sequence append");
+    })
+}
+
+/// Creates the sequence `<table>_<column>_lexo_seq<suffix>`, owned by the
+/// column, and returns its qualified name.
+fn create_append_sequence(
+    quoted_table: &str,
+    quoted_lexo_column: &str,
+    lexo_column_name: &str,
+    suffix: &str,
+) -> String {
+    // An owned sequence must live in its table's schema
+    let (schema, table) = Spi::get_two::<String, String>(&format!(
+        "SELECT relnamespace::regnamespace::text, relname::text FROM pg_catalog.pg_class \
+         WHERE oid = {}::regclass",
+        quote_literal(quoted_table)
+    ))
+    .expect("Failed to look up the table's schema");
+    let quoted_sequence = format!(
+        "{}.{}",
+        schema.expect("schema was NULL"),
+        quote_identifier(format!(
+            "{}_{}_lexo_seq{}",
+            table.expect("table name was NULL"),
+            lexo_column_name,
+            suffix
+        ))
+    );
+    Spi::run(&format!(
+        "CREATE SEQUENCE {} AS bigint MINVALUE 0 START 0 OWNED BY {}.{}",
+        quoted_sequence, quoted_table, quoted_lexo_column
+    ))
+    .expect("Failed to create append sequence");
+    quoted_sequence
+}
+
+/// Returns the prefix and sequence of one list of a column appended to with a
+/// sequence per list, creating them the first time.
+fn list_append_sequence(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: &str,
+    key_value: &str,
+    column_sequence: &str,
+) -> (String, String) {
+    let quoted_table = quote_table_name(table_name);
+    let lookup = format!(
+        "SELECT prefix, sequence_name::text FROM {}.lexo_sequence_keys \
+         WHERE table_name = {}::regclass AND column_name = {} AND key_value = {}",
+        extension_schema(),
+        quote_literal(&quoted_table),
+        quote_literal(lexo_column_name),
+        quote_literal(key_value)
+    );
+    let find = || {
+        Spi::get_two::<String, String>(&lookup)
+            .ok()
+            .and_then(|(prefix, sequence)| prefix.zip(sequence))
+    };
+    if let Some(found) = find() {
+        return found;
+    }
+
+    let (_, sort_key, _) = position_sort_key(table_name, &quoted_table, lexo_column_name);
+    let max_position = Spi::get_one::<String>(&format!(
+        "SELECT max({})::text FROM {}{}",
+        sort_key,
+        quoted_table,
+        list_filter(Some(key_column_name), Some(key_value))
+    ))
+    .expect("Failed to find the maximum position");
+    let prefix = generate_after(max_position.as_deref().unwrap_or(""));
+
+    let number = Spi::get_one::<i64>(&format!(
+        "SELECT nextval({})",
+        quote_literal(column_sequence)
+    ))
+    .expect("Failed to number the list's sequence")
+    .expect("nextval returned NULL");
+    let quoted_sequence = create_append_sequence(
+        &quoted_table,
+        &quote_identifier(lexo_column_name),
+        lexo_column_name,
+        &format!("_{}", number),
+    );
+
+    // A concurrent first append to the same list may have recorded its own
+    let recorded = Spi::get_one::<String>(&format!(
+        "INSERT INTO {}.lexo_sequence_keys \
+         (table_name, column_name, key_value, prefix, sequence_name) \
+         VALUES ({}::regclass, {}, {}, {}, {}::regclass) \
+         ON CONFLICT DO NOTHING RETURNING sequence_name::text",
+        extension_schema(),
+        quote_literal(&quoted_table),
+        quote_literal(lexo_column_name),
+        quote_literal(key_value),
+        quote_literal(&prefix),
+        quote_literal(&quoted_sequence)
+    ));
+    match recorded {
+        Ok(Some(_)) => (prefix, quoted_sequence),
+        _ => {
+            Spi::run(&format!("DROP SEQUENCE {}", quoted_sequence))
+                .expect("Failed to drop append sequence");
+            find().expect("list append sequence vanished")
+        }
+    }
+}
+
+/// Returns the next append position of a column, without locking its tail.
+///
+/// The position is the list's prefix followed by the next value of its
+/// sequence, encoded as fixed-width Base62 behind a width character, so
+/// concurrent appenders never wait for each other. Positions keep increasing
+/// when the counter outgrows its width: the next width's positions are one
+/// character longer and sort after the narrower ones, so no rewrite is
+/// needed.
+///
+/// `lexo_between()` between any two rows, including sequence ones, keeps
+/// working. Other appends (`lexo_next()`, `lexo_after()`) can overtake the
+/// sequence, so each position is checked against the list's positions at or
+/// above it, one probe of an index on the column. If one is there, the
+/// sequence skips past it; if that would take more than `SEQUENCE_SKIP_LIMIT`
+/// values, or no value of the prefix sorts after it, this raises
+/// `object_not_in_prerequisite_state`.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `key_value` - The list to append to, when the column has a sequence per list
+///
+/// # Example
+/// ```sql
+/// INSERT INTO messages (channel_id, body, position)
+/// VALUES (7, 'hi', lexo_seq_next('messages', 'position', '7'));
+/// ```
+#[pg_extern]
+pub fn lexo_seq_next(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_value: default!(Option<&str>, "NULL"),
+) -> Lexo {
+    with_pinned_search_path(|| {
+        let quoted_table = quote_table_name(table_name);
+
+        let query = format!(
+            "SELECT prefix, sequence_name::text, key_column::text FROM {}.lexo_sequence_appends \
+             WHERE table_name = pg_catalog.to_regclass({}) AND column_name = {}",
+            extension_schema(),
+            quote_literal(&quoted_table),
+            quote_literal(lexo_column_name)
+        );
+        let enabled = Spi::connect(|client| {
+            client
+                .select(&query, Some(1), &[])
+                .expect("Failed to look up the append sequence")
+                .next()
+                .map(|row| {
+                    (
+                        row.get::<String>(1)
+                            .expect("Failed to get prefix")
+                            .expect("prefix was NULL"),
+                        row.get::<String>(2)
+                            .expect("Failed to get sequence")
+                            .expect("sequence was NULL"),
+                        row.get::<String>(3).expect("Failed to get key column"),
+                    )
+                })
+        });
+        let Some((prefix, sequence, key_column)) = enabled else {
+            raise_error!(
+                PgSqlErrorCode::ERRCODE_OBJECT_NOT_IN_PREREQUISITE_STATE,
+                format!(
+                    "sequence append is not enabled for {}.{}",
+                    table_name, lexo_column_name
+                ),
+                hint = "Call lexo_enable_sequence_append() for the column first.",
+            )
+        };
+
+        let (prefix, sequence) = match (key_column.as_deref(), key_value) {
+            (None, None) => (prefix, sequence),
+            (Some(key_col), Some(value)) => {
+                list_append_sequence(table_name, lexo_column_name, key_col, value, &sequence)
+            }
+            (Some(key_col), None) => pgrx::error!(
+                "sequence append for {}.{} is per {}: key_value must not be NULL",
+                table_name,
+                lexo_column_name,
+                key_col
+            ),
+            (None, Some(_)) => pgrx::error!(
+                "sequence append for {}.{} has no key column: key_value must be NULL",
+                table_name,
+                lexo_column_name
+            ),
+        };
+
+        let next_value = |count: u64| -> u64 {
+            Spi::get_one::<i64>(&format!(
+                "SELECT max(nextval({})) FROM pg_catalog.generate_series(1, {})",
+                quote_literal(&sequence),
+                count
+            ))
+            .expect("Failed to read the append sequence")
+            .expect("nextval returned NULL") as u64
+        };
+        let mut counter = next_value(1);
+        let mut position = sequence_position(&prefix, counter);
+
+        // Appends by other means may have reached past the sequence
+        let (_, sort_key, _) = position_sort_key(table_name, &quoted_table, lexo_column_name);
+        let overtaken = Spi::get_one::<String>(&format!(
+            "SELECT max({sort_key})::text FROM {}{}",
+            quoted_table,
+            filter_and(
+                &list_filter(key_column.as_deref(), key_value),
+                &format!("{sort_key} >= {}", quote_literal(&position))
+            )
+        ))
+        .expect("Failed to check the list's maximum position");
+        if let Some(max_position) = overtaken {
+            match sequence_counter_after(&prefix, &max_position) {
+                Some(needed) if needed - counter <= SEQUENCE_SKIP_LIMIT => {
+                    counter = next_value(needed - counter);
+                    position = sequence_position(&prefix, counter);
+                }
+                _ => {
+                    raise_error!(
+                        PgSqlErrorCode::ERRCODE_OBJECT_NOT_IN_PREREQUISITE_STATE,
+                        format!(
+                            "lexo_seq_next() cannot append after '{}' in {}.{}",
+                            max_position, table_name, lexo_column_name
+                        ),
+                        hint = "Append with lexo_seq_next() only. Disabling and enabling sequence append again starts after the current maximum.",
+                    );
+                }
+            }
+        }
+
+        let result = Lexo::new(position);
+        stats::count_call(Operation::Next, result.as_str());
+        result
+    })
+}
+
+/// Stops `lexo_seq_next()` for a column and drops its sequences.
+///
+/// Positions already handed out stay in the table.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_disable_sequence_append('messages', 'position');
+/// ```
+#[pg_extern]
+pub fn lexo_disable_sequence_append(table_name: &str, lexo_column_name: &str) {
+    with_pinned_search_path(|| {
+        let quoted_table = quote_table_name(table_name);
+
+        let query = format!(
+            "WITH lists AS (DELETE FROM {schema}.lexo_sequence_keys \
+                 WHERE table_name = {table}::regclass AND column_name = {col} \
+                 RETURNING sequence_name), \
+             columns AS (DELETE FROM {schema}.lexo_sequence_appends \
+                 WHERE table_name = {table}::regclass AND column_name = {col} \
+                 RETURNING sequence_name) \
+             SELECT (SELECT sequence_name::text FROM columns), \
+                    (SELECT pg_catalog.string_agg(sequence_name::text, ', ') FROM lists)",
+            schema = extension_schema(),
+            table = quote_literal(&quoted_table),
+            col = quote_literal(lexo_column_name)
+        );
+        let (sequence, list_sequences) = Spi::get_two::<String, String>(&query)
+            .expect("Failed to remove from lexo_sequence_appends");
+        match sequence {
+            Some(sequence) => {
+                let sequences = match list_sequences {
+                    Some(lists) => format!("{}, {}", sequence, lists),
+                    None => sequence,
+                };
+                Spi::run(&format!("DROP SEQUENCE IF EXISTS {}", sequences))
+                    .expect("Failed to drop append sequence")
+            }
+            None => pgrx::error!(
+                "sequence append is not enabled for {}.{}",
+                table_name,
+                lexo_column_name
+            ),
+        }
+    })
+}
+
+/// Returns the next position of a descending list, before the minimum.
+///
+/// The counterpart of `lexo_next()` for lists displayed with
+/// `ORDER BY position DESC`: appending puts the new row at the end of the
+/// display, so it gets a position below every existing one.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `identifier_column_name` - Optional: column to filter by (e.g., 'collection_id')
+/// * `identifier_value` - Optional: value to filter by
+/// * `empty_default` - Optional: position to return when no rows match (default 'i')
+/// * `only` - Optional: leave out inheritance children and partitions, as
+///   `FROM ONLY` does (default false)
+///
+/// # Returns
+/// A new Lexo before the minimum, or `empty_default` ('i' if NULL) if table is empty
+///
+/// # Example
+/// ```sql
+/// INSERT INTO feed (post_id, position)
+/// VALUES (7, lexo_next_desc('feed', 'position', NULL, NULL));
+/// SELECT * FROM feed ORDER BY position DESC;  -- post 7 comes last
+/// ```
+#[pg_extern(cost = 100)]
+pub fn lexo_next_desc(
+    table_name: &str,
+    lexo_column_name: &str,
+    identifier_column_name: Option<&str>,
+    identifier_value: Option<&str>,
+    empty_default: default!(Option<Lexo>, "NULL"),
+    only: default!(bool, false),
+) -> Lexo {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[Some(lexo_column_name), identifier_column_name],
+        );
+        if empty_default.as_ref().is_some_and(Lexo::is_empty) {
+            pgrx::error!("empty_default must be a non-empty position");
+        }
+
+        let min_position = query_min_position(
+            table_name,
+            lexo_column_name,
+            identifier_column_name,
+            identifier_value,
+            only,
+        );
+
+        let result = match min_position {
+            Some(pos) => Lexo::new(generate_before(pos.as_str()).unwrap_or_else(|| {
+                raise_error!(
+                    PgSqlErrorCode::ERRCODE_PROGRAM_LIMIT_EXCEEDED,
+                    format!(
+                        "cannot add to the descending list: its lowest position '{}' is the minimum possible position",
+                        pos
+                    ),
+                    hint = "Run lexo_rebalance() on the list with descending => true to make room below it.",
+                )
+            })),
+            None => empty_default.unwrap_or_else(lexo_first_desc),
+        };
+        stats::count_call(Operation::Next, result.as_str());
+        result
+    })
+}
+
+/// Reserves a block of positions after the maximum, serialized per list.
+///
+/// Takes the same transaction-scoped advisory lock as `lexo_claim_next()`, then
+/// returns `count` increasing positions after the current maximum. The block is
+/// spread by bisection rather than single increments, so later inserts inside it
+/// have room. Concurrent loaders appending to the same list get disjoint blocks,
+/// as long as each inserts its block before committing.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `count` - Number of positions to reserve
+/// * `identifier_column_name` - Optional: column to filter by (e.g., 'collection_id')
+/// * `identifier_value` - Optional: value to filter by
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_reserve_range('items', 'position', 1000, 'collection_id', '42');
+/// ```
+#[pg_extern(cost = 100)]
+pub fn lexo_reserve_range(
+    table_name: &str,
+    lexo_column_name: &str,
+    count: i32,
+    identifier_column_name: Option<&str>,
+    identifier_value: Option<&str>,
+) -> Vec<Lexo> {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[Some(lexo_column_name), identifier_column_name],
+        );
+        if count < 0 {
+            pgrx::error!("count must not be negative, got {}", count);
+        }
+
+        lock_list(&table_list_lock_key(
+            table_name,
+            lexo_column_name,
+            identifier_column_name,
+            identifier_value,
+        ));
+        let max_position = query_max_position(
+            table_name,
+            lexo_column_name,
+            identifier_column_name,
+            identifier_value,
+            false,
+        );
+        let before = max_position.as_ref().map(|r| r.as_str()).unwrap_or("");
+
+        generate_n_between(before, "", count as usize)
+            .into_iter()
+            .map(Lexo::new)
+            .collect()
+    })
+}
+
+/// Places a new position at an approximate fraction of the way down a list.
+///
+/// Counts the rows of the list, finds the two neighbours around offset
+/// `fraction * count` in position order and returns a position between them.
+/// A fraction of 0 or less prepends, 1 or more appends, and an empty list gets
+/// the first position.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `fraction` - Where to insert, from 0.0 (start) to 1.0 (end)
+/// * `identifier_column_name` - Optional: column to filter by (e.g., 'collection_id')
+/// * `identifier_value` - Optional: value to filter by
+///
+/// # Example
+/// ```sql
+/// -- Roughly three quarters of the way down a playlist
+/// SELECT lexo_at_fraction('playlist_songs', 'position', 0.75, 'playlist_id', 'abc-123');
+/// ```
+#[pg_extern(cost = 100)]
+pub fn lexo_at_fraction(
+    table_name: &str,
+    lexo_column_name: &str,
+    fraction: f64,
+    identifier_column_name: default!(Option<&str>, "NULL"),
+    identifier_value: default!(Option<&str>, "NULL"),
+) -> Lexo {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[Some(lexo_column_name), identifier_column_name],
+        );
+        if !fraction.is_finite() {
+            pgrx::error!("fraction must be a finite number, got {}", fraction);
+        }
+
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+
+        let quoted_table = quote_table_name(table_name);
+
+        // Rows without a position sort after the list, so they must not count towards it
+        let filter = filter_and(
+            &list_filter(identifier_column_name, identifier_value),
+            &format!("{} IS NOT NULL", quoted_lexo_column),
+        );
+
+        let count_query = format!("SELECT COUNT(*) FROM {}{}", quoted_table, filter);
+        let count: i64 = Spi::get_one(&count_query)
+            .expect("Failed to count rows in table")
+            .unwrap_or(0);
+        if count == 0 {
+            return Lexo::first();
+        }
+
+        // The new row goes after the first `offset` rows
+        let offset = (fraction * count as f64).floor().clamp(0.0, count as f64) as i64;
+
+        let position_at = |index: i64| -> Option<Lexo> {
+            if index < 0 || index >= count {
+                return None;
+            }
+            let query = format!(
+                "SELECT {}::{ext}.lexo FROM {}{} ORDER BY {}::{ext}.lexo LIMIT 1 OFFSET {}",
+                quoted_lexo_column,
+                quoted_table,
+                filter,
+                quoted_lexo_column,
+                index,
+                ext = extension_schema()
+            );
+            Spi::get_one(&query).expect("Failed to query table for neighbouring position")
+        };
+
+        let before = position_at(offset - 1);
+        let after = position_at(offset);
+        counted_between(before, after)
+    })
+}
+
+/// Returns the position at percentile `pct` of a list.
+///
+/// Follows `percentile_disc`: the first position, in order, at which at least
+/// `pct` of the list's positions have been seen, so the result is always a
+/// position of the list. Rows with a NULL position are not counted. The
+/// position is read with `LIMIT 1 OFFSET n`, which an index on the position
+/// column (or on the key column and the position column) serves directly.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `pct` - The percentile, from 0.0 (first position) to 1.0 (last position)
+/// * `identifier_column_name` - Optional: column to filter by (e.g., 'collection_id')
+/// * `identifier_value` - Optional: value to filter by
+///
+/// # Returns
+/// The position at that percentile, or NULL for an empty list
+///
+/// # Example
+/// ```sql
+/// -- Split points for four shards
+/// SELECT lexo_rank_at_percentile('events', 'position', p) FROM unnest(ARRAY[0.25, 0.5, 0.75]) AS p;
+/// ```
+#[pg_extern]
+pub fn lexo_rank_at_percentile(
+    table_name: &str,
+    lexo_column_name: &str,
+    pct: f64,
+    identifier_column_name: default!(Option<&str>, "NULL"),
+    identifier_value: default!(Option<&str>, "NULL"),
+) -> Option<Lexo> {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[Some(lexo_column_name), identifier_column_name],
+        );
+        if !(0.0..=1.0).contains(&pct) {
+            pgrx::error!("pct must be between 0 and 1, got {}", pct);
+        }
+
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+        let quoted_table = quote_table_name(table_name);
+        let filter = filter_and(
+            &list_filter(identifier_column_name, identifier_value),
+            &format!("{} IS NOT NULL", quoted_lexo_column),
+        );
+
+        let count_query = format!("SELECT COUNT(*) FROM {}{}", quoted_table, filter);
+        let count: i64 = Spi::get_one(&count_query)
+            .expect("Failed to count rows in table")
+            .unwrap_or(0);
+        let index = percentile_index(count as usize, pct)?;
+
+        let query = format!(
+            "SELECT {col}::{ext}.lexo FROM {table}{filter} ORDER BY {col}::{ext}.lexo LIMIT 1 OFFSET {index}",
+            col = quoted_lexo_column,
+            table = quoted_table,
+            filter = filter,
+            index = index,
+            ext = extension_schema()
+        );
+        Spi::get_one(&query).expect("Failed to query table for the percentile position")
+    })
+}
+
+/// Returns the median position of a list, `lexo_rank_at_percentile()` at 0.5.
+///
+/// For an even number of positions this is the lower of the two middle ones.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_median('playlist_songs', 'position', 'playlist_id', 'abc-123');
+/// ```
+#[pg_extern]
+pub fn lexo_median(
+    table_name: &str,
+    lexo_column_name: &str,
+    identifier_column_name: default!(Option<&str>, "NULL"),
+    identifier_value: default!(Option<&str>, "NULL"),
+) -> Option<Lexo> {
+    with_pinned_search_path(|| {
+        lexo_rank_at_percentile(
+            table_name,
+            lexo_column_name,
+            0.5,
+            identifier_column_name,
+            identifier_value,
+        )
+    })
+}
+
+/// Returns the number of rows whose position lies strictly between two positions.
+///
+/// NULL bounds are open-ended, as in `lexo_between()`. The count is a single
+/// `COUNT(*)` with a range predicate on the bare column, so an index on the
+/// position column (or on the key column and the position column) serves it.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `low` - The lower bound (NULL for the start of the list)
+/// * `high` - The upper bound (NULL for the end of the list)
+/// * `identifier_column_name` - Optional: column to filter by (e.g., 'collection_id')
+/// * `identifier_value` - Optional: value to filter by
+///
+/// # Example
+/// ```sql
+/// -- "You are item 240 of 3,100": rows before the current one, plus one
+/// SELECT lexo_count_between('playlist_songs', 'position', NULL, 'Hk', 'playlist_id', 'abc-123') + 1;
+/// ```
+#[pg_extern]
+pub fn lexo_count_between(
+    table_name: &str,
+    lexo_column_name: &str,
+    low: Option<Lexo>,
+    high: Option<Lexo>,
+    identifier_column_name: default!(Option<&str>, "NULL"),
+    identifier_value: default!(Option<&str>, "NULL"),
+) -> i64 {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[Some(lexo_column_name), identifier_column_name],
+        );
+        let low_str = bound_str(&low);
+        let high_str = bound_str(&high);
+        if !low_str.is_empty() && !high_str.is_empty() && low_str >= high_str {
+            pgrx::error!(
+                "low position '{}' must be less than high position '{}'",
+                low_str,
+                high_str
+            );
+        }
+
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+        let mut range = vec![format!("{} IS NOT NULL", quoted_lexo_column)];
+        if !low_str.is_empty() {
+            range.push(format!(
+                "{} > {}",
+                quoted_lexo_column,
+                quote_literal(low_str)
+            ));
+        }
+        if !high_str.is_empty() {
+            range.push(format!(
+                "{} < {}",
+                quoted_lexo_column,
+                quote_literal(high_str)
+            ));
+        }
+        let query = format!(
+            "SELECT COUNT(*) FROM {}{}",
+            quote_table_name(table_name),
+            filter_and(
+                &list_filter(identifier_column_name, identifier_value),
+                &range.join(" AND ")
+            )
+        );
+
+        Spi::get_one(&query)
+            .expect("Failed to count rows between the positions")
+            .unwrap_or(0)
+    })
+}
+
+/// Returns a position that places a new row at `index` of a list, locking its neighbours.
+///
+/// Takes the same per-list advisory lock as `lexo_claim_next()`, then locks the
+/// two rows around the zero-based `index` with `FOR UPDATE` and returns a
+/// position between them. Index 0 prepends, indexes past the end append, and an
+/// empty list gets the first position. The locks are held until the transaction
+/// ends, so the neighbours can't move or disappear before the caller's INSERT.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `index` - Zero-based index the new row should get
+/// * `identifier_column_name` - Optional: column to filter by (e.g., 'collection_id')
+/// * `identifier_value` - Optional: value to filter by
+///
+/// # Example
+/// ```sql
+/// -- Make the new song the fourth of the playlist
+/// BEGIN;
+/// INSERT INTO playlist_songs (playlist_id, song_id, position)
+/// VALUES ('abc-123', 42, lexo_insert_at('playlist_songs', 'position', 3, 'playlist_id', 'abc-123'));
+/// COMMIT;
+/// ```
+#[pg_extern(cost = 100)]
+pub fn lexo_insert_at(
+    table_name: &str,
+    lexo_column_name: &str,
+    index: i64,
+    identifier_column_name: default!(Option<&str>, "NULL"),
+    identifier_value: default!(Option<&str>, "NULL"),
+) -> Lexo {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[Some(lexo_column_name), identifier_column_name],
+        );
+        lock_list(&table_list_lock_key(
+            table_name,
+            lexo_column_name,
+            identifier_column_name,
+            identifier_value,
+        ));
+
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+
+        let quoted_table = quote_table_name(table_name);
+
+        // Only rows with a position take part in the index arithmetic
+        let filter = filter_and(
+            &list_filter(identifier_column_name, identifier_value),
+            &format!("{} IS NOT NULL", quoted_lexo_column),
+        );
+
+        let count_query = format!("SELECT COUNT(*) FROM {}{}", quoted_table, filter);
+        let count: i64 = Spi::get_one(&count_query)
+            .expect("Failed to count rows in table")
+            .unwrap_or(0);
+        if count == 0 {
+            return Lexo::first();
+        }
+
+        let index = index.clamp(0, count);
+        let neighbours_query = format!(
+            "SELECT {col}::{ext}.lexo::text FROM {table}{filter} ORDER BY {col}::{ext}.lexo LIMIT 2 OFFSET {offset} FOR UPDATE",
+            col = quoted_lexo_column,
+            table = quoted_table,
+            filter = filter,
+            offset = (index - 1).max(0),
+            ext = extension_schema()
+        );
+        let neighbours: Vec<Lexo> = Spi::connect_mut(|client| {
+            client
+                .update(&neighbours_query, None, &[])
+                .expect("Failed to lock neighbouring rows")
+                .filter_map(|row| {
+                    row.get::<String>(1)
+                        .expect("Failed to get position")
+                        .map(Lexo::new)
+                })
+                .collect()
+        });
+
+        let mut neighbours = neighbours.into_iter();
+        let before = if index == 0 { None } else { neighbours.next() };
+        counted_between(before, neighbours.next())
+    })
+}
+
+/// Returns the condition selecting the children of `parent_pk` in a tree, or
+/// the root nodes when it is NULL.
+fn children_filter(parent_column_name: &str, parent_pk: Option<&str>) -> String {
+    match parent_pk {
+        Some(pk) => format!(
+            " WHERE {} = {}",
+            quote_identifier(parent_column_name),
+            quote_literal(pk)
+        ),
+        None => format!(" WHERE {} IS NULL", quote_identifier(parent_column_name)),
+    }
+}
+
+/// Returns a position after sibling `after_sibling_pk` (first when NULL) among
+/// the children of `parent_pk`, locking the children's list and the siblings
+/// around the gap. `moving_pk` is left out of the siblings, so a node can be
+/// moved within its own parent.
+#[allow(clippy::too_many_arguments)]
+fn child_position(
+    table_name: &str,
+    pk_column_name: &str,
+    parent_column_name: &str,
+    lexo_column_name: &str,
+    parent_pk: Option<&str>,
+    after_sibling_pk: Option<&str>,
+    moving_pk: Option<&str>,
+) -> Lexo {
+    lock_list(&table_list_lock_key(
+        table_name,
+        lexo_column_name,
+        Some(parent_column_name),
+        parent_pk,
+    ));
+
+    let quoted_table = quote_table_name(table_name);
+    let quoted_pk_column = quote_identifier(pk_column_name);
+    let quoted_lexo_column = quote_identifier(lexo_column_name);
+    let mut filter = filter_and(
+        &children_filter(parent_column_name, parent_pk),
+        &format!("{} IS NOT NULL", quoted_lexo_column),
+    );
+    if let Some(moving) = moving_pk {
+        filter = filter_and(
+            &filter,
+            &format!("{} <> {}", quoted_pk_column, quote_literal(moving)),
+        );
+    }
+
+    let before: Option<Lexo> = match after_sibling_pk {
+        None => None,
+        Some(sibling) => {
+            if moving_pk == Some(sibling) {
+                pgrx::error!("cannot move node {} after itself", sibling);
+            }
+            let sibling_query = format!(
+                "SELECT {col}::{ext}.lexo FROM {table}{filter} AND {pk} = {sibling} FOR UPDATE",
+                col = quoted_lexo_column,
+                table = quoted_table,
+                filter = filter,
+                pk = quoted_pk_column,
+                sibling = quote_literal(sibling),
+                ext = extension_schema()
+            );
+            let position: Option<Lexo> =
+                Spi::get_one(&sibling_query).expect("Failed to lock the sibling row");
+            if position.is_none() {
+                match parent_pk {
+                    Some(parent) => pgrx::error!(
+                        "node {} is not a positioned child of {} in {}",
+                        sibling,
+                        parent,
+                        table_name
+                    ),
+                    None => pgrx::error!(
+                        "node {} is not a positioned root node of {}",
+                        sibling,
+                        table_name
+                    ),
+                }
+            }
+            position
+        }
+    };
+
+    let next_filter = match &before {
+        Some(position) => filter_and(
+            &filter,
+            &format!(
+                "{}::{ext}.lexo > {}::{ext}.lexo",
+                quoted_lexo_column,
+                quote_literal(position.as_str()),
+                ext = extension_schema()
+            ),
+        ),
+        None => filter,
+    };
+    let next_query = format!(
+        "SELECT {col}::{ext}.lexo FROM {table}{filter} ORDER BY {col}::{ext}.lexo LIMIT 1 FOR UPDATE",
+        col = quoted_lexo_column,
+        table = quoted_table,
+        filter = next_filter,
+        ext = extension_schema()
+    );
+    let after: Option<Lexo> = Spi::get_one(&next_query).expect("Failed to lock the next sibling");
+    counted_between(before, after)
+}
+
+/// Returns a position that makes a new node the first child of `parent_pk`.
+///
+/// For trees stored with a self-referencing parent column, where siblings are
+/// ordered per parent. A NULL `parent_pk` addresses the root nodes. Takes the
+/// same per-list advisory lock as `lexo_claim_next()` on (table, column,
+/// parent) and locks the current first child, until the transaction ends.
+/// The tree helpers all start with the same four arguments.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `pk_column_name` - The table's primary key column
+/// * `parent_column_name` - The column referencing the parent node
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `parent_pk` - The parent node, or NULL for the root nodes
+///
+/// # Example
+/// ```sql
+/// INSERT INTO outline (id, parent_id, position)
+/// VALUES (7, 3, lexo_child_first('outline', 'id', 'parent_id', 'position', '3'));
+/// ```
+#[pg_extern(cost = 100)]
+pub fn lexo_child_first(
+    table_name: &str,
+    pk_column_name: &str,
+    parent_column_name: &str,
+    lexo_column_name: &str,
+    parent_pk: Option<&str>,
+) -> Lexo {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[
+                Some(pk_column_name),
+                Some(parent_column_name),
+                Some(lexo_column_name),
+            ],
+        );
+        child_position(
+            table_name,
+            pk_column_name,
+            parent_column_name,
+            lexo_column_name,
+            parent_pk,
+            None,
+            None,
+        )
+    })
+}
+
+/// Returns a position that puts a new node right after a sibling under `parent_pk`.
+///
+/// The position lies between `after_sibling_pk` and the sibling that follows
+/// it; a NULL `after_sibling_pk` makes the node the first child. Both siblings
+/// are locked with `FOR UPDATE`, under the same advisory lock as
+/// `lexo_child_first()`.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `pk_column_name` - The table's primary key column
+/// * `parent_column_name` - The column referencing the parent node
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `parent_pk` - The parent node, or NULL for the root nodes
+/// * `after_sibling_pk` - The child of `parent_pk` the new node follows, or NULL
+///
+/// # Example
+/// ```sql
+/// INSERT INTO outline (id, parent_id, position)
+/// VALUES (8, 3, lexo_child_between('outline', 'id', 'parent_id', 'position', '3', '7'));
+/// ```
+#[pg_extern(cost = 100)]
+pub fn lexo_child_between(
+    table_name: &str,
+    pk_column_name: &str,
+    parent_column_name: &str,
+    lexo_column_name: &str,
+    parent_pk: Option<&str>,
+    after_sibling_pk: Option<&str>,
+) -> Lexo {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[
+                Some(pk_column_name),
+                Some(parent_column_name),
+                Some(lexo_column_name),
+            ],
+        );
+        child_position(
+            table_name,
+            pk_column_name,
+            parent_column_name,
+            lexo_column_name,
+            parent_pk,
+            after_sibling_pk,
+            None,
+        )
+    })
+}
+
+/// Moves a node, with its subtree, under a new parent right after a sibling.
+///
+/// Sets the node's parent to `new_parent_pk` (NULL makes it a root node) and
+/// gives it a position between `after_sibling_pk` and the following sibling,
+/// or before the first child when `after_sibling_pk` is NULL. Descendants
+/// keep their parent and positions, so they move along. The node and the
+/// siblings around its new place are locked like in `lexo_child_between()`.
+/// Moving a node under itself or one of its descendants raises an error.
+///
+/// # Returns
+/// The node's new position
+///
+/// # Example
+/// ```sql
+/// -- Make node 12 the child of node 3 that follows node 7
+/// SELECT lexo_move_subtree('outline', 'id', 'parent_id', 'position', '12', '3', '7');
+/// ```
+#[pg_extern]
+pub fn lexo_move_subtree(
+    table_name: &str,
+    pk_column_name: &str,
+    parent_column_name: &str,
+    lexo_column_name: &str,
+    moving_pk: &str,
+    new_parent_pk: Option<&str>,
+    after_sibling_pk: Option<&str>,
+) -> Lexo {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[
+                Some(pk_column_name),
+                Some(parent_column_name),
+                Some(lexo_column_name),
+            ],
+        );
+        let quoted_table = quote_table_name(table_name);
+        let quoted_pk_column = quote_identifier(pk_column_name);
+        let quoted_parent_column = quote_identifier(parent_column_name);
+
+        let moving: Option<bool> = Spi::get_one(&format!(
+            "SELECT true FROM {} WHERE {} = {} FOR UPDATE",
+            quoted_table,
+            quoted_pk_column,
+            quote_literal(moving_pk)
+        ))
+        .expect("Failed to lock the moving node");
+        if moving.is_none() {
+            pgrx::error!("node {} does not exist in {}", moving_pk, table_name);
+        }
+
+        if let Some(parent) = new_parent_pk {
+            // Walk up from the new parent; UNION stops at a cycle already in the table
+            let ancestors_query = format!(
+                "WITH RECURSIVE up(node, parent) AS ( \
+                     SELECT {pk}::text, {parent_col}::text FROM {table} WHERE {pk} = {start} \
+                     UNION \
+                     SELECT t.{pk}::text, t.{parent_col}::text \
+                     FROM {table} t JOIN up ON t.{pk}::text = up.parent \
+                 ) \
+                 SELECT count(*), count(*) FILTER (WHERE node = {moving}) FROM up",
+                pk = quoted_pk_column,
+                parent_col = quoted_parent_column,
+                table = quoted_table,
+                start = quote_literal(parent),
+                moving = quote_literal(moving_pk)
+            );
+            let (path, cycle): (Option<i64>, Option<i64>) =
+                Spi::get_two(&ancestors_query).expect("Failed to read the new parent's ancestors");
+            if path.unwrap_or(0) == 0 {
+                pgrx::error!("node {} does not exist in {}", parent, table_name);
+            }
+            if cycle.unwrap_or(0) > 0 {
+                pgrx::error!(
+                    "cannot move node {} under {}, which is in its own subtree",
+                    moving_pk,
+                    parent
+                );
+            }
+        }
+
+        let position = child_position(
+            table_name,
+            pk_column_name,
+            parent_column_name,
+            lexo_column_name,
+            new_parent_pk,
+            after_sibling_pk,
+            Some(moving_pk),
+        );
+        Spi::run(&format!(
+            "UPDATE {} SET {} = {}, {} = {} WHERE {} = {}",
+            quoted_table,
+            quoted_parent_column,
+            new_parent_pk.map_or("NULL".to_string(), quote_literal),
+            quote_identifier(lexo_column_name),
+            quote_literal(position.as_str()),
+            quoted_pk_column,
+            quote_literal(moving_pk)
+        ))
+        .expect("Failed to move the node");
+        position
+    })
+}
+
+/// Estimates how many more inserts a list can take before a position exceeds `max_length`.
+///
+/// Reads the list's positions in order and reports the number of appends after
+/// the last row and prepends before the first, plus two figures for inserts
+/// between neighbours. Each adjacent pair is given the number of inserts that
+/// fit in its gap when they all go right after the first row of the pair;
+/// `worst_case_between` is the smallest of these and `typical_between` the
+/// median. `deepest_prefix` is the length of the longest prefix two neighbours
+/// share, which marks the most degraded region. All three are NULL for lists
+/// with fewer than two rows, and an empty list is treated as if it held
+/// `lexo_first()`.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `identifier_column_name` - Optional: column to filter by (e.g., 'collection_id')
+/// * `identifier_value` - Optional: value to filter by
+/// * `max_length` - The longest acceptable position (default 12)
+///
+/// # Example
+/// ```sql
+/// SELECT * FROM lexo_insertion_budget('playlist_songs', 'position', 'playlist_id', 'abc-123');
+/// ```
+#[allow(clippy::type_complexity)]
+#[pg_extern]
+pub fn lexo_insertion_budget(
+    table_name: &str,
+    lexo_column_name: &str,
+    identifier_column_name: default!(Option<&str>, "NULL"),
+    identifier_value: default!(Option<&str>, "NULL"),
+    max_length: default!(i32, 12),
+) -> TableIterator<
+    'static,
+    (
+        name!(appends, i64),
+        name!(prepends, i64),
+        name!(worst_case_between, Option<i64>),
+        name!(typical_between, Option<i64>),
+        name!(deepest_prefix, Option<i32>),
+    ),
+> {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[Some(lexo_column_name), identifier_column_name],
+        );
+        if max_length < 1 {
+            pgrx::error!("max_length must be at least 1, got {}", max_length);
+        }
+        let max_length = max_length as usize;
+
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+
+        let quoted_table = quote_table_name(table_name);
+
+        let query = format!(
+            "SELECT {col}::{ext}.lexo::text FROM {table}{filter} ORDER BY {col}::{ext}.lexo",
+            col = quoted_lexo_column,
+            table = quoted_table,
+            filter = list_filter(identifier_column_name, identifier_value),
+            ext = extension_schema()
+        );
+        let positions: Vec<String> = Spi::connect(|client| {
+            client
+                .select(&query, None, &[])
+                .expect("Failed to read positions")
+                .filter_map(|row| row.get::<String>(1).expect("Failed to get position"))
+                .collect()
+        });
+
+        let first = positions
+            .first()
+            .cloned()
+            .unwrap_or_else(|| MID_CHAR.to_string());
+        let last = positions.last().unwrap_or(&first);
+
+        let mut gaps: Vec<u64> = positions
+            .windows(2)
+            .map(|pair| between_capacity(&pair[0], &pair[1], max_length))
+            .collect();
+        gaps.sort_unstable();
+        let deepest_prefix = positions
+            .windows(2)
+            .map(|pair| common_prefix(&pair[0], &pair[1]).chars().count() as i32)
+            .max();
+
+        TableIterator::once((
+            append_capacity(last, max_length) as i64,
+            prepend_capacity(&first, max_length) as i64,
+            gaps.first().map(|&g| g as i64),
+            gaps.get(gaps.len() / 2).map(|&g| g as i64),
+            deepest_prefix,
+        ))
+    })
+}
+
+/// Replays a synthetic insert workload and reports how position length evolves.
+///
+/// Nothing is read or written: the list starts empty and every insert uses the
+/// same generation code as the rest of the extension. `pattern` is one of
+/// 'append', 'prepend', 'middle' (between the two middle rows), 'random' (a
+/// uniformly random gap) or 'hotspot' (always right after the first row).
+/// Random choices come from `seed`, so the same call returns the same rows.
+///
+/// # Arguments
+/// * `n` - The number of inserts to simulate (at most 10000)
+/// * `pattern` - Where each insert goes
+/// * `seed` - Seed for the random gaps (default 0)
+///
+/// # Returns
+/// One row per insert: its step number (from 1), the generated position and its length
+///
+/// # Example
+/// ```sql
+/// -- After how many hotspot inserts do positions exceed 12 characters?
+/// SELECT min(step) FROM lexo_simulate_insertions(1000, 'hotspot') WHERE length > 12;
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_simulate_insertions(
+    n: i32,
+    pattern: &str,
+    seed: default!(i64, 0),
+) -> TableIterator<'static, (name!(step, i32), name!(rank, String), name!(length, i32))> {
+    let pattern = parse_insert_pattern(pattern);
+    simulation_rows(n, &[(pattern, 1)], seed)
+}
+
+/// Replays a workload that mixes several insert patterns.
+///
+/// `mix` maps pattern names to weights: `{"append": 8, "random": 2}` sends
+/// about 80% of inserts to the end of the list and the rest to random gaps.
+///
+/// # Example
+/// ```sql
+/// SELECT max(length)
+/// FROM lexo_simulate_insertions(5000, '{"append": 9, "hotspot": 1}'::jsonb);
+/// ```
+#[pg_extern(immutable, parallel_safe, name = "lexo_simulate_insertions")]
+pub fn lexo_simulate_insertions_mix(
+    n: i32,
+    mix: pgrx::JsonB,
+    seed: default!(i64, 0),
+) -> TableIterator<'static, (name!(step, i32), name!(rank, String), name!(length, i32))> {
+    let Value::Object(entries) = mix.0 else {
+        pgrx::error!("mix must be a JSON object mapping pattern names to weights");
+    };
+    let mix: Vec<(InsertPattern, u64)> = entries
+        .iter()
+        .map(|(name, weight)| match weight.as_u64() {
+            Some(weight) => (parse_insert_pattern(name), weight),
+            None => pgrx::error!(
+                "weight for pattern '{}' must be a non-negative integer, got {}",
+                name,
+                weight
+            ),
+        })
+        .collect();
+    if mix.iter().all(|&(_, weight)| weight == 0) {
+        pgrx::error!("mix must give at least one pattern a positive weight");
+    }
+    simulation_rows(n, &mix, seed)
+}
+
+fn parse_insert_pattern(name: &str) -> InsertPattern {
+    InsertPattern::parse(name).unwrap_or_else(|| {
+        pgrx::error!(
+            "Invalid pattern '{}': must be 'append', 'prepend', 'middle', 'random' or 'hotspot'",
+            name
+        )
+    })
+}
+
+#[allow(clippy::type_complexity)]
+fn simulation_rows(
+    n: i32,
+    mix: &[(InsertPattern, u64)],
+    seed: i64,
+) -> TableIterator<'static, (name!(step, i32), name!(rank, String), name!(length, i32))> {
+    if n < 0 || n as usize > MAX_SIMULATION_STEPS {
+        pgrx::error!(
+            "n must be between 0 and {}, got {}",
+            MAX_SIMULATION_STEPS,
+            n
+        );
+    }
+    let positions = simulate_insertions(n as usize, mix, seed as u64);
+    TableIterator::new(positions.into_iter().enumerate().map(|(i, rank)| {
+        let length = rank.len() as i32;
+        (i as i32 + 1, rank, length)
+    }))
+}
+
+/// Splits a table name, optionally qualified with its schema and database,
+/// into its parts.
+///
+/// Parts are read the way PostgreSQL reads identifiers: unquoted letters are
+/// folded to lower case, while inside double quotes case and dots belong to
+/// the name and `""` stands for one quote. The output of `regclass::text`
+/// reads back as the same table.
+pub(crate) fn split_table_name(table_name: &str) -> Result<Vec<String>, String> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut chars = table_name.chars().peekable();
+    loop {
+        match chars.next() {
+            Some('"') => loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        part.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => part.push(c),
+                    None => return Err("unterminated quoted identifier".to_string()),
+                }
+            },
+            Some('.') => {
+                if part.is_empty() {
+                    return Err("zero-length name".to_string());
+                }
+                parts.push(std::mem::take(&mut part));
+            }
+            Some(c) => part.push(c.to_ascii_lowercase()),
+            None => {
+                if part.is_empty() {
+                    return Err("zero-length name".to_string());
+                }
+                parts.push(part);
+                break;
+            }
+        }
+    }
+    if parts.len() > 3 {
+        return Err("too many dotted names".to_string());
+    }
+    Ok(parts)
+}
+
+/// Returns `table_name` as a quoted identifier, schema-qualified if it was,
+/// ready to be pasted into a query.
+pub(crate) fn quote_table_name(table_name: &str) -> String {
+    match split_table_name(table_name) {
+        Ok(parts) => parts
+            .iter()
+            .map(quote_identifier)
+            .collect::<Vec<_>>()
+            .join("."),
+        Err(reason) => {
+            raise_error!(
+                PgSqlErrorCode::ERRCODE_INVALID_NAME,
+                format!(
+                    "invalid table name {}: {}",
+                    quote_literal(table_name),
+                    reason
+                ),
+            );
+        }
+    }
+}
+
+/// Returns `table_name` quoted for a FROM or UPDATE clause, with `ONLY` in
+/// front when inheritance children and partitions should be left out.
+fn table_scope(table_name: &str, only: bool) -> String {
+    let quoted_table = quote_table_name(table_name);
+    if only {
+        format!("ONLY {}", quoted_table)
+    } else {
+        quoted_table
+    }
+}
+
+/// Runs `f` with `pg_catalog` and the extension's schema at the front of
+/// `search_path`, the way a function's `SET search_path` clause would.
+///
+/// The caller's schemas follow them, so unqualified table names and the
+/// caller's own functions in predicates still resolve, but nothing those
+/// schemas define can stand in for the types, functions and operators the
+/// generated SQL uses. `pg_temp` goes last for the same reason. The caller's
+/// setting is restored afterwards, or by the transaction machinery when `f`
+/// raises an error.
+///
+/// Entry points call each other, so a call made while the path is already
+/// pinned just runs `f`: pinning again would put the extension's path in
+/// place of the caller's schemas.
+fn with_pinned_search_path<T>(f: impl FnOnce() -> T) -> T {
+    if SEARCH_PATH_PINNED.get() {
+        return f();
+    }
+    // Every name is qualified: this runs under the caller's search_path
+    let caller_schemas: Option<String> = Spi::get_one(
+        "SELECT pg_catalog.string_agg(pg_catalog.quote_ident(s), ', ') \
+         FROM pg_catalog.unnest(pg_catalog.current_schemas(false)) AS s",
+    )
+    .expect("Failed to read the search_path");
+    let mut path = format!("pg_catalog, {}", extension_schema());
+    if let Some(schemas) = caller_schemas {
+        path.push_str(", ");
+        path.push_str(&schemas);
+    }
+    path.push_str(", pg_temp");
+    let path = CString::new(path).expect("search_path contains a NUL byte");
+
+    let nest_level = unsafe { pg_sys::NewGUCNestLevel() };
+    unsafe {
+        pg_sys::set_config_option(
+            c"search_path".as_ptr(),
+            path.as_ptr(),
+            pg_sys::GucContext::PGC_USERSET,
+            pg_sys::GucSource::PGC_S_SESSION,
+            pg_sys::GucAction::GUC_ACTION_SAVE,
+            true,
+            0,
+            false,
+        );
+    }
+    let result = {
+        let _pinned = PinnedSearchPath::enter();
+        f()
+    };
+    unsafe { pg_sys::AtEOXact_GUC(true, nest_level) };
+    result
+}
+
+thread_local! {
+    static SEARCH_PATH_PINNED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the search_path pinned until dropped, including when an error
+/// unwinds out of the pinned call.
+struct PinnedSearchPath;
+
+impl PinnedSearchPath {
+    fn enter() -> Self {
+        SEARCH_PATH_PINNED.set(true);
+        PinnedSearchPath
+    }
+}
+
+impl Drop for PinnedSearchPath {
+    fn drop(&mut self) {
+        SEARCH_PATH_PINNED.set(false);
+    }
+}
+
+/// Returns the quoted name of the schema the extension is installed in.
+fn extension_schema() -> String {
+    unsafe {
+        let extension = pg_sys::get_extension_oid(c"pg_lexo".as_ptr(), false);
+        let schema = pg_sys::get_namespace_name(pg_sys::get_extension_schema(extension));
+        quote_identifier(CStr::from_ptr(schema).to_string_lossy())
+    }
+}
+
+/// Checks that `table_name` resolves to a relation and that each of `columns`
+/// exists on it, raising `undefined_table` or `undefined_column` with the
+/// names as given rather than failing inside a generated query.
+///
+/// An unqualified name that is only found in schemas outside the
+/// `search_path` gets a hint naming the qualified tables.
+fn require_columns(table_name: &str, columns: &[Option<&str>]) -> pg_sys::Oid {
+    let quoted_table = quote_table_name(table_name);
+    let relation: Option<pg_sys::Oid> = Spi::get_one(&format!(
+        "SELECT pg_catalog.to_regclass({})::pg_catalog.oid",
+        quote_literal(&quoted_table)
+    ))
+    .expect("Failed to look up table");
+
+    let Some(relation) = relation else {
+        let message = format!("relation \"{}\" does not exist", table_name);
+        let candidates = other_schema_tables(table_name);
+        if candidates.is_empty() {
+            raise_error!(PgSqlErrorCode::ERRCODE_UNDEFINED_TABLE, message);
+        }
+        raise_error!(
+            PgSqlErrorCode::ERRCODE_UNDEFINED_TABLE,
+            message,
+            hint = format!(
+                "Did you mean {}? Its schema is not on the search_path, so the name must be schema-qualified.",
+                candidates.join(" or ")
+            ),
+        );
+    };
+
+    for column in columns.iter().flatten() {
+        let name = CString::new(*column).expect("column name contains a NUL byte");
+        if unsafe { pg_sys::get_attnum(relation, name.as_ptr()) } == 0 {
+            raise_error!(
+                PgSqlErrorCode::ERRCODE_UNDEFINED_COLUMN,
+                format!(
+                    "column \"{}\" of relation \"{}\" does not exist",
+                    column, table_name
+                ),
+            );
+        }
+    }
+    relation
+}
+
+/// Returns the quoted, schema-qualified names of the tables called like an
+/// unqualified `table_name` in any schema, for hinting at a missing qualification.
+fn other_schema_tables(table_name: &str) -> Vec<String> {
+    let parts = split_table_name(table_name);
+    let Ok([name]) = parts.as_deref() else {
+        return Vec::new();
+    };
+    let query = format!(
+        "SELECT pg_catalog.quote_ident(n.nspname) OPERATOR(pg_catalog.||) '.' \
+         OPERATOR(pg_catalog.||) pg_catalog.quote_ident(c.relname) \
+         FROM pg_catalog.pg_class c \
+         JOIN pg_catalog.pg_namespace n ON n.oid OPERATOR(pg_catalog.=) c.relnamespace \
+         WHERE c.relname OPERATOR(pg_catalog.=) {} ORDER BY n.nspname",
+        quote_literal(name)
+    );
+    Spi::connect(|client| {
+        client
+            .select(&query, None, &[])
+            .expect("Failed to look up tables in other schemas")
+            .filter_map(|row| row.get::<String>(1).expect("Failed to get table name"))
+            .map(|name| format!("'{}'", name))
+            .collect()
+    })
+}
+
+/// Looks up a column's type, returning its name and `lexo` for position
+/// types, `text` for `text`/`varchar` (and their domains) or None otherwise.
+fn position_column_kind(table_name: &str, column: &str) -> (Option<String>, Option<String>) {
+    let quoted_table = quote_table_name(table_name);
+    let schema = extension_schema();
+    let query = format!(
+        "SELECT pg_catalog.format_type(a.atttypid, a.atttypmod), CASE \
+             WHEN a.atttypid OPERATOR(pg_catalog.=) pg_catalog.to_regtype({}) THEN 'lexo' \
+             WHEN b.oid OPERATOR(pg_catalog.=) ANY (ARRAY[pg_catalog.to_regtype({}), \
+                  pg_catalog.to_regtype({})]::pg_catalog.oid[]) THEN 'lexo' \
+             WHEN b.oid OPERATOR(pg_catalog.=) ANY (ARRAY['pg_catalog.text'::pg_catalog.regtype, \
+                  'pg_catalog.varchar'::pg_catalog.regtype]::pg_catalog.oid[]) THEN 'text' \
+         END \
+         FROM pg_catalog.pg_attribute a \
+         JOIN pg_catalog.pg_type t ON t.oid OPERATOR(pg_catalog.=) a.atttypid \
+         JOIN pg_catalog.pg_type b ON b.oid OPERATOR(pg_catalog.=) \
+              CASE WHEN t.typtype OPERATOR(pg_catalog.=) 'd' THEN t.typbasetype ELSE t.oid END \
+         WHERE a.attrelid OPERATOR(pg_catalog.=) pg_catalog.to_regclass({}) \
+         AND a.attname OPERATOR(pg_catalog.=) {} AND NOT a.attisdropped",
+        quote_literal(format!("{}.lexo_text", schema)),
+        quote_literal(format!("{}.lexo", schema)),
+        quote_literal(format!("{}.smalllexo", schema)),
+        quote_literal(&quoted_table),
+        quote_literal(column)
+    );
+    Spi::get_two(&query).expect("Failed to look up the column type")
+}
+
+/// Checks that a column holds positions before a helper rewrites it: `lexo`,
+/// `smalllexo`, a domain over either, or the shipped `lexo_text` domain. With
+/// `allow_text`, plain `text` and `varchar` columns (and their domains) are
+/// accepted too. Anything else raises `datatype_mismatch` naming the type.
+fn require_position_column(table_name: &str, column: &str, allow_text: bool) {
+    let (type_name, kind) = position_column_kind(table_name, column);
+    let hint = match kind.as_deref() {
+        Some("lexo") => return,
+        Some("text") if allow_text => return,
+        Some("text") => {
+            "Pass allow_text => true if it holds Base62 positions, or convert it with lexo_convert_column()."
+        }
+        _ => "Position columns are of type lexo, smalllexo or lexo_text, or a domain over lexo.",
+    };
+    raise_error!(
+        PgSqlErrorCode::ERRCODE_DATATYPE_MISMATCH,
+        format!(
+            "column \"{}\" of relation \"{}\" is of type {}, not a position type",
+            column,
+            table_name,
+            type_name.unwrap_or_default()
+        ),
+        hint = hint,
+    );
+}
+
+/// Raises `insufficient_privilege` unless the current user holds each of
+/// `privileges` on the table, so a helper fails before doing any work instead
+/// of partway through. `UPDATE` is checked on `column` alone, so a column-level
+/// grant on the position column is enough.
+fn require_table_privileges(table_name: &str, column: &str, privileges: &[&str]) {
+    let quoted_table = quote_table_name(table_name);
+    for privilege in privileges {
+        let (query, detail) = if *privilege == "UPDATE" {
+            (
+                format!(
+                    "SELECT pg_catalog.has_column_privilege({}, {}, {})",
+                    quote_literal(&quoted_table),
+                    quote_literal(column),
+                    quote_literal(privilege)
+                ),
+                format!(
+                    "{} privilege on column \"{}\" is required.",
+                    privilege, column
+                ),
+            )
+        } else {
+            (
+                format!(
+                    "SELECT pg_catalog.has_table_privilege({}, {})",
+                    quote_literal(&quoted_table),
+                    quote_literal(privilege)
+                ),
+                format!("{} privilege is required.", privilege),
+            )
+        };
+        let granted: bool = Spi::get_one(&query)
+            .expect("Failed to check table privileges")
+            .unwrap_or(false);
+        if !granted {
+            raise_error!(
+                PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+                format!("permission denied for table {}", table_name),
+                detail = detail,
+            );
+        }
+    }
+}
+
+/// Raises `insufficient_privilege` when row-level security policies apply to
+/// the current user on `table_name`, unless `allow_rls` is set.
+fn require_rls_allowed(table_name: &str, allow_rls: bool) {
+    if allow_rls {
+        return;
+    }
+    let query = format!(
+        "SELECT pg_catalog.row_security_active({}::pg_catalog.regclass)",
+        quote_literal(quote_table_name(table_name))
+    );
+    let active: bool = Spi::get_one(&query)
+        .expect("Failed to check row-level security")
+        .unwrap_or(false);
+    if active {
+        raise_error!(
+            PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            format!("row-level security is active on table {}", table_name),
+            detail = "Only the rows the policies allow would be rebalanced, and their new positions \
+             could collide with the rows that are hidden.",
+            hint = "Run it as the table owner or a role with BYPASSRLS, or pass allow_rls => true \
+             to rebalance only the visible rows.",
+        );
+    }
+}
+
+/// Raises `insufficient_privilege` unless the current user owns the table
+/// (directly or through a role), as `ALTER TABLE` requires.
+fn require_table_owner(table_name: &str) {
+    let quoted_table = quote_table_name(table_name);
+    let query = format!(
+        "SELECT pg_catalog.pg_has_role(c.relowner, 'USAGE') FROM pg_catalog.pg_class c \
+         WHERE c.oid OPERATOR(pg_catalog.=) {}::pg_catalog.regclass",
+        quote_literal(&quoted_table)
+    );
+    let owner: bool = Spi::get_one(&query)
+        .expect("Failed to check table ownership")
+        .unwrap_or(false);
+    if !owner {
+        raise_error!(
+            PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            format!("must be owner of table {}", table_name),
+        );
+    }
+}
+
+/// Builds the WHERE clause restricting a query to one list, or nothing when no filter is given.
+fn list_filter(identifier_column_name: Option<&str>, identifier_value: Option<&str>) -> String {
+    match (identifier_column_name, identifier_value) {
+        (Some(id_col), Some(id_val)) => format!(
+            " WHERE {} = {}",
+            quote_identifier(id_col),
+            quote_literal(id_val)
+        ),
+        _ => String::new(),
+    }
+}
+
+/// Returns the maximum position in a table column, optionally filtered.
+fn query_max_position(
+    table_name: &str,
+    lexo_column_name: &str,
+    identifier_column_name: Option<&str>,
+    identifier_value: Option<&str>,
+    only: bool,
+) -> Option<Lexo> {
+    query_bound_position(
+        "MAX",
+        table_name,
+        lexo_column_name,
+        identifier_column_name,
+        identifier_value,
+        only,
+    )
+}
+
+fn query_min_position(
+    table_name: &str,
+    lexo_column_name: &str,
+    identifier_column_name: Option<&str>,
+    identifier_value: Option<&str>,
+    only: bool,
+) -> Option<Lexo> {
+    query_bound_position(
+        "MIN",
+        table_name,
+        lexo_column_name,
+        identifier_column_name,
+        identifier_value,
+        only,
+    )
+}
+
+fn query_bound_position(
+    aggregate: &str,
+    table_name: &str,
+    lexo_column_name: &str,
+    identifier_column_name: Option<&str>,
+    identifier_value: Option<&str>,
+    only: bool,
+) -> Option<Lexo> {
+    let quoted_lexo_column = quote_identifier(lexo_column_name);
+
+    let quoted_table = table_scope(table_name, only);
+
+    let query = format!(
+        "SELECT {}({}::{ext}.lexo) FROM {}{}",
+        aggregate,
+        quoted_lexo_column,
+        quoted_table,
+        list_filter(identifier_column_name, identifier_value),
+        ext = extension_schema()
+    );
+
+    Spi::get_one(&query).expect("Failed to query table for the bounding position")
+}
+
+/// Adds a lexo position column to an existing table.
+///
+/// The column will be of type `lexo` to ensure proper
+/// lexicographic ordering with the custom type.
+///
+/// Only the table's owner may add a column; anyone else gets an
+/// `insufficient_privilege` error before the table is touched.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `column_name` - The name of the new column to add
+/// * `type_name` - Optional: a domain over `lexo` to use instead of the base type
+///   (e.g. `'lexo_nonempty'` or `'lexo_short'`)
+///
+/// # Example
+/// ```sql
+/// -- Add a 'position' column to 'items' table
+/// SELECT lexo_add_column('items', 'position');
+///
+/// -- The column is created as:
+/// -- ALTER TABLE items ADD COLUMN position lexo;
+///
+/// -- Use one of the shipped domains instead
+/// SELECT lexo_add_column('items', 'position', 'lexo_short');
+/// ```
+#[pg_extern]
+pub fn lexo_add_column(
+    table_name: &str,
+    column_name: &str,
+    type_name: default!(Option<&str>, "NULL"),
+) {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[]);
+        let quoted_table = quote_table_name(table_name);
+
+        let quoted_column = quote_identifier(column_name);
+
+        require_table_owner(table_name);
+        let column_type = match type_name {
+            Some(name) => resolve_lexo_type(name),
+            None => format!("{}.lexo", extension_schema()),
+        };
+
+        let query = format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            quoted_table, quoted_column, column_type
+        );
+
+        Spi::run(&query).expect("Failed to add lexo column to table");
+    })
+}
+
+/// Drops a lexo position column from a table.
+///
+/// The views `lexo_create_ordered_view()` created for the table select all of
+/// its columns, so they are dropped first. Those over another of the table's
+/// position columns are created again afterwards, without the dropped column.
+///
+/// Only the table's owner may drop a column; anyone else gets an
+/// `insufficient_privilege` error before the table is touched.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `column_name` - The name of the column to drop
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_drop_column('items', 'position');
+/// ```
+#[pg_extern]
+pub fn lexo_drop_column(table_name: &str, column_name: &str) {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(column_name)]);
+        let quoted_table = quote_table_name(table_name);
+
+        require_table_owner(table_name);
+
+        let query = format!(
+            "SELECT view_name::text, column_name::text, key_column::text \
+             FROM {}.lexo_ordered_views \
+             WHERE table_name = {}::regclass AND column_name <> {col} \
+             AND key_column IS DISTINCT FROM {col} \
+             AND EXISTS (SELECT 1 FROM pg_catalog.pg_class WHERE oid = view_name)",
+            extension_schema(),
+            quote_literal(&quoted_table),
+            col = quote_literal(column_name)
+        );
+        let kept: Vec<(String, String, Option<String>)> = Spi::connect(|client| {
+            client
+                .select(&query, None, &[])
+                .expect("Failed to look up ordered views")
+                .map(|row| {
+                    (
+                        row.get(1).ok().flatten().unwrap_or_default(),
+                        row.get(2).ok().flatten().unwrap_or_default(),
+                        row.get(3).ok().flatten(),
+                    )
+                })
+                .collect()
+        });
+
+        lexo_drop_ordered_views(Some(table_name), None);
+        Spi::run(&format!(
+            "ALTER TABLE {} DROP COLUMN {}",
+            quoted_table,
+            quote_identifier(column_name)
+        ))
+        .expect("Failed to drop lexo column from table");
+
+        for (view, column, key_column) in &kept {
+            lexo_create_ordered_view(table_name, column, Some(view), key_column.as_deref());
+        }
+    })
+}
+
+/// Creates the btree index that serves ordered queries on a list.
+///
+/// The index is on `(key_columns..., lexo_column_name)`, so per-list queries
+/// like `WHERE list_id = $1 ORDER BY position` read it in order. `lexo` and
+/// `smalllexo` columns use their default operator class; text columns (such
+/// as the `lexo_text` domain) get `COLLATE "C"` so the index order is
+/// position order. If a valid btree index on the same columns already
+/// exists (a unique one, when `is_unique` is set), nothing is created and its
+/// name is returned with a NOTICE.
+///
+/// PostgreSQL does not allow `CREATE INDEX CONCURRENTLY` inside a function,
+/// so this takes a lock that blocks writes while the index builds. Use
+/// `lexo_create_index_sql()` to build it concurrently instead.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The position column
+/// * `key_columns` - Columns identifying the list, in order (default none)
+/// * `is_unique` - Create a UNIQUE index (default false)
+/// * `index_name` - Name for the index (default: chosen by PostgreSQL)
+///
+/// # Returns
+/// The name of the new or existing index
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_create_index('playlist_songs', 'position', ARRAY['playlist_id'], true);
+/// ```
+#[pg_extern]
+pub fn lexo_create_index(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_columns: default!(Vec<String>, "'{}'"),
+    is_unique: default!(bool, false),
+    index_name: default!(Option<&str>, "NULL"),
+) -> String {
+    with_pinned_search_path(|| {
+        let plan = plan_lexo_index(table_name, lexo_column_name, &key_columns, is_unique);
+        if let Some(existing) = plan.find_equivalent() {
+            pgrx::notice!(
+                "index \"{}\" already covers ({}), skipping",
+                existing,
+                plan.columns
+            );
+            return existing;
+        }
+
+        Spi::run(&plan.statement(index_name, false)).expect("Failed to create index");
+        plan.find_equivalent()
+            .expect("created index should match its own definition")
+    })
+}
+
+/// Returns the `CREATE INDEX CONCURRENTLY` statement `lexo_create_index()`
+/// would run, or NULL with a NOTICE when an equivalent index already exists.
+///
+/// Run the result outside a transaction block, for example with psql's
+/// `\gexec`, to build the index without blocking writes.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_create_index_sql('playlist_songs', 'position', ARRAY['playlist_id']) \gexec
+/// ```
+#[pg_extern]
+pub fn lexo_create_index_sql(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_columns: default!(Vec<String>, "'{}'"),
+    is_unique: default!(bool, false),
+    index_name: default!(Option<&str>, "NULL"),
+) -> Option<String> {
+    with_pinned_search_path(|| {
+        let plan = plan_lexo_index(table_name, lexo_column_name, &key_columns, is_unique);
+        if let Some(existing) = plan.find_equivalent() {
+            pgrx::notice!(
+                "index \"{}\" already covers ({}), skipping",
+                existing,
+                plan.columns
+            );
+            return None;
+        }
+        Some(plan.statement(index_name, true))
+    })
+}
+
+/// What `lexo_create_index()` builds, resolved against the catalog.
+struct LexoIndexPlan {
+    table: String,
+    quoted_table: String,
+    /// The index column list, as written in CREATE INDEX
+    columns: String,
+    /// Column numbers of the key columns, then the position column
+    attnums: Vec<i16>,
+    text_column: bool,
+    unique: bool,
+}
+
+/// Looks up a column's number and whether it holds positions: `"lexo"` for
+/// `lexo`, `smalllexo` and domains over them, `"text"` for text and its
+/// domains such as `lexo_text`, NULL otherwise.
+fn column_info(table_name: &str, quoted_table: &str, column: &str) -> (i16, Option<String>) {
+    require_columns(table_name, &[Some(column)]);
+    let query = format!(
+        "SELECT a.attnum, CASE \
+             WHEN t.oid IN ({lexo}::regtype, {small}::regtype) \
+               OR t.typbasetype IN ({lexo}::regtype, {small}::regtype) THEN 'lexo' \
+             WHEN t.oid = 'text'::regtype OR t.typbasetype = 'text'::regtype THEN 'text' \
+         END \
+         FROM pg_catalog.pg_attribute a JOIN pg_catalog.pg_type t ON t.oid = a.atttypid \
+         WHERE a.attrelid = pg_catalog.to_regclass({}) AND a.attname = {} \
+         AND a.attnum > 0 AND NOT a.attisdropped",
+        quote_literal(quoted_table),
+        quote_literal(column),
+        lexo = quote_literal(format!("{}.lexo", extension_schema())),
+        small = quote_literal(format!("{}.smalllexo", extension_schema()))
+    );
+    Spi::connect(|client| {
+        let row = client
+            .select(&query, Some(1), &[])
+            .expect("Failed to look up column")
+            .next();
+        match row {
+            Some(row) => (
+                row.get::<i16>(1)
+                    .expect("Failed to get attnum")
+                    .expect("attnum was NULL"),
+                row.get::<String>(2).expect("Failed to get column kind"),
+            ),
+            None => pgrx::error!(
+                "column \"{}\" of table {} does not exist",
+                column,
+                table_name
+            ),
+        }
+    })
+}
+
+/// Returns the position column's number, the expression that sorts it in
+/// position order (with `COLLATE "C"` for text columns), and whether it is
+/// a text column.
+fn position_sort_key(
+    table_name: &str,
+    quoted_table: &str,
+    lexo_column_name: &str,
+) -> (i16, String, bool) {
+    let (attnum, kind) = column_info(table_name, quoted_table, lexo_column_name);
+    match kind.as_deref() {
+        Some("lexo") => (attnum, quote_identifier(lexo_column_name), false),
+        Some("text") => (
+            attnum,
+            format!("{} COLLATE \"C\"", quote_identifier(lexo_column_name)),
+            true,
+        ),
+        _ => pgrx::error!(
+            "column \"{}\" is not a lexo, smalllexo or text column",
+            lexo_column_name
+        ),
+    }
+}
+
+fn plan_lexo_index(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_columns: &[String],
+    unique: bool,
+) -> LexoIndexPlan {
+    let quoted_table = quote_table_name(table_name);
+
+    let mut attnums = Vec::with_capacity(key_columns.len() + 1);
+    let mut columns: Vec<String> = Vec::with_capacity(key_columns.len() + 1);
+    for key in key_columns {
+        attnums.push(column_info(table_name, &quoted_table, key).0);
+        columns.push(quote_identifier(key));
+    }
+
+    let (attnum, sort_key, text_column) =
+        position_sort_key(table_name, &quoted_table, lexo_column_name);
+    attnums.push(attnum);
+    columns.push(sort_key);
+
+    LexoIndexPlan {
+        table: table_name.to_string(),
+        quoted_table,
+        columns: columns.join(", "),
+        attnums,
+        text_column,
+        unique,
+    }
+}
+
+impl LexoIndexPlan {
+    fn statement(&self, index_name: Option<&str>, concurrently: bool) -> String {
+        format!(
+            "CREATE {}INDEX {}{}ON {} ({})",
+            if self.unique { "UNIQUE " } else { "" },
+            if concurrently { "CONCURRENTLY " } else { "" },
+            index_name
+                .map(|name| format!("{} ", quote_identifier(name)))
+                .unwrap_or_default(),
+            self.quoted_table,
+            self.columns
+        )
+    }
+
+    /// Finds a valid plain btree index on exactly these columns, in this order.
+    fn find_equivalent(&self) -> Option<String> {
+        let attnums: Vec<String> = self.attnums.iter().map(i16::to_string).collect();
+        let query = format!(
+            "SELECT c.relname::text FROM pg_catalog.pg_index i \
+             JOIN pg_catalog.pg_class c ON c.oid = i.indexrelid \
+             JOIN pg_catalog.pg_am am ON am.oid = c.relam \
+             WHERE i.indrelid = pg_catalog.to_regclass({table}) AND am.amname = 'btree' \
+             AND i.indisvalid AND i.indpred IS NULL AND i.indexprs IS NULL \
+             AND (i.indisunique OR NOT {unique}) \
+             AND (i.indkey::int2[])[0:i.indnkeyatts - 1] = '{{{attnums}}}'::int2[] \
+             AND (NOT {text} OR (i.indcollation::oid[])[i.indnkeyatts - 1] \
+                  = 'pg_catalog.\"C\"'::regcollation) \
+             ORDER BY c.relname LIMIT 1",
+            table = quote_literal(&self.quoted_table),
+            unique = self.unique,
+            attnums = attnums.join(","),
+            text = self.text_column
+        );
+        Spi::get_one(&query)
+            .unwrap_or_else(|e| pgrx::error!("Failed to look up indexes on {}: {}", self.table, e))
+    }
+}
+
+// Views created by `lexo_create_ordered_view()`, so they can be found and dropped later.
+extension_sql!(
+    r#"
+CREATE TABLE lexo_ordered_views (
+    view_name regclass PRIMARY KEY,
+    table_name regclass NOT NULL,
+    column_name name NOT NULL,
+    key_column name
+);
+SELECT pg_catalog.pg_extension_config_dump('lexo_ordered_views', '');
+"#,
+    name = "lexo_ordered_views"
+);
+
+/// Creates (or replaces) a view of a table with each row's 1-based place in
+/// its list as an `ordinal` column.
+///
+/// The view selects every column of the table plus
+/// `row_number() OVER (PARTITION BY key_column ORDER BY position)`, with text
+/// position columns sorted `COLLATE "C"`, so tools reading it never see the
+/// rank strings' ordering rules. The view's column list is fixed when it is
+/// created: call this again after adding columns to the table. The view is
+/// recorded in `lexo_ordered_views`; `lexo_drop_ordered_views()` removes it.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The position column
+/// * `view_name` - Name of the view (default: `<table>_ordered` in the table's schema)
+/// * `key_column_name` - Column identifying the list; ordinals restart for each value (default NULL)
+///
+/// # Returns
+/// The name of the view
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_create_ordered_view('playlist_songs', 'position', key_column_name => 'playlist_id');
+/// SELECT song_id, ordinal FROM playlist_songs_ordered WHERE playlist_id = 1 ORDER BY ordinal;
+/// ```
+#[pg_extern]
+pub fn lexo_create_ordered_view(
+    table_name: &str,
+    lexo_column_name: &str,
+    view_name: default!(Option<&str>, "NULL"),
+    key_column_name: default!(Option<&str>, "NULL"),
+) -> String {
+    with_pinned_search_path(|| {
+        let quoted_table = quote_table_name(table_name);
+        let quoted_view = match view_name {
+            Some(name) => quote_table_name(name),
+            None => {
+                let mut parts = split_table_name(table_name).unwrap_or_default();
+                if let Some(table) = parts.last_mut() {
+                    table.push_str("_ordered");
+                }
+                parts
+                    .iter()
+                    .map(quote_identifier)
+                    .collect::<Vec<_>>()
+                    .join(".")
+            }
+        };
+
+        let (_, sort_key, _) = position_sort_key(table_name, &quoted_table, lexo_column_name);
+        let partition = match key_column_name {
+            Some(key_col) => {
+                column_info(table_name, &quoted_table, key_col);
+                format!("PARTITION BY {} ", quote_identifier(key_col))
+            }
+            None => String::new(),
+        };
+
+        Spi::run(&format!(
+            "CREATE OR REPLACE VIEW {} AS SELECT t.*, \
+             row_number() OVER ({}ORDER BY {}) AS ordinal FROM {} t",
+            quoted_view, partition, sort_key, quoted_table
+        ))
+        .expect("Failed to create ordered view");
+
+        Spi::run(&format!(
+            "INSERT INTO {ext}.lexo_ordered_views (view_name, table_name, column_name, key_column) \
+             VALUES ({view}::regclass, {table}::regclass, {col}, {key}) \
+             ON CONFLICT (view_name) DO UPDATE SET table_name = EXCLUDED.table_name, \
+             column_name = EXCLUDED.column_name, key_column = EXCLUDED.key_column",
+            view = quote_literal(&quoted_view),
+            table = quote_literal(&quoted_table),
+            col = quote_literal(lexo_column_name),
+            key = key_column_name
+                .map(quote_literal)
+                .unwrap_or_else(|| "NULL".to_string()),
+            ext = extension_schema()
+        ))
+        .expect("Failed to record ordered view");
+
+        Spi::get_one::<String>(&format!(
+            "SELECT {}::regclass::text",
+            quote_literal(&quoted_view)
+        ))
+        .expect("Failed to look up ordered view")
+        .expect("ordered view name was NULL")
+    })
+}
+
+/// Drops the views `lexo_create_ordered_view()` created for a table.
+///
+/// `lexo_drop_column()` calls this before dropping a position column. With a
+/// NULL `table_name`, every registered view is dropped, which is the first
+/// step of uninstalling the extension, since the views depend on it.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified), or NULL for every table
+/// * `lexo_column_name` - Only drop views over this column (default: all of the table's views)
+///
+/// # Returns
+/// The number of views dropped
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_drop_ordered_views('playlist_songs');
+/// ```
+#[pg_extern]
+pub fn lexo_drop_ordered_views(
+    table_name: Option<&str>,
+    lexo_column_name: default!(Option<&str>, "NULL"),
+) -> i64 {
+    with_pinned_search_path(|| {
+        let table = match table_name {
+            Some(name) => format!(
+                "pg_catalog.to_regclass({})",
+                quote_literal(quote_table_name(name))
+            ),
+            None => "table_name".to_string(),
+        };
+
+        // Views dropped by hand leave a bare OID behind, which is skipped.
+        let query = format!(
+            "DELETE FROM {}.lexo_ordered_views \
+             WHERE table_name = {} AND ({col} IS NULL OR column_name = {col}) \
+             RETURNING CASE WHEN EXISTS (SELECT 1 FROM pg_catalog.pg_class WHERE oid = view_name) \
+                       THEN view_name::text END",
+            extension_schema(),
+            table,
+            col = lexo_column_name
+                .map(quote_literal)
+                .unwrap_or_else(|| "NULL::name".to_string())
+        );
+
+        let views: Vec<String> = Spi::connect_mut(|client| {
+            client
+                .update(&query, None, &[])
+                .expect("Failed to deregister ordered views")
+                .filter_map(|row| row.get::<String>(1).ok().flatten())
+                .collect()
+        });
+        for view in &views {
+            Spi::run(&format!("DROP VIEW IF EXISTS {}", view))
+                .expect("Failed to drop ordered view");
+        }
+        views.len() as i64
+    })
+}
+
+// Statement-level trigger behind lexo_maintain_ordinal(). Its arguments are the
+// ORDER BY expression for the position column, the ordinal column and the key
+// column ('' for a single list). Only rows whose ordinal changes are written.
+// The UPDATE trigger has a column list, which can't be combined with
+// transition tables, so an UPDATE renumbers every list.
+extension_sql!(
+    r#"
+CREATE FUNCTION lexo_ordinal_refresh() RETURNS trigger
+LANGUAGE plpgsql
+SET search_path = pg_catalog, @extschema@, pg_temp AS $$
+DECLARE
+    order_by text := TG_ARGV[0];
+    ordinal_column text := TG_ARGV[1];
+    key_column text := TG_ARGV[2];
+    touched text;
+    window_clause text := format('ORDER BY %s', order_by);
+    filter text := '';
+BEGIN
+    -- Rows written by another trigger are renumbered by the statement that
+    -- fired it.
+    IF pg_trigger_depth() > 1 THEN
+        RETURN NULL;
+    END IF;
+    -- Statement triggers fire even when no row changed.
+    IF TG_OP = 'DELETE' THEN
+        PERFORM 1 FROM lexo_old_rows LIMIT 1;
+    ELSIF TG_OP = 'INSERT' THEN
+        PERFORM 1 FROM lexo_new_rows LIMIT 1;
+    END IF;
+    IF TG_OP <> 'UPDATE' AND NOT FOUND THEN
+        RETURN NULL;
+    END IF;
+    IF key_column <> '' THEN
+        window_clause := format('PARTITION BY %I %s', key_column, window_clause);
+    END IF;
+    IF key_column <> '' AND TG_OP <> 'UPDATE' THEN
+        touched := format('SELECT %I FROM %s', key_column,
+                          CASE TG_OP WHEN 'INSERT' THEN 'lexo_new_rows' ELSE 'lexo_old_rows' END);
+        filter := format(
+            'WHERE %1$I IN (SELECT v FROM (%2$s) k(v)) '
+            'OR (%1$I IS NULL AND EXISTS (SELECT 1 FROM (%2$s) k(v) WHERE v IS NULL))',
+            key_column, touched);
+    END IF;
+    EXECUTE format(
+        'UPDATE %1$s t SET %2$I = s.ordinal '
+        'FROM (SELECT tableoid AS rel, ctid AS row_id, row_number() OVER (%3$s) AS ordinal '
+        'FROM %1$s %4$s) s '
+        'WHERE t.tableoid = s.rel AND t.ctid = s.row_id AND t.%2$I IS DISTINCT FROM s.ordinal',
+        TG_RELID::regclass, ordinal_column, window_clause, filter);
+    RETURN NULL;
+END
+$$;
+"#,
+    name = "lexo_ordinal_refresh"
+);
+
+/// Keeps an integer column in sync with position order for consumers that
+/// need plain numbers.
+///
+/// Adds `ordinal_column_name` as an `integer` column if the table lacks it,
+/// fills it with each row's 1-based place in its list, and installs
+/// statement-level triggers that renumber only the lists an `INSERT` or
+/// `DELETE` touched, in one `UPDATE` per statement. An `UPDATE` of the
+/// position or key column renumbers every list. Every renumber reads the
+/// whole list, so this suits read-heavy lists of modest size.
+/// `lexo_detach_ordinal()` removes the triggers.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The position column
+/// * `ordinal_column_name` - The integer column to maintain
+/// * `key_column_name` - Column identifying the list; ordinals restart for each value (default NULL)
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_maintain_ordinal('playlist_songs', 'position', 'display_order', 'playlist_id');
+/// ```
+#[pg_extern(requires = ["lexo_ordinal_refresh"])]
+pub fn lexo_maintain_ordinal(
+    table_name: &str,
+    lexo_column_name: &str,
+    ordinal_column_name: &str,
+    key_column_name: default!(Option<&str>, "NULL"),
+) {
+    with_pinned_search_path(|| {
+        let quoted_table = quote_table_name(table_name);
+        let quoted_ordinal = quote_identifier(ordinal_column_name);
+
+        let (_, sort_key, _) = position_sort_key(table_name, &quoted_table, lexo_column_name);
+        let partition = match key_column_name {
+            Some(key_col) => {
+                column_info(table_name, &quoted_table, key_col);
+                format!("PARTITION BY {} ", quote_identifier(key_col))
+            }
+            None => String::new(),
+        };
+
+        Spi::run(&format!(
+            "ALTER TABLE {} ADD COLUMN IF NOT EXISTS {} integer",
+            quoted_table, quoted_ordinal
+        ))
+        .expect("Failed to add ordinal column");
+        Spi::run(&format!(
+            "UPDATE {table} t SET {ord} = s.ordinal \
+             FROM (SELECT tableoid AS rel, ctid AS row_id, \
+                          row_number() OVER ({}ORDER BY {}) AS ordinal FROM {table}) s \
+             WHERE t.tableoid = s.rel AND t.ctid = s.row_id AND t.{ord} IS DISTINCT FROM s.ordinal",
+            partition,
+            sort_key,
+            table = quoted_table,
+            ord = quoted_ordinal
+        ))
+        .expect("Failed to fill ordinal column");
+
+        let args = [
+            sort_key.as_str(),
+            ordinal_column_name,
+            key_column_name.unwrap_or(""),
+        ]
+        .map(quote_literal)
+        .join(", ");
+        // Only updates that can move a row within or between lists renumber
+        let updated_columns = std::iter::once(lexo_column_name)
+            .chain(key_column_name)
+            .map(quote_identifier)
+            .collect::<Vec<_>>()
+            .join(", ");
+        for (event, transitions) in ORDINAL_TRIGGERS {
+            let fired_by = match event {
+                "UPDATE" => format!("UPDATE OF {}", updated_columns),
+                _ => format!("{} REFERENCING {}", event, transitions),
+            };
+            Spi::run(&format!(
+                "CREATE TRIGGER {} AFTER {} ON {} \
+                 FOR EACH STATEMENT EXECUTE FUNCTION {ext}.lexo_ordinal_refresh({})",
+                ordinal_trigger_name(ordinal_column_name, event),
+                fired_by,
+                quoted_table,
+                args,
+                ext = extension_schema()
+            ))
+            .expect("Failed to create ordinal trigger");
+        }
+    })
+}
+
+/// Removes the triggers installed by `lexo_maintain_ordinal()`.
+///
+/// The ordinal column and its current values are kept.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `ordinal_column_name` - The maintained integer column
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_detach_ordinal('playlist_songs', 'display_order');
+/// ```
+#[pg_extern]
+pub fn lexo_detach_ordinal(table_name: &str, ordinal_column_name: &str) {
+    with_pinned_search_path(|| {
+        let quoted_table = quote_table_name(table_name);
+
+        for (event, _) in ORDINAL_TRIGGERS {
+            Spi::run(&format!(
+                "DROP TRIGGER {} ON {}",
+                ordinal_trigger_name(ordinal_column_name, event),
+                quoted_table
+            ))
+            .expect("Failed to drop ordinal trigger");
+        }
+    })
+}
+
+/// The events `lexo_maintain_ordinal()` installs a trigger for, with the
+/// transition tables `lexo_ordinal_refresh()` reads. The `UPDATE` trigger
+/// lists columns instead, which rules out transition tables.
+const ORDINAL_TRIGGERS: [(&str, &str); 3] = [
+    ("INSERT", "NEW TABLE AS lexo_new_rows"),
+    ("UPDATE", ""),
+    ("DELETE", "OLD TABLE AS lexo_old_rows"),
+];
+
+fn ordinal_trigger_name(ordinal_column_name: &str, event: &str) -> String {
+    quote_identifier(format!(
+        "lexo_ordinal_{}_{}",
+        ordinal_column_name,
+        event.to_lowercase()
+    ))
+}
+
+/// Resolves a type name to its SQL form, checking that it is `lexo` or a domain over it.
+fn resolve_lexo_type(type_name: &str) -> String {
+    let query = format!(
+        "SELECT t.oid::regtype::text FROM pg_catalog.pg_type t \
+         WHERE t.oid = pg_catalog.to_regtype({}) \
+         AND (t.oid = {lexo}::regtype OR t.typbasetype = {lexo}::regtype)",
+        quote_literal(type_name),
+        lexo = quote_literal(format!("{}.lexo", extension_schema()))
+    );
+
+    let resolved: Option<String> = Spi::get_one(&query).expect("Failed to look up column type");
+
+    resolved.unwrap_or_else(|| {
+        pgrx::error!(
+            "Type '{}' does not exist or is not lexo or a domain over lexo",
+            type_name
+        )
+    })
+}
+
+/// Converts a position column between the native `lexo` type and the `lexo_text` domain.
+///
+/// The `lexo_text` domain is a text-backed alternative for tools (such as some
+/// ORMs) that can't handle custom types. Existing values are kept as-is.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `column_name` - The name of the position column
+/// * `target` - Either `'text_domain'` (convert to `lexo_text`) or `'native'` (convert to `lexo`)
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_convert_column('items', 'position', 'text_domain');
+/// SELECT lexo_convert_column('items', 'position', 'native');
+/// ```
+#[pg_extern]
+pub fn lexo_convert_column(table_name: &str, column_name: &str, target: &str) {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(column_name)]);
+        let column_type = match target {
+            "text_domain" => "lexo_text",
+            "native" => "lexo",
+            _ => pgrx::error!(
+                "Invalid target '{}': must be 'text_domain' or 'native'",
+                target
+            ),
+        };
+        let column_type = format!("{}.{}", extension_schema(), column_type);
+
+        let quoted_table = quote_table_name(table_name);
+
+        let quoted_column = quote_identifier(column_name);
+
+        let query = format!(
+            "ALTER TABLE {} ALTER COLUMN {} TYPE {} USING {}::text::{}",
+            quoted_table, quoted_column, column_type, quoted_column, column_type
+        );
+
+        Spi::run(&query).expect("Failed to convert lexo column");
+    })
+}
+
+/// Converts a text column holding positions to the `lexo` type.
+///
+/// Checks every value first and handles invalid ones according to `on_invalid`:
+/// - `'error'` aborts, listing up to 10 offending values
+/// - `'null'` sets invalid values to NULL
+/// - `'rerank'` gives invalid values fresh positions between their valid
+///   neighbours, keeping the column's current byte-wise order
+///
+/// The column is then converted with `ALTER COLUMN ... TYPE lexo`, which also
+/// rebuilds every index on it.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `column_name` - The text column to convert
+/// * `on_invalid` - `'error'` (default), `'null'` or `'rerank'`
+///
+/// # Returns
+/// A summary row: values checked, invalid values found, the action taken and
+/// the number of indexes rebuilt
+///
+/// # Example
+/// ```sql
+/// SELECT * FROM lexo_convert_text_column('items', 'sort_key', 'rerank');
+/// ```
+#[pg_extern]
+pub fn lexo_convert_text_column(
+    table_name: &str,
+    column_name: &str,
+    on_invalid: default!(&str, "'error'"),
+) -> TableIterator<
+    'static,
+    (
+        name!(rows_checked, i64),
+        name!(invalid_rows, i64),
+        name!(action, String),
+        name!(indexes_rebuilt, i64),
+    ),
+> {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(column_name)]);
+        if !matches!(on_invalid, "error" | "null" | "rerank") {
+            pgrx::error!(
+                "Invalid on_invalid '{}': must be 'error', 'null' or 'rerank'",
+                on_invalid
+            );
+        }
+
+        let quoted_table = quote_table_name(table_name);
+
+        let quoted_column = quote_identifier(column_name);
+
+        let (rows_checked, invalid_rows) = Spi::connect_mut(|client| {
+            // Read the column in byte-wise order, which is `lexo` order for valid values
+            let select_query = format!(
+                "SELECT tableoid::text, ctid::text, {col}::text FROM {table} WHERE {col} IS NOT NULL \
+                 ORDER BY {col}::text COLLATE \"C\"",
+                col = quoted_column,
+                table = quoted_table
+            );
+            let rows: Vec<(String, String)> = client
+                .select(&select_query, None, &[])
+                .expect("Failed to read column values")
+                .map(|row| {
+                    let value: String =
+                        row.get(3).expect("Failed to get value").unwrap_or_default();
+                    (row_locator(&row), value)
+                })
+                .collect();
+
+            let invalid: Vec<usize> = (0..rows.len())
+                .filter(|&i| !is_valid_base62(&rows[i].1))
+                .collect();
+
+            if !invalid.is_empty() {
+                match on_invalid {
+                    "error" => {
+                        let examples = invalid
+                            .iter()
+                            .take(10)
+                            .map(|&i| format!("'{}'", rows[i].1))
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        pgrx::error!(
+                            "{} values in column {} are not valid positions: {}",
+                            invalid.len(),
+                            column_name,
+                            examples
+                        );
+                    }
+                    "null" => {
+                        for &i in &invalid {
+                            let update_query = format!(
+                                "UPDATE {} SET {} = NULL WHERE {}",
+                                quoted_table, quoted_column, rows[i].0
+                            );
+                            client
+                                .update(&update_query, None, &[])
+                                .expect("Failed to clear invalid value");
+                        }
+                    }
+                    _ => {
+                        let values: Vec<&str> = rows.iter().map(|(_, v)| v.as_str()).collect();
+                        let replacements = reposition_invalid(&values);
+
+                        // Valid neighbours that leave no room (e.g. 'H' and 'H0')
+                        // would break the order
+                        let is_invalid = |i: usize| invalid.binary_search(&i).is_ok();
+                        let mut result: Vec<&str> = values.clone();
+                        for (i, position) in &replacements {
+                            result[*i] = position;
+                        }
+                        if let Some(k) = (1..result.len()).find(|&k| {
+                            (is_invalid(k - 1) || is_invalid(k)) && result[k - 1] >= result[k]
+                        }) {
+                            let value = if is_invalid(k) {
+                                values[k]
+                            } else {
+                                values[k - 1]
+                            };
+                            raise_error!(
+                                PgSqlErrorCode::ERRCODE_PROGRAM_LIMIT_EXCEEDED,
+                                format!(
+                                    "no room to rerank invalid value '{}' between its neighbours",
+                                    value
+                                ),
+                                hint = "Pass on_invalid => 'null', then rebalance the converted column and place those rows again.",
+                            );
+                        }
+
+                        for (i, position) in replacements {
+                            let update_query = format!(
+                                "UPDATE {} SET {} = {} WHERE {}",
+                                quoted_table,
+                                quoted_column,
+                                quote_literal(&position),
+                                rows[i].0
+                            );
+                            client
+                                .update(&update_query, None, &[])
+                                .expect("Failed to rerank invalid value");
+                        }
+                    }
+                }
+            }
+
+            (rows.len() as i64, invalid.len() as i64)
+        });
+
+        let index_query = format!(
+            "SELECT count(*) FROM pg_catalog.pg_index i \
+             JOIN pg_catalog.pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY (i.indkey) \
+             WHERE i.indrelid = {}::regclass AND a.attname = {}",
+            quote_literal(&quoted_table),
+            quote_literal(column_name)
+        );
+        let indexes_rebuilt: i64 = Spi::get_one(&index_query)
+            .expect("Failed to count indexes on the column")
+            .unwrap_or(0);
+
+        let alter_query = format!(
+            "ALTER TABLE {} ALTER COLUMN {} TYPE {ext}.lexo USING {}::text::{ext}.lexo",
+            quoted_table,
+            quoted_column,
+            quoted_column,
+            ext = extension_schema()
+        );
+        Spi::run(&alter_query).expect("Failed to convert column to lexo");
+
+        let action = if invalid_rows == 0 {
+            "none"
+        } else {
+            on_invalid
+        };
+        TableIterator::once((
+            rows_checked,
+            invalid_rows,
+            action.to_string(),
+            indexes_rebuilt,
+        ))
+    })
+}
+
+/// Rebalances lexicographic position values in a table.
+///
+/// This function recalculates all position values to be evenly distributed,
+/// which is useful when positions have become too long due to many insertions
+/// or when you want to "clean up" the ordering.
+///
+/// The function preserves the current order of rows while assigning new,
+/// optimally distributed position values.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `key_column_name` - Optional: column to group by (e.g., 'playlist_id')
+/// * `key_value` - Optional: value to filter by (rebalance only rows with this key)
+/// * `where_clause` - Optional: SQL predicate selecting the rows to rebalance
+/// * `mode` - `'equal'` (default) spreads rows evenly; `'proportional'` keeps
+///   each row near its current fractional position and only shortens it
+/// * `target_length` - Optional: make every new position exactly this long
+/// * `descending` - The list is displayed with `ORDER BY position DESC`
+/// * `allow_text` - Also accept a `text` or `varchar` column holding Base62
+///   positions
+/// * `include_nulls` - `'append'` (default), `'skip'` or `'error'` for rows
+///   whose position is NULL
+/// * `empty_as` - `'keep_order'` (default), `'append'` or `'error'` for rows
+///   whose position is the empty string
+/// * `allow_rls` - Rebalance the visible rows even when row-level security
+///   hides others
+/// * `only` - Leave out inheritance children and partitions
+/// * `pin_where` - Optional: SQL predicate selecting rows that keep their
+///   positions
+///
+/// The column must be `lexo`, `smalllexo`, a domain over one of them or
+/// `lexo_text`; any other type is rejected up front with the column's actual
+/// type in the message.
+///
+/// With `where_clause`, only the rows of the list matching the predicate get
+/// new positions. Each run of consecutive matching rows is spread out within
+/// the gap left by the non-matching rows around it, which keep their positions,
+/// so the overall order is unchanged. Like `pin_where`, the predicate must be a
+/// single expression: one that could close the parentheses it is wrapped in,
+/// or that contains a semicolon, comment or dollar quote, is rejected. It is
+/// still inserted into the query as is, so it must come from trusted code,
+/// never from end users.
+///
+/// `pin_where` works the other way round: rows matching it keep their
+/// positions, for example because other systems refer to them, and the other
+/// rows are spread out in the gaps between the pinned rows and the ends of the
+/// keyspace. It can be combined with `where_clause`; rows with a NULL position
+/// are never pinned. Two pinned rows with the same position, or pinned rows
+/// too close together to fit the rows between them, raise an error.
+///
+/// The queries run with `pg_catalog` and the extension's schema ahead of the
+/// caller's `search_path`, so a function in the predicate only resolves to
+/// the caller's own when no built-in or extension function matches its name
+/// and argument types.
+///
+/// `include_nulls` decides what happens to rows whose position is NULL:
+/// `'append'` ranks them after all other rows in primary key order (storage
+/// order without a primary key), `'skip'` leaves them NULL and out of the
+/// count, and `'error'` refuses to rebalance a list that has any.
+///
+/// Empty positions sort before all others, so by default they are ranked
+/// first. `empty_as => 'append'` ranks them after the other rows together with
+/// the NULL positions, even when `include_nulls` skips those, and `'error'`
+/// refuses to rebalance a list that has any.
+///
+/// The caller needs `SELECT` and `UPDATE` on the table. Without them the
+/// function raises `insufficient_privilege` before any row is read.
+///
+/// Row-level security would hide rows from the rebalance, and the rows it
+/// sees could be given positions that collide with the hidden ones. When
+/// policies apply to the caller, it refuses with `insufficient_privilege`
+/// unless `allow_rls` is set, in which case only the visible rows are
+/// rebalanced.
+///
+/// Rows of inheritance children and partitions are part of the list, as in
+/// a plain `SELECT` on the table. With `only`, the table's own rows are
+/// rebalanced alone, as `UPDATE ONLY` would.
+///
+/// # Returns
+/// The number of rows that were rebalanced
+///
+/// With `descending`, the list is read as `ORDER BY position DESC` and given
+/// new positions from the highest one down, so rows with a NULL position are
+/// appended at the bottom of the displayed list. Either way, rows that share a
+/// position end up in storage order (by `ctid`) in the direction the list is
+/// displayed, so the row stored first is shown first.
+///
+/// # Example
+/// ```sql
+/// -- Rebalance all positions in a table
+/// SELECT lexo_rebalance('items', 'position', NULL, NULL);
+///
+/// -- Rebalance positions for a specific playlist
+/// SELECT lexo_rebalance('playlist_songs', 'position', 'playlist_id', 'abc-123');
+///
+/// -- Rebalance only the rows that are not archived
+/// SELECT lexo_rebalance('tasks', 'position', 'project_id', '7', 'NOT archived');
+///
+/// -- Shorten positions but keep the deliberate gaps between them
+/// SELECT lexo_rebalance('tasks', 'position', NULL, NULL, mode => 'proportional');
+///
+/// -- Leave headroom: every position becomes exactly 6 characters long
+/// SELECT lexo_rebalance('tasks', 'position', NULL, NULL, target_length => 6);
+///
+/// -- A newest-first feed built with lexo_next_desc()
+/// SELECT lexo_rebalance('feed', 'position', NULL, NULL, descending => true);
+/// ```
+#[pg_extern]
+#[allow(clippy::too_many_arguments)]
+pub fn lexo_rebalance(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: Option<&str>,
+    key_value: Option<&str>,
+    where_clause: default!(Option<&str>, "NULL"),
+    mode: default!(&str, "'equal'"),
+    target_length: default!(Option<i32>, "NULL"),
+    descending: default!(bool, false),
+    allow_text: default!(bool, false),
+    include_nulls: default!(&str, "'append'"),
+    empty_as: default!(&str, "'keep_order'"),
+    allow_rls: default!(bool, false),
+    only: default!(bool, false),
+    pin_where: default!(Option<&str>, "NULL"),
+) -> i64 {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(lexo_column_name), key_column_name]);
+        require_position_column(table_name, lexo_column_name, allow_text);
+        let include_nulls = NullPositions::parse(include_nulls);
+        let empty_as = EmptyPositions::parse(empty_as);
+        require_table_privileges(table_name, lexo_column_name, &["SELECT", "UPDATE"]);
+        require_rls_allowed(table_name, allow_rls);
+        with_history_summary(
+            table_name,
+            lexo_column_name,
+            key_column_name,
+            key_value,
+            || {
+                rebalance_list(
+                    table_name,
+                    lexo_column_name,
+                    key_column_name,
+                    key_value,
+                    where_clause,
+                    mode,
+                    target_length,
+                    descending,
+                    include_nulls,
+                    empty_as,
+                    only,
+                    pin_where,
+                )
+            },
+        )
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn rebalance_list(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: Option<&str>,
+    key_value: Option<&str>,
+    where_clause: Option<&str>,
+    mode: &str,
+    target_length: Option<i32>,
+    descending: bool,
+    include_nulls: NullPositions,
+    empty_as: EmptyPositions,
+    only: bool,
+    pin_where: Option<&str>,
+) -> i64 {
+    if !matches!(mode, "equal" | "proportional") {
+        pgrx::error!("Invalid mode '{}': must be 'equal' or 'proportional'", mode);
+    }
+    let proportional = mode == "proportional";
+    if let Some(length) = target_length {
+        if length < 1 {
+            pgrx::error!("target_length must be at least 1, got {}", length);
+        }
+        if where_clause.is_some() || pin_where.is_some() || proportional {
+            pgrx::error!(
+                "target_length cannot be combined with where_clause, pin_where or mode => 'proportional'"
+            );
+        }
+    }
+
+    if let Some(clause) = where_clause
+        && let Err(message) = check_order_by_expression(clause)
+    {
+        pgrx::error!("invalid where_clause expression: {}", message);
+    }
+    let quoted_lexo_column = quote_identifier(lexo_column_name);
+    let pinned = pin_where.map(|pin| pinned_condition(pin, &quoted_lexo_column));
+    // Pinned rows are the ones left out of the matching rows
+    let predicate = match (where_clause, &pinned) {
+        (None, None) => None,
+        (Some(clause), None) => Some(clause.to_string()),
+        (None, Some(pinned)) => Some(format!("NOT ({})", pinned)),
+        (Some(clause), Some(pinned)) => Some(format!("({}) AND NOT ({})", clause, pinned)),
+    };
+
+    let quoted_table = table_scope(table_name, only);
+
+    let mut filter = list_filter(key_column_name, key_value);
+    match include_nulls {
+        NullPositions::Append => {}
+        NullPositions::Skip => {
+            filter = filter_and(&filter, &format!("{} IS NOT NULL", quoted_lexo_column));
+        }
+        NullPositions::Error => {
+            let nulls = count_null_positions(&quoted_table, &quoted_lexo_column, &filter);
+            if nulls > 0 {
+                raise_error!(
+                    PgSqlErrorCode::ERRCODE_NULL_VALUE_NOT_ALLOWED,
+                    format!(
+                        "{} rows of {} have a NULL {}",
+                        nulls, table_name, lexo_column_name
+                    ),
+                    hint = "Pass include_nulls => 'append' to rank them after the other rows, or 'skip' to leave them NULL.",
+                );
+            }
+        }
+    }
+    if let EmptyPositions::Error = empty_as {
+        let empties = count_empty_positions(&quoted_table, &quoted_lexo_column, &filter);
+        if empties > 0 {
+            raise_error!(
+                PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+                format!(
+                    "{} rows of {} have an empty {}",
+                    empties, table_name, lexo_column_name
+                ),
+                hint = "Pass empty_as => 'append' to rank them after the other rows, or 'keep_order' to rank them first.",
+            );
+        }
+    }
+
+    // Empty positions sort first as they are; 'append' sorts them with the NULLs
+    let position_order = match empty_as {
+        EmptyPositions::Append => format!(
+            "NULLIF({}::{ext}.lexo, ''::{ext}.lexo)",
+            quoted_lexo_column,
+            ext = extension_schema()
+        ),
+        _ => format!(
+            "{}::{ext}.lexo",
+            quoted_lexo_column,
+            ext = extension_schema()
+        ),
+    };
+    // Matching rows are repositioned in ascending order, so on a descending
+    // list the NULLs, shown at its bottom in key order, come first in reverse
+    let matching = predicate.is_some() || proportional;
+    let null_order = null_position_order(table_name, &position_order, matching && descending);
+    let order_by = format!(
+        "{}{}{}{}",
+        position_order,
+        if matching && descending {
+            " NULLS FIRST"
+        } else {
+            ""
+        },
+        null_order,
+        rebalance_tie_break(descending)
+    );
+
+    if let Some(pinned) = &pinned {
+        require_distinct_pins(&quoted_table, &quoted_lexo_column, &filter, pinned);
+    }
+
+    if matching {
+        let rows = rebalance_matching(
+            &quoted_table,
+            &quoted_lexo_column,
+            &filter,
+            predicate.as_deref().unwrap_or("true"),
+            proportional,
+            &order_by,
+        );
+        stats::count_rebalance(rows);
+        return rows;
+    }
+
+    let count_query = format!("SELECT COUNT(*) FROM {}{}", quoted_table, filter);
+
+    let count: Option<i64> = Spi::get_one(&count_query).expect("Failed to count rows in table");
+    let row_count = count.unwrap_or(0);
+
+    if row_count == 0 {
+        stats::count_rebalance(0);
+        return 0;
+    }
+
+    // Generate evenly distributed positions for all rows
+    let positions = match target_length {
+        Some(length) => generate_balanced_positions_of_length(row_count as usize, length as usize)
+            .unwrap_or_else(|| {
+                raise_error!(
+                    PgSqlErrorCode::ERRCODE_PROGRAM_LIMIT_EXCEEDED,
+                    format!(
+                        "{} rows do not fit in positions of length {}",
+                        row_count, length
+                    ),
+                    hint = "Use a larger target_length, or leave it NULL.",
+                )
+            }),
+        None => generate_balanced_positions(row_count as usize),
+    };
+
+    // Build query to get all rows in display order. A descending list is read
+    // from the top down and gets the positions from the highest one down, so
+    // NULLs are appended at its bottom. A ctid is only unique within one
+    // table, so children and partitions also need tableoid
+    let display_order = if descending {
+        format!("{} DESC NULLS LAST{}, ctid", position_order, null_order)
+    } else {
+        order_by
+    };
+    let select_query = format!(
+        "SELECT tableoid::text, ctid::text FROM {}{} ORDER BY {}",
+        quoted_table, filter, display_order
+    );
+
+    // Update each row with its new position, fetching the rows in batches
+    let batch_size = crate::gucs::REBALANCE_BATCH_SIZE.get() as std::ffi::c_long;
+    let mut progress = RebalanceProgress::new(row_count);
+    Spi::connect_mut(|client| {
+        let mut cursor = client.open_cursor(&select_query, &[]);
+        let mut idx = 0;
+        loop {
+            let rows: Vec<String> = cursor
+                .fetch(batch_size)
+                .expect("Failed to fetch rows for rebalancing")
+                .map(|row| row_locator(&row))
+                .collect();
+            if rows.is_empty() {
+                break;
+            }
+
+            for locator in rows {
+                let new_position = if descending {
+                    &positions[positions.len() - 1 - idx]
+                } else {
+                    &positions[idx]
+                };
+                let quoted_new_position = quote_literal(new_position);
+
+                let update_query = format!(
+                    "UPDATE {} SET {} = {} WHERE {}",
+                    quoted_table, quoted_lexo_column, quoted_new_position, locator
+                );
+
+                client
+                    .update(&update_query, None, &[])
+                    .expect("Failed to update row position");
+                idx += 1;
+                progress.row_done();
+            }
+        }
+    });
+
+    stats::count_rebalance(row_count);
+    row_count
+}
+
+/// Rebalances a list like `lexo_rebalance()` and reports what changed.
+///
+/// Takes the same arguments. When `key_column_name` is given without a
+/// `key_value`, every non-NULL group of that column is rebalanced as its own
+/// list. Rows that keep their position, including rows whose key is NULL in
+/// that case, are counted as skipped.
+///
+/// # Returns
+/// One row with `rows_updated`, `rows_skipped`, `null_count` and `empty_count`
+/// (rows whose position was NULL or empty before), `pinned_count` (rows that
+/// kept their position because of `pin_where`), `groups_processed`, the longest
+/// position before and after as `max_length_before`/`max_length_after`, and
+/// `duration_ms`
+///
+/// # Example
+/// ```sql
+/// -- Rebalance every playlist and log the improvement
+/// SELECT * FROM lexo_rebalance_summary('playlist_songs', 'position', 'playlist_id');
+/// ```
+#[allow(clippy::type_complexity)]
+#[pg_extern]
+#[allow(clippy::too_many_arguments)]
+pub fn lexo_rebalance_summary(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: default!(Option<&str>, "NULL"),
+    key_value: default!(Option<&str>, "NULL"),
+    where_clause: default!(Option<&str>, "NULL"),
+    mode: default!(&str, "'equal'"),
+    target_length: default!(Option<i32>, "NULL"),
+    descending: default!(bool, false),
+    allow_text: default!(bool, false),
+    include_nulls: default!(&str, "'append'"),
+    empty_as: default!(&str, "'keep_order'"),
+    allow_rls: default!(bool, false),
+    only: default!(bool, false),
+    pin_where: default!(Option<&str>, "NULL"),
+) -> TableIterator<
+    'static,
+    (
+        name!(rows_updated, i64),
+        name!(rows_skipped, i64),
+        name!(null_count, i64),
+        name!(empty_count, i64),
+        name!(pinned_count, i64),
+        name!(groups_processed, i64),
+        name!(max_length_before, Option<i32>),
+        name!(max_length_after, Option<i32>),
+        name!(duration_ms, f64),
+    ),
+> {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(lexo_column_name), key_column_name]);
+        require_position_column(table_name, lexo_column_name, allow_text);
+        let started = std::time::Instant::now();
+
+        require_table_privileges(table_name, lexo_column_name, &["SELECT", "UPDATE"]);
+        require_rls_allowed(table_name, allow_rls);
+        let quoted_table = table_scope(table_name, only);
+        let length_query = format!(
+            "SELECT max(length({}::text))::int4 FROM {}{}",
+            quote_identifier(lexo_column_name),
+            quoted_table,
+            list_filter(key_column_name, key_value)
+        );
+        let count_query = format!(
+            "SELECT count(*) FROM {}{}",
+            quoted_table,
+            list_filter(key_column_name, key_value)
+        );
+
+        let max_length_before: Option<i32> =
+            Spi::get_one(&length_query).expect("Failed to query position lengths");
+        let total: i64 = Spi::get_one(&count_query)
+            .expect("Failed to count rows in table")
+            .unwrap_or(0);
+        let null_count = count_null_positions(
+            &quoted_table,
+            &quote_identifier(lexo_column_name),
+            &list_filter(key_column_name, key_value),
+        );
+        let empty_count = count_empty_positions(
+            &quoted_table,
+            &quote_identifier(lexo_column_name),
+            &list_filter(key_column_name, key_value),
+        );
+        let pinned_count: i64 = match pin_where {
+            Some(pin) => Spi::get_one(&format!(
+                "SELECT count(*) FROM {}{}",
+                quoted_table,
+                filter_and(
+                    &list_filter(key_column_name, key_value),
+                    &pinned_condition(pin, &quote_identifier(lexo_column_name))
+                )
+            ))
+            .expect("Failed to count pinned rows")
+            .unwrap_or(0),
+            None => 0,
+        };
+
+        let groups: Vec<Option<String>> = match (key_column_name, key_value) {
+            (Some(key_col), None) => {
+                let quoted_key_column = quote_identifier(key_col);
+                let groups_query = format!(
+                    "SELECT DISTINCT {key}::text FROM {table} WHERE {key} IS NOT NULL ORDER BY 1",
+                    key = quoted_key_column,
+                    table = quoted_table
+                );
+                Spi::connect(|client| {
+                    client
+                        .select(&groups_query, None, &[])
+                        .expect("Failed to list groups")
+                        .map(|row| row.get::<String>(1).expect("Failed to get group key"))
+                        .collect()
+                })
+            }
+            _ if total == 0 => Vec::new(),
+            _ => vec![key_value.map(str::to_string)],
+        };
+
+        let rows_updated: i64 = groups
+            .iter()
+            .map(|group| {
+                lexo_rebalance(
+                    table_name,
+                    lexo_column_name,
+                    key_column_name,
+                    group.as_deref(),
+                    where_clause,
+                    mode,
+                    target_length,
+                    descending,
+                    allow_text,
+                    include_nulls,
+                    empty_as,
+                    allow_rls,
+                    only,
+                    pin_where,
+                )
+            })
+            .sum();
+
+        let max_length_after: Option<i32> =
+            Spi::get_one(&length_query).expect("Failed to query position lengths");
+
+        TableIterator::once((
+            rows_updated,
+            total - rows_updated,
+            null_count,
+            empty_count,
+            pinned_count,
+            groups.len() as i64,
+            max_length_before,
+            max_length_after,
+            started.elapsed().as_secs_f64() * 1000.0,
+        ))
+    })
+}
+
+/// Raises a NOTICE every `lexo.rebalance_report_every` rewritten rows.
+struct RebalanceProgress {
+    total: i64,
+    every: i64,
+    done: i64,
+    started: std::time::Instant,
+}
+
+impl RebalanceProgress {
+    fn new(total: i64) -> Self {
+        RebalanceProgress {
+            total,
+            every: crate::gucs::REBALANCE_REPORT_EVERY.get() as i64,
+            done: 0,
+            started: std::time::Instant::now(),
+        }
+    }
+
+    fn row_done(&mut self) {
+        self.done += 1;
+        if self.every > 0 && self.done % self.every == 0 {
+            pgrx::notice!(
+                "lexo_rebalance: {} of {} rows done, {:.1}s elapsed",
+                self.done,
+                self.total,
+                self.started.elapsed().as_secs_f64()
+            );
+        }
+    }
+}
+
+/// Orders rows that share a position so they come out in storage order in the
+/// list's display direction, once the new positions are handed out ascending.
+fn rebalance_tie_break(descending: bool) -> &'static str {
+    if descending { ", ctid DESC" } else { "" }
+}
+
+/// How `lexo_rebalance()` treats rows whose position is NULL.
+#[derive(Clone, Copy)]
+enum NullPositions {
+    /// Rank them after every other row, in primary key order
+    Append,
+    /// Leave them NULL and out of the count
+    Skip,
+    /// Refuse to rebalance a list that has any
+    Error,
+}
+
+impl NullPositions {
+    fn parse(include_nulls: &str) -> Self {
+        match include_nulls {
+            "append" => NullPositions::Append,
+            "skip" => NullPositions::Skip,
+            "error" => NullPositions::Error,
+            other => pgrx::error!(
+                "Invalid include_nulls '{}': must be 'append', 'skip' or 'error'",
+                other
+            ),
+        }
+    }
+}
+
+/// How `lexo_rebalance()` treats rows whose position is the empty string.
+#[derive(Clone, Copy)]
+enum EmptyPositions {
+    /// Rank them where they sort, before every other row
+    KeepOrder,
+    /// Rank them after every other row, with the NULL positions
+    Append,
+    /// Refuse to rebalance a list that has any
+    Error,
+}
+
+impl EmptyPositions {
+    fn parse(empty_as: &str) -> Self {
+        match empty_as {
+            "keep_order" => EmptyPositions::KeepOrder,
+            "append" => EmptyPositions::Append,
+            "error" => EmptyPositions::Error,
+            other => pgrx::error!(
+                "Invalid empty_as '{}': must be 'keep_order', 'append' or 'error'",
+                other
+            ),
+        }
+    }
+}
+
+/// Extends a `list_filter()` clause with another condition.
+fn filter_and(filter: &str, condition: &str) -> String {
+    if filter.is_empty() {
+        format!(" WHERE {}", condition)
+    } else {
+        format!("{} AND {}", filter, condition)
+    }
+}
+
+fn count_null_positions(quoted_table: &str, quoted_lexo_column: &str, filter: &str) -> i64 {
+    Spi::get_one(&format!(
+        "SELECT count(*) FROM {}{}",
+        quoted_table,
+        filter_and(filter, &format!("{} IS NULL", quoted_lexo_column))
+    ))
+    .expect("Failed to count NULL positions")
+    .unwrap_or(0)
+}
+
+fn count_empty_positions(quoted_table: &str, quoted_lexo_column: &str, filter: &str) -> i64 {
+    Spi::get_one(&format!(
+        "SELECT count(*) FROM {}{}",
+        quoted_table,
+        filter_and(filter, &format!("{}::text = ''", quoted_lexo_column))
+    ))
+    .expect("Failed to count empty positions")
+    .unwrap_or(0)
+}
+
+/// ORDER BY terms that sort rows whose `position_order` is NULL by primary key,
+/// or by `ctid` if the table has none, in reverse with `descending`. Other
+/// rows all compare equal on them.
+fn null_position_order(table_name: &str, position_order: &str, descending: bool) -> String {
+    let query = format!(
+        "SELECT pg_catalog.quote_ident(a.attname) FROM pg_catalog.pg_index i \
+         JOIN pg_catalog.pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY (i.indkey) \
+         WHERE i.indrelid = pg_catalog.to_regclass({})::pg_catalog.oid AND i.indisprimary \
+         ORDER BY pg_catalog.array_position(i.indkey::pg_catalog.int2[], a.attnum)",
+        quote_literal(quote_table_name(table_name))
+    );
+    let mut keys: Vec<String> = Spi::connect(|client| {
+        client
+            .select(&query, None, &[])
+            .expect("Failed to look up the primary key")
+            .filter_map(|row| row.get::<String>(1).expect("Failed to get key column"))
+            .collect()
+    });
+    if keys.is_empty() {
+        keys.push("ctid".to_string());
+    }
+    keys.iter()
+        .map(|key| {
+            format!(
+                ", CASE WHEN {} IS NULL THEN {} END{}",
+                position_order,
+                key,
+                if descending { " DESC" } else { "" }
+            )
+        })
+        .collect()
+}
+
+/// Returns the condition selecting the rows `pin_where` pins. A NULL result
+/// does not pin, and neither does a NULL position, which has nothing to keep.
+fn pinned_condition(pin_where: &str, quoted_lexo_column: &str) -> String {
+    if let Err(message) = check_order_by_expression(pin_where) {
+        pgrx::error!("invalid pin_where expression: {}", message);
+    }
+    format!(
+        "COALESCE(({}), false) AND {} IS NOT NULL",
+        pin_where, quoted_lexo_column
+    )
+}
+
+/// Raises `invalid_parameter_value` when two pinned rows share a position, as
+/// the rows between them could not be ordered.
+fn require_distinct_pins(quoted_table: &str, quoted_lexo_column: &str, filter: &str, pinned: &str) {
+    let query = format!(
+        "SELECT {col}::text FROM {table}{filter} GROUP BY {col}::text HAVING count(*) > 1 \
+         ORDER BY 1 LIMIT 1",
+        col = quoted_lexo_column,
+        table = quoted_table,
+        filter = filter_and(filter, pinned)
+    );
+    let shared: Option<String> = Spi::connect(|client| {
+        client
+            .select(&query, None, &[])
+            .expect("Failed to check pinned positions")
+            .next()
+            .and_then(|row| row.get(1).ok().flatten())
+    });
+    if let Some(position) = shared {
+        raise_error!(
+            PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            format!("several pinned rows share the position '{}'", position),
+            hint = "Pin at most one of them, so the others can be given positions around it.",
+        );
+    }
+}
+
+/// Returns a WHERE condition that matches exactly the row whose `tableoid`
+/// and `ctid` are the first two columns of `row`.
+fn row_locator(row: &pgrx::spi::SpiHeapTupleData) -> String {
+    let tableoid: String = row
+        .get(1)
+        .expect("Failed to get tableoid")
+        .expect("tableoid was NULL");
+    let ctid: String = row
+        .get(2)
+        .expect("Failed to get ctid")
+        .expect("ctid was NULL");
+    format!(
+        "tableoid = {}::oid AND ctid = {}::tid",
+        quote_literal(&tableoid),
+        quote_literal(&ctid)
+    )
+}
+
+/// Rebalances the rows of a list matching `predicate`, between the rows that don't.
+///
+/// With `proportional`, each matching row is moved to the shortest position
+/// near its current one instead of spreading runs of rows evenly. Matching rows
+/// whose position is NULL have none to keep, so they are spread evenly past the
+/// others, at the end `order_by` sorts NULLs to; the other NULL rows stay NULL.
+fn rebalance_matching(
+    quoted_table: &str,
+    quoted_lexo_column: &str,
+    filter: &str,
+    predicate: &str,
+    proportional: bool,
+    order_by: &str,
+) -> i64 {
+    let select_query = format!(
+        "SELECT tableoid::text, ctid::text, {}::text, ({}) IS TRUE FROM {}{} ORDER BY {}",
+        quoted_lexo_column, predicate, quoted_table, filter, order_by
+    );
+
+    Spi::connect_mut(|client| {
+        let mut locators = Vec::new();
+        let mut values = Vec::new();
+        let mut selected = Vec::new();
+        for row in client
+            .select(&select_query, None, &[])
+            .expect("Failed to select rows for rebalancing")
+        {
+            let value = row.get::<String>(3).expect("Failed to get position");
+            let is_selected = row
+                .get::<bool>(4)
+                .expect("Failed to evaluate where_clause")
+                .unwrap_or(false);
+            if value.is_none() && !is_selected {
+                continue;
+            }
+            locators.push(row_locator(&row));
+            values.push(value);
+            selected.push(is_selected);
+        }
+
+        let value_refs: Vec<&str> = values.iter().map(|v| v.as_deref().unwrap_or("")).collect();
+        let lower = START_CHAR.to_string();
+        let upper = END_CHAR.to_string();
+        let replacements: Vec<(usize, String)> = if proportional {
+            let mut replacements: Vec<(usize, String)> = (0..values.len())
+                .filter(|&i| selected[i] && values[i].is_some())
+                .map(|i| {
+                    let before = i.checked_sub(1).and_then(|j| values[j].as_deref());
+                    let after = values.get(i + 1).and_then(|v| v.as_deref());
+                    match proportional_position(before, value_refs[i], after) {
+                        Some(position) => (i, position),
+                        None => {
+                            raise_error!(
+                                PgSqlErrorCode::ERRCODE_DATA_EXCEPTION,
+                                format!(
+                                    "cannot rebalance position '{}' proportionally: its neighbours are duplicates",
+                                    value_refs[i]
+                                ),
+                                hint = "Rebalance with mode => 'equal' to separate duplicate positions.",
+                            )
+                        }
+                    }
+                })
+                .collect();
+            let mut moved = value_refs.clone();
+            for (i, position) in &replacements {
+                moved[*i] = position;
+            }
+            let nulls = reposition_runs(&moved, &lower, &upper, |i| values[i].is_none());
+            replacements.extend(nulls);
+            replacements
+        } else {
+            reposition_runs(&value_refs, &lower, &upper, |i| selected[i])
+        };
+
+        // Neighbours that leave no room (e.g. 'H' and 'H0') would break the order
+        let mut result: Vec<&str> = value_refs.clone();
+        for (i, position) in &replacements {
+            result[*i] = position;
+        }
+        if let Some(k) = (1..result.len()).find(|&k| {
+            (selected[k - 1] || selected[k]) && !result[k].is_empty() && result[k - 1] >= result[k]
+        }) {
+            pgrx::error!(
+                "no room to rebalance the matching rows between '{}' and '{}'",
+                value_refs[k - 1],
+                value_refs[k]
+            );
+        }
+
+        let changed: Vec<_> = replacements
+            .iter()
+            .filter(|(i, position)| values[*i].as_deref() != Some(position.as_str()))
+            .collect();
+        let mut progress = RebalanceProgress::new(changed.len() as i64);
+        for (i, position) in &changed {
+            let update_query = format!(
+                "UPDATE {} SET {} = {} WHERE {}",
+                quoted_table,
+                quoted_lexo_column,
+                quote_literal(position),
+                locators[*i]
+            );
+            client
+                .update(&update_query, None, &[])
+                .expect("Failed to update row position");
+            progress.row_done();
+        }
+
+        changed.len() as i64
+    })
+}
+
+/// Applies an externally supplied order to the rows of a table.
+///
+/// Assigns fresh balanced positions so that the rows whose primary keys are
+/// listed in `ordered_pks` come first, in that order. Rows that are not listed
+/// keep their relative order after the listed ones, unless `require_complete`
+/// is true, in which case they are an error. All rows are written in a single
+/// `UPDATE ... FROM (VALUES ...)`.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `pk_column_name` - The primary key column; keys are compared as values of its type
+/// * `ordered_pks` - JSON array of primary keys (strings or numbers) in the desired order
+/// * `key_column_name` - Optional: column to group by (e.g., 'playlist_id')
+/// * `key_value` - Optional: value to filter by (reorder only rows with this key)
+/// * `require_complete` - Whether every row in scope must be listed
+///
+/// # Returns
+/// The number of rows that were updated
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_import_order('playlist_songs', 'position', 'id', '[3, 1, 2]',
+///                          'playlist_id', 'abc-123');
+/// ```
+#[pg_extern]
+pub fn lexo_import_order(
+    table_name: &str,
+    lexo_column_name: &str,
+    pk_column_name: &str,
+    ordered_pks: pgrx::JsonB,
+    key_column_name: default!(Option<&str>, "NULL"),
+    key_value: default!(Option<&str>, "NULL"),
+    require_complete: default!(bool, false),
+) -> i64 {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[
+                Some(lexo_column_name),
+                Some(pk_column_name),
+                key_column_name,
+            ],
+        );
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+        let quoted_pk_column = quote_identifier(pk_column_name);
+
+        let quoted_table = quote_table_name(table_name);
+
+        let filter = match (key_column_name, key_value) {
+            (Some(key_col), Some(key_val)) => {
+                format!("{} = {}", quote_identifier(key_col), quote_literal(key_val))
+            }
+            _ => "true".to_string(),
+        };
+
+        let pk_type = column_type(table_name, pk_column_name);
+        let listed = json_pk_values(ordered_pks.0, "ordered_pks", &pk_type);
+
+        let select_query = format!(
+            "SELECT {}::text FROM {} WHERE {} ORDER BY {}::{ext}.lexo",
+            quoted_pk_column,
+            quoted_table,
+            filter,
+            quoted_lexo_column,
+            ext = extension_schema()
+        );
+
+        Spi::connect_mut(|client| {
+            let current: Vec<String> = client
+                .select(&select_query, None, &[])
+                .expect("Failed to select rows for reordering")
+                .map(|row| {
+                    row.get::<String>(1)
+                        .expect("Failed to get primary key")
+                        .expect("primary key was NULL")
+                })
+                .collect();
+
+            let existing: std::collections::HashSet<&str> =
+                current.iter().map(String::as_str).collect();
+            let mut seen = std::collections::HashSet::new();
+            for pk in &listed {
+                if !existing.contains(pk.as_str()) {
+                    pgrx::error!("primary key {} does not exist in {}", pk, table_name);
+                }
+                if !seen.insert(pk.as_str()) {
+                    pgrx::error!("primary key {} is listed more than once", pk);
+                }
+            }
+
+            let unlisted: Vec<&String> = current
+                .iter()
+                .filter(|pk| !seen.contains(pk.as_str()))
+                .collect();
+            if require_complete && !unlisted.is_empty() {
+                pgrx::error!(
+                    "{} rows are missing from ordered_pks, e.g. primary key {}",
+                    unlisted.len(),
+                    unlisted[0]
+                );
+            }
+
+            if current.is_empty() {
+                return 0;
+            }
+
+            let positions = generate_balanced_positions(current.len());
+            let values = listed
+                .iter()
+                .chain(unlisted)
+                .zip(&positions)
+                .map(|(pk, position)| {
+                    format!("({}, {})", quote_literal(pk), quote_literal(position))
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let update_query = format!(
+                "UPDATE {table} SET {col} = v.position::{ext}.lexo \
+                 FROM (VALUES {values}) AS v(pk, position) \
+                 WHERE {table}.{pk} = v.pk::{pk_type} AND {filter}",
+                table = quoted_table,
+                col = quoted_lexo_column,
+                values = values,
+                pk = quoted_pk_column,
+                pk_type = pk_type,
+                filter = filter,
+                ext = extension_schema()
+            );
+
+            client
+                .update(&update_query, None, &[])
+                .expect("Failed to apply the imported order")
+                .len() as i64
+        })
+    })
+}
+
+/// Returns the type of a table's column, as `format_type()` writes it.
+fn column_type(table_name: &str, column: &str) -> String {
+    Spi::get_one::<String>(&format!(
+        "SELECT pg_catalog.format_type(atttypid, atttypmod) FROM pg_catalog.pg_attribute \
+         WHERE attrelid = pg_catalog.to_regclass({}) AND attname = {} AND NOT attisdropped",
+        quote_literal(quote_table_name(table_name)),
+        quote_literal(column)
+    ))
+    .expect("Failed to look up the column type")
+    .unwrap_or_else(|| pgrx::error!("column \"{}\" of {} does not exist", column, table_name))
+}
+
+/// Reads a JSON array of primary keys (strings or numbers) in the text form
+/// of `pk_type`, the key column's type, so they compare with `pk::text` the
+/// way `pk = literal` would: an upper-case UUID finds its lower-case key.
+fn json_pk_values(value: Value, argument: &str, pk_type: &str) -> Vec<String> {
+    let keys = json_pk_list(value, argument);
+    if keys.is_empty() {
+        return keys;
+    }
+    let array = keys
+        .iter()
+        .map(quote_literal)
+        .collect::<Vec<_>>()
+        .join(", ");
+    Spi::get_one::<Vec<String>>(&format!(
+        "SELECT pg_catalog.array_agg(k::{}::text ORDER BY n) \
+         FROM pg_catalog.unnest(ARRAY[{}]::text[]) WITH ORDINALITY AS u(k, n)",
+        pk_type, array
+    ))
+    .expect("Failed to read the primary keys")
+    .unwrap_or_default()
+}
+
+/// Reads a JSON array of primary keys (strings or numbers) as their text forms.
+fn json_pk_list(value: Value, argument: &str) -> Vec<String> {
+    match value {
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::String(s) => s,
+                Value::Number(n) => n.to_string(),
+                other => pgrx::error!(
+                    "{} must contain only strings or numbers, got {}",
+                    argument,
+                    other
+                ),
+            })
+            .collect(),
+        other => pgrx::error!("{} must be a JSON array, got {}", argument, other),
+    }
+}
+
+/// Number of rows `lexo_export_order()` fetches from its cursor at a time.
+const EXPORT_BATCH_SIZE: i64 = 1000;
+
+/// Returns the current order of a table's rows as a JSON array.
+///
+/// The inverse of `lexo_import_order()`: returns the primary keys ordered by
+/// position, or `{"pk": ..., "position": ...}` objects when `with_positions` is
+/// true. Rows are read through an SPI cursor in batches, so the query result is
+/// never materialized in full.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `pk_column_name` - The primary key column
+/// * `key_column_name` - Optional: column to group by (e.g., 'playlist_id')
+/// * `key_value` - Optional: value to filter by (export only rows with this key)
+/// * `with_positions` - Whether to include each row's position
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_export_order('playlist_songs', 'position', 'id', 'playlist_id', 'abc-123');
+/// -- Returns e.g. [3, 1, 2]
+/// ```
+#[pg_extern]
+pub fn lexo_export_order(
+    table_name: &str,
+    lexo_column_name: &str,
+    pk_column_name: &str,
+    key_column_name: default!(Option<&str>, "NULL"),
+    key_value: default!(Option<&str>, "NULL"),
+    with_positions: default!(bool, false),
+) -> pgrx::JsonB {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[
+                Some(lexo_column_name),
+                Some(pk_column_name),
+                key_column_name,
+            ],
+        );
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+        let quoted_pk_column = quote_identifier(pk_column_name);
+
+        let quoted_table = quote_table_name(table_name);
+
+        let filter = match (key_column_name, key_value) {
+            (Some(key_col), Some(key_val)) => {
+                format!("{} = {}", quote_identifier(key_col), quote_literal(key_val))
+            }
+            _ => "true".to_string(),
+        };
+
+        let select_query = format!(
+            "SELECT to_jsonb({pk}), {col}::text FROM {table} WHERE {filter} ORDER BY {col}::{ext}.lexo",
+            pk = quoted_pk_column,
+            col = quoted_lexo_column,
+            table = quoted_table,
+            filter = filter,
+            ext = extension_schema()
+        );
+
+        let entries = Spi::connect(|client| {
+            let mut cursor = client.open_cursor(&select_query, &[]);
+            let mut entries = Vec::new();
+            loop {
+                let batch = cursor
+                    .fetch(EXPORT_BATCH_SIZE)
+                    .expect("Failed to fetch rows for export");
+                if batch.is_empty() {
+                    break;
+                }
+                for row in batch {
+                    let pk = row
+                        .get::<pgrx::JsonB>(1)
+                        .expect("Failed to get primary key")
+                        .map(|pk| pk.0)
+                        .unwrap_or(Value::Null);
+                    if with_positions {
+                        let position: Option<String> = row.get(2).expect("Failed to get position");
+                        entries.push(serde_json::json!({ "pk": pk, "position": position }));
+                    } else {
+                        entries.push(pk);
+                    }
+                }
+            }
+            entries
+        });
+
+        pgrx::JsonB(Value::Array(entries))
+    })
+}
+
+/// Checks that a table's rows, ordered by position, have exactly the expected primary keys.
+///
+/// Returns true when the sequence of primary keys matches `expected` element
+/// by element and neither side has extra entries. Use
+/// `lexo_order_divergence()` to find out where the sequences differ, or
+/// `lexo_assert_matches()` to raise an error instead.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `pk_column_name` - The primary key column; keys are compared as values of its type
+/// * `expected` - JSON array of primary keys (strings or numbers) in the expected order
+/// * `key_column_name` - Optional: column to group by (e.g., 'playlist_id')
+/// * `key_value` - Optional: value to filter by (check only rows with this key)
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_verify_order('tasks', 'position', 'id',
+///        (SELECT jsonb_agg(id ORDER BY sort_order) FROM tasks));
+/// ```
+#[pg_extern]
+pub fn lexo_verify_order(
+    table_name: &str,
+    lexo_column_name: &str,
+    pk_column_name: &str,
+    expected: pgrx::JsonB,
+    key_column_name: default!(Option<&str>, "NULL"),
+    key_value: default!(Option<&str>, "NULL"),
+) -> bool {
+    with_pinned_search_path(|| {
+        order_divergence(
+            table_name,
+            lexo_column_name,
+            pk_column_name,
+            expected,
+            key_column_name,
+            key_value,
+        )
+        .is_none()
+    })
+}
+
+/// Returns where a table's order first differs from the expected primary keys.
+///
+/// Takes the same arguments as `lexo_verify_order()`. Returns no rows when the
+/// order matches, or one row with the zero-based index of the first
+/// difference, the expected and actual primary keys there (NULL past the end
+/// of either sequence) and a description.
+///
+/// # Example
+/// ```sql
+/// SELECT * FROM lexo_order_divergence('tasks', 'position', 'id', '[3, 1, 2]');
+/// ```
+#[pg_extern]
+pub fn lexo_order_divergence(
+    table_name: &str,
+    lexo_column_name: &str,
+    pk_column_name: &str,
+    expected: pgrx::JsonB,
+    key_column_name: default!(Option<&str>, "NULL"),
+    key_value: default!(Option<&str>, "NULL"),
+) -> TableIterator<
+    'static,
+    (
+        name!(index, i64),
+        name!(expected_pk, Option<String>),
+        name!(actual_pk, Option<String>),
+        name!(detail, String),
+    ),
+> {
+    with_pinned_search_path(|| {
+        TableIterator::new(order_divergence(
+            table_name,
+            lexo_column_name,
+            pk_column_name,
+            expected,
+            key_column_name,
+            key_value,
+        ))
+    })
+}
+
+/// Streams a table's primary keys in position order and compares them with `expected`.
+fn order_divergence(
+    table_name: &str,
+    lexo_column_name: &str,
+    pk_column_name: &str,
+    expected: pgrx::JsonB,
+    key_column_name: Option<&str>,
+    key_value: Option<&str>,
+) -> Option<(i64, Option<String>, Option<String>, String)> {
+    require_columns(
+        table_name,
+        &[
+            Some(lexo_column_name),
+            Some(pk_column_name),
+            key_column_name,
+        ],
+    );
+    let quoted_table = quote_table_name(table_name);
+
+    let expected = json_pk_values(
+        expected.0,
+        "expected",
+        &column_type(table_name, pk_column_name),
+    );
+
+    let select_query = format!(
+        "SELECT {pk}::text FROM {table}{filter} ORDER BY {col}::{ext}.lexo",
+        pk = quote_identifier(pk_column_name),
+        col = quote_identifier(lexo_column_name),
+        table = quoted_table,
+        filter = list_filter(key_column_name, key_value),
+        ext = extension_schema()
+    );
+
+    let (rows, divergence) = Spi::connect(|client| {
+        let mut cursor = client.open_cursor(&select_query, &[]);
+        let mut index = 0;
+        loop {
+            let batch = cursor
+                .fetch(EXPORT_BATCH_SIZE)
+                .expect("Failed to fetch rows for comparison");
+            if batch.is_empty() {
+                return (index, None);
+            }
+            for row in batch {
+                let actual: Option<String> = row.get(1).expect("Failed to get primary key");
+                let wanted = expected.get(index).cloned();
+                let detail = match (&wanted, &actual) {
+                    (None, _) => format!(
+                        "the table has more rows than the {} expected",
+                        expected.len()
+                    ),
+                    (Some(wanted), Some(actual)) if wanted == actual => {
+                        index += 1;
+                        continue;
+                    }
+                    (Some(wanted), Some(actual)) => format!(
+                        "expected primary key {} at index {}, found {}",
+                        wanted, index, actual
+                    ),
+                    (Some(wanted), None) => format!(
+                        "expected primary key {} at index {}, found a NULL primary key",
+                        wanted, index
+                    ),
+                };
+                return (index, Some((index as i64, wanted, actual, detail)));
+            }
+        }
+    });
+
+    divergence.or_else(|| {
+        expected.get(rows).map(|wanted| {
+            (
+                rows as i64,
+                Some(wanted.clone()),
+                None,
+                format!(
+                    "the table has only {} rows but {} were expected, with primary key {} next",
+                    rows,
+                    expected.len(),
+                    wanted
+                ),
+            )
+        })
+    })
+}
+
+/// Raises `assert_failure` unless a table's rows, ordered by position, have
+/// exactly the expected primary keys.
+///
+/// Takes the same arguments as `lexo_verify_order()`, which is its boolean
+/// twin. The message describes the first difference and includes the
+/// offending row as JSON, so a failing test log says which row to look at.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_assert_matches('tasks', 'position', 'id', '[3, 1, 2]');
+/// ```
+#[pg_extern]
+pub fn lexo_assert_matches(
+    table_name: &str,
+    lexo_column_name: &str,
+    pk_column_name: &str,
+    expected: pgrx::JsonB,
+    key_column_name: default!(Option<&str>, "NULL"),
+    key_value: default!(Option<&str>, "NULL"),
+) {
+    with_pinned_search_path(|| {
+        let Some((_, _, actual, detail)) = order_divergence(
+            table_name,
+            lexo_column_name,
+            pk_column_name,
+            expected,
+            key_column_name,
+            key_value,
+        ) else {
+            return;
+        };
+        let row = actual.and_then(|pk| {
+            first_row_json(
+                table_name,
+                &format!(
+                    "{} = {}",
+                    quote_identifier(pk_column_name),
+                    quote_literal(&pk)
+                ),
+            )
+        });
+        assertion_failed(table_name, &detail, row);
+    })
+}
+
+/// Returns true when no two rows of a list share a position and, unless
+/// `allow_nulls` is set, no position is NULL.
+///
+/// With `key_column_name`, each value of the key column is a separate list.
+/// `lexo_assert_ordered()` raises instead of returning false.
+///
+/// # Example
+/// ```sql
+/// SELECT ok(lexo_verify_ordered('playlist_songs', 'position', 'playlist_id'));
+/// ```
+#[pg_extern]
+pub fn lexo_verify_ordered(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: default!(Option<&str>, "NULL"),
+    allow_nulls: default!(bool, false),
+) -> bool {
+    with_pinned_search_path(|| {
+        ordered_violation(table_name, lexo_column_name, key_column_name, allow_nulls).is_none()
+    })
+}
+
+/// Raises `assert_failure` when two rows of a list share a position or,
+/// unless `allow_nulls` is set, a position is NULL.
+///
+/// Takes the same arguments as `lexo_verify_ordered()`. The message includes
+/// the first offending row as JSON.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_assert_ordered('playlist_songs', 'position', 'playlist_id');
+/// ```
+#[pg_extern]
+pub fn lexo_assert_ordered(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: default!(Option<&str>, "NULL"),
+    allow_nulls: default!(bool, false),
+) {
+    with_pinned_search_path(|| {
+        if let Some((detail, row)) =
+            ordered_violation(table_name, lexo_column_name, key_column_name, allow_nulls)
+        {
+            assertion_failed(table_name, &detail, row);
+        }
+    })
+}
+
+/// Finds the first NULL or shared position, with the row it was found in.
+fn ordered_violation(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: Option<&str>,
+    allow_nulls: bool,
+) -> Option<(String, Option<String>)> {
+    require_columns(table_name, &[Some(lexo_column_name), key_column_name]);
+    let quoted_column = quote_identifier(lexo_column_name);
+
+    if !allow_nulls
+        && let Some(row) = first_row_json(table_name, &format!("{} IS NULL", quoted_column))
+    {
+        return Some(("found a NULL position".to_string(), Some(row)));
+    }
+
+    let key_expr = key_column_name.map_or("NULL::text".to_string(), |key_column| {
+        format!("{}::text", quote_identifier(key_column))
+    });
+    let query = format!(
+        "SELECT {key}, {col}::text FROM {table} WHERE {col} IS NOT NULL \
+         GROUP BY 1, 2 HAVING count(*) > 1 ORDER BY 1, 2 LIMIT 1",
+        key = key_expr,
+        col = quoted_column,
+        table = quote_table_name(table_name)
+    );
+    let (key, position): (Option<String>, String) = Spi::connect(|client| {
+        client
+            .select(&query, None, &[])
+            .expect("Failed to look for shared positions")
+            .next()
+            .map(|row| {
+                (
+                    row.get(1).ok().flatten(),
+                    row.get(2).ok().flatten().unwrap_or_default(),
+                )
+            })
+    })?;
+
+    let mut condition = format!("{}::text = {}", quoted_column, quote_literal(&position));
+    let mut detail = format!("position '{}' is shared by several rows", position);
+    match (key_column_name, key) {
+        (Some(key_column), Some(key)) => {
+            let quoted_key = quote_identifier(key_column);
+            condition = format!(
+                "{} AND {}::text = {}",
+                condition,
+                quoted_key,
+                quote_literal(&key)
+            );
+            detail = format!("{} where {} = {}", detail, quoted_key, quote_literal(&key));
+        }
+        (Some(key_column), None) => {
+            condition = format!("{} AND {} IS NULL", condition, quote_identifier(key_column));
+            detail = format!("{} where {} IS NULL", detail, quote_identifier(key_column));
+        }
+        (None, _) => {}
+    }
+    Some((detail, first_row_json(table_name, &condition)))
+}
+
+/// Returns true when no position is longer than `max_length` characters.
+///
+/// `lexo_assert_max_length()` raises instead of returning false.
+///
+/// # Example
+/// ```sql
+/// SELECT ok(lexo_verify_max_length('playlist_songs', 'position', 12));
+/// ```
+#[pg_extern]
+pub fn lexo_verify_max_length(table_name: &str, lexo_column_name: &str, max_length: i32) -> bool {
+    with_pinned_search_path(|| {
+        max_length_violation(table_name, lexo_column_name, max_length).is_none()
+    })
+}
+
+/// Raises `assert_failure` when a position is longer than `max_length`
+/// characters.
+///
+/// The message gives the number of positions over the limit and includes the
+/// row with the longest one as JSON.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_assert_max_length('playlist_songs', 'position', 12);
+/// ```
+#[pg_extern]
+pub fn lexo_assert_max_length(table_name: &str, lexo_column_name: &str, max_length: i32) {
+    with_pinned_search_path(|| {
+        if let Some((detail, row)) = max_length_violation(table_name, lexo_column_name, max_length)
+        {
+            assertion_failed(table_name, &detail, row);
+        }
+    })
+}
+
+/// Counts the positions longer than `max_length`, returning a description and
+/// the row with the longest one.
+fn max_length_violation(
+    table_name: &str,
+    lexo_column_name: &str,
+    max_length: i32,
+) -> Option<(String, Option<String>)> {
+    require_columns(table_name, &[Some(lexo_column_name)]);
+    let quoted_column = quote_identifier(lexo_column_name);
+    let query = format!(
+        "SELECT count(*), max(length({col}::text)) FROM {table} \
+         WHERE length({col}::text) > {max_length}",
+        col = quoted_column,
+        table = quote_table_name(table_name),
+        max_length = max_length
+    );
+    let (too_long, longest): (Option<i64>, Option<i32>) =
+        Spi::get_two(&query).expect("Failed to measure positions");
+    let too_long = too_long.filter(|&count| count > 0)?;
+    let longest = longest.unwrap_or_default();
+
+    let row = first_row_json(
+        table_name,
+        &format!("length({}::text) = {}", quoted_column, longest),
+    );
+    Some((
+        format!(
+            "{} positions are longer than {} characters, the longest has {}",
+            too_long, max_length, longest
+        ),
+        row,
+    ))
+}
+
+/// Returns the first row of `table_name` matching `condition` as JSON, in
+/// physical order.
+fn first_row_json(table_name: &str, condition: &str) -> Option<String> {
+    Spi::connect(|client| {
+        client
+            .select(
+                &format!(
+                    "SELECT pg_catalog.row_to_json(t.*)::text FROM {} AS t WHERE {} \
+                     ORDER BY t.ctid LIMIT 1",
+                    quote_table_name(table_name),
+                    condition
+                ),
+                None,
+                &[],
+            )
+            .expect("Failed to read the offending row")
+            .next()
+            .and_then(|row| row.get(1).ok().flatten())
+    })
+}
+
+/// Raises the `assert_failure` error of the `lexo_assert_*` functions.
+fn assertion_failed(table_name: &str, detail: &str, row: Option<String>) -> ! {
+    let message = match row {
+        Some(row) => format!("{}: {}; first offending row: {}", table_name, detail, row),
+        None => format!("{}: {}", table_name, detail),
+    };
+    raise_error!(PgSqlErrorCode::ERRCODE_ASSERT_FAILURE, message);
+}
+
+/// Returns the page of a list that follows a cursor position.
+///
+/// Selects up to `page_size` rows whose position is strictly greater than
+/// `cursor`, in position order, as `WHERE column > cursor ORDER BY column
+/// LIMIT page_size`, so an index on the position column (or on the key column
+/// and the position column) serves it directly. A NULL cursor returns the
+/// first page. Pass the `rank` of the last row as the next cursor. Rows that
+/// share the cursor's position are skipped, so keep positions unique.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `cursor` - The position of the last row of the previous page, or NULL
+/// * `page_size` - The maximum number of rows to return
+/// * `key_column_name` - Optional: column to filter by (e.g., 'board_id')
+/// * `key_value` - Optional: value to filter by
+/// * `pk_column_name` - Optional: column returned as `pk` (default: the
+///   table's single-column primary key)
+///
+/// # Example
+/// ```sql
+/// SELECT * FROM lexo_page_after('cards', 'position', NULL, 50, 'board_id', '7');
+/// SELECT * FROM lexo_page_after('cards', 'position', lexo_decode_cursor($1), 50, 'board_id', '7');
+/// ```
+#[pg_extern]
+pub fn lexo_page_after(
+    table_name: &str,
+    lexo_column_name: &str,
+    cursor: Option<Lexo>,
+    page_size: i32,
+    key_column_name: default!(Option<&str>, "NULL"),
+    key_value: default!(Option<&str>, "NULL"),
+    pk_column_name: default!(Option<&str>, "NULL"),
+) -> TableIterator<'static, (name!(pk, Option<String>), name!(rank, Lexo))> {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[Some(lexo_column_name), key_column_name, pk_column_name],
+        );
+        if page_size < 1 {
+            pgrx::error!("page_size must be at least 1, got {}", page_size);
+        }
+
+        let quoted_table = quote_table_name(table_name);
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+        let quoted_pk_column =
+            pk_column(table_name, &quoted_table, pk_column_name, "pk_column_name");
+
+        // Rows without a position are not part of the list and cannot be paged past
+        let mut conditions = vec![format!("{} IS NOT NULL", quoted_lexo_column)];
+        if let (Some(key_col), Some(key_val)) = (key_column_name, key_value) {
+            conditions.push(format!(
+                "{} = {}",
+                quote_identifier(key_col),
+                quote_literal(key_val)
+            ));
+        }
+        if let Some(cursor) = &cursor {
+            conditions.push(format!(
+                "{} > {}::{ext}.lexo",
+                quoted_lexo_column,
+                quote_literal(cursor.as_str()),
+                ext = extension_schema()
+            ));
+        }
+        let filter = format!(" WHERE {}", conditions.join(" AND "));
+
+        let query = format!(
+            "SELECT {pk}::text, {col} FROM {table}{filter} ORDER BY {col} LIMIT {limit}",
+            pk = quoted_pk_column,
+            col = quoted_lexo_column,
+            table = quoted_table,
+            filter = filter,
+            limit = page_size
+        );
+        TableIterator::new(select_pk_ranks(&query))
+    })
+}
+
+/// Returns the rows of a list whose position lies strictly between two positions.
+///
+/// Meant for loading the viewport of a virtualized list. NULL bounds are
+/// open-ended. The rows come in position order, or in reverse with
+/// `descending`, and at most `max_rows` of them: the query is one range
+/// predicate with `ORDER BY column LIMIT max_rows`, so an index on the position
+/// column (or on the key column and the position column) serves it as a single
+/// range scan. `max_rows` may not exceed `lexo.slice_max_rows`.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `pk_column_name` - Column returned as `pk`, or NULL for the table's
+///   single-column primary key
+/// * `low` - The lower bound (NULL for the start of the list)
+/// * `high` - The upper bound (NULL for the end of the list)
+/// * `max_rows` - The maximum number of rows to return
+/// * `key_column_name` - Optional: column to filter by (e.g., 'board_id')
+/// * `key_value` - Optional: value to filter by
+/// * `descending` - Return the rows from `high` down to `low` (default false)
+///
+/// # Example
+/// ```sql
+/// SELECT * FROM lexo_slice('cards', 'position', 'id', 'Hk', 'Hz', 200, 'board_id', '7');
+/// ```
+#[pg_extern]
+#[allow(clippy::too_many_arguments)]
+pub fn lexo_slice(
+    table_name: &str,
+    lexo_column_name: &str,
+    pk_column_name: Option<&str>,
+    low: Option<Lexo>,
+    high: Option<Lexo>,
+    max_rows: i32,
+    key_column_name: default!(Option<&str>, "NULL"),
+    key_value: default!(Option<&str>, "NULL"),
+    descending: default!(bool, false),
+) -> TableIterator<'static, (name!(pk, Option<String>), name!(rank, Lexo))> {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[Some(lexo_column_name), key_column_name, pk_column_name],
+        );
+        if max_rows < 1 {
+            pgrx::error!("max_rows must be at least 1, got {}", max_rows);
+        }
+        let cap = crate::gucs::SLICE_MAX_ROWS.get();
+        if max_rows > cap {
+            raise_error!(
+                PgSqlErrorCode::ERRCODE_PROGRAM_LIMIT_EXCEEDED,
+                format!(
+                    "max_rows {} exceeds lexo.slice_max_rows ({})",
+                    max_rows, cap
+                ),
+                hint = "Load the rows in smaller slices, or raise lexo.slice_max_rows.",
+            );
+        }
+        let low_str = bound_str(&low);
+        let high_str = bound_str(&high);
+        if !low_str.is_empty() && !high_str.is_empty() && low_str >= high_str {
+            pgrx::error!(
+                "low position '{}' must be less than high position '{}'",
+                low_str,
+                high_str
+            );
+        }
+
+        let quoted_table = quote_table_name(table_name);
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+        let quoted_pk_column =
+            pk_column(table_name, &quoted_table, pk_column_name, "pk_column_name");
+
+        let mut range = vec![format!("{} IS NOT NULL", quoted_lexo_column)];
+        if !low_str.is_empty() {
+            range.push(format!(
+                "{} > {}::{ext}.lexo",
+                quoted_lexo_column,
+                quote_literal(low_str),
+                ext = extension_schema()
+            ));
+        }
+        if !high_str.is_empty() {
+            range.push(format!(
+                "{} < {}::{ext}.lexo",
+                quoted_lexo_column,
+                quote_literal(high_str),
+                ext = extension_schema()
+            ));
+        }
+        let query = format!(
+            "SELECT {pk}::text, {col} FROM {table}{filter} ORDER BY {col}{direction} LIMIT {limit}",
+            pk = quoted_pk_column,
+            col = quoted_lexo_column,
+            table = quoted_table,
+            filter = filter_and(
+                &list_filter(key_column_name, key_value),
+                &range.join(" AND ")
+            ),
+            direction = if descending { " DESC" } else { "" },
+            limit = max_rows
+        );
+        TableIterator::new(select_pk_ranks(&query))
+    })
+}
+
+/// Returns the quoted `pk_column_name`, or the table's single-column primary key.
+/// `argument` names the parameter to suggest when there is none.
+fn pk_column(
+    table_name: &str,
+    quoted_table: &str,
+    pk_column_name: Option<&str>,
+    argument: &str,
+) -> String {
+    if let Some(pk_col) = pk_column_name {
+        return quote_identifier(pk_col);
+    }
+    let pk_query = format!(
+        "SELECT quote_ident(a.attname) FROM pg_catalog.pg_index i \
+         JOIN pg_catalog.pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = i.indkey[0] \
+         WHERE i.indrelid = {}::regclass AND i.indisprimary AND i.indnkeyatts = 1",
+        quote_literal(quoted_table)
+    );
+    Spi::get_one::<String>(&pk_query)
+        .expect("Failed to look up the primary key")
+        .unwrap_or_else(|| {
+            pgrx::error!(
+                "{} has no single-column primary key: pass {}",
+                table_name,
+                argument
+            )
+        })
+}
+
+/// Runs a query selecting `(pk text, position)` rows.
+fn select_pk_ranks(query: &str) -> Vec<(Option<String>, Lexo)> {
+    Spi::connect(|client| {
+        client
+            .select(query, None, &[])
+            .expect("Failed to select the rows")
+            .map(|row| {
+                (
+                    row.get::<String>(1).expect("Failed to get primary key"),
+                    row.get::<Lexo>(2)
+                        .expect("Failed to get position")
+                        .expect("position was NULL"),
+                )
+            })
+            .collect()
+    })
+}
+
+/// Encodes a position as an opaque, URL-safe pagination cursor.
+///
+/// The token is base64url of the position and a checksum. Hand it to clients
+/// instead of the raw position and turn it back with `lexo_decode_cursor()`.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_encode_cursor('Hzz');
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_encode_cursor(rank: Lexo) -> String {
+    if rank.is_empty() {
+        pgrx::error!("cannot encode an empty position as a cursor");
+    }
+    encode_cursor(rank.as_str())
+}
+
+/// Decodes a cursor made by `lexo_encode_cursor()`.
+///
+/// Tokens that are malformed, truncated or edited raise an
+/// `invalid_parameter_value` error instead of yielding a wrong position.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_decode_cursor(lexo_encode_cursor('Hzz'));  -- Returns 'Hzz'
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_decode_cursor(token: &str) -> Lexo {
+    match decode_cursor(token) {
+        Ok(position) => Lexo::new(position),
+        Err(reason) => {
+            raise_error!(
+                PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+                format!("invalid pagination cursor: {}", reason),
+            );
+        }
+    }
+}
+
+/// Merges the list of one key value into the list of another.
+///
+/// With `mode => 'append'`, the source rows are given fresh positions after
+/// the target list's last one, in their current order. With
+/// `mode => 'interleave'`, both lists are merged by their current positions,
+/// the target's rows first where two positions are equal: target rows keep
+/// their positions, and so do source rows that fit, while source rows that
+/// collide with the row before them, or have a NULL position, are given fresh
+/// positions in their gap. With `rewrite_key` (the default) the source rows'
+/// key column is set to `target_key` as well. Both lists are locked like in
+/// `lexo_claim_next()`, and the rows are written in a single `UPDATE`.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `key_column_name` - The column holding the list key (e.g., 'board_id')
+/// * `source_key` - The key value of the list that is merged in
+/// * `target_key` - The key value of the list it is merged into
+/// * `mode` - `'append'` (default) or `'interleave'`
+/// * `rewrite_key` - Also move the source rows to `target_key` (default true)
+///
+/// # Returns
+/// The number of source rows updated
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_merge_lists('cards', 'position', 'board_id', '8', '7');
+/// SELECT lexo_merge_lists('cards', 'position', 'board_id', '8', '7', mode => 'interleave');
+/// ```
+#[pg_extern]
+pub fn lexo_merge_lists(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: &str,
+    source_key: &str,
+    target_key: &str,
+    mode: default!(&str, "'append'"),
+    rewrite_key: default!(bool, true),
+) -> i64 {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(lexo_column_name), Some(key_column_name)]);
+        require_position_column(table_name, lexo_column_name, false);
+        if !matches!(mode, "append" | "interleave") {
+            pgrx::error!("Invalid mode '{}': must be 'append' or 'interleave'", mode);
+        }
+        if source_key == target_key {
+            pgrx::error!(
+                "source_key and target_key must differ, both are '{}'",
+                source_key
+            );
+        }
+
+        let quoted_table = quote_table_name(table_name);
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+        let quoted_key_column = quote_identifier(key_column_name);
+        let source = quote_literal(source_key);
+        let target = quote_literal(target_key);
+
+        require_table_privileges(table_name, lexo_column_name, &["SELECT", "UPDATE"]);
+        // Always lock in the same order, so two merges in opposite directions
+        // can't deadlock
+        let mut keys = [source_key, target_key];
+        keys.sort_unstable();
+        for key in keys {
+            lock_list(&table_list_lock_key(
+                table_name,
+                lexo_column_name,
+                Some(key_column_name),
+                Some(key),
+            ));
+        }
+
+        let query = format!(
+            "SELECT tableoid::text, ctid::text, {col}::text, {key} = {source} FROM {table} \
+             WHERE {key} = {source} OR ({key} = {target} AND {col} IS NOT NULL) \
+             ORDER BY {col}::{ext}.lexo NULLS LAST, {key} = {source}, ctid",
+            col = quoted_lexo_column,
+            key = quoted_key_column,
+            table = quoted_table,
+            source = source,
+            target = target,
+            ext = extension_schema()
+        );
+        let rows: Vec<(String, String, Option<String>, bool)> = Spi::connect(|client| {
+            client
+                .select(&query, None, &[])
+                .expect("Failed to read the lists to merge")
+                .map(|row| {
+                    (
+                        row.get(1).ok().flatten().expect("tableoid was NULL"),
+                        row.get(2).ok().flatten().expect("ctid was NULL"),
+                        row.get(3).expect("Failed to get position"),
+                        row.get(4).ok().flatten().unwrap_or(false),
+                    )
+                })
+                .collect()
+        });
+        let values: Vec<&str> = rows
+            .iter()
+            .map(|row| row.2.as_deref().unwrap_or(""))
+            .collect();
+        let from_source = |i: usize| rows[i].3;
+
+        let mut positions: Vec<Option<String>> = vec![None; rows.len()];
+        if mode == "append" {
+            let last = (0..rows.len())
+                .rfind(|&i| !from_source(i))
+                .map_or("", |i| values[i]);
+            let moved: Vec<usize> = (0..rows.len()).filter(|&i| from_source(i)).collect();
+            for (i, position) in moved.iter().zip(generate_n_between(last, "", moved.len())) {
+                positions[*i] = Some(position);
+            }
+        } else {
+            let collides = |i: usize| {
+                from_source(i) && (rows[i].2.is_none() || (i > 0 && values[i] == values[i - 1]))
+            };
+            for (i, position) in reposition_runs(&values, "", "", collides) {
+                positions[i] = Some(position);
+            }
+        }
+        let merged: Vec<&str> = (0..rows.len())
+            .map(|i| positions[i].as_deref().unwrap_or(values[i]))
+            .collect();
+        // Appended rows always fit after the last position; interleaved ones can
+        // land between neighbours that leave no room, such as 'H' and 'H0'
+        for i in (0..rows.len()).filter(|&i| mode == "interleave" && positions[i].is_some()) {
+            let before = if i > 0 { merged[i - 1] } else { "" };
+            let after = merged.get(i + 1).copied().unwrap_or("");
+            if !is_between(merged[i], before, after) {
+                raise_error!(
+                    PgSqlErrorCode::ERRCODE_PROGRAM_LIMIT_EXCEEDED,
+                    format!(
+                        "no room to merge the source rows between '{}' and '{}'",
+                        before, after
+                    ),
+                    hint = "Rebalance the target list first.",
+                );
+            }
+        }
+
+        let updates: Vec<String> = (0..rows.len())
+            .filter(|&i| from_source(i))
+            .filter(|&i| rewrite_key || positions[i].is_some())
+            .map(|i| {
+                format!(
+                    "({}::oid, {}::tid, {})",
+                    quote_literal(&rows[i].0),
+                    quote_literal(&rows[i].1),
+                    quote_literal(merged[i])
+                )
+            })
+            .collect();
+        if updates.is_empty() {
+            return 0;
+        }
+
+        let key_update = if rewrite_key {
+            format!(", {} = {}", quoted_key_column, target)
+        } else {
+            String::new()
+        };
+        let update_query = format!(
+            "UPDATE {table} SET {col} = merged.position::{ext}.lexo{key_update} \
+             FROM (VALUES {values}) AS merged(rel, row_ctid, position) \
+             WHERE {table}.tableoid = merged.rel AND {table}.ctid = merged.row_ctid \
+             RETURNING 1",
+            table = quoted_table,
+            col = quoted_lexo_column,
+            key_update = key_update,
+            values = updates.join(", "),
+            ext = extension_schema()
+        );
+        Spi::connect_mut(|client| {
+            client
+                .update(&update_query, None, &[])
+                .expect("Failed to merge the lists")
+                .len() as i64
+        })
+    })
+}
+
+/// Gives a copied list the order of the list it was copied from.
+///
+/// Assigns fresh balanced positions, as `lexo_rank_all()` does, to the rows
+/// whose `key_column_name` is `target_key`, following the positions of their
+/// originals in the `source_key` list. A target row's original is the source
+/// row whose `source_column_name` (by default the single-column primary key)
+/// equals the target row's `map_by`. Target rows without an original, or whose
+/// original has no position, go to the end in their current order. Only the
+/// target list is written, in a single `UPDATE`, so both lists can diverge
+/// afterwards.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `key_column_name` - The column holding the list key (e.g., 'playlist_id')
+/// * `source_key` - The key value of the list to copy the order from
+/// * `target_key` - The key value of the list to rank
+/// * `map_by` - The target rows' column pointing at their originals
+/// * `source_column_name` - Optional: the source rows' column `map_by` refers
+///   to (default: the table's single-column primary key)
+///
+/// # Returns
+/// The number of target rows ranked
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_clone_order('playlist_songs', 'position', 'playlist_id', 'abc-123', 'def-456', 'copied_from');
+/// ```
+#[pg_extern]
+pub fn lexo_clone_order(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: &str,
+    source_key: &str,
+    target_key: &str,
+    map_by: &str,
+    source_column_name: default!(Option<&str>, "NULL"),
+) -> i64 {
+    with_pinned_search_path(|| {
+        require_columns(
+            table_name,
+            &[
+                Some(lexo_column_name),
+                Some(key_column_name),
+                Some(map_by),
+                source_column_name,
+            ],
+        );
+        require_position_column(table_name, lexo_column_name, false);
+        if source_key == target_key {
+            pgrx::error!(
+                "source_key and target_key must differ, both are '{}'",
+                source_key
+            );
+        }
+
+        let quoted_table = quote_table_name(table_name);
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+        let quoted_key_column = quote_identifier(key_column_name);
+
+        require_table_privileges(table_name, lexo_column_name, &["SELECT", "UPDATE"]);
+        let source_column = pk_column(
+            table_name,
+            &quoted_table,
+            source_column_name,
+            "source_column_name",
+        );
+        lock_list(&table_list_lock_key(
+            table_name,
+            lexo_column_name,
+            Some(key_column_name),
+            Some(target_key),
+        ));
+
+        let update_query = format!(
+            "WITH ranked AS (\
+                 SELECT t.tableoid AS rel, t.ctid AS row_ctid, \
+                        row_number() OVER (ORDER BY (\
+                            SELECT min(s.{col}::{ext}.lexo) FROM {table} AS s \
+                            WHERE s.{key} = {source} AND s.{source_column} = t.{map_by}\
+                        ) NULLS LAST, t.{col}::{ext}.lexo NULLS LAST, t.ctid) AS idx, \
+                        count(*) OVER () AS total \
+                 FROM {table} AS t WHERE t.{key} = {target}\
+             ) \
+             UPDATE {table} SET {col} = {ext}.lexo_from_int(ranked.idx - 1, ranked.total) \
+             FROM ranked WHERE {table}.tableoid = ranked.rel AND {table}.ctid = ranked.row_ctid \
+             RETURNING 1",
+            col = quoted_lexo_column,
+            table = quoted_table,
+            key = quoted_key_column,
+            source = quote_literal(source_key),
+            target = quote_literal(target_key),
+            source_column = source_column,
+            map_by = quote_identifier(map_by),
+            ext = extension_schema()
+        );
+        Spi::connect_mut(|client| {
+            client
+                .update(&update_query, None, &[])
+                .expect("Failed to clone the order")
+                .len() as i64
+        })
+    })
+}
+
+/// Reverses the order of a list in place.
+///
+/// Assigns balanced positions, as `lexo_rank_all()` does, in the exact
+/// opposite of the current position order, in a single `UPDATE ... FROM`. Rows
+/// that share a position stay next to each other. Rows with a NULL position
+/// are left alone and not counted, so they still sort last. With
+/// `key_column_name` but no `key_value`, every non-NULL group is reversed on
+/// its own.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `key_column_name` - Optional: column to filter or group by (e.g., 'playlist_id')
+/// * `key_value` - Optional: value to filter by
+///
+/// # Returns
+/// The number of rows updated
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_reverse('playlist_songs', 'position', 'playlist_id', 'abc-123');
+/// ```
+#[pg_extern]
+pub fn lexo_reverse(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: default!(Option<&str>, "NULL"),
+    key_value: default!(Option<&str>, "NULL"),
+) -> i64 {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(lexo_column_name), key_column_name]);
+        require_position_column(table_name, lexo_column_name, false);
+
+        let quoted_table = quote_table_name(table_name);
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+        let mut filter = filter_and(
+            &list_filter(key_column_name, key_value),
+            &format!("{} IS NOT NULL", quoted_lexo_column),
+        );
+        let mut partition = String::new();
+        if let (Some(key_col), None) = (key_column_name, key_value) {
+            let quoted_key_column = quote_identifier(key_col);
+            filter = filter_and(&filter, &format!("{} IS NOT NULL", quoted_key_column));
+            partition = format!("PARTITION BY {}", quoted_key_column);
+        }
+
+        require_table_privileges(table_name, lexo_column_name, &["SELECT", "UPDATE"]);
+        lock_list(&table_list_lock_key(
+            table_name,
+            lexo_column_name,
+            key_column_name,
+            key_value,
+        ));
+
+        let update_query = format!(
+            "WITH ranked AS (\
+                 SELECT tableoid AS rel, ctid AS row_ctid, \
+                        row_number() OVER ({partition} ORDER BY {col}::{ext}.lexo DESC, ctid) AS idx, \
+                        count(*) OVER ({partition}) AS total \
+                 FROM {table}{filter}\
+             ) \
+             UPDATE {table} SET {col} = {ext}.lexo_from_int(ranked.idx - 1, ranked.total) \
+             FROM ranked WHERE {table}.tableoid = ranked.rel AND {table}.ctid = ranked.row_ctid \
+             RETURNING 1",
+            partition = partition,
+            col = quoted_lexo_column,
+            table = quoted_table,
+            filter = filter,
+            ext = extension_schema()
+        );
+        Spi::connect_mut(|client| {
+            client
+                .update(&update_query, None, &[])
+                .expect("Failed to reverse the list")
+                .len() as i64
+        })
+    })
+}
+
+/// Assigns balanced positions to a table's rows following an arbitrary order.
+///
+/// Evaluates `ORDER BY (order_by)` over the table, or within each
+/// `key_column_name` group when given, and writes `lexo_from_int()` positions
+/// matching that order in a single `UPDATE`. Rows that tie keep their current
+/// relative order. Useful to initialize a freshly added column from e.g.
+/// `created_at`, or to re-sort a list on demand.
+///
+/// `order_by` must be a single expression: it is parenthesized, and semicolons,
+/// comments, unbalanced parentheses, dollar quotes and backslashes are rejected.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column to write positions to
+/// * `order_by` - SQL expression giving the desired order
+/// * `key_column_name` - Optional: column to group by (e.g., 'playlist_id')
+///
+/// # Returns
+/// One row per group with the number of rows updated in it
+///
+/// # Example
+/// ```sql
+/// SELECT * FROM lexo_rank_all('playlist_songs', 'position', 'lower(title)', 'playlist_id');
+/// ```
+#[pg_extern]
+pub fn lexo_rank_all(
+    table_name: &str,
+    lexo_column_name: &str,
+    order_by: &str,
+    key_column_name: default!(Option<&str>, "NULL"),
+) -> TableIterator<'static, (name!(group_key, Option<String>), name!(rows_updated, i64))> {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(lexo_column_name), key_column_name]);
+        if let Err(message) = check_order_by_expression(order_by) {
+            pgrx::error!("invalid order_by expression: {}", message);
+        }
+
+        let quoted_table = quote_table_name(table_name);
+
+        let results = rank_rows(
+            &quoted_table,
+            &quote_identifier(lexo_column_name),
+            &format!("({})", order_by),
+            key_column_name,
+        );
+
+        TableIterator::new(results)
+    })
+}
+
+/// Writes balanced positions following `order_clause`, a trusted SQL sort list,
+/// and returns the number of rows updated per group.
+fn rank_rows(
+    quoted_table: &str,
+    quoted_lexo_column: &str,
+    order_clause: &str,
+    key_column_name: Option<&str>,
+) -> Vec<(Option<String>, i64)> {
+    let (partition, group_expr) = match key_column_name {
+        Some(key_col) => {
+            let quoted_key_column = quote_identifier(key_col);
+            (
+                format!("PARTITION BY {}", quoted_key_column),
+                format!("{}.{}::text", quoted_table, quoted_key_column),
+            )
+        }
+        None => (String::new(), "NULL::text".to_string()),
+    };
+
+    let update_query = format!(
+        "WITH ranked AS (\
+             SELECT tableoid AS rel, ctid AS row_ctid, \
+                    row_number() OVER ({partition} ORDER BY {order_clause}, {col}::{ext}.lexo) AS idx, \
+                    count(*) OVER ({partition}) AS total \
+             FROM {table}\
+         ), updated AS (\
+             UPDATE {table} SET {col} = {ext}.lexo_from_int(ranked.idx - 1, ranked.total) \
+         FROM ranked WHERE {table}.tableoid = ranked.rel AND {table}.ctid = ranked.row_ctid \
+             RETURNING {group_expr} AS group_key\
+         ) \
+         SELECT group_key, count(*) FROM updated GROUP BY group_key ORDER BY group_key",
+        order_clause = order_clause,
+        col = quoted_lexo_column,
+        partition = partition,
+        table = quoted_table,
+        group_expr = group_expr,
+        ext = extension_schema()
+    );
+
+    Spi::connect_mut(|client| {
+        client
+            .update(&update_query, None, &[])
+            .expect("Failed to rank rows")
+            .map(|row| {
+                let group_key: Option<String> = row.get(1).expect("Failed to get group key");
+                let rows_updated: i64 = row.get(2).expect("Failed to get row count").unwrap_or(0);
+                (group_key, rows_updated)
+            })
+            .collect()
+    })
+}
+
+/// Returns a position that keeps a list sorted by `sort_expr` for a new row.
+///
+/// Finds the last row whose `(sort_expr)` is at most `new_value` and the first
+/// row whose `(sort_expr)` is above it, each with an `ORDER BY (sort_expr),
+/// position LIMIT 1` query that an index on the expression can serve, and
+/// returns a position between theirs. A new row therefore goes after the rows
+/// with an equal value, at the start of the list when every value is larger and
+/// at the end when none is. Rows where `sort_expr` or the position is NULL are
+/// ignored. `new_value` is compared as an untyped literal, so it is read as the
+/// expression's type.
+///
+/// `sort_expr` is checked like `lexo_rank_all()`'s `order_by`. If the two
+/// neighbours are not in position order, the list is not sorted by `sort_expr`
+/// and the call fails; re-sort it with `lexo_rank_all()`.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `lexo_column_name` - The name of the column containing position values
+/// * `sort_expr` - SQL expression the list is sorted by
+/// * `new_value` - The new row's value of `sort_expr`
+/// * `key_column_name` - Optional: column to filter by (e.g., 'playlist_id')
+/// * `key_value` - Optional: value to filter by
+///
+/// # Example
+/// ```sql
+/// INSERT INTO playlist_songs (playlist_id, title, position)
+/// VALUES ('abc-123', 'Hey Jude',
+///         lexo_insert_sorted('playlist_songs', 'position', 'lower(title)', lower('Hey Jude'), 'playlist_id', 'abc-123'));
+/// ```
+#[pg_extern]
+pub fn lexo_insert_sorted(
+    table_name: &str,
+    lexo_column_name: &str,
+    sort_expr: &str,
+    new_value: &str,
+    key_column_name: default!(Option<&str>, "NULL"),
+    key_value: default!(Option<&str>, "NULL"),
+) -> Lexo {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(lexo_column_name), key_column_name]);
+        if let Err(message) = check_order_by_expression(sort_expr) {
+            pgrx::error!("invalid sort_expr expression: {}", message);
+        }
+
+        let quoted_lexo_column = quote_identifier(lexo_column_name);
+        let quoted_table = quote_table_name(table_name);
+        let filter = list_filter(key_column_name, key_value);
+        let neighbour = |comparison: &str, direction: &str| -> Option<Lexo> {
+            let query = format!(
+                "SELECT {col}::{ext}.lexo FROM {table}{filter} \
+                 ORDER BY ({expr}) {direction}, {col}::{ext}.lexo {direction} LIMIT 1",
+                col = quoted_lexo_column,
+                table = quoted_table,
+                filter = filter_and(
+                    &filter,
+                    &format!(
+                        "{} IS NOT NULL AND ({}) {} {}",
+                        quoted_lexo_column,
+                        sort_expr,
+                        comparison,
+                        quote_literal(new_value)
+                    )
+                ),
+                expr = sort_expr,
+                direction = direction,
+                ext = extension_schema()
+            );
+            Spi::get_one(&query).expect("Failed to query table for the sorted neighbours")
+        };
+
+        let before = neighbour("<=", "DESC");
+        let after = neighbour(">", "ASC");
+        if let (Some(before), Some(after)) = (&before, &after)
+            && before >= after
+        {
+            raise_error!(
+                PgSqlErrorCode::ERRCODE_DATA_EXCEPTION,
+                format!("{} is not sorted by {}", table_name, sort_expr),
+                detail = format!(
+                    "The row before the new value has position '{}' and the row after it '{}'.",
+                    before.as_str(),
+                    after.as_str()
+                ),
+                hint = "Re-sort the list with lexo_rank_all() first.",
+            );
+        }
+        counted_between(before, after)
+    })
+}
+
+/// Replaces a numeric ordering column with a `lexo` column.
+///
+/// Adds `new_column_name` as a `lexo` column, assigns balanced positions
+/// following the numeric column (NULLs last, ties broken by primary key), and
+/// creates an index on the new column, prefixed by `key_column_name` when given.
+/// The old column is dropped only when `drop_old` is true.
+///
+/// # Arguments
+/// * `table_name` - The name of the table (can be schema-qualified)
+/// * `numeric_column_name` - The existing integer/float ordering column
+/// * `new_column_name` - The name of the `lexo` column to add (default `'position'`)
+/// * `key_column_name` - Optional: column to group by (e.g., 'playlist_id')
+/// * `drop_old` - Whether to drop the numeric column afterwards
+///
+/// # Returns
+/// One row per group with the number of rows migrated in it
+///
+/// # Example
+/// ```sql
+/// SELECT * FROM lexo_migrate_from_numeric('tasks', 'sort_order', key_column_name => 'project_id');
+/// ```
+#[pg_extern]
+pub fn lexo_migrate_from_numeric(
+    table_name: &str,
+    numeric_column_name: &str,
+    new_column_name: default!(&str, "'position'"),
+    key_column_name: default!(Option<&str>, "NULL"),
+    drop_old: default!(bool, false),
+) -> TableIterator<'static, (name!(group_key, Option<String>), name!(rows_updated, i64))> {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(numeric_column_name), key_column_name]);
+        require_table_owner(table_name);
+        require_table_privileges(table_name, numeric_column_name, &["SELECT", "UPDATE"]);
+        let quoted_table = quote_table_name(table_name);
+        let quoted_numeric_column = quote_identifier(numeric_column_name);
+        let quoted_new_column = quote_identifier(new_column_name);
+
+        // Break ties by primary key, or by physical order if there is none
+        let pk_query = format!(
+            "SELECT pg_catalog.string_agg(pg_catalog.quote_ident(a.attname), ', ' \
+             ORDER BY pg_catalog.array_position(i.indkey, a.attnum)) \
+             FROM pg_catalog.pg_index i \
+             JOIN pg_catalog.pg_attribute a ON a.attrelid OPERATOR(pg_catalog.=) i.indrelid \
+             AND a.attnum OPERATOR(pg_catalog.=) ANY (i.indkey) \
+             WHERE i.indrelid OPERATOR(pg_catalog.=) {}::pg_catalog.regclass AND i.indisprimary",
+            quote_literal(&quoted_table)
+        );
+        let pk_columns: Option<String> =
+            Spi::get_one(&pk_query).expect("Failed to look up the primary key");
+        let tie_breaker = pk_columns.unwrap_or_else(|| "ctid".to_string());
+
+        lexo_add_column(table_name, new_column_name, None);
+
+        let results = rank_rows(
+            &quoted_table,
+            &quoted_new_column,
+            &format!("{} NULLS LAST, {}", quoted_numeric_column, tie_breaker),
+            key_column_name,
+        );
+
+        let index_columns = match key_column_name {
+            Some(key_col) => format!("{}, {}", quote_identifier(key_col), quoted_new_column),
+            None => quoted_new_column.clone(),
+        };
+        Spi::run(&format!(
+            "CREATE INDEX ON {} ({})",
+            quoted_table, index_columns
+        ))
+        .expect("Failed to create index on the new column");
+
+        if drop_old {
+            Spi::run(&format!(
+                "ALTER TABLE {} DROP COLUMN {}",
+                quoted_table, quoted_numeric_column
+            ))
+            .expect("Failed to drop the numeric column");
+        }
+
+        TableIterator::new(results)
+    })
+}
+
+/// Checks that `expr` is a single SQL expression that can't escape the
+/// parentheses it is wrapped in.
+pub(crate) fn check_order_by_expression(expr: &str) -> Result<(), String> {
+    if expr.trim().is_empty() {
+        return Err("expression is empty".to_string());
+    }
+
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    let mut chars = expr.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            return Err("backslashes are not allowed".to_string());
+        }
+        if let Some(q) = quote {
+            if c == q {
+                // A doubled quote is an escaped quote, not the end of the literal
+                if chars.peek() == Some(&q) {
+                    chars.next();
+                } else {
+                    quote = None;
+                }
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' => quote = Some(c),
+            '(' => depth += 1,
+            ')' => {
+                if depth == 0 {
+                    return Err("unbalanced parentheses".to_string());
+                }
+                depth -= 1;
+            }
+            ';' => return Err("semicolons are not allowed".to_string()),
+            '$' => return Err("dollar quoting is not allowed".to_string()),
+            '-' if chars.peek() == Some(&'-') => {
+                return Err("comments are not allowed".to_string());
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                return Err("comments are not allowed".to_string());
+            }
+            _ => {}
+        }
+    }
+
+    if quote.is_some() {
+        return Err("unterminated quoted string or identifier".to_string());
+    }
+    if depth != 0 {
+        return Err("unbalanced parentheses".to_string());
+    }
+    Ok(())
+}
+
+/// Parses the type modifier of a `lexo(n)` column declaration.
+///
+/// # Arguments
+/// * `mods` - The modifiers written in the declaration; exactly one positive length
+///
+/// # Returns
+/// The maximum number of characters allowed in the column
+///
+/// # Example
+/// ```sql
+/// CREATE TABLE items (position lexo(16) NOT NULL);
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_typmod_in(mods: Array<'_, &CStr>) -> i32 {
+    let mods: Vec<&str> = mods
+        .iter()
+        .map(|m| m.and_then(|m| m.to_str().ok()).unwrap_or(""))
+        .collect();
+
+    match parse_length_typmod(&mods) {
+        Ok(length) => length,
+        Err(message) => pgrx::error!("{}", message),
+    }
+}
+
+/// Renders the type modifier of a `lexo(n)` column as `(n)`.
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_typmod_out(typmod: i32) -> CString {
+    let rendered = if typmod >= 0 {
+        format!("({})", typmod)
+    } else {
+        String::new()
+    };
+    CString::new(rendered).expect("typmod output contains no NUL bytes")
+}
+
+/// Applies a `lexo(n)` length limit to a value.
+///
+/// This is the length coercion cast PostgreSQL runs when a value is stored in,
+/// or explicitly cast to, a length-limited column. Unlike `varchar(n)`, an
+/// explicit cast never truncates: a shortened rank would sort in a different
+/// place, so over-long values are always rejected.
+///
+/// # Arguments
+/// * `rank` - The value being stored
+/// * `typmod` - The declared length, or -1 for an unconstrained column
+/// * `_is_explicit` - Whether the cast was explicit (ignored, see above)
+///
+/// # Example
+/// ```sql
+/// SELECT 'HzzzzV'::lexo(4);  -- ERROR: value too long for type lexo(4)
+/// ```
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_enforce_typmod(rank: Lexo, typmod: i32, _is_explicit: bool) -> Lexo {
+    check_typmod(&rank, typmod);
+    rank
+}
+
+/// Raises `string_data_right_truncation` if `rank` is longer than `typmod` allows.
+fn check_typmod(rank: &Lexo, typmod: i32) {
+    if !fits_typmod(rank.as_str(), typmod) {
+        raise_error!(
+            PgSqlErrorCode::ERRCODE_STRING_DATA_RIGHT_TRUNCATION,
+            format!("value too long for type lexo({})", typmod),
+            hint = "Run lexo_rebalance() on the list to shorten its positions.",
+        );
+    }
+}
+
+/// Reads a `lexo` value from its text form, applying a `lexo(n)` length limit.
+///
+/// This replaces the body of the type's input function `lexo_in(cstring)`,
+/// which takes no typmod in SQL. PostgreSQL still passes the target's typmod
+/// as a third argument whenever it knows it, as `COPY ... FROM` and the input
+/// of composite values do; other callers pass none and the value is checked
+/// by the length coercion cast instead.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    sql = r#"
+CREATE OR REPLACE FUNCTION lexo_in(cstring) RETURNS lexo
+IMMUTABLE PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'lexo_in_typmod_wrapper';
+"#
+)]
+fn lexo_in_typmod(input: Option<&CStr>, fcinfo: pg_sys::FunctionCallInfo) -> Option<Lexo> {
+    let rank = <Lexo as InOutFuncs>::input(input?);
+    let fcinfo = unsafe { &*fcinfo };
+    if fcinfo.nargs >= 3 {
+        let typmod = unsafe { fcinfo.args.as_slice(3) }[2].value.value() as i32;
+        check_typmod(&rank, typmod);
+    }
+    Some(rank)
+}
+
+/// Reads a `lexo` value from the binary protocol, applying a `lexo(n)` length limit.
+///
+/// The binary form of a position is its characters, as for `text`. Unlike the
+/// text input, surrounding whitespace is not trimmed.
+#[pg_extern(immutable, strict, parallel_safe)]
+pub fn lexo_recv(buf: pgrx::Internal, _typioparam: pg_sys::Oid, typmod: i32) -> Lexo {
+    let buf = buf
+        .unwrap()
+        .expect("lexo_recv called without a message buffer")
+        .cast_mut_ptr::<pg_sys::StringInfoData>();
+
+    let text = unsafe {
+        let mut nbytes = 0;
+        let remaining = (*buf).len - (*buf).cursor;
+        CStr::from_ptr(pg_sys::pq_getmsgtext(buf, remaining, &mut nbytes))
+    };
+    let rank = Lexo::new(
+        text.to_str()
+            .expect("Invalid UTF-8 in Lexo input")
+            .to_string(),
+    );
+    check_typmod(&rank, typmod);
+    rank
+}
+
+/// Writes a `lexo` value in the binary protocol: the characters of the position.
+#[pg_extern(immutable, parallel_safe)]
+pub fn lexo_send(rank: Lexo) -> Vec<u8> {
+    rank.into_inner().into_bytes()
+}
+
+// Hook the typmod and binary I/O functions into the type and register the
+// length coercion cast.
+extension_sql!(
+    r#"
+ALTER TYPE lexo SET (
+    TYPMOD_IN = lexo_typmod_in,
+    TYPMOD_OUT = lexo_typmod_out,
+    RECEIVE = lexo_recv,
+    SEND = lexo_send
+);
+CREATE CAST (lexo AS lexo)
+    WITH FUNCTION lexo_enforce_typmod(lexo, integer, boolean) AS IMPLICIT;
+"#,
+    name = "lexo_typmod",
+    requires = [
+        lexo_typmod_in,
+        lexo_typmod_out,
+        lexo_enforce_typmod,
+        lexo_in_typmod,
+        lexo_recv,
+        lexo_send
+    ]
+);
+
+/// Returns the usage counters of every database tracked since the server started.
+///
+/// Backs the `lexo_stat` view. Counters are only collected when pg_lexo is
+/// listed in `shared_preload_libraries`; otherwise no rows are returned. Reading
+/// takes no lock, only a pass over a small fixed-size table, so it is cheap to
+/// scrape. Only the volatile functions that place positions in a list,
+/// `lexo_next()` and its variants, and rebalances are counted; the `IMMUTABLE`
+/// and `STABLE` generators such as `lexo_after()` are not.
+///
+/// # Example
+/// ```sql
+/// SELECT * FROM lexo_stat;
+/// ```
+#[allow(clippy::type_complexity)]
+#[pg_extern(volatile, parallel_safe)]
+pub fn lexo_stat_counters() -> TableIterator<
+    'static,
+    (
+        name!(dbid, pg_sys::Oid),
+        name!(after_calls, i64),
+        name!(before_calls, i64),
+        name!(between_calls, i64),
+        name!(next_calls, i64),
+        name!(rebalance_calls, i64),
+        name!(rows_rebalanced, i64),
+        name!(long_ranks, i64),
+    ),
+> {
+    TableIterator::new(stats::snapshot().into_iter().map(|s| {
+        (
+            pg_sys::Oid::from(s.database),
+            s.after_calls,
+            s.before_calls,
+            s.between_calls,
+            s.next_calls,
+            s.rebalance_calls,
+            s.rows_rebalanced,
+            s.long_ranks,
+        )
+    }))
+}
+
+/// Resets the usage counters of the current database.
+///
+/// # Example
+/// ```sql
+/// SELECT lexo_stat_reset();
+/// ```
+#[pg_extern(volatile, parallel_unsafe)]
+pub fn lexo_stat_reset() {
+    stats::reset_current();
+}
+
+/// Reports which pg_lexo build and algorithm the server is running.
+///
+/// `extension_version` is the version installed in the current database, which
+/// can lag behind the loaded library's `crate_version` until
+/// `ALTER EXTENSION pg_lexo UPDATE` is run.
+///
+/// # Example
+/// ```sql
+/// SELECT * FROM lexo_version();
+/// ```
+#[pg_extern(stable, parallel_safe)]
+pub fn lexo_version() -> TableIterator<
+    'static,
+    (
+        name!(extension_version, Option<String>),
+        name!(crate_version, String),
+        name!(algorithm_version, i32),
+        name!(alphabet, String),
+        name!(mid_char, String),
+    ),
+> {
+    let extension_version: Option<String> = Spi::get_one(
+        "SELECT extversion::text FROM pg_catalog.pg_extension WHERE extname = 'pg_lexo'",
+    )
+    .unwrap_or(None);
+
+    TableIterator::once((
+        extension_version,
+        env!("CARGO_PKG_VERSION").to_string(),
+        ALGORITHM_VERSION,
+        String::from_utf8_lossy(BASE62_CHARS).into_owned(),
+        MID_CHAR.to_string(),
+    ))
+}
+
+/// Casts the extension installs, as (source, target) type names.
+const EXPECTED_CASTS: &[(&str, &str)] = &[
+    ("lexo", "lexo"),
+    ("lexo", "json"),
+    ("lexo", "jsonb"),
+    ("json", "lexo"),
+    ("jsonb", "lexo"),
+    ("smalllexo", "lexo"),
+    ("lexo", "smalllexo"),
+];
+
+/// Verifies that the extension's objects are intact and its settings consistent.
+///
+/// Returns one row per check. With `check_indexes`, also verifies that every
+/// column registered in `lexo_autorebalance_targets` is covered by an index.
+///
+/// # Arguments
+/// * `check_indexes` - Whether to check registered tables for supporting indexes
+///
+/// # Example
+/// ```sql
+/// SELECT * FROM lexo_health() WHERE NOT ok;
+/// ```
+#[pg_extern(stable)]
+pub fn lexo_health(
+    check_indexes: default!(bool, false),
+) -> TableIterator<'static, (name!(check, String), name!(ok, bool), name!(detail, String))> {
+    with_pinned_search_path(|| {
+        let mut results = vec![
+            catalog_check(
+                "type",
+                "SELECT EXISTS (SELECT 1 FROM pg_catalog.pg_type t \
+                 JOIN pg_catalog.pg_proc i ON i.oid = t.typinput \
+                 JOIN pg_catalog.pg_proc o ON o.oid = t.typoutput \
+                 WHERE t.oid = to_regtype('lexo') \
+                 AND i.proname = 'lexo_in' AND o.proname = 'lexo_out')",
+                "type lexo uses lexo_in/lexo_out",
+                "type lexo is missing or has unexpected input/output functions",
+            ),
+            catalog_check(
+                "btree_opclass",
+                &default_opclass_query("btree"),
+                "lexo_btree_ops is the default btree operator class",
+                "no default btree operator class for lexo",
+            ),
+            catalog_check(
+                "hash_opclass",
+                &default_opclass_query("hash"),
+                "lexo has a default hash operator class",
+                "no default hash operator class for lexo",
+            ),
+            catalog_check(
+                "sortsupport",
+                "SELECT EXISTS (SELECT 1 FROM pg_catalog.pg_amproc p \
+                 JOIN pg_catalog.pg_opfamily f ON f.oid = p.amprocfamily \
+                 WHERE f.opfname = 'lexo_btree_ops' AND p.amprocnum = 2 \
+                 AND p.amproc = to_regproc('lexo_sortsupport'))",
+                "lexo_sortsupport is registered",
+                "lexo_sortsupport is not registered in lexo_btree_ops",
+            ),
+            casts_check(),
+            settings_check(),
+            targets_check(),
+        ];
+
+        if check_indexes {
+            results.push(indexes_check());
+        }
+
+        TableIterator::new(results)
+    })
+}
+
+type HealthRow = (String, bool, String);
+
+fn catalog_check(check: &str, query: &str, ok_detail: &str, failed_detail: &str) -> HealthRow {
+    let ok: bool = Spi::get_one(query)
+        .expect("Failed to query the catalog")
+        .unwrap_or(false);
+    let detail = if ok { ok_detail } else { failed_detail };
+    (check.to_string(), ok, detail.to_string())
+}
+
+fn default_opclass_query(method: &str) -> String {
+    format!(
+        "SELECT EXISTS (SELECT 1 FROM pg_catalog.pg_opclass c \
+         JOIN pg_catalog.pg_am a ON a.oid = c.opcmethod \
+         WHERE a.amname = {} AND c.opcintype = to_regtype('lexo') AND c.opcdefault)",
+        quote_literal(method)
+    )
+}
+
+fn casts_check() -> HealthRow {
+    let missing: Vec<String> = EXPECTED_CASTS
+        .iter()
+        .filter(|(source, target)| {
+            let query = format!(
+                "SELECT EXISTS (SELECT 1 FROM pg_catalog.pg_cast \
+                 WHERE castsource = to_regtype({}) AND casttarget = to_regtype({}))",
+                quote_literal(source),
+                quote_literal(target)
+            );
+            let exists: Option<bool> = Spi::get_one(&query).expect("Failed to query pg_cast");
+            exists != Some(true)
+        })
+        .map(|(source, target)| format!("{} -> {}", source, target))
+        .collect();
+
+    if missing.is_empty() {
+        (
+            "casts".to_string(),
+            true,
+            format!("all {} casts installed", EXPECTED_CASTS.len()),
+        )
+    } else {
+        (
+            "casts".to_string(),
+            false,
+            format!("missing casts: {}", missing.join(", ")),
+        )
+    }
+}
+
+fn settings_check() -> HealthRow {
+    let mut problems = Vec::new();
+
+    let window = crate::gucs::AUTOREBALANCE_WINDOW
+        .get()
+        .and_then(|w| w.into_string().ok())
+        .unwrap_or_default();
+    if crate::worker::parse_window(&window).is_none() {
+        problems.push(format!(
+            "lexo.autorebalance_window '{}' is not HH:MM-HH:MM",
+            window
+        ));
+    }
+
+    let writer_id = writer_id_setting();
+    if !writer_id.is_empty() && parse_writer_id(&writer_id).is_none() {
+        problems.push(format!(
+            "lexo.writer_id '{}' is not a single Base62 character other than '0'",
+            writer_id
+        ));
+    }
+
+    if crate::gucs::AUTOREBALANCE.get() {
+        let preloaded: Option<bool> = Spi::get_one(
+            "SELECT 'pg_lexo' = ANY (string_to_array(\
+             replace(current_setting('shared_preload_libraries'), ' ', ''), ','))",
+        )
+        .expect("Failed to read shared_preload_libraries");
+        if preloaded != Some(true) {
+            problems.push(
+                "lexo.autorebalance is on but pg_lexo is not in shared_preload_libraries"
+                    .to_string(),
+            );
+        }
+    }
+
+    if problems.is_empty() {
+        (
+            "settings".to_string(),
+            true,
+            "settings are consistent".to_string(),
+        )
+    } else {
+        ("settings".to_string(), false, problems.join("; "))
+    }
+}
+
+fn targets_check() -> HealthRow {
+    let targets: Vec<(String, String)> = Spi::connect(|client| {
+        client
+            .select(
+                &format!(
+                    "SELECT table_name::text, column_name::text \
+                     FROM {}.lexo_autorebalance_targets ORDER BY 1, 2",
+                    extension_schema()
+                ),
+                None,
+                &[],
+            )
+            .expect("Failed to read lexo_autorebalance_targets")
+            .filter_map(|row| Some((row.get(1).ok()??, row.get(2).ok()??)))
+            .collect()
+    });
+    let invalid: Vec<String> = targets
+        .iter()
+        .filter_map(
+            |(table, column)| match position_column_kind(table, column) {
+                (_, Some(_)) => None,
+                (Some(type_name), None) => Some(format!("{}.{} ({})", table, column, type_name)),
+                (None, None) => Some(format!("{}.{} (missing)", table, column)),
+            },
+        )
+        .collect();
+
+    if invalid.is_empty() {
+        (
+            "targets".to_string(),
+            true,
+            "every registered column holds positions".to_string(),
+        )
+    } else {
+        (
+            "targets".to_string(),
+            false,
+            format!("not a position column: {}", invalid.join(", ")),
+        )
+    }
+}
+
+fn indexes_check() -> HealthRow {
+    let unindexed: Vec<String> = Spi::connect(|client| {
+        client
+            .select(
+                "SELECT t.table_name::text || '.' || t.column_name \
+                 FROM lexo_autorebalance_targets t \
+                 JOIN pg_catalog.pg_attribute a \
+                   ON a.attrelid = t.table_name AND a.attname = t.column_name \
+                 WHERE NOT EXISTS (SELECT 1 FROM pg_catalog.pg_index i \
+                                   WHERE i.indrelid = t.table_name \
+                                   AND a.attnum = ANY (i.indkey))",
+                None,
+                &[],
+            )
+            .expect("Failed to check lexo_autorebalance_targets for indexes")
+            .filter_map(|row| row.get::<String>(1).ok().flatten())
+            .collect()
+    });
+
+    if unindexed.is_empty() {
+        (
+            "indexes".to_string(),
+            true,
+            "every registered column is indexed".to_string(),
+        )
+    } else {
+        (
+            "indexes".to_string(),
+            false,
+            format!("no index on: {}", unindexed.join(", ")),
+        )
+    }
+}
+
+// Per-database usage counters, named like the pg_stat_* views. after_calls,
+// before_calls and between_calls only count positions placed in a list, never
+// direct calls to lexo_after(), lexo_before() or lexo_between().
+extension_sql!(
+    r#"
+CREATE VIEW lexo_stat AS
+SELECT s.dbid, d.datname, s.after_calls, s.before_calls, s.between_calls,
+       s.next_calls, s.rebalance_calls, s.rows_rebalanced, s.long_ranks
+FROM lexo_stat_counters() s
+LEFT JOIN pg_catalog.pg_database d ON d.oid = s.dbid;
+"#,
+    name = "lexo_stat_view",
+    requires = [lexo_stat_counters]
+);
+
+// Columns the autorebalance background worker keeps in shape.
+extension_sql!(
+    r#"
+CREATE TABLE lexo_autorebalance_targets (
+    table_name regclass NOT NULL,
+    column_name name NOT NULL,
+    key_column name,
+    max_length integer NOT NULL DEFAULT 16 CHECK (max_length > 0),
+    PRIMARY KEY (table_name, column_name)
+);
+SELECT pg_catalog.pg_extension_config_dump('lexo_autorebalance_targets', '');
+"#,
+    name = "lexo_autorebalance_targets"
+);
+
+/// Rebalances every list of `table_name` that has a position longer than
+/// `max_length` characters, returning the number of rows rewritten.
+///
+/// With `key_column_name`, each value of the key column is a separate list and
+/// rows whose key is NULL are left alone. This is the function
+/// `lexo_schedule_rebalance()` has pg_cron run. `text` and `varchar` columns
+/// are accepted as if `allow_text` had been passed to `lexo_rebalance()`.
+#[pg_extern]
+pub fn lexo_rebalance_if_needed(
+    table_name: &str,
+    lexo_column_name: &str,
+    key_column_name: default!(Option<&str>, "NULL"),
+    max_length: default!(i32, 12),
+) -> i64 {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(lexo_column_name), key_column_name]);
+        require_position_column(table_name, lexo_column_name, true);
+        if max_length < 1 {
+            pgrx::error!("max_length must be at least 1, got {}", max_length);
+        }
+
+        // The column was registered on purpose, so a text column of positions is
+        // rebalanced the same as a lexo one
+        let allow_text = position_column_kind(table_name, lexo_column_name)
+            .1
+            .as_deref()
+            == Some("text");
+        let quoted_table = quote_table_name(table_name);
+        let quoted_column = quote_identifier(lexo_column_name);
+        let query = match key_column_name {
+            Some(key_column) => format!(
+                "SELECT {key}::text FROM {table} WHERE {key} IS NOT NULL GROUP BY {key} \
+                 HAVING max(length({col}::text)) > {max_length}",
+                key = quote_identifier(key_column),
+                table = quoted_table,
+                col = quoted_column,
+                max_length = max_length
+            ),
+            None => format!(
+                "SELECT NULL::text FROM {table} HAVING max(length({col}::text)) > {max_length}",
+                table = quoted_table,
+                col = quoted_column,
+                max_length = max_length
+            ),
+        };
+        let degraded: Vec<Option<String>> = Spi::connect(|client| {
+            client
+                .select(&query, None, &[])
+                .expect("Failed to find lists that need rebalancing")
+                .map(|row| row.get(1).ok().flatten())
+                .collect()
+        });
+
+        degraded
+            .iter()
+            .map(|key_value| {
+                lexo_rebalance(
+                    table_name,
+                    lexo_column_name,
+                    key_column_name,
+                    key_value.as_deref(),
+                    None,
+                    "equal",
+                    None,
+                    false,
+                    allow_text,
+                    "append",
+                    "keep_order",
+                    false,
+                    false,
+                    None,
+                )
+            })
+            .sum()
+    })
+}
+
+/// Raises `object_not_in_prerequisite_state` unless pg_cron is installed in
+/// the current database.
+fn require_pg_cron() {
+    let installed: Option<bool> = Spi::get_one(
+        "SELECT EXISTS (SELECT 1 FROM pg_catalog.pg_extension \
+         WHERE extname OPERATOR(pg_catalog.=) 'pg_cron')",
+    )
+    .expect("Failed to look up pg_cron");
+    if installed != Some(true) {
+        raise_error!(
+            PgSqlErrorCode::ERRCODE_OBJECT_NOT_IN_PREREQUISITE_STATE,
+            "pg_cron is not installed in this database",
+            hint = "Add pg_cron to shared_preload_libraries, set cron.database_name to this \
+             database and run CREATE EXTENSION pg_cron.",
+        );
+    }
+}
+
+/// Returns `table_name` qualified with its schema, quoted for use in SQL.
+fn qualified_table_name(table_name: &str) -> String {
+    Spi::get_one::<String>(&format!(
+        "SELECT pg_catalog.quote_ident(n.nspname) || '.' || pg_catalog.quote_ident(c.relname) \
+         FROM pg_catalog.pg_class c \
+         JOIN pg_catalog.pg_namespace n ON n.oid OPERATOR(pg_catalog.=) c.relnamespace \
+         WHERE c.oid OPERATOR(pg_catalog.=) {}::pg_catalog.regclass",
+        quote_literal(quote_table_name(table_name))
+    ))
+    .expect("Failed to look up the table's schema")
+    .expect("table has no schema")
+}
+
+/// Registers a pg_cron job that runs `lexo_rebalance_if_needed()` on a column
+/// at `schedule`, returning the job id.
+///
+/// The job is recorded in `lexo_rebalance_schedules`; a column can only be
+/// scheduled once, so call `lexo_unschedule_rebalance()` first to change it.
+#[pg_extern]
+pub fn lexo_schedule_rebalance(
+    table_name: &str,
+    lexo_column_name: &str,
+    schedule: default!(&str, "'0 4 * * *'"),
+    key_column_name: default!(Option<&str>, "NULL"),
+    max_length: default!(i32, 12),
+) -> i64 {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(lexo_column_name), key_column_name]);
+        require_position_column(table_name, lexo_column_name, true);
+        if max_length < 1 {
+            pgrx::error!("max_length must be at least 1, got {}", max_length);
+        }
+        require_pg_cron();
+
+        let table = qualified_table_name(table_name);
+        let scheduled: Option<bool> = Spi::get_one(&format!(
+            "SELECT EXISTS (SELECT 1 FROM lexo_rebalance_schedules \
+             WHERE table_name = {}::regclass AND column_name = {})",
+            quote_literal(&table),
+            quote_literal(lexo_column_name)
+        ))
+        .expect("Failed to read lexo_rebalance_schedules");
+        if scheduled == Some(true) {
+            raise_error!(
+                PgSqlErrorCode::ERRCODE_DUPLICATE_OBJECT,
+                format!(
+                    "a rebalance of {}.{} is already scheduled",
+                    table,
+                    quote_identifier(lexo_column_name)
+                ),
+                hint = "Call lexo_unschedule_rebalance() first to change its schedule.",
+            );
+        }
+
+        let key_column = key_column_name.map_or("NULL".to_string(), quote_literal);
+        let command = format!(
+            "SELECT {}.lexo_rebalance_if_needed({}, {}, {}, {})",
+            extension_schema(),
+            quote_literal(&table),
+            quote_literal(lexo_column_name),
+            key_column,
+            max_length
+        );
+        let job_name = format!("lexo_rebalance {}.{}", table, lexo_column_name);
+        let job_id: i64 = Spi::get_one(&format!(
+            "SELECT cron.schedule({}, {}, {})",
+            quote_literal(&job_name),
+            quote_literal(schedule),
+            quote_literal(&command)
+        ))
+        .expect("Failed to schedule the rebalance")
+        .expect("cron.schedule() returned NULL");
+
+        Spi::run(&format!(
+            "INSERT INTO lexo_rebalance_schedules \
+             (table_name, column_name, key_column, schedule, max_length, job_id) \
+             VALUES ({}::regclass, {}, {}, {}, {}, {})",
+            quote_literal(&table),
+            quote_literal(lexo_column_name),
+            key_column,
+            quote_literal(schedule),
+            max_length,
+            job_id
+        ))
+        .expect("Failed to record the schedule");
+        job_id
+    })
+}
+
+/// Removes the pg_cron job `lexo_schedule_rebalance()` registered for a
+/// column. Returns false if the column had no schedule.
+#[pg_extern]
+pub fn lexo_unschedule_rebalance(table_name: &str, lexo_column_name: &str) -> bool {
+    with_pinned_search_path(|| {
+        require_columns(table_name, &[Some(lexo_column_name)]);
+        let job_id: Option<i64> = Spi::get_one(&format!(
+            "DELETE FROM lexo_rebalance_schedules \
+             WHERE table_name = {}::regclass AND column_name = {} \
+             RETURNING job_id",
+            quote_literal(quote_table_name(table_name)),
+            quote_literal(lexo_column_name)
+        ))
+        .expect("Failed to update lexo_rebalance_schedules");
+        let Some(job_id) = job_id else {
+            return false;
+        };
+
+        // The job may already be gone if pg_cron was dropped or it was
+        // unscheduled by hand; the registry row is removed either way
+        let cron_installed: Option<bool> =
+            Spi::get_one("SELECT to_regclass('cron.job') IS NOT NULL")
+                .expect("Failed to look up pg_cron");
+        if cron_installed != Some(true) {
+            return true;
+        }
+        let job_exists: Option<bool> = Spi::get_one(&format!(
+            "SELECT EXISTS (SELECT 1 FROM cron.job WHERE jobid = {})",
+            job_id
+        ))
+        .expect("Failed to read cron.job");
+        if job_exists == Some(true) {
+            Spi::run(&format!("SELECT cron.unschedule({}::bigint)", job_id))
+                .expect("Failed to unschedule the rebalance");
+        }
+        true
+    })
+}
+
+// Rebalances scheduled through pg_cron by lexo_schedule_rebalance().
+extension_sql!(
+    r#"
+CREATE TABLE lexo_rebalance_schedules (
+    table_name regclass NOT NULL,
+    column_name name NOT NULL,
+    key_column name,
+    schedule text NOT NULL,
+    max_length integer NOT NULL CHECK (max_length > 0),
+    job_id bigint NOT NULL,
+    PRIMARY KEY (table_name, column_name)
+);
+SELECT pg_catalog.pg_extension_config_dump('lexo_rebalance_schedules', '');
+
+CREATE FUNCTION lexo_schedules()
+RETURNS TABLE (table_name regclass, column_name name, key_column name,
+               schedule text, max_length integer, job_id bigint)
+LANGUAGE sql STABLE
+AS $$
+    SELECT table_name, column_name, key_column, schedule, max_length, job_id
+    FROM @extschema@.lexo_rebalance_schedules
+    ORDER BY table_name::text, column_name
+$$;
+"#,
+    name = "lexo_rebalance_schedules"
+);
+
+// Ready-made domains for per-column guarantees.
+extension_sql!(
+    r#"
+CREATE DOMAIN lexo_nonempty AS lexo
+    CHECK (VALUE::text <> '');
+CREATE DOMAIN lexo_short AS lexo
+    CHECK (length(VALUE::text) <= 16);
+"#,
+    name = "lexo_domains",
+    requires = [Lexo]
+);
+
+// Text-backed alternative to the `lexo` type for tools that only understand text.
+extension_sql!(
+    r#"
+CREATE DOMAIN lexo_text AS text
+    CHECK (lexo_is_valid(VALUE));
+"#,
+    name = "lexo_text_domain",
+    requires = [lexo_is_valid]
+);
+
+// Objects that depend on the btree operator class generated by `PostgresOrd`.
+// pgrx can't express a dependency on the operator class itself, so these run last.
+extension_sql!(
+    r#"
+CREATE TYPE lexorange AS RANGE (
+    SUBTYPE = lexo,
+    SUBTYPE_OPCLASS = lexo_btree_ops,
+    SUBTYPE_DIFF = lexo_range_diff
+);
+ALTER OPERATOR FAMILY lexo_btree_ops USING btree
+    ADD FUNCTION 2 (lexo, lexo) lexo_sortsupport(internal);
+"#,
+    name = "lexo_finalize",
+    finalize
+);
+
+#[cfg(feature = "pg_test")]
+#[pg_schema]
+mod tests {
+    use std::cell::RefCell;
+    use std::ffi::CStr;
+
+    use pgrx::prelude::*;
+    use pgrx::spi::quote_literal;
+
+    use crate::lexo_recv;
+    use crate::operations::{generate_after, generate_before, generate_between};
+
+    fn select_text(query: &str) -> String {
+        Spi::get_one::<String>(query)
+            .expect("query failed")
+            .expect("query returned NULL")
+    }
+
+    thread_local! {
+        static LOGGED: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    #[pg_guard]
+    unsafe extern "C-unwind" fn capture_log(edata: *mut pg_sys::ErrorData) {
+        let message = unsafe { CStr::from_ptr((*edata).message) }
+            .to_string_lossy()
+            .into_owned();
+        LOGGED.with(|logged| logged.borrow_mut().push(message));
+    }
+
+    /// Runs `query` and returns the messages it sent to the server log.
+    fn logged_by(query: &str) -> Vec<String> {
+        let result = unsafe {
+            let previous = pg_sys::emit_log_hook;
+            pg_sys::emit_log_hook = Some(capture_log);
+            let result = Spi::run(query);
+            pg_sys::emit_log_hook = previous;
+            result
+        };
+        result.expect("query failed");
+        LOGGED.with(RefCell::take)
+    }
+
+    fn ordered_ids(from: &str) -> String {
+        select_text(&format!(
+            "SELECT string_agg(id::text, ',' ORDER BY position) FROM {}",
+            from
+        ))
+    }
+
+    /// Runs `statement` in a subtransaction and returns the error it raised, as
+    /// `SQLSTATE: message` followed by any detail and hint on their own lines.
+    fn error_of(statement: &str) -> Option<String> {
+        Spi::run(
+            "CREATE OR REPLACE FUNCTION error_of(statement text) RETURNS text
+             LANGUAGE plpgsql AS $$
+             DECLARE
+                 state text;
+                 message text;
+                 detail text;
+                 hint text;
+             BEGIN
+                 EXECUTE statement;
+                 RETURN NULL;
+             EXCEPTION WHEN OTHERS THEN
+                 GET STACKED DIAGNOSTICS state = RETURNED_SQLSTATE, message = MESSAGE_TEXT,
+                     detail = PG_EXCEPTION_DETAIL, hint = PG_EXCEPTION_HINT;
+                 RETURN state || ': ' || message
+                     || coalesce(E'\\nDETAIL: ' || nullif(detail, ''), '')
+                     || coalesce(E'\\nHINT: ' || nullif(hint, ''), '');
+             END $$",
+        )
+        .unwrap();
+        Spi::get_one::<String>(&format!("SELECT error_of({})", quote_literal(statement)))
+            .expect("query failed")
+    }
+
+    /// The value `query` returns, or `error <SQLSTATE>` if it fails.
+    fn outcome(query: &str) -> Option<String> {
+        match error_of(query) {
+            Some(error) => Some(format!("error {}", &error[..5])),
+            None => Spi::get_one::<String>(query).expect("query failed"),
+        }
+    }
+
+    /// Builds a five-row list through lexo_next(), lexo_rebalance() and
+    /// lexo_at_fraction(); in position order its ids are 1, 3, 5, 2, 4.
+    fn create_items() {
+        Spi::run(
+            "CREATE TABLE items (id serial PRIMARY KEY, position lexo NOT NULL);
+             CREATE INDEX items_position_idx ON items (position);
+             INSERT INTO items (position) VALUES (lexo_first());
+             INSERT INTO items (position) VALUES (lexo_after(lexo_first()));
+             INSERT INTO items (position) VALUES (lexo_between(lexo_first(), lexo_after(lexo_first())));
+             INSERT INTO items (position) VALUES (lexo_next('items', 'position', NULL, NULL));
+             SELECT lexo_rebalance('items', 'position', NULL, NULL);
+             INSERT INTO items (position) VALUES (lexo_at_fraction('items', 'position', 0.5))",
+        )
+        .unwrap();
+    }
+
+    #[pg_test]
+    fn test_sql_generators_match_the_library() {
+        for (before, after) in [("A", "B"), ("A", "Z"), ("Az", "B"), ("Hz", "I")] {
+            assert_eq!(
+                select_text(&format!(
+                    "SELECT lexo_between('{}'::lexo, '{}'::lexo)::text",
+                    before, after
+                )),
+                generate_between(before, after).unwrap()
+            );
+        }
+        for rank in ["H", "Hz", "zz", "1"] {
+            assert_eq!(
+                select_text(&format!("SELECT lexo_after('{}'::lexo)::text", rank)),
+                generate_after(rank)
+            );
+            assert_eq!(
+                select_text(&format!("SELECT lexo_before('{}'::lexo)::text", rank)),
+                generate_before(rank).unwrap()
+            );
+        }
+    }
+
+    #[pg_test(
+        error = "cannot generate a position between 'H' and 'H0': no position sorts between them"
+    )]
+    fn test_between_rejects_bounds_without_room() {
+        Spi::run("SELECT lexo_between('H'::lexo, 'H0'::lexo)").unwrap();
+    }
+
+    #[pg_test]
+    fn test_page_after_skips_unpositioned_rows() {
+        Spi::run(
+            "CREATE TABLE paged (id int PRIMARY KEY, position lexo);
+             INSERT INTO paged VALUES (1, 'B'), (2, NULL), (3, 'A'), (4, NULL), (5, 'C')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(pk, ',' ORDER BY rank) FROM lexo_page_after('paged', 'position', NULL, 10)"
+            ),
+            "3,1,5"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(pk, ',' ORDER BY rank) FROM lexo_page_after('paged', 'position', 'A', 1)"
+            ),
+            "1"
+        );
+    }
+
+    #[pg_test]
+    fn test_at_fraction_ignores_unpositioned_rows() {
+        Spi::run(
+            "CREATE TABLE fractions (id int PRIMARY KEY, position lexo);
+             INSERT INTO fractions VALUES (1, 'B'), (2, 'D');
+             INSERT INTO fractions SELECT g, NULL FROM generate_series(3, 10) g",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT lexo_at_fraction('fractions', 'position', 0.5)::text"),
+            "C"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_at_fraction('fractions', 'position', 0.9)::text"),
+            "C"
+        );
+    }
+
+    #[pg_test]
+    fn test_insert_at_ignores_unpositioned_rows() {
+        Spi::run(
+            "CREATE TABLE indexed (id int PRIMARY KEY, position lexo);
+             INSERT INTO indexed VALUES (1, 'B'), (2, NULL), (3, 'D'), (4, NULL)",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT lexo_insert_at('indexed', 'position', 1)::text"),
+            "C"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_insert_at('indexed', 'position', 3)::text"),
+            select_text("SELECT lexo_after('D'::lexo)::text")
+        );
+    }
+
+    #[pg_test(error = "invalid pin_where expression: unbalanced parentheses")]
+    fn test_rebalance_rejects_pin_where_that_escapes() {
+        Spi::run("CREATE TABLE pinned (id int PRIMARY KEY, position lexo)").unwrap();
+        Spi::run(
+            "SELECT lexo_rebalance('pinned', 'position', NULL, NULL, pin_where => 'true) OR (true')",
+        )
+        .unwrap();
+    }
+
+    #[pg_test(error = "invalid where_clause expression: unbalanced parentheses")]
+    fn test_rebalance_rejects_where_clause_that_escapes() {
+        Spi::run("CREATE TABLE filtered (id int PRIMARY KEY, position lexo)").unwrap();
+        Spi::run("SELECT lexo_rebalance('filtered', 'position', NULL, NULL, 'true) OR (true')")
+            .unwrap();
+    }
+
+    #[pg_test]
+    fn test_compare_is_the_btree_support_function() {
+        assert_eq!(
+            select_text(
+                "SELECT p.amproc::regprocedure::text FROM pg_amproc p \
+                 JOIN pg_opfamily f ON f.oid = p.amprocfamily \
+                 WHERE f.opfname = 'lexo_btree_ops' AND p.amprocnum = 1"
+            ),
+            "lexo_compare(lexo,lexo)"
+        );
+        assert_eq!(
+            Spi::get_one::<bool>(
+                "WITH ranks AS (SELECT lexo_between_seeded(NULL, NULL, g::text) AS r FROM generate_series(1, 500) g) \
+                 SELECT bool_and(lexo_compare(a.r, b.r) = CASE WHEN a.r < b.r THEN -1 WHEN a.r = b.r THEN 0 ELSE 1 END) \
+                 FROM ranks a CROSS JOIN (SELECT r FROM ranks LIMIT 50) b"
+            ),
+            Ok(Some(true))
+        );
+    }
+
+    #[pg_test]
+    fn test_rebalance_if_needed_rebalances_text_columns() {
+        Spi::run(
+            "CREATE TABLE texts (id int PRIMARY KEY, position text);
+             INSERT INTO texts VALUES (1, 'H0000000000000001'), (2, 'H0000000000000002')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT lexo_rebalance_if_needed('texts', 'position')::text"),
+            "2"
+        );
+        assert_eq!(
+            select_text("SELECT max(length(position))::text FROM texts"),
+            "1"
+        );
+    }
+
+    #[pg_test]
+    fn test_debug_logs_the_branch_lexo_between_took() {
+        Spi::run("SET LOCAL lexo.debug = on").unwrap();
+        assert_eq!(
+            logged_by("SELECT lexo_between('A', 'B')"),
+            [
+                "lexo_between('A', 'B'): adjacent characters at index 0, extended the lower bound -> 'AH'"
+            ]
+        );
+        assert_eq!(
+            logged_by("SELECT lexo_after('H'), lexo_before('H')"),
+            [
+                format!("lexo_after('H') -> '{}'", generate_after("H")),
+                format!("lexo_before('H') -> '{}'", generate_before("H").unwrap()),
+            ]
+        );
+        Spi::run("SET LOCAL lexo.debug = off").unwrap();
+        assert!(logged_by("SELECT lexo_between('A', 'B')").is_empty());
+    }
+
+    #[pg_test]
+    fn test_sortsupport_orders_like_lexo_cmp() {
+        // Every other rank shares its first 8 bytes, so the abbreviated keys tie
+        // and the full comparator has to break them.
+        Spi::run(
+            "CREATE TABLE ranked AS
+             SELECT i AS id, (CASE WHEN i % 2 = 0 THEN 'zzzzzzzz' ELSE '' END
+                 || left(md5(i::text), 1 + i % 12) || 'H')::lexo AS position
+             FROM generate_series(1, 1000000) AS i",
+        )
+        .unwrap();
+        // Index builds always sort, so timing them compares the two comparators
+        let build_index = || {
+            let started = std::time::Instant::now();
+            Spi::run(
+                "DROP INDEX IF EXISTS ranked_position;
+                 CREATE INDEX ranked_position ON ranked (position, id)",
+            )
+            .unwrap();
+            started.elapsed()
+        };
+        let with_sortsupport = build_index();
+        Spi::run("ANALYZE ranked").unwrap();
+        let order = |query: &str| {
+            select_text(&format!(
+                "SELECT md5(string_agg(id::text, ',')) FROM ({}) s",
+                query
+            ))
+        };
+        let expected = order("SELECT id FROM ranked ORDER BY position::text COLLATE \"C\", id");
+
+        assert_eq!(
+            order("SELECT id FROM ranked ORDER BY position, id"),
+            expected
+        );
+        // With a shared 8-byte prefix every abbreviated key is equal, so the
+        // sort abandons abbreviation and compares full values
+        assert_eq!(
+            order("SELECT id FROM ranked ORDER BY ('zzzzzzzz' || position::text)::lexo, id"),
+            expected
+        );
+        Spi::run(
+            "SET LOCAL enable_seqscan = off;
+             SET LOCAL enable_bitmapscan = off;
+             SET LOCAL enable_sort = off",
+        )
+        .unwrap();
+        assert_eq!(
+            order("SELECT id FROM ranked ORDER BY position, id"),
+            expected
+        );
+
+        // Without sortsupport, the index build and sorts go through lexo_cmp()
+        Spi::run("ALTER OPERATOR FAMILY lexo_btree_ops USING btree DROP FUNCTION 2 (lexo, lexo)")
+            .unwrap();
+        let through_lexo_cmp = build_index();
+        assert_eq!(
+            order("SELECT id FROM ranked ORDER BY position, id"),
+            expected
+        );
+        Spi::run("RESET enable_seqscan; RESET enable_bitmapscan; RESET enable_sort").unwrap();
+        assert_eq!(
+            order("SELECT id FROM ranked ORDER BY position, id"),
+            expected
+        );
+
+        // Timing note: the test passes either way; the server log shows how
+        // the two builds of the same 1M-row index compare on this machine
+        pgrx::log!(
+            "building the 1M-row index took {:?} with sortsupport and {:?} through lexo_cmp()",
+            with_sortsupport,
+            through_lexo_cmp
+        );
+    }
+
+    #[pg_test]
+    fn test_max_is_an_index_only_probe() {
+        // 'Z' sorts after 'a' in most collations but before it byte-wise
+        Spi::run(
+            "CREATE TABLE maxed (id int, position lexo);
+             INSERT INTO maxed SELECT i, (left(md5(i::text), 6) || 'H')::lexo
+             FROM generate_series(1, 10000) AS i;
+             INSERT INTO maxed VALUES (10001, 'ZzzzzH'), (10002, 'gH');
+             CREATE INDEX maxed_position ON maxed (position);
+             ANALYZE maxed",
+        )
+        .unwrap();
+        let plan = Spi::explain("SELECT max(position) FROM maxed")
+            .unwrap()
+            .0
+            .to_string();
+        assert!(plan.contains("\"Index Only Scan\""), "{}", plan);
+        assert!(plan.contains("\"Backward\""), "{}", plan);
+        assert_eq!(select_text("SELECT max(position)::text FROM maxed"), "gH");
+    }
+
+    #[pg_test]
+    fn test_health_is_green_on_a_fresh_install() {
+        assert_eq!(
+            select_text(
+                "SELECT coalesce(string_agg(\"check\" || ': ' || detail, '; '), '') \
+                 FROM lexo_health(true) WHERE NOT ok"
+            ),
+            ""
+        );
+        assert_eq!(
+            select_text("SELECT string_agg(\"check\", ',') FROM lexo_health(true)"),
+            "type,btree_opclass,hash_opclass,sortsupport,casts,settings,targets,indexes"
+        );
+    }
+
+    #[pg_test]
+    fn test_health_reports_targets_that_are_not_position_columns() {
+        Spi::run(
+            "CREATE TABLE targeted (id int PRIMARY KEY, position int);
+             INSERT INTO lexo_autorebalance_targets (table_name, column_name) \
+             VALUES ('targeted', 'position')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT detail FROM lexo_health() WHERE \"check\" = 'targets'"),
+            "not a position column: targeted.position (integer)"
+        );
+    }
+
+    #[pg_test(
+        error = "column \"position\" of relation \"counted\" is of type integer, not a position type"
+    )]
+    fn test_rebalance_if_needed_checks_the_column_type() {
+        Spi::run("CREATE TABLE counted (id int PRIMARY KEY, position int)").unwrap();
+        Spi::run("SELECT lexo_rebalance_if_needed('counted', 'position')").unwrap();
+    }
+
+    #[pg_test]
+    fn test_rebalance_accepts_a_column_update_grant() {
+        Spi::run(
+            "CREATE ROLE lexo_column_grantee NOLOGIN;
+             CREATE TABLE column_granted (id int PRIMARY KEY, label text, position lexo);
+             INSERT INTO column_granted VALUES (1, 'a', 'H'), (2, 'b', 'Hzzz'), (3, 'c', 'I');
+             GRANT USAGE ON SCHEMA tests TO lexo_column_grantee;
+             GRANT SELECT, UPDATE (position) ON column_granted TO lexo_column_grantee;
+             SET LOCAL ROLE lexo_column_grantee",
+        )
+        .unwrap();
+        Spi::run("SELECT lexo_rebalance('column_granted', 'position', NULL, NULL)").unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(id::text || ':' || length(position::text), ',' ORDER BY position) \
+                 FROM column_granted"
+            ),
+            "1:1,2:1,3:1"
+        );
+    }
+
+    #[pg_test(error = "must be owner of table numbered")]
+    fn test_migrate_from_numeric_requires_the_table_owner() {
+        Spi::run(
+            "CREATE ROLE lexo_migrator NOLOGIN;
+             CREATE TABLE numbered (id int PRIMARY KEY, sort_order int);
+             GRANT USAGE ON SCHEMA tests TO lexo_migrator;
+             GRANT SELECT, UPDATE ON numbered TO lexo_migrator;
+             SET LOCAL ROLE lexo_migrator",
+        )
+        .unwrap();
+        Spi::run("SELECT * FROM lexo_migrate_from_numeric('numbered', 'sort_order')").unwrap();
+    }
+
+    #[pg_test]
+    fn test_descending_rebalance_appends_nulls_at_the_bottom() {
+        Spi::run(
+            "CREATE TABLE newest_first (id int PRIMARY KEY, position lexo);
+             INSERT INTO newest_first VALUES (1, 'i'), (2, NULL), (3, 'Z'), (4, 'Z'), (5, 'H')",
+        )
+        .unwrap();
+        Spi::run(
+            "SELECT lexo_rebalance('newest_first', 'position', NULL, NULL, descending => true)",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(id::text, ',' ORDER BY position DESC) FROM newest_first"
+            ),
+            "1,3,4,5,2"
+        );
+    }
+
+    #[pg_test(error = "no room to rerank invalid value 'H!' between its neighbours")]
+    fn test_convert_text_column_rerank_needs_room() {
+        Spi::run(
+            "CREATE TABLE crowded (id int PRIMARY KEY, sort_key text);
+             INSERT INTO crowded VALUES (1, 'H'), (2, 'H!'), (3, 'H0')",
+        )
+        .unwrap();
+        Spi::run("SELECT lexo_convert_text_column('crowded', 'sort_key', 'rerank')").unwrap();
+    }
+
+    #[pg_test(error = "position of length 80000 is out of range for type numeric")]
+    fn test_to_numeric_rejects_positions_too_long_for_numeric() {
+        Spi::run("SELECT lexo_to_numeric(repeat('z', 80000)::lexo)").unwrap();
+    }
+
+    #[pg_test]
+    fn test_insertion_budget_reports_the_deepest_shared_prefix() {
+        Spi::run(
+            "CREATE TABLE budgeted (id int PRIMARY KEY, position lexo);
+             INSERT INTO budgeted VALUES (1, 'A'), (2, 'HzzA'), (3, 'HzzB'), (4, 'Q')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT deepest_prefix::text || '/' || worst_case_between::text \
+                 FROM lexo_insertion_budget('budgeted', 'position', max_length => 3)"
+            ),
+            "3/0"
+        );
+    }
+
+    #[pg_test]
+    fn test_rank_all_tells_apart_rows_of_child_tables() {
+        Spi::run(
+            "CREATE TABLE ranked_parent (id int, position lexo);
+             CREATE TABLE ranked_child () INHERITS (ranked_parent);
+             INSERT INTO ranked_parent VALUES (1, NULL), (3, NULL);
+             INSERT INTO ranked_child VALUES (2, NULL), (4, NULL)",
+        )
+        .unwrap();
+        Spi::run("SELECT * FROM lexo_rank_all('ranked_parent', 'position', 'id')").unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(id::text, ',' ORDER BY position) || '/' || \
+                 count(DISTINCT position)::text FROM ranked_parent"
+            ),
+            "1,2,3,4/4"
+        );
+    }
+
+    #[pg_test]
+    fn test_convert_text_column_tells_apart_rows_of_child_tables() {
+        Spi::run(
+            "CREATE TABLE converted_parent (id int, sort_key text);
+             CREATE TABLE converted_child () INHERITS (converted_parent);
+             INSERT INTO converted_parent VALUES (1, 'A'), (3, 'C');
+             INSERT INTO converted_child VALUES (2, 'B-'), (4, 'D')",
+        )
+        .unwrap();
+        Spi::run("SELECT * FROM lexo_convert_text_column('converted_parent', 'sort_key', 'null')")
+            .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(id::text || '=' || coalesce(sort_key::text, 'NULL'), ',' ORDER BY id) \
+                 FROM converted_parent"
+            ),
+            "1=A,2=NULL,3=C,4=D"
+        );
+    }
+
+    #[pg_test]
+    fn test_maintained_ordinal_tells_apart_rows_of_child_tables() {
+        Spi::run(
+            "CREATE TABLE numbered_parent (id int, position lexo, label text);
+             CREATE TABLE numbered_child () INHERITS (numbered_parent);
+             INSERT INTO numbered_parent VALUES (1, 'B', NULL), (3, 'D', NULL);
+             INSERT INTO numbered_child VALUES (2, 'C', NULL), (4, 'E', NULL)",
+        )
+        .unwrap();
+        Spi::run("SELECT lexo_maintain_ordinal('numbered_parent', 'position', 'display_order')")
+            .unwrap();
+        Spi::run("UPDATE numbered_parent SET position = 'F' WHERE id = 1").unwrap();
+        Spi::run("UPDATE numbered_parent SET label = 'unrelated'").unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(id::text || '=' || display_order::text, ',' ORDER BY id) \
+                 FROM numbered_parent"
+            ),
+            "1=4,2=1,3=2,4=3"
+        );
+    }
+
+    #[pg_test]
+    fn test_enforce_unique_per_key_separates_rows_of_child_tables() {
+        Spi::run(
+            "CREATE TABLE unique_parent (id int, list_id int, position lexo);
+             CREATE TABLE unique_child () INHERITS (unique_parent);
+             INSERT INTO unique_parent VALUES (1, 7, 'H'), (2, 7, 'K');
+             INSERT INTO unique_child VALUES (3, 7, 'H')",
+        )
+        .unwrap();
+        Spi::run(
+            "SELECT * FROM lexo_enforce_unique_per_key('unique_parent', 'position', 'list_id', fix => true)",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT count(DISTINCT position)::text || '/' || \
+                 string_agg(id::text, ',' ORDER BY position, id) FROM unique_parent"
+            ),
+            "3/1,3,2"
+        );
+    }
+
+    #[pg_test]
+    fn test_descending_rebalance_where_appends_nulls_at_the_bottom() {
+        Spi::run(
+            "CREATE TABLE newest_matching (id int PRIMARY KEY, position lexo);
+             INSERT INTO newest_matching VALUES (1, 'i'), (2, NULL), (3, NULL), (4, 'H')",
+        )
+        .unwrap();
+        Spi::run(
+            "SELECT lexo_rebalance('newest_matching', 'position', NULL, NULL, 'id > 0', \
+             descending => true)",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(id::text, ',' ORDER BY position DESC) FROM newest_matching"
+            ),
+            "1,4,2,3"
+        );
+    }
+
+    #[pg_test]
+    fn test_proportional_rebalance_places_nulls_past_the_other_rows() {
+        Spi::run(
+            "CREATE TABLE kept_gaps (id int PRIMARY KEY, position lexo);
+             INSERT INTO kept_gaps VALUES (1, 'H'), (2, 'V'), (3, NULL), (4, NULL)",
+        )
+        .unwrap();
+        Spi::run(
+            "SELECT lexo_rebalance('kept_gaps', 'position', NULL, NULL, mode => 'proportional')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(id::text || '=' || (position::text > 'V')::text, ',' \
+                 ORDER BY position) FROM kept_gaps"
+            ),
+            "1=false,2=false,3=true,4=true"
+        );
+        assert_eq!(
+            select_text("SELECT position::text FROM kept_gaps WHERE id = 2"),
+            "V"
+        );
+    }
+
+    #[pg_test]
+    fn test_drop_column_recreates_the_other_ordered_views() {
+        Spi::run(
+            "CREATE TABLE two_orders (id int PRIMARY KEY, position lexo, backup lexo);
+             INSERT INTO two_orders VALUES (1, 'B', 'A'), (2, 'A', 'B');
+             SELECT lexo_create_ordered_view('two_orders', 'position');
+             SELECT lexo_create_ordered_view('two_orders', 'backup', 'two_orders_backup');
+             SELECT lexo_drop_column('two_orders', 'position')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT coalesce(to_regclass('two_orders_ordered')::text, 'gone') || ' ' || \
+                 (SELECT string_agg(id || ':' || ordinal, ',' ORDER BY id) FROM two_orders_backup)"
+            ),
+            "gone 1:1,2:2"
+        );
+        assert_eq!(
+            select_text("SELECT string_agg(view_name::text, ',') FROM lexo_ordered_views"),
+            "two_orders_backup"
+        );
+    }
+
+    #[pg_test]
+    fn test_drop_ordered_views_without_a_table_drops_every_view() {
+        Spi::run(
+            "CREATE TABLE first_list (position lexo);
+             CREATE TABLE second_list (position lexo);
+             SELECT lexo_create_ordered_view('first_list', 'position');
+             SELECT lexo_create_ordered_view('second_list', 'position')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT lexo_drop_ordered_views(NULL)::text"),
+            "2"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (to_regclass('first_list_ordered') IS NULL \
+                 AND to_regclass('second_list_ordered') IS NULL \
+                 AND NOT EXISTS (SELECT 1 FROM lexo_ordered_views))::text"
+            ),
+            "true"
+        );
+    }
+
+    #[pg_test]
+    fn test_seq_next_skips_past_positions_appended_otherwise() {
+        Spi::run(
+            "CREATE TABLE overtaken_chat (id int, position lexo);
+             INSERT INTO overtaken_chat VALUES (1, 'H'), (2, 'Z');
+             SELECT lexo_enable_sequence_append('overtaken_chat', 'position');
+             INSERT INTO overtaken_chat VALUES (3, lexo_seq_next('overtaken_chat', 'position'));
+             INSERT INTO overtaken_chat VALUES (4, 'a6000003')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT lexo_seq_next('overtaken_chat', 'position')::text"),
+            "a6000003H"
+        );
+    }
+
+    #[pg_test(error = "lexo_seq_next() cannot append after 'b' in overtaken_chat.position")]
+    fn test_seq_next_refuses_positions_past_its_prefix() {
+        Spi::run(
+            "CREATE TABLE overtaken_chat (id int, position lexo);
+             INSERT INTO overtaken_chat VALUES (1, 'H'), (2, 'Z');
+             SELECT lexo_enable_sequence_append('overtaken_chat', 'position');
+             INSERT INTO overtaken_chat VALUES (3, 'b')",
+        )
+        .unwrap();
+        Spi::run("SELECT lexo_seq_next('overtaken_chat', 'position')").unwrap();
+    }
+
+    #[pg_test]
+    fn test_seq_next_keeps_a_sequence_per_list() {
+        Spi::run(
+            "CREATE TABLE channel_chat (channel_id int, position lexo);
+             INSERT INTO channel_chat VALUES (1, 'H'), (2, 'Z');
+             SELECT lexo_enable_sequence_append('channel_chat', 'position', 'channel_id');
+             INSERT INTO channel_chat VALUES (1, lexo_seq_next('channel_chat', 'position', '1'));
+             INSERT INTO channel_chat VALUES (2, lexo_seq_next('channel_chat', 'position', '2'));
+             INSERT INTO channel_chat VALUES (1, lexo_seq_next('channel_chat', 'position', '1'))",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(channel_id || ':' || position::text, ',' \
+                 ORDER BY channel_id, position) FROM channel_chat"
+            ),
+            "1:H,1:I6000000H,1:I6000001H,2:Z,2:a6000000H"
+        );
+        Spi::run("SELECT lexo_disable_sequence_append('channel_chat', 'position')").unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT count(*)::text FROM pg_class \
+                 WHERE relkind = 'S' AND relname LIKE 'channel\\_chat\\_position\\_lexo\\_seq%'"
+            ),
+            "0"
+        );
+    }
+
+    #[pg_test(
+        error = "sequence append for channel_chat.position is per channel_id: key_value must not be NULL"
+    )]
+    fn test_seq_next_needs_the_list_of_a_per_list_sequence() {
+        Spi::run(
+            "CREATE TABLE channel_chat (channel_id int, position lexo);
+             SELECT lexo_enable_sequence_append('channel_chat', 'position', 'channel_id')",
+        )
+        .unwrap();
+        Spi::run("SELECT lexo_seq_next('channel_chat', 'position')").unwrap();
+    }
+
+    #[pg_test]
+    fn test_import_order_matches_keys_as_values_of_their_type() {
+        Spi::run(
+            "CREATE TABLE imported (id uuid PRIMARY KEY, position lexo);
+             INSERT INTO imported VALUES
+                 ('00000000-0000-0000-0000-00000000000a', 'A'),
+                 ('00000000-0000-0000-0000-00000000000b', 'B')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT lexo_import_order('imported', 'position', 'id', \
+                 '[\"00000000-0000-0000-0000-00000000000B\", \"00000000-0000-0000-0000-00000000000A\"]', \
+                 require_complete => true)::text"
+            ),
+            "2"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(right(id::text, 1), ',' ORDER BY position) FROM imported"
+            ),
+            "b,a"
+        );
+    }
+
+    #[pg_test]
+    fn test_export_order_lists_keys_in_position_order() {
+        Spi::run(
+            "CREATE TABLE exported (id int PRIMARY KEY, list_id int, position lexo);
+             INSERT INTO exported VALUES (1, 1, 'C'), (2, 1, 'A'), (3, 2, 'B'), (4, 1, 'B')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT lexo_export_order('exported', 'position', 'id')::text"),
+            "[2, 3, 4, 1]"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT lexo_export_order('exported', 'position', 'id', 'list_id', '1', \
+                 with_positions => true)::text"
+            ),
+            r#"[{"pk": 2, "position": "A"}, {"pk": 4, "position": "B"}, {"pk": 1, "position": "C"}]"#
+        );
+    }
+
+    #[pg_test]
+    fn test_export_order_round_trips_through_import_order() {
+        Spi::run(
+            "CREATE TABLE exported (id text PRIMARY KEY, position lexo);
+             INSERT INTO exported VALUES ('x', 'z'), ('y', 'H'), ('z', 'A')",
+        )
+        .unwrap();
+        let before = select_text("SELECT lexo_export_order('exported', 'position', 'id')::text");
+        assert_eq!(before, r#"["z", "y", "x"]"#);
+        Spi::run(
+            "SELECT lexo_import_order('exported', 'position', 'id', \
+             lexo_export_order('exported', 'position', 'id'), require_complete => true)",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT lexo_export_order('exported', 'position', 'id')::text"),
+            before
+        );
+    }
+
+    #[pg_test]
+    fn test_verify_order_matches_keys_as_values_of_their_type() {
+        Spi::run(
+            "CREATE TABLE verified (id uuid PRIMARY KEY, position lexo);
+             INSERT INTO verified VALUES
+                 ('00000000-0000-0000-0000-00000000000a', 'B'),
+                 ('00000000-0000-0000-0000-00000000000b', 'A')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT lexo_verify_order('verified', 'position', 'id', \
+                 '[\"00000000-0000-0000-0000-00000000000B\", \"00000000-0000-0000-0000-00000000000A\"]')::text"
+            ),
+            "true"
+        );
+    }
+
+    #[pg_test(
+        error = "matched: expected primary key 1 at index 0, found 2; first offending row: {\"id\":2,\"position\":\"A\"}"
+    )]
+    fn test_assert_matches_reports_the_offending_row() {
+        Spi::run(
+            "CREATE TABLE matched (id int PRIMARY KEY, position lexo);
+             INSERT INTO matched VALUES (1, 'B'), (2, 'A')",
+        )
+        .unwrap();
+        Spi::run("SELECT lexo_assert_matches('matched', 'position', 'id', '[\"01\", 2]')").unwrap();
+    }
+
+    #[pg_test]
+    fn test_assign_rank_gives_peers_distinct_positions() {
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(r::text, ',' ORDER BY r) FROM \
+                 (SELECT lexo_assign_rank() OVER (ORDER BY g / 2) AS r \
+                  FROM generate_series(0, 3) AS g) AS t"
+            ),
+            "H0,H1,H2,H3"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(g || ':' || r, ',' ORDER BY g) FROM \
+                 (SELECT g, lexo_assign_rank() OVER (PARTITION BY g % 2 ORDER BY g \
+                  RANGE BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING) AS r \
+                  FROM generate_series(1, 5) AS g) AS t"
+            ),
+            "1:H0,2:H0,3:H1,4:H1,5:H2"
+        );
+    }
+
+    #[pg_test]
+    fn test_generated_sql_ignores_lookalikes_on_the_search_path() {
+        Spi::run(
+            "CREATE SCHEMA hostile;
+             CREATE FUNCTION hostile.lexo_from_int(bigint, bigint) RETURNS lexo
+                 LANGUAGE sql AS $$ SELECT 'hostile'::lexo $$;
+             CREATE FUNCTION hostile.lexo_ordinal_refresh() RETURNS trigger
+                 LANGUAGE plpgsql AS $$ BEGIN RAISE EXCEPTION 'hostile trigger'; END $$;
+             CREATE TABLE hostile.ranked (id int PRIMARY KEY, position lexo);
+             INSERT INTO hostile.ranked VALUES (1, NULL), (2, NULL);
+             SET LOCAL search_path = hostile, public",
+        )
+        .unwrap();
+        Spi::run("SELECT * FROM lexo_rank_all('ranked', 'position', 'id')").unwrap();
+        assert_eq!(
+            select_text("SELECT count(*)::text FROM ranked WHERE position = 'hostile'"),
+            "0"
+        );
+
+        Spi::run(
+            "SELECT lexo_maintain_ordinal('ranked', 'position', 'ordinal');
+             INSERT INTO ranked SELECT 3, lexo_next('ranked', 'position')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT string_agg(ordinal::text, ',' ORDER BY id) FROM ranked"),
+            "1,2,3"
+        );
+    }
+
+    #[pg_test]
+    fn test_table_names_fold_unquoted_parts() {
+        Spi::run(
+            "CREATE TABLE \"MixedCase\" (id int, position lexo);
+             CREATE TABLE mixedcase (id int, position lexo);
+             INSERT INTO \"MixedCase\" VALUES (1, 'J');
+             INSERT INTO mixedcase VALUES (1, 'S');
+             SET LOCAL lexo.next_cache = off",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT lexo_next('MixedCase', 'position')::text"),
+            "T"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_next('\"MixedCase\"', 'position')::text"),
+            "K"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT lexo_next(current_database() || '.' || current_schema() || '.MixedCase', 'position')::text"
+            ),
+            "T"
+        );
+    }
+
+    #[pg_test(error = "invalid table name 'a.b.c.d': too many dotted names")]
+    fn test_table_names_take_at_most_three_parts() {
+        Spi::run("SELECT lexo_next('a.b.c.d', 'position')").unwrap();
+    }
+
+    #[pg_test]
+    fn test_typmod_applies_to_composite_input() {
+        Spi::run("CREATE TYPE lexo_typmod_row AS (position lexo(4))").unwrap();
+        assert_eq!(
+            select_text("SELECT ('(HzzV)'::lexo_typmod_row).position::text"),
+            "HzzV"
+        );
+    }
+
+    #[pg_test(error = "value too long for type lexo(4)")]
+    fn test_typmod_rejects_long_composite_input() {
+        Spi::run("CREATE TYPE lexo_typmod_row AS (position lexo(4))").unwrap();
+        Spi::run("SELECT '(HzzzzV)'::lexo_typmod_row").unwrap();
+    }
+
+    #[pg_test]
+    fn test_typmod_alter_column_type() {
+        Spi::run(
+            "CREATE TABLE typmod_items (position lexo(8));
+             INSERT INTO typmod_items VALUES ('HzzzzV');
+             ALTER TABLE typmod_items ALTER COLUMN position TYPE lexo(16);
+             ALTER TABLE typmod_items ALTER COLUMN position TYPE lexo(6);
+             ALTER TABLE typmod_items ALTER COLUMN position TYPE lexo;
+             INSERT INTO typmod_items VALUES (repeat('z', 40))",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT pg_catalog.format_type(atttypid, atttypmod) FROM pg_catalog.pg_attribute \
+                 WHERE attrelid = 'typmod_items'::regclass AND attname = 'position'"
+            ),
+            "lexo"
+        );
+    }
+
+    #[pg_test(error = "value too long for type lexo(4)")]
+    fn test_typmod_alter_column_type_rechecks_rows() {
+        Spi::run(
+            "CREATE TABLE typmod_items (position lexo(8));
+             INSERT INTO typmod_items VALUES ('HzzzzV');
+             ALTER TABLE typmod_items ALTER COLUMN position TYPE lexo(4)",
+        )
+        .unwrap();
+    }
+
+    #[pg_test]
+    fn test_binary_io_round_trips() {
+        assert_eq!(
+            select_text("SELECT encode(lexo_send('HzzzzV'), 'escape')"),
+            "HzzzzV"
+        );
+        let buf = pgrx::StringInfo::from("HzzzzV").into_pg();
+        let rank = lexo_recv(
+            pgrx::Internal::from(Some(pg_sys::Datum::from(buf))),
+            pg_sys::Oid::INVALID,
+            -1,
+        );
+        assert_eq!(rank.as_str(), "HzzzzV");
+    }
+
+    #[pg_test(error = "value too long for type lexo(4)")]
+    fn test_binary_receive_applies_typmod() {
+        let buf = pgrx::StringInfo::from("HzzzzV").into_pg();
+        lexo_recv(
+            pgrx::Internal::from(Some(pg_sys::Datum::from(buf))),
+            pg_sys::Oid::INVALID,
+            4,
+        );
+    }
+
+    #[pg_test]
+    fn test_text_variants_return_the_lexo_text_domain() {
+        assert_eq!(
+            select_text("SELECT pg_typeof(lexo_after_text('H'))::text"),
+            "lexo_text"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_rebalance_text(ARRAY['Hzzz', 'H', 'Hz'])::text"),
+            "{pfKfKf,AKfKfK,V}"
+        );
+    }
+
+    #[pg_test]
+    fn test_lexo_next_text_appends_to_lexo_text_column() {
+        Spi::run(
+            "CREATE TABLE text_cards (id serial, position lexo_text);
+             INSERT INTO text_cards (position) VALUES (lexo_next_text('text_cards', 'position', NULL, NULL));
+             INSERT INTO text_cards (position) VALUES (lexo_next_text('text_cards', 'position', NULL, NULL))",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT string_agg(position, ',' ORDER BY id) FROM text_cards"),
+            format!("H,{}", generate_after("H"))
+        );
+    }
+
+    #[pg_test(error = "lexo array must not contain NULL elements (found at index 2)")]
+    fn test_lexo_rebalance_text_rejects_null_elements() {
+        Spi::run("SELECT lexo_rebalance_text(ARRAY['H', NULL])").unwrap();
+    }
+
+    #[pg_test]
+    fn test_claim_next_serializes_concurrent_sessions() {
+        // Each claim has to commit on its own, so they run in two more backends
+        // connected through dblink; the table is created there for them to see.
+        Spi::run(
+            "CREATE EXTENSION IF NOT EXISTS dblink;
+             SELECT dblink_connect(name, format('host=localhost port=%s dbname=%s user=%s',
+                 current_setting('port'), current_database(), current_user))
+             FROM unnest(ARRAY['holder', 'waiter']) AS name;
+             SELECT dblink_exec('holder', 'CREATE TABLE public.claims (id serial, k text, \"k=x\" text, position lexo)');
+             SELECT dblink_exec('holder', 'BEGIN');
+             SELECT dblink_exec('holder', $$INSERT INTO public.claims (k, position)
+                 VALUES ('x=1', lexo_claim_next('public.claims', 'position', 'k', 'x=1'))$$)",
+        )
+        .unwrap();
+
+        // "k" = 'x=1' and "k=x" = '1' are different lists, so this must not wait
+        Spi::run("SELECT dblink_exec('waiter', $$SET lock_timeout = '500ms'$$)").unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT position FROM dblink('waiter',
+                     $$SELECT lexo_claim_next('public.claims', 'position', 'k=x', '1')::text$$)
+                 AS t(position text)"
+            ),
+            "H"
+        );
+
+        // The same list waits for the holder's lock ...
+        Spi::run("SELECT dblink_exec('waiter', 'RESET lock_timeout')").unwrap();
+        let waiter_pid = Spi::get_one::<i32>(
+            "SELECT pid FROM dblink('waiter', 'SELECT pg_backend_pid()') AS t(pid int)",
+        )
+        .unwrap()
+        .unwrap();
+        Spi::run(
+            "SELECT dblink_send_query('waiter',
+                 $$SELECT lexo_claim_next('public.claims', 'position', 'k', 'x=1')::text$$)",
+        )
+        .unwrap();
+        let waiting = (0..200).any(|_| {
+            Spi::run("SELECT pg_sleep(0.05)").unwrap();
+            Spi::get_one::<bool>(&format!(
+                "SELECT EXISTS (SELECT FROM pg_locks
+                 WHERE pid = {} AND locktype = 'advisory' AND NOT granted)",
+                waiter_pid
+            ))
+            .unwrap()
+            .unwrap()
+        });
+        assert!(
+            waiting,
+            "lexo_claim_next() did not wait for the list's lock"
+        );
+
+        // ... then follows the row it committed
+        Spi::run("SELECT dblink_exec('holder', 'COMMIT')").unwrap();
+        let second =
+            select_text("SELECT position FROM dblink_get_result('waiter') AS t(position text)");
+        Spi::run("SELECT * FROM dblink_get_result('waiter') AS t(position text)").unwrap();
+        let first = select_text(
+            "SELECT position FROM dblink('holder', 'SELECT position::text FROM public.claims')
+             AS t(position text)",
+        );
+        assert_eq!((first.as_str(), second.as_str()), ("H", "I"));
+
+        Spi::run(
+            "SELECT dblink_exec('holder', 'DROP TABLE public.claims');
+             SELECT dblink_disconnect(name) FROM unnest(ARRAY['holder', 'waiter']) AS name",
+        )
+        .unwrap();
+    }
+
+    #[pg_test]
+    fn test_at_fraction_inserts_in_the_middle() {
+        create_items();
+        assert_eq!(ordered_ids("items"), "1,3,5,2,4");
+    }
+
+    #[pg_test]
+    fn test_enforce_unique_reports_duplicates_when_checked() {
+        Spi::run(
+            "CREATE TABLE unique_items (id int, position lexo);
+             INSERT INTO unique_items VALUES (1, 'H');
+             SELECT lexo_enforce_unique('unique_items', 'position');
+             INSERT INTO unique_items VALUES (2, 'H')",
+        )
+        .unwrap();
+        let error = error_of("SET CONSTRAINTS ALL IMMEDIATE").unwrap();
+        assert!(error.starts_with("23505"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_attach_validation_rejects_empty_positions() {
+        Spi::run(
+            "CREATE TABLE validated (id int, position lexo);
+             SELECT lexo_attach_validation('validated', 'position')",
+        )
+        .unwrap();
+        let error = error_of("INSERT INTO validated VALUES (1, '')").unwrap();
+        assert!(error.starts_with("23514"), "{}", error);
+
+        Spi::run("SELECT lexo_detach_validation('validated', 'position')").unwrap();
+        assert_eq!(error_of("INSERT INTO validated VALUES (1, '')"), None);
+    }
+
+    #[pg_test]
+    fn test_rebalance_where_clause_leaves_other_rows_alone() {
+        create_items();
+        let kept = select_text("SELECT position::text FROM items WHERE id = 2");
+        Spi::run("SELECT lexo_rebalance('items', 'position', NULL, NULL, 'id <> 2')").unwrap();
+        assert_eq!(
+            select_text("SELECT position::text FROM items WHERE id = 2"),
+            kept
+        );
+        assert_eq!(ordered_ids("items"), "1,3,5,2,4");
+    }
+
+    #[pg_test]
+    fn test_proportional_rebalance_keeps_the_order() {
+        create_items();
+        Spi::run("SELECT lexo_rebalance('items', 'position', NULL, NULL, mode => 'proportional')")
+            .unwrap();
+        assert_eq!(ordered_ids("items"), "1,3,5,2,4");
+    }
+
+    #[pg_test]
+    fn test_rebalance_target_length_sets_every_length() {
+        create_items();
+        Spi::run("SELECT lexo_rebalance('items', 'position', NULL, NULL, target_length => 4)")
+            .unwrap();
+        assert_eq!(ordered_ids("items"), "1,3,5,2,4");
+        assert_eq!(
+            select_text("SELECT count(*)::text FROM items WHERE length(position::text) <> 4"),
+            "0"
+        );
+    }
+
+    #[pg_test]
+    fn test_rebalance_reports_its_progress() {
+        create_items();
+        Spi::run(
+            "SET LOCAL lexo.rebalance_report_every = 2;
+             SET LOCAL lexo.rebalance_batch_size = 2;
+             SET LOCAL log_min_messages = notice",
+        )
+        .unwrap();
+        let logged = logged_by("SELECT lexo_rebalance('items', 'position', NULL, NULL)");
+        assert!(
+            logged
+                .iter()
+                .any(|message| message.contains("lexo_rebalance: 4 of 5 rows done")),
+            "{:?}",
+            logged
+        );
+    }
+
+    #[pg_test]
+    fn test_rebalance_summary_reports_the_run() {
+        create_items();
+        assert_eq!(
+            select_text(
+                "SELECT (rows_updated, rows_skipped, null_count, empty_count, groups_processed, \
+                 max_length_after)::text FROM lexo_rebalance_summary('items', 'position')"
+            ),
+            "(5,0,0,0,1,1)"
+        );
+    }
+
+    #[pg_test]
+    fn test_insert_at_places_rows_next_to_their_neighbours() {
+        create_items();
+        assert_eq!(
+            select_text("SELECT lexo_insert_at('items', 'position', 1)::text"),
+            select_text(
+                "SELECT lexo_between((SELECT position FROM items WHERE id = 1), \
+                 (SELECT position FROM items WHERE id = 3))::text"
+            )
+        );
+        assert_eq!(
+            select_text("SELECT lexo_insert_at('items', 'position', 100)::text"),
+            select_text("SELECT lexo_after((SELECT max(position) FROM items))::text")
+        );
+    }
+
+    #[pg_test]
+    fn test_insertion_budget_is_plausible() {
+        create_items();
+        assert_eq!(
+            select_text(
+                "SELECT (appends > 0 AND prepends > 0 AND worst_case_between <= typical_between)::text \
+                 FROM lexo_insertion_budget('items', 'position')"
+            ),
+            "true"
+        );
+    }
+
+    #[pg_test]
+    fn test_next_returns_empty_default_for_an_empty_list() {
+        create_items();
+        assert_eq!(
+            select_text(
+                "SELECT lexo_next('items', 'position', 'id', '0', empty_default => '1')::text"
+            ),
+            "1"
+        );
+    }
+
+    #[pg_test]
+    fn test_start_for_estimated_size_plans_the_spacing() {
+        assert_eq!(
+            select_text(
+                "SELECT (first_rank::text, rank_length, step = 147)::text \
+                 FROM lexo_start_for_estimated_size(50000)"
+            ),
+            "(FV00,4,t)"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_nth_planned('FV00', 147, 1)::text"),
+            "FV2N"
+        );
+    }
+
+    #[pg_test]
+    fn test_prefix_match_is_an_index_range_scan() {
+        create_items();
+        Spi::run("SET LOCAL enable_seqscan = off").unwrap();
+        let plan = Spi::explain("SELECT * FROM items WHERE position ^@ 'H'")
+            .unwrap()
+            .0
+            .to_string();
+        assert!(
+            plan.contains("(position >= ") && plan.contains("(position < "),
+            "{}",
+            plan
+        );
+        assert_eq!(
+            select_text("SELECT count(*)::text FROM items WHERE position ^@ 'H'"),
+            select_text("SELECT count(*)::text FROM items WHERE left(position::text, 1) = 'H'")
+        );
+    }
+
+    #[pg_test]
+    fn test_verify_order_and_order_divergence() {
+        create_items();
+        assert_eq!(
+            select_text(
+                "SELECT lexo_verify_order('items', 'position', 'id', '[1, 3, 5, 2, 4]')::text"
+            ),
+            "true"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT lexo_verify_order('items', 'position', 'id', '[1, 3, 5, 2]')::text"
+            ),
+            "false"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT index::text FROM lexo_order_divergence('items', 'position', 'id', '[1, 3, 2, 5, 4]')"
+            ),
+            "2"
+        );
+    }
+
+    #[pg_test]
+    fn test_typed_positions_are_trimmed_and_point_at_the_bad_character() {
+        assert_eq!(select_text("SELECT ' Hz '::lexo::text"), "Hz");
+        assert_eq!(
+            select_text("SELECT lexo_after_text(E'\\tH\\n')"),
+            generate_after("H")
+        );
+        let error = error_of("SELECT 'H z'::lexo").unwrap();
+        assert!(error.starts_with("22P02"), "{}", error);
+        assert!(
+            error.contains("\nDETAIL: Invalid character ' ' at byte offset 1."),
+            "{}",
+            error
+        );
+    }
+
+    #[pg_test]
+    fn test_text_overloads_match_the_lexo_functions() {
+        assert_eq!(select_text("SELECT lexo_after('H'::text)::text"), "I");
+        assert_eq!(
+            select_text("SELECT lexo_before('H'::text)::text"),
+            select_text("SELECT lexo_before('H'::lexo)::text")
+        );
+        assert_eq!(
+            select_text("SELECT lexo_between('A'::text, 'Z'::text)::text"),
+            select_text("SELECT lexo_between('A'::lexo, 'Z'::lexo)::text")
+        );
+        assert_eq!(
+            select_text("SELECT lexo_between('A'::text, 'Z'::lexo)::text"),
+            "M"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_between('A'::lexo, 'Z'::text)::text"),
+            "M"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_between('A', 'H'::lexo)::text"),
+            generate_between("A", "H").unwrap()
+        );
+        assert_eq!(
+            select_text("SELECT lexo_between(NULL::text, 'H')::text"),
+            select_text("SELECT lexo_before('H'::lexo)::text")
+        );
+        assert_eq!(
+            select_text("SELECT pg_typeof(lexo_between('A'::text, NULL))::text"),
+            "lexo"
+        );
+        assert_eq!(select_text("SELECT lexo_between_text('A', 'Z')::text"), "M");
+    }
+
+    #[pg_test]
+    fn test_descending_list_appends_rebalances_and_reaches_the_floor() {
+        Spi::run(
+            "CREATE TABLE feed (id serial PRIMARY KEY, position lexo NOT NULL);
+             INSERT INTO feed (position) VALUES (lexo_next_desc('feed', 'position', NULL, NULL));
+             INSERT INTO feed (position) VALUES (lexo_next_desc('feed', 'position', NULL, NULL));
+             INSERT INTO feed (position) VALUES (lexo_next_desc('feed', 'position', NULL, NULL))",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT (position = lexo_first_desc())::text FROM feed WHERE id = 1"),
+            "true"
+        );
+
+        // Two rows sharing a position are separated in storage order
+        Spi::run(
+            "INSERT INTO feed (position) VALUES (lexo_between(
+                 (SELECT position FROM feed WHERE id = 2), (SELECT position FROM feed WHERE id = 1)));
+             INSERT INTO feed (position) SELECT position FROM feed WHERE id = 3;
+             SELECT lexo_rebalance('feed', 'position', NULL, NULL, descending => true)",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT string_agg(id::text, ',' ORDER BY position DESC) FROM feed"),
+            "1,4,2,3,5"
+        );
+
+        Spi::run("UPDATE feed SET position = '00' WHERE id = 5").unwrap();
+        let error = error_of("SELECT lexo_next_desc('feed', 'position', NULL, NULL)").unwrap();
+        assert!(error.starts_with("54000"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_page_after_follows_encoded_cursors() {
+        create_items();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(pk::text, ',' ORDER BY rank) \
+                 FROM lexo_page_after('items', 'position', NULL, 2)"
+            ),
+            "1,3"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(pk::text, ',' ORDER BY rank) \
+                 FROM lexo_page_after('items', 'position', lexo_decode_cursor(lexo_encode_cursor(\
+                 (SELECT rank FROM lexo_page_after('items', 'position', NULL, 2) ORDER BY rank DESC LIMIT 1))), 2)"
+            ),
+            "5,2"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT count(*)::text FROM lexo_page_after('items', 'position', 'zz', 10)"
+            ),
+            "0"
+        );
+        let error = error_of("SELECT lexo_decode_cursor('not-a-cursor')").unwrap();
+        assert!(error.starts_with("22023"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_between_tagged_keeps_writers_apart() {
+        let error = error_of("SELECT lexo_between_tagged('A', 'B')").unwrap();
+        assert!(error.starts_with("22023"), "{}", error);
+
+        Spi::run("SET LOCAL lexo.writer_id = 'e'").unwrap();
+        let first = select_text("SELECT lexo_between_tagged('A', 'B')::text");
+        Spi::run("SET LOCAL lexo.writer_id = 'w'").unwrap();
+        let second = select_text("SELECT lexo_between_tagged('A', 'B')::text");
+        assert!(
+            "A" < first.as_str() && first < second && second.as_str() < "B",
+            "{} {}",
+            first,
+            second
+        );
+        assert!(select_text("SELECT lexo_between_tagged('H', 'H1')::text").as_str() < "H1");
+    }
+
+    #[pg_test]
+    fn test_history_records_changes_and_summarizes_rebalances() {
+        create_items();
+        Spi::run(
+            "SELECT lexo_enable_history('items', 'position', 'id');
+             UPDATE items SET position = lexo_after(position) WHERE id = 1;
+             UPDATE items SET position = position WHERE id = 1",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT count(*)::text FROM lexo_history WHERE pk = '1' AND new_rank > old_rank"
+            ),
+            "1"
+        );
+        assert_eq!(select_text("SELECT count(*)::text FROM lexo_history"), "1");
+
+        Spi::run(
+            "SET LOCAL lexo.history_summarize_rebalance = on;
+             SELECT lexo_rebalance('items', 'position', NULL, NULL)",
+        )
+        .unwrap();
+        assert_eq!(select_text("SELECT count(*)::text FROM lexo_history"), "2");
+        let summary = select_text("SELECT detail FROM lexo_history WHERE pk IS NULL");
+        assert!(
+            summary.starts_with("lexo_rebalance rewrote ") && summary.ends_with(" rows"),
+            "{}",
+            summary
+        );
+
+        assert_eq!(
+            select_text("SELECT lexo_prune_history('-1 day')::text"),
+            "2"
+        );
+    }
+
+    #[pg_test]
+    fn test_simulate_insertions_replays_the_workload() {
+        assert_eq!(
+            select_text(
+                "SELECT min(step)::text FROM lexo_simulate_insertions(1000, 'hotspot') WHERE length > 12"
+            ),
+            "58"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT rank::text FROM lexo_simulate_insertions(2, 'append') WHERE step = 2"
+            ),
+            generate_after("H")
+        );
+        assert_eq!(
+            select_text(
+                "SELECT count(DISTINCT rank)::text \
+                 FROM lexo_simulate_insertions(200, '{\"append\": 1, \"random\": 1}'::jsonb)"
+            ),
+            "200"
+        );
+    }
+
+    #[pg_test]
+    fn test_between_avoiding_skips_taken_positions() {
+        assert_eq!(
+            select_text(
+                "SELECT lexo_between_avoiding('A', 'C', ARRAY['B', 'Z', NULL]::lexo[])::text"
+            ),
+            "BH"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_between_avoiding('A', 'C', NULL)::text"),
+            "B"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (lexo_between_avoiding('A', 'C', ARRAY['B', 'BH']::lexo[]) NOT IN ('B', 'BH'))::text"
+            ),
+            "true"
+        );
+    }
+
+    #[pg_test]
+    fn test_between_seeded_is_deterministic_per_seed() {
+        assert_eq!(
+            select_text("SELECT lexo_between_seeded('A', 'B', 'order-42')::text"),
+            "AVwKt"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (lexo_between_seeded('A', 'A01', 'order-42') BETWEEN 'A' AND 'A01')::text"
+            ),
+            "true"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (lexo_between_seeded(NULL, NULL, 'a') <> lexo_between_seeded(NULL, NULL, 'b'))::text"
+            ),
+            "true"
+        );
+    }
+
+    #[pg_test]
+    fn test_midpoints_bisect_the_gap() {
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(m::text, ',' ORDER BY n) \
+                 FROM (SELECT * FROM lexo_midpoints('A', 'B') WITH ORDINALITY AS s(m, n) \
+                 LIMIT 3) AS s"
+            ),
+            "AH,A8,Ae"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT count(DISTINCT m)::text \
+                 FROM (SELECT * FROM lexo_midpoints(NULL, NULL) LIMIT 500) AS s(m)"
+            ),
+            "500"
+        );
+        assert_eq!(
+            select_text("SELECT count(*)::text FROM lexo_midpoints('A', 'A0')"),
+            "0"
+        );
+        assert_eq!(
+            select_text("SELECT count(*)::text FROM lexo_midpoints('A', 'B', 10)"),
+            "10"
+        );
+    }
+
+    #[pg_test]
+    fn test_smalllexo_behaves_like_lexo() {
+        Spi::run(
+            "CREATE TABLE small_items (id int PRIMARY KEY, position smalllexo NOT NULL UNIQUE);
+             INSERT INTO small_items VALUES (1, 'H'), (2, 'A0'), (3, 'A'), (4, 'zzzzzzzzzz'), (5, '0')",
+        )
+        .unwrap();
+        assert_eq!(ordered_ids("small_items"), "5,3,2,1,4");
+        assert_eq!(
+            select_text("SELECT max(position)::text FROM small_items"),
+            "zzzzzzzzzz"
+        );
+        assert_eq!(
+            select_text("SELECT pg_typeof(lexo_between('A'::smalllexo, 'B'::smalllexo))::text"),
+            "smalllexo"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_between('A'::smalllexo, 'B'::smalllexo)::text"),
+            "AH"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_after('H'::smalllexo)::lexo::text"),
+            generate_after("H")
+        );
+        assert_eq!(select_text("SELECT ' Hz '::smalllexo::text"), "Hz");
+        assert_eq!(
+            select_text("SELECT ('Hz'::smalllexo = 'Hz'::lexo)::text"),
+            "true"
+        );
+
+        let error = error_of("SELECT lexo_after('zzzzzzzzzz'::smalllexo)").unwrap();
+        assert!(error.starts_with("22001"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_brin_index_prunes_block_ranges() {
+        // Positions that grow with insertion order keep each block range narrow
+        Spi::run(
+            "CREATE TABLE events (id int, position lexo NOT NULL);
+             INSERT INTO events SELECT i, lexo_from_int(i, 20000) FROM generate_series(0, 19999) i;
+             CREATE INDEX events_position_brin ON events USING brin (position) WITH (pages_per_range = 4);
+             ANALYZE events;
+             SET LOCAL enable_seqscan = off",
+        )
+        .unwrap();
+        let range = "position BETWEEN lexo_from_int(5000, 20000) AND lexo_from_int(5100, 20000)";
+        let plan = Spi::explain(&format!("SELECT * FROM events WHERE {}", range))
+            .unwrap()
+            .0
+            .to_string();
+        assert!(
+            plan.contains("\"Bitmap Index Scan\"") && plan.contains("events_position_brin"),
+            "{}",
+            plan
+        );
+        assert_eq!(
+            select_text(&format!(
+                "SELECT count(*)::text FROM events WHERE {}",
+                range
+            )),
+            "101"
+        );
+    }
+
+    #[pg_test]
+    fn test_analyze_builds_a_histogram_for_range_estimates() {
+        Spi::run(
+            "CREATE TABLE analyzed (id int, position lexo NOT NULL);
+             INSERT INTO analyzed SELECT i, lexo_from_int(i, 10000) FROM generate_series(0, 9999) i;
+             ANALYZE analyzed",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT (histogram_bounds IS NOT NULL)::text FROM pg_stats \
+                 WHERE tablename = 'analyzed' AND attname = 'position'"
+            ),
+            "true"
+        );
+
+        // A tenth of the rows match; without a histogram the planner guesses a third
+        let plan =
+            Spi::explain("SELECT * FROM analyzed WHERE position < lexo_from_int(1000, 10000)")
+                .unwrap()
+                .0;
+        let rows = plan[0]["Plan"]["Plan Rows"].as_f64().unwrap();
+        assert!((500.0..2000.0).contains(&rows), "{}", plan);
+    }
+
+    #[pg_test]
+    fn test_create_index_reuses_the_list_index() {
+        Spi::run("CREATE TABLE indexed_items (list_id int, position lexo, label lexo_text)")
+            .unwrap();
+        let created = select_text(
+            "SELECT lexo_create_index('indexed_items', 'position', ARRAY['list_id'], true)",
+        );
+        assert_eq!(
+            select_text("SELECT lexo_create_index('indexed_items', 'position', ARRAY['list_id'])"),
+            created
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (lexo_create_index_sql('indexed_items', 'position', ARRAY['list_id']) IS NULL)::text"
+            ),
+            "true"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT count(*)::text FROM pg_index WHERE indrelid = 'indexed_items'::regclass"
+            ),
+            "1"
+        );
+
+        assert_eq!(
+            select_text(
+                "SELECT lexo_create_index('indexed_items', 'label', index_name => 'indexed_items_label')"
+            ),
+            "indexed_items_label"
+        );
+        assert!(
+            select_text("SELECT pg_get_indexdef('indexed_items_label'::regclass)")
+                .ends_with("(label COLLATE \"C\")")
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (lexo_create_index_sql('indexed_items', 'position', ARRAY['list_id'], true, 'x') \
+                 IS NULL)::text"
+            ),
+            "true"
+        );
+    }
+
+    #[pg_test]
+    fn test_ordered_view_numbers_rows_within_each_list() {
+        Spi::run(
+            "CREATE TABLE viewed_items (list_id int, position lexo);
+             INSERT INTO viewed_items VALUES (1, 'B'), (1, 'A'), (2, 'H'), (1, 'AH')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT lexo_create_ordered_view('viewed_items', 'position', key_column_name => 'list_id')"
+            ),
+            "viewed_items_ordered"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(ordinal::text, ',' ORDER BY list_id, position) FROM viewed_items_ordered"
+            ),
+            "1,2,3,1"
+        );
+        assert_eq!(
+            select_text("SELECT ordinal::text FROM viewed_items_ordered WHERE position = 'AH'"),
+            "2"
+        );
+
+        assert_eq!(
+            select_text("SELECT lexo_drop_ordered_views('viewed_items')::text"),
+            "1"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (to_regclass('viewed_items_ordered') IS NULL \
+                 AND NOT EXISTS (SELECT 1 FROM lexo_ordered_views))::text"
+            ),
+            "true"
+        );
+    }
+
+    #[pg_test]
+    fn test_maintained_ordinal_follows_inserts_moves_and_deletes() {
+        Spi::run(
+            "CREATE TABLE numbered_items (id int PRIMARY KEY, list_id int, position lexo);
+             INSERT INTO numbered_items VALUES (1, 1, 'B'), (2, 1, 'A'), (3, 2, 'H');
+             SELECT lexo_maintain_ordinal('numbered_items', 'position', 'display_order', 'list_id');
+             INSERT INTO numbered_items VALUES (4, 1, 'AH');
+             UPDATE numbered_items SET position = 'C' WHERE id = 2;
+             DELETE FROM numbered_items WHERE id = 1;
+             UPDATE numbered_items SET list_id = 2, position = 'A' WHERE id = 4",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(display_order::text, ',' ORDER BY id) FROM numbered_items"
+            ),
+            "1,2,1"
+        );
+
+        Spi::run(
+            "SELECT lexo_detach_ordinal('numbered_items', 'display_order');
+             INSERT INTO numbered_items VALUES (5, 1, 'D')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT (display_order IS NULL)::text FROM numbered_items WHERE id = 5"),
+            "true"
+        );
+    }
+
+    #[pg_test]
+    fn test_enforce_unique_per_key_reports_fixes_and_indexes() {
+        Spi::run(
+            "CREATE TABLE keyed_items (id int PRIMARY KEY, list_id int, position lexo);
+             INSERT INTO keyed_items VALUES (1, 1, 'H'), (2, 1, 'H'), (3, 1, 'H'), (4, 1, 'I'), (5, 2, 'H')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(key_value || ':' || position || ':' || duplicates, ',') \
+                 FROM lexo_enforce_unique_per_key('keyed_items', 'position', 'list_id')"
+            ),
+            "1:H:3"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT count(*)::text FROM pg_index \
+                 WHERE indrelid = 'keyed_items'::regclass AND indisunique AND indnatts > 1"
+            ),
+            "0"
+        );
+
+        Spi::run(
+            "SELECT lexo_enforce_unique_per_key('keyed_items', 'position', 'list_id', fix => true)",
+        )
+        .unwrap();
+        assert_eq!(ordered_ids("keyed_items WHERE list_id = 1"), "1,2,3,4");
+        assert_eq!(
+            select_text("SELECT count(DISTINCT position)::text FROM keyed_items WHERE list_id = 1"),
+            "4"
+        );
+        assert_eq!(
+            select_text("SELECT position::text FROM keyed_items WHERE id = 5"),
+            "H"
+        );
+
+        let error = error_of("INSERT INTO keyed_items VALUES (6, 1, 'I')").unwrap();
+        assert!(error.starts_with("23505"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_or_first_variants_treat_null_as_an_empty_list() {
+        assert_eq!(
+            select_text("SELECT (lexo_after(NULL::lexo) IS NULL)::text"),
+            "true"
+        );
+        assert_eq!(select_text("SELECT lexo_after_or_first(NULL)::text"), "H");
+        assert_eq!(select_text("SELECT lexo_before_or_first(NULL)::text"), "H");
+        assert_eq!(
+            select_text("SELECT lexo_after_or_first('H')::text"),
+            generate_after("H")
+        );
+        assert_eq!(
+            select_text("SELECT lexo_before_or_first('H')::text"),
+            generate_before("H").unwrap()
+        );
+    }
+
+    #[pg_test]
+    fn test_seq_next_appends_after_rows_and_each_other() {
+        Spi::run(
+            "CREATE TABLE chat (channel_id int, position lexo);
+             INSERT INTO chat VALUES (1, 'H'), (2, 'Z');
+             SELECT lexo_enable_sequence_append('chat', 'position')",
+        )
+        .unwrap();
+        let first = select_text("SELECT lexo_seq_next('chat', 'position')::text");
+        assert_eq!(first, "a6000000H");
+        Spi::run(&format!(
+            "INSERT INTO chat VALUES (1, '{}'), (2, lexo_seq_next('chat', 'position')),
+                 (1, lexo_seq_next('chat', 'position'))",
+            first
+        ))
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT count(DISTINCT position)::text FROM chat"),
+            "5"
+        );
+        assert_eq!(
+            select_text("SELECT max(position)::text FROM chat WHERE channel_id = 1"),
+            "a6000002H"
+        );
+        assert!(generate_after(&first).as_str() < "a6000001H");
+        assert_eq!(
+            select_text(
+                "SELECT lexo_is_between(lexo_between('a6000000H', 'a6000001H'), 'a6000000H', 'a6000001H')::text"
+            ),
+            "true"
+        );
+
+        Spi::run("SELECT lexo_disable_sequence_append('chat', 'position')").unwrap();
+        assert_eq!(
+            select_text("SELECT (to_regclass('chat_position_lexo_seq') IS NULL)::text"),
+            "true"
+        );
+    }
+
+    #[pg_test]
+    fn test_table_helpers_ignore_objects_earlier_on_the_search_path() {
+        Spi::run(
+            "CREATE SCHEMA evil;
+             CREATE FUNCTION evil.boom(state text, value lexo) RETURNS text
+                 LANGUAGE plpgsql AS $$ BEGIN RAISE EXCEPTION 'hijacked'; END $$;
+             CREATE AGGREGATE evil.max(lexo) (SFUNC = evil.boom, STYPE = text);
+             CREATE AGGREGATE evil.min(lexo) (SFUNC = evil.boom, STYPE = text);
+             CREATE DOMAIN evil.lexo AS text CHECK (evil.boom(NULL, NULL) IS NULL);
+             CREATE TABLE hijack (id int, position lexo);
+             INSERT INTO hijack VALUES (1, 'H'), (2, 'Hzzz'), (3, 'I');
+             SET LOCAL search_path = evil, tests, public",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT lexo_next('hijack', 'position', NULL, NULL)::text"),
+            "J"
+        );
+        assert!(
+            select_text("SELECT lexo_next_desc('hijack', 'position', NULL, NULL)::text").as_str()
+                < "H"
+        );
+        Spi::run(
+            "SELECT lexo_rebalance('hijack', 'position', NULL, NULL);
+             SELECT lexo_rebalance_summary('hijack', 'position');
+             SELECT lexo_add_column('hijack', 'other_position')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT current_setting('search_path')"),
+            "evil, tests, public"
+        );
+
+        Spi::run("SET LOCAL search_path = tests, public").unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT format_type(atttypid, NULL) FROM pg_attribute \
+                 WHERE attrelid = 'hijack'::regclass AND attname = 'other_position'"
+            ),
+            "lexo"
+        );
+        assert_eq!(ordered_ids("hijack"), "1,2,3");
+    }
+
+    /// A table the role `lexo_outsider` may read but not otherwise change.
+    fn create_guarded() {
+        Spi::run(
+            "CREATE ROLE lexo_outsider NOLOGIN;
+             CREATE TABLE guarded (id int, position lexo);
+             INSERT INTO guarded VALUES (1, 'H'), (2, 'Hzzz'), (3, 'I');
+             GRANT USAGE ON SCHEMA tests TO lexo_outsider;
+             GRANT SELECT ON guarded TO lexo_outsider",
+        )
+        .unwrap();
+    }
+
+    #[pg_test(error = "permission denied for table guarded")]
+    fn test_rebalance_requires_update_on_the_table() {
+        create_guarded();
+        Spi::run(
+            "SET LOCAL ROLE lexo_outsider;
+             SELECT lexo_rebalance('guarded', 'position', NULL, NULL)",
+        )
+        .unwrap();
+    }
+
+    #[pg_test(error = "permission denied for table guarded")]
+    fn test_rebalance_summary_requires_update_on_the_table() {
+        create_guarded();
+        Spi::run(
+            "SET LOCAL ROLE lexo_outsider;
+             SELECT lexo_rebalance_summary('guarded', 'position')",
+        )
+        .unwrap();
+    }
+
+    #[pg_test(error = "must be owner of table guarded")]
+    fn test_add_column_requires_the_table_owner() {
+        create_guarded();
+        Spi::run(
+            "GRANT UPDATE ON guarded TO lexo_outsider;
+             SET LOCAL ROLE lexo_outsider;
+             SELECT lexo_add_column('guarded', 'other_position')",
+        )
+        .unwrap();
+    }
+
+    #[pg_test(error = "relation \"itemz\" does not exist")]
+    fn test_missing_table_is_reported_by_name() {
+        Spi::run("SELECT lexo_next('itemz', 'position', NULL, NULL)").unwrap();
+    }
+
+    #[pg_test(error = "column \"posiiton\" of relation \"items\" does not exist")]
+    fn test_missing_column_is_reported_by_name() {
+        create_items();
+        Spi::run("SELECT lexo_rebalance('items', 'posiiton', NULL, NULL)").unwrap();
+    }
+
+    #[pg_test]
+    fn test_missing_columns_are_undefined_columns() {
+        create_items();
+        Spi::run("CREATE TABLE labels (id int, colour text)").unwrap();
+        let error = error_of("SELECT lexo_next('labels', 'position', NULL, NULL)").unwrap();
+        assert!(error.starts_with("42703"), "{}", error);
+        let error = error_of("SELECT lexo_next('items', 'position', 'colour', 'red')").unwrap();
+        assert!(error.starts_with("42703"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_missing_table_hints_at_a_schema_off_the_search_path() {
+        Spi::run(
+            "CREATE SCHEMA app;
+             CREATE TABLE app.tickets (id int, position lexo)",
+        )
+        .unwrap();
+        let error = error_of("SELECT lexo_next('tickets', 'position', NULL, NULL)").unwrap();
+        assert!(error.starts_with("42P01"), "{}", error);
+        assert!(
+            error.contains("\nHINT: Did you mean 'app.tickets'?"),
+            "{}",
+            error
+        );
+        assert_eq!(
+            select_text("SELECT lexo_next('app.tickets', 'position', NULL, NULL)::text"),
+            "H"
+        );
+    }
+
+    #[pg_test(
+        error = "column \"id\" of relation \"mixed\" is of type integer, not a position type"
+    )]
+    fn test_rebalance_rejects_an_integer_column() {
+        Spi::run(
+            "CREATE TABLE mixed (id int, position lexo);
+             SELECT lexo_rebalance('mixed', 'id', NULL, NULL)",
+        )
+        .unwrap();
+    }
+
+    #[pg_test]
+    fn test_rebalance_rewrites_text_columns_on_request() {
+        Spi::run(
+            "CREATE TABLE mixed (id int, position lexo, plain varchar(20), typed lexo_text);
+             INSERT INTO mixed VALUES (1, 'H', 'H', 'H'), (2, 'Hzzz', 'Hzzz', 'Hzzz'), (3, 'I', 'I', 'I')",
+        )
+        .unwrap();
+        let error = error_of("SELECT lexo_rebalance_summary('mixed', 'plain')").unwrap();
+        assert!(error.starts_with("42804"), "{}", error);
+
+        Spi::run(
+            "SELECT lexo_rebalance('mixed', 'plain', NULL, NULL, allow_text => true);
+             SELECT lexo_rebalance('mixed', 'typed', NULL, NULL)",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT string_agg(id::text, ',' ORDER BY plain COLLATE \"C\") FROM mixed"),
+            "1,2,3"
+        );
+        assert_eq!(
+            select_text("SELECT string_agg(id::text, ',' ORDER BY typed COLLATE \"C\") FROM mixed"),
+            "1,2,3"
+        );
+        assert_eq!(
+            select_text("SELECT count(*)::text FROM mixed WHERE length(plain) > 1"),
+            "0"
+        );
+    }
+
+    #[pg_test]
+    fn test_table_names_split_on_dots_outside_quotes() {
+        Spi::run(
+            "CREATE SCHEMA \"my.schema\";
+             CREATE TABLE \"my.schema\".\"weird.name\" (id int, position lexo);
+             CREATE TABLE \"say \"\"hi\"\"\" (id int, position lexo);
+             INSERT INTO \"my.schema\".\"weird.name\" VALUES (1, 'H');
+             INSERT INTO \"say \"\"hi\"\"\" VALUES (1, 'I')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT lexo_next('\"my.schema\".\"weird.name\"', 'position')::text"),
+            "I"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_next('\"say \"\"hi\"\"\"', 'position')::text"),
+            "J"
+        );
+        Spi::run("SELECT lexo_rebalance('\"my.schema\".\"weird.name\"', 'position', NULL, NULL)")
+            .unwrap();
+    }
+
+    #[pg_test]
+    fn test_lexo_and_text_variants_agree_on_null_and_empty_arguments() {
+        let bounds = ["NULL", "''", "'A'", "'Z'"];
+        for before in bounds {
+            for after in bounds {
+                let expected = outcome(&format!(
+                    "SELECT lexo_between({}::lexo, {}::lexo)::text",
+                    before, after
+                ));
+                assert_eq!(
+                    outcome(&format!(
+                        "SELECT lexo_between({}::text, {}::text)::text",
+                        before, after
+                    )),
+                    expected,
+                    "lexo_between({}, {})",
+                    before,
+                    after
+                );
+                assert_eq!(
+                    outcome(&format!("SELECT lexo_between_text({}, {})", before, after)),
+                    expected,
+                    "lexo_between_text({}, {})",
+                    before,
+                    after
+                );
+                if before == "''" || after == "''" {
+                    assert_eq!(expected.as_deref(), Some("error 22023"));
+                }
+            }
+
+            for function in ["after", "before"] {
+                let expected =
+                    outcome(&format!("SELECT lexo_{}({}::lexo)::text", function, before));
+                assert_eq!(
+                    outcome(&format!("SELECT lexo_{}_text({})", function, before)),
+                    expected,
+                    "lexo_{}({})",
+                    function,
+                    before
+                );
+                if before == "''" {
+                    assert_eq!(expected.as_deref(), Some("error 22023"));
+                }
+            }
+
+            let cast = outcome(&format!("SELECT {}::lexo_text::text", before));
+            if before == "''" {
+                assert_eq!(cast.as_deref(), Some("error 23514"), "''::lexo_text");
+            } else {
+                assert_eq!(cast, outcome(&format!("SELECT {}::text", before)));
+            }
+        }
+    }
+
+    #[pg_test]
+    fn test_advance_and_retreat_match_repeated_steps() {
+        for start in ["H", "Hz", "zz", "01"] {
+            let mut after_rank = start.to_string();
+            let mut before_rank = start.to_string();
+            for steps in 0..=130 {
+                assert_eq!(
+                    select_text(&format!(
+                        "SELECT lexo_advance('{}', {})::text",
+                        start, steps
+                    )),
+                    after_rank
+                );
+                assert_eq!(
+                    select_text(&format!(
+                        "SELECT lexo_retreat('{}', {})::text",
+                        start, steps
+                    )),
+                    before_rank
+                );
+                after_rank = generate_after(&after_rank);
+                before_rank = generate_before(&before_rank).unwrap();
+            }
+        }
+        assert_eq!(
+            select_text(
+                "SELECT count(*)::text FROM pg_proc \
+                 WHERE proname IN ('lexo_advance', 'lexo_retreat') AND provolatile = 'i'"
+            ),
+            "2"
+        );
+        let error = error_of("SELECT lexo_retreat('00', 1)").unwrap();
+        assert!(error.starts_with("22023"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_assign_rank_numbers_each_partition_in_window_order() {
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(format('%s:%s', k, r), ',' ORDER BY k, g) \
+                 FROM (SELECT g % 2 AS k, g, \
+                              lexo_assign_rank() OVER (PARTITION BY g % 2 ORDER BY g) AS r \
+                       FROM generate_series(1, 5) AS g) AS t"
+            ),
+            "0:H0,0:H1,1:H0,1:H1,1:H2"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT count(*)::text FROM ( \
+                     SELECT r, lag(r) OVER (ORDER BY g) AS prev \
+                     FROM (SELECT g, lexo_assign_rank() OVER (ORDER BY g) AS r \
+                           FROM generate_series(1, 5000) AS g) AS t) AS p \
+                 WHERE r <= prev"
+            ),
+            "0"
+        );
+    }
+
+    #[pg_test]
+    fn test_rebalance_array_assigns_what_rebalance_assigns() {
+        Spi::run(
+            "CREATE TABLE rebalance_array_check (id int PRIMARY KEY, position lexo);
+             INSERT INTO rebalance_array_check
+                 VALUES (1, 'Hzzz'), (2, 'H'), (3, 'Hz'), (4, '01'), (5, 'zzzz')",
+        )
+        .unwrap();
+        let ranks =
+            select_text("SELECT array_agg(position ORDER BY id)::text FROM rebalance_array_check");
+        Spi::run("SELECT lexo_rebalance('rebalance_array_check', 'position', NULL, NULL)").unwrap();
+        assert_eq!(
+            select_text(&format!(
+                "SELECT lexo_rebalance_array({}::lexo[])::text",
+                quote_literal(&ranks)
+            )),
+            select_text("SELECT array_agg(position ORDER BY id)::text FROM rebalance_array_check")
+        );
+        assert_eq!(
+            select_text("SELECT lexo_rebalance_array('{}'::lexo[])::text"),
+            "{}"
+        );
+    }
+
+    #[pg_test(error = "lexo array must not contain NULL elements (found at index 2)")]
+    fn test_rebalance_array_rejects_null_elements() {
+        Spi::run("SELECT lexo_rebalance_array(ARRAY['H', NULL]::lexo[])").unwrap();
+    }
+
+    #[pg_test]
+    fn test_rebalance_jsonb_only_rewrites_the_rank_field() {
+        Spi::run(
+            "CREATE TABLE docs (doc jsonb);
+             INSERT INTO docs VALUES ('[{\"id\": 1, \"rank\": \"Hz\", \"meta\": {\"rank\": \"x\", \"tags\": [1, 2]}},
+                                       {\"id\": 2, \"rank\": \"H\"}, {\"id\": 3}, {\"id\": 4, \"rank\": \"!\"}]')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(e->>'rank', ',' ORDER BY n) \
+                 FROM docs, jsonb_array_elements(lexo_rebalance_jsonb(doc)) WITH ORDINALITY AS t(e, n)"
+            ),
+            select_text(
+                "SELECT string_agg(r::text, ',' ORDER BY n) \
+                 FROM unnest(lexo_rebalance_array(ARRAY['Hz', 'H', 'y', 'z']::lexo[])) WITH ORDINALITY AS t(r, n)"
+            )
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (SELECT jsonb_agg(e - 'rank' ORDER BY n) \
+                         FROM jsonb_array_elements(lexo_rebalance_jsonb(doc)) WITH ORDINALITY AS t(e, n)) \
+                        = (SELECT jsonb_agg(e - 'rank' ORDER BY n) \
+                           FROM jsonb_array_elements(doc) WITH ORDINALITY AS t(e, n)) \
+                 FROM docs"
+            ),
+            "true"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (lexo_rebalance_jsonb(lexo_rebalance_jsonb(doc)) = lexo_rebalance_jsonb(doc))::text \
+                 FROM docs"
+            ),
+            "true"
+        );
+
+        let error = error_of("SELECT lexo_rebalance_jsonb('[{\"rank\": \"H\"}, 1]')").unwrap();
+        assert!(error.contains("element 1"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_transcode_keeps_foreign_ranks_in_order() {
+        assert_eq!(
+            select_text(
+                "WITH foreign_ranks AS ( \
+                     SELECT r, row_number() OVER (ORDER BY r COLLATE \"C\") AS n \
+                     FROM (SELECT substr(a, i, 1) || substr(a, j, 1) AS r \
+                           FROM (SELECT '-0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ_abcdefghijklmnopqrstuvwxyz' AS a) AS s, \
+                                generate_series(1, 64) AS i, generate_series(0, 64) AS j) AS t), \
+                 transcoded AS ( \
+                     SELECT array_agg(lexo_transcode(r, \
+                         '-0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ_abcdefghijklmnopqrstuvwxyz') ORDER BY n) AS ranks \
+                     FROM foreign_ranks) \
+                 SELECT (lexo_array_is_sorted(ranks) \
+                         AND (SELECT count(DISTINCT r) FROM unnest(ranks) AS r) \
+                             = (SELECT count(*) FROM foreign_ranks))::text \
+                 FROM transcoded"
+            ),
+            "true"
+        );
+        let error = error_of("SELECT lexo_transcode('a', 'abca')").unwrap();
+        assert!(error.starts_with("22023"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_next_returns_increasing_positions_within_a_transaction() {
+        Spi::run(
+            "CREATE TABLE appended (id serial PRIMARY KEY, list int, position lexo);
+             INSERT INTO appended (list, position) VALUES (1, 'H');
+             INSERT INTO appended (list, position)
+             SELECT 1, lexo_next('appended', 'position', 'list', '1') FROM generate_series(1, 5);
+             INSERT INTO appended (list, position) SELECT 2, lexo_next('appended', 'position', 'list', '2');
+             INSERT INTO appended (list, position) SELECT 2, lexo_next('appended', 'position', 'list', '2');
+             SELECT lexo_next('appended', 'position', 'list', '2');
+             INSERT INTO appended (list, position) SELECT 2, lexo_next('appended', 'position', 'list', '2')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(position::text, ',' ORDER BY id) FROM appended WHERE list = 1"
+            ),
+            "H,I,J,K,L,M"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(position::text, ',' ORDER BY id) FROM appended WHERE list = 2"
+            ),
+            "H,I,K"
+        );
+
+        Spi::run("SET LOCAL lexo.next_cache = off").unwrap();
+        assert_eq!(
+            select_text("SELECT lexo_next('appended', 'position', 'list', '2')::text"),
+            "L"
+        );
+    }
+
+    #[pg_test]
+    fn test_next_forgets_positions_from_a_rolled_back_subtransaction() {
+        // Each BEGIN ... EXCEPTION block runs in a subtransaction, like a savepoint
+        Spi::run(
+            "CREATE TABLE saved (id serial PRIMARY KEY, position lexo);
+             INSERT INTO saved (position) VALUES ('H');
+             DO $$
+             BEGIN
+                 INSERT INTO saved (position) VALUES (lexo_next('saved', 'position', NULL, NULL));
+                 BEGIN
+                     INSERT INTO saved (position) VALUES (lexo_next('saved', 'position', NULL, NULL));
+                     INSERT INTO saved (position) VALUES (lexo_next('saved', 'position', NULL, NULL));
+                     RAISE EXCEPTION 'roll back to the savepoint';
+                 EXCEPTION WHEN raise_exception THEN
+                     NULL;
+                 END;
+                 INSERT INTO saved (position) VALUES (lexo_next('saved', 'position', NULL, NULL));
+             END
+             $$",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text("SELECT string_agg(position::text, ',' ORDER BY id) FROM saved"),
+            "H,I,J"
+        );
+    }
+
+    #[pg_test]
+    fn test_include_nulls_decides_what_happens_to_null_positions() {
+        Spi::run(
+            "CREATE TABLE half_ranked (id int PRIMARY KEY, position lexo);
+             INSERT INTO half_ranked VALUES (5, NULL), (1, 'Hz'), (4, NULL), (2, 'H'), (3, NULL)",
+        )
+        .unwrap();
+        let error = error_of(
+            "SELECT lexo_rebalance('half_ranked', 'position', NULL, NULL, include_nulls => 'error')",
+        )
+        .unwrap();
+        assert!(error.starts_with("22004"), "{}", error);
+
+        assert_eq!(
+            select_text(
+                "SELECT lexo_rebalance('half_ranked', 'position', NULL, NULL, include_nulls => 'skip')::text"
+            ),
+            "2"
+        );
+        assert_eq!(
+            select_text("SELECT count(*)::text FROM half_ranked WHERE position IS NULL"),
+            "3"
+        );
+        assert_eq!(ordered_ids("half_ranked WHERE position IS NOT NULL"), "2,1");
+
+        assert_eq!(
+            select_text(
+                "SELECT (null_count, rows_updated, rows_skipped)::text \
+                 FROM lexo_rebalance_summary('half_ranked', 'position')"
+            ),
+            "(3,5,0)"
+        );
+        assert_eq!(ordered_ids("half_ranked"), "2,1,3,4,5");
+
+        Spi::run(
+            "SELECT lexo_rebalance('half_ranked', 'position', NULL, NULL, include_nulls => 'error')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT null_count::text FROM lexo_rebalance_summary('half_ranked', 'position')"
+            ),
+            "0"
+        );
+
+        let error = error_of(
+            "SELECT lexo_rebalance('half_ranked', 'position', NULL, NULL, include_nulls => 'first')",
+        )
+        .unwrap();
+        assert!(error.contains("include_nulls"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_empty_as_decides_where_empty_positions_go() {
+        Spi::run(
+            "CREATE TABLE empty_ranked (id int PRIMARY KEY, position lexo);
+             INSERT INTO empty_ranked VALUES (1, 'H'), (2, ''), (3, 'I'), (4, NULL), (5, '')",
+        )
+        .unwrap();
+        let error = error_of(
+            "SELECT lexo_rebalance('empty_ranked', 'position', NULL, NULL, empty_as => 'error')",
+        )
+        .unwrap();
+        assert!(error.starts_with("22023"), "{}", error);
+
+        assert_eq!(
+            select_text(
+                "SELECT (null_count, empty_count)::text \
+                 FROM lexo_rebalance_summary('empty_ranked', 'position', empty_as => 'append')"
+            ),
+            "(1,2)"
+        );
+        assert_eq!(ordered_ids("empty_ranked"), "1,3,2,4,5");
+
+        // By default empty positions keep their place at the front of the list
+        Spi::run(
+            "UPDATE empty_ranked SET position = '' WHERE id = 5;
+             UPDATE empty_ranked SET position = NULL WHERE id = 1;
+             SELECT lexo_rebalance('empty_ranked', 'position', NULL, NULL, include_nulls => 'skip')",
+        )
+        .unwrap();
+        assert_eq!(
+            ordered_ids("empty_ranked WHERE position IS NOT NULL"),
+            "5,3,2,4"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT count(*)::text FROM empty_ranked \
+                 WHERE position::text = '' OR (id = 1 AND position IS NOT NULL)"
+            ),
+            "0"
+        );
+        Spi::run(
+            "SELECT lexo_rebalance('empty_ranked', 'position', NULL, NULL, \
+             include_nulls => 'skip', empty_as => 'error')",
+        )
+        .unwrap();
+    }
+
+    #[pg_test]
+    fn test_rebalance_if_needed_only_rewrites_long_lists() {
+        Spi::run(
+            "CREATE TABLE scheduled_lists (id int PRIMARY KEY, list int, position lexo);
+             INSERT INTO scheduled_lists VALUES
+                 (1, 1, 'H'), (2, 1, 'HV'), (3, 2, 'H'), (4, 2, 'HVVVVVVVVVVVVV'), (5, NULL, 'HVVVVVVVVVVVVVV')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT lexo_rebalance_if_needed('scheduled_lists', 'position', 'list')::text"
+            ),
+            "2"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(position::text, ',' ORDER BY id) FROM scheduled_lists WHERE id IN (2, 5)"
+            ),
+            "HV,HVVVVVVVVVVVVVV"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (max(length(position::text)) <= 12)::text FROM scheduled_lists WHERE list = 2"
+            ),
+            "true"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT lexo_rebalance_if_needed('scheduled_lists', 'position', 'list')::text"
+            ),
+            "0"
+        );
+    }
+
+    #[pg_test]
+    fn test_schedule_rebalance_needs_pg_cron() {
+        Spi::run("CREATE TABLE scheduled_lists (id int PRIMARY KEY, list int, position lexo)")
+            .unwrap();
+        let error = error_of(
+            "SELECT lexo_schedule_rebalance('scheduled_lists', 'position', key_column_name => 'list')",
+        )
+        .unwrap();
+        assert!(
+            error.starts_with("55000") && error.contains("pg_cron"),
+            "{}",
+            error
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (lexo_unschedule_rebalance('scheduled_lists', 'position') \
+                 OR EXISTS (SELECT 1 FROM lexo_schedules()))::text"
+            ),
+            "false"
+        );
+    }
+
+    #[pg_test]
+    fn test_asserts_raise_assert_failure_with_the_offending_row() {
+        Spi::run(
+            "CREATE TABLE asserted (id int PRIMARY KEY, list int, position lexo);
+             INSERT INTO asserted VALUES (1, 1, 'H'), (2, 1, 'V'), (3, 2, 'H'), (4, 2, 'HVVVVV');
+             SELECT lexo_assert_ordered('asserted', 'position', 'list');
+             SELECT lexo_assert_max_length('asserted', 'position', 6);
+             SELECT lexo_assert_matches('asserted', 'position', 'id', '[3, 4]', 'list', '2')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT (lexo_verify_ordered('asserted', 'position') \
+                 OR lexo_verify_max_length('asserted', 'position', 5))::text"
+            ),
+            "false"
+        );
+
+        for (statement, expected) in [
+            (
+                "SELECT lexo_assert_ordered('asserted', 'position')",
+                ["position 'H' is shared", "\"id\":1,"],
+            ),
+            (
+                "SELECT lexo_assert_max_length('asserted', 'position', 5)",
+                ["1 positions are longer than 5", "\"id\":4,"],
+            ),
+            (
+                "SELECT lexo_assert_matches('asserted', 'position', 'id', '[4, 3]', 'list', '2')",
+                ["expected primary key 4 at index 0, found 3", "\"id\":3,"],
+            ),
+        ] {
+            let error = error_of(statement).unwrap();
+            assert!(error.starts_with("P0004"), "{}", error);
+            let message = error.lines().next().unwrap();
+            let at = message.find(expected[0]).expect(&error);
+            assert!(message[at..].contains(expected[1]), "{}", error);
+        }
+
+        Spi::run("UPDATE asserted SET position = NULL WHERE id = 2").unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT (lexo_verify_ordered('asserted', 'position', 'list'), \
+                 lexo_verify_ordered('asserted', 'position', 'list', allow_nulls => true))::text"
+            ),
+            "(f,t)"
+        );
+    }
+
+    /// A table with a row-level security policy that shows the role
+    /// `lexo_tenant` only its own rows, ids 1 and 3.
+    fn create_tenant_items() {
+        Spi::run(
+            "CREATE ROLE lexo_tenant NOLOGIN;
+             CREATE TABLE tenant_items (id int PRIMARY KEY, tenant text, position lexo);
+             INSERT INTO tenant_items VALUES (1, 'lexo_tenant', 'H'), (2, 'other', 'V'), (3, 'lexo_tenant', 'HV');
+             ALTER TABLE tenant_items ENABLE ROW LEVEL SECURITY;
+             CREATE POLICY own_rows ON tenant_items USING (tenant = current_user);
+             GRANT USAGE ON SCHEMA tests TO lexo_tenant;
+             GRANT SELECT, UPDATE ON tenant_items TO lexo_tenant",
+        )
+        .unwrap();
+    }
+
+    #[pg_test(error = "row-level security is active on table tenant_items")]
+    fn test_rebalance_refuses_row_level_security() {
+        create_tenant_items();
+        Spi::run(
+            "SET LOCAL ROLE lexo_tenant;
+             SELECT lexo_rebalance('tenant_items', 'position', NULL, NULL)",
+        )
+        .unwrap();
+    }
+
+    #[pg_test(error = "row-level security is active on table tenant_items")]
+    fn test_rebalance_summary_refuses_row_level_security() {
+        create_tenant_items();
+        Spi::run(
+            "SET LOCAL ROLE lexo_tenant;
+             SELECT lexo_rebalance_summary('tenant_items', 'position')",
+        )
+        .unwrap();
+    }
+
+    #[pg_test]
+    fn test_allow_rls_rebalances_the_visible_rows() {
+        create_tenant_items();
+        Spi::run("SET LOCAL ROLE lexo_tenant").unwrap();
+        assert!(
+            select_text("SELECT lexo_next('tenant_items', 'position', NULL, NULL)::text").as_str()
+                < "V"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT lexo_rebalance('tenant_items', 'position', 'tenant', 'lexo_tenant', \
+                 allow_rls => true)::text"
+            ),
+            "2"
+        );
+
+        Spi::run("RESET ROLE").unwrap();
+        assert_eq!(
+            select_text("SELECT position::text FROM tenant_items WHERE id = 2"),
+            "V"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_rebalance('tenant_items', 'position', NULL, NULL)::text"),
+            "3"
+        );
+    }
+
+    #[pg_test]
+    fn test_table_functions_cover_inheritance_children_unless_only() {
+        Spi::run(
+            "CREATE TABLE ranked_parent (id int, position lexo);
+             CREATE TABLE ranked_child () INHERITS (ranked_parent);
+             INSERT INTO ranked_parent VALUES (1, 'H'), (2, 'I');
+             INSERT INTO ranked_child VALUES (3, 'HV'), (4, 'V');
+             SET LOCAL lexo.next_cache = off",
+        )
+        .unwrap();
+        assert!(
+            select_text("SELECT lexo_next('ranked_parent', 'position', NULL, NULL)::text").as_str()
+                > "V"
+        );
+        assert!(
+            select_text(
+                "SELECT lexo_next('ranked_parent', 'position', NULL, NULL, only => true)::text"
+            )
+            .as_str()
+                < "V"
+        );
+        assert!(
+            select_text("SELECT lexo_next_desc('ranked_child', 'position', NULL, NULL)::text")
+                .as_str()
+                < "HV"
+        );
+
+        assert_eq!(
+            select_text(
+                "SELECT lexo_rebalance('ranked_parent', 'position', NULL, NULL, only => true)::text"
+            ),
+            "2"
+        );
+        assert_eq!(
+            select_text("SELECT string_agg(position::text, ',' ORDER BY id) FROM ranked_child"),
+            "HV,V"
+        );
+        let hierarchy_order = ordered_ids("ranked_parent");
+        assert_eq!(
+            select_text("SELECT lexo_rebalance('ranked_parent', 'position', NULL, NULL)::text"),
+            "4"
+        );
+        assert_eq!(ordered_ids("ranked_parent"), hierarchy_order);
+        assert_eq!(
+            select_text("SELECT count(DISTINCT position)::text FROM ranked_parent"),
+            "4"
+        );
+    }
+
+    #[pg_test]
+    fn test_table_functions_cover_partitions_unless_only() {
+        Spi::run(
+            "CREATE TABLE ranked_parted (id int, position lexo) PARTITION BY RANGE (id);
+             CREATE TABLE ranked_parted_low PARTITION OF ranked_parted FOR VALUES FROM (0) TO (10);
+             CREATE TABLE ranked_parted_high PARTITION OF ranked_parted FOR VALUES FROM (10) TO (20);
+             INSERT INTO ranked_parted VALUES (1, 'H'), (2, 'V'), (11, 'HV'), (12, 'HVV')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT lexo_next('ranked_parted', 'position', NULL, NULL, only => true)::text"
+            ),
+            "H"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT rows_updated::text \
+                 FROM lexo_rebalance_summary('ranked_parted', 'position', only => true)"
+            ),
+            "0"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_rebalance('ranked_parted', 'position', NULL, NULL)::text"),
+            "4"
+        );
+        assert_eq!(ordered_ids("ranked_parted"), "1,11,12,2");
+        assert_eq!(
+            select_text("SELECT count(DISTINCT position)::text FROM ranked_parted"),
+            "4"
+        );
+    }
+
+    #[pg_test]
+    fn test_tree_helpers_order_siblings_and_move_subtrees() {
+        Spi::run(
+            "CREATE TABLE outline (id int PRIMARY KEY, parent_id int REFERENCES outline, position lexo);
+             INSERT INTO outline VALUES (1, NULL, lexo_child_first('outline', 'id', 'parent_id', 'position', NULL));
+             INSERT INTO outline VALUES (2, NULL, lexo_child_between('outline', 'id', 'parent_id', 'position', NULL, '1'));
+             INSERT INTO outline VALUES (3, 1, lexo_child_first('outline', 'id', 'parent_id', 'position', '1'));
+             INSERT INTO outline VALUES (4, 1, lexo_child_first('outline', 'id', 'parent_id', 'position', '1'));
+             INSERT INTO outline VALUES (5, 1, lexo_child_between('outline', 'id', 'parent_id', 'position', '1', '4'));
+             INSERT INTO outline VALUES (6, 5, lexo_child_first('outline', 'id', 'parent_id', 'position', '5'))",
+        )
+        .unwrap();
+        assert_eq!(ordered_ids("outline WHERE parent_id IS NULL"), "1,2");
+        assert_eq!(ordered_ids("outline WHERE parent_id = 1"), "4,5,3");
+
+        // Node 5 and its child move to the end of the root nodes
+        Spi::run(
+            "SELECT lexo_move_subtree('outline', 'id', 'parent_id', 'position', '5', NULL, '2')",
+        )
+        .unwrap();
+        assert_eq!(ordered_ids("outline WHERE parent_id IS NULL"), "1,2,5");
+        assert_eq!(ordered_ids("outline WHERE parent_id = 1"), "4,3");
+        assert_eq!(
+            select_text("SELECT parent_id::text FROM outline WHERE id = 6"),
+            "5"
+        );
+
+        // Moving within the same parent, and to the front
+        Spi::run(
+            "SELECT lexo_move_subtree('outline', 'id', 'parent_id', 'position', '4', '1', '3');
+             SELECT lexo_move_subtree('outline', 'id', 'parent_id', 'position', '2', NULL, NULL)",
+        )
+        .unwrap();
+        assert_eq!(ordered_ids("outline WHERE parent_id = 1"), "3,4");
+        assert_eq!(ordered_ids("outline WHERE parent_id IS NULL"), "2,1,5");
+
+        let error = error_of(
+            "SELECT lexo_move_subtree('outline', 'id', 'parent_id', 'position', '5', '6', NULL)",
+        )
+        .unwrap();
+        assert!(error.contains("in its own subtree"), "{}", error);
+        let error = error_of(
+            "SELECT lexo_child_between('outline', 'id', 'parent_id', 'position', '1', '2')",
+        )
+        .unwrap();
+        assert!(error.contains("not a positioned child of 1"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_rank_at_percentile_agrees_with_percentile_disc() {
+        Spi::run(
+            "CREATE TABLE percentiles (id int, list int, position lexo);
+             INSERT INTO percentiles
+             SELECT i, i % 3, position
+             FROM unnest(lexo_array_fill_between(NULL, NULL, 100)) WITH ORDINALITY AS f(position, i);
+             INSERT INTO percentiles VALUES (0, 0, NULL)",
+        )
+        .unwrap();
+        for pct in ["0", "0.01", "0.25", "0.333", "0.5", "0.75", "0.99", "1"] {
+            assert_eq!(
+                select_text(&format!(
+                    "SELECT (lexo_rank_at_percentile('percentiles', 'position', {0}) \
+                     IS NOT DISTINCT FROM (SELECT percentile_disc({0}) WITHIN GROUP (ORDER BY position) \
+                                           FROM percentiles))::text",
+                    pct
+                )),
+                "true",
+                "{}",
+                pct
+            );
+            assert_eq!(
+                select_text(&format!(
+                    "SELECT (lexo_rank_at_percentile('percentiles', 'position', {0}, 'list', '1') \
+                     IS NOT DISTINCT FROM (SELECT percentile_disc({0}) WITHIN GROUP (ORDER BY position) \
+                                           FROM percentiles WHERE list = 1))::text",
+                    pct
+                )),
+                "true",
+                "{}",
+                pct
+            );
+        }
+        assert_eq!(
+            select_text("SELECT lexo_median('percentiles', 'position')::text"),
+            select_text("SELECT position::text FROM percentiles WHERE id = 50")
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (lexo_median('percentiles', 'position', 'list', '7') IS NULL)::text"
+            ),
+            "true"
+        );
+
+        let error =
+            error_of("SELECT lexo_rank_at_percentile('percentiles', 'position', 1.5)").unwrap();
+        assert!(error.contains(": pct must be between 0 and 1"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_array_insert_at_keeps_arrays_sorted() {
+        assert_eq!(
+            select_text("SELECT lexo_array_insert_at('{}'::lexo[], 0)::text"),
+            "H"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_array_insert_at('{}'::lexo[], 5)::text"),
+            "H"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_array_with_insert('{}'::lexo[], 0)::text"),
+            "{H}"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (lexo_array_insert_at(ARRAY['H']::lexo[], 0) < 'H' \
+                 AND lexo_array_insert_at(ARRAY['H']::lexo[], 1) > 'H' \
+                 AND lexo_array_insert_at(ARRAY['H']::lexo[], 7) > 'H' \
+                 AND lexo_array_insert_at(ARRAY['H']::lexo[], -1) < 'H')::text"
+            ),
+            "true"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_array_insert_at(ARRAY['H', 'V', 'l']::lexo[], 2)::text"),
+            generate_between("V", "l").unwrap()
+        );
+        for i in 0..=4 {
+            assert_eq!(
+                select_text(&format!(
+                    "SELECT (lexo_array_is_sorted(inserted) AND array_length(inserted, 1) = 4 \
+                     AND inserted[least({0}, 3) + 1] = lexo_array_insert_at(ranks, {0}))::text \
+                     FROM (SELECT ranks, lexo_array_with_insert(ranks, {0}) AS inserted \
+                           FROM (SELECT ARRAY['H', 'V', 'l']::lexo[] AS ranks) AS r) AS t",
+                    i
+                )),
+                "true",
+                "lexo_array_with_insert(ranks, {})",
+                i
+            );
+        }
+
+        let error = error_of("SELECT lexo_array_insert_at(ARRAY['V', 'H']::lexo[], 1)").unwrap();
+        assert!(error.contains("must be sorted"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_pinned_rows_keep_their_positions() {
+        Spi::run(
+            "CREATE TABLE pinned_items (id int PRIMARY KEY, position lexo, pinned boolean NOT NULL DEFAULT false);
+             INSERT INTO pinned_items (id, position)
+             SELECT i, (lexo_between(NULL, NULL) || repeat('a', i))::lexo FROM generate_series(1, 8) AS i;
+             UPDATE pinned_items SET pinned = true WHERE id IN (3, 6)",
+        )
+        .unwrap();
+        let kept = select_text(
+            "SELECT string_agg(position::text, ',' ORDER BY id) FROM pinned_items WHERE pinned",
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (pinned_count, rows_updated)::text \
+                 FROM lexo_rebalance_summary('pinned_items', 'position', pin_where => 'pinned')"
+            ),
+            "(2,6)"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(position::text, ',' ORDER BY id) FROM pinned_items WHERE pinned"
+            ),
+            kept
+        );
+        assert_eq!(ordered_ids("pinned_items"), "1,2,3,4,5,6,7,8");
+
+        Spi::run(
+            "UPDATE pinned_items SET position = (SELECT position FROM pinned_items WHERE id = 3) WHERE id = 6",
+        )
+        .unwrap();
+        let error =
+            error_of("SELECT lexo_rebalance('pinned_items', 'position', pin_where => 'pinned')")
+                .unwrap();
+        assert!(
+            error.contains(": several pinned rows share the position"),
+            "{}",
+            error
+        );
+    }
+
+    #[pg_test]
+    fn test_concatenated_ranks_group_under_their_parent() {
+        assert_eq!(select_text("SELECT lexo_concat('H', 'V')::text"), "HV");
+        assert_eq!(select_text("SELECT ('H'::lexo || 'a0'::text)::text"), "Ha0");
+        assert_eq!(
+            select_text("SELECT lexo_strip_prefix('HV', 'H')::text"),
+            "V"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT (lexo_strip_prefix('VV', 'H') IS NULL AND lexo_strip_prefix('H', 'H') IS NULL)::text"
+            ),
+            "true"
+        );
+        assert_eq!(
+            select_text("SELECT lexo_strip_prefix('H'::lexo || 'a0', 'H')::text"),
+            "a0"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(rank::text, ',' ORDER BY rank) \
+                 FROM (VALUES ('V'::lexo || 'A'), ('H'::lexo), ('V'::lexo), ('H'::lexo || 'z'), \
+                              ('A'::lexo || 'z'), ('H'::lexo || '0'), ('A'::lexo)) AS t(rank)"
+            ),
+            "A,Az,H,H0,Hz,V,VA"
+        );
+
+        let error = error_of("SELECT 'H'::lexo || 'a-0'::text").unwrap();
+        assert!(error.starts_with("22P02"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_count_between_counts_rows_strictly_between() {
+        Spi::run(
+            "CREATE TABLE counted (id int PRIMARY KEY, list int NOT NULL, position lexo);
+             INSERT INTO counted
+             SELECT i, i % 3, (lexo_array_fill_between(NULL, NULL, 300))[i]
+             FROM generate_series(1, 300) AS i;
+             INSERT INTO counted VALUES (301, 1, NULL)",
+        )
+        .unwrap();
+        let low = "(SELECT position FROM counted WHERE id = 40)";
+        let high = "(SELECT position FROM counted WHERE id = 250)";
+        for (bounds, expected) in [
+            ("NULL, NULL".to_string(), "300"),
+            (format!("{}, {}", low, high), "209"),
+            (format!("{}, NULL", low), "260"),
+            (format!("NULL, {}", high), "249"),
+            (
+                format!("{}, (SELECT position FROM counted WHERE id = 41)", low),
+                "0",
+            ),
+            ("NULL, NULL, 'list', '1'".to_string(), "100"),
+        ] {
+            assert_eq!(
+                select_text(&format!(
+                    "SELECT lexo_count_between('counted', 'position', {})::text",
+                    bounds
+                )),
+                expected,
+                "{}",
+                bounds
+            );
+        }
+        assert_eq!(
+            select_text(&format!(
+                "SELECT lexo_count_between('counted', 'position', {0}, {1}, 'list', '1')::text",
+                low, high
+            )),
+            select_text(&format!(
+                "SELECT count(*)::text FROM counted WHERE list = 1 AND position > {} AND position < {}",
+                low, high
+            ))
+        );
+
+        let error = error_of(&format!(
+            "SELECT lexo_count_between('counted', 'position', {}, {})",
+            high, low
+        ))
+        .unwrap();
+        assert!(error.contains(": low position "), "{}", error);
+        assert!(
+            error.contains(" must be less than high position "),
+            "{}",
+            error
+        );
+    }
+
+    #[pg_test]
+    fn test_slice_returns_the_rows_between_two_positions() {
+        Spi::run(
+            "CREATE TABLE sliced (id int PRIMARY KEY, list int NOT NULL, position lexo);
+             INSERT INTO sliced
+             SELECT i, i % 2, (lexo_array_fill_between(NULL, NULL, 100))[i] FROM generate_series(1, 100) AS i;
+             INSERT INTO sliced VALUES (101, 0, NULL);
+             CREATE INDEX sliced_position_idx ON sliced (position)",
+        )
+        .unwrap();
+        let low = "(SELECT position FROM sliced WHERE id = 10)";
+        let high = "(SELECT position FROM sliced WHERE id = 60)";
+        for (arguments, expected) in [
+            (format!("'id', {}, {}, 5", low, high), "11,12,13,14,15"),
+            (
+                format!("NULL, {}, {}, 3, descending => true", low, high),
+                "59,58,57",
+            ),
+            (format!("'id', NULL, {}, 3, 'list', '1'", high), "1,3,5"),
+            (
+                format!("'id', {}, NULL, 3, 'list', '0', true", high),
+                "100,98,96",
+            ),
+        ] {
+            assert_eq!(
+                select_text(&format!(
+                    "SELECT string_agg(pk::text, ',') FROM lexo_slice('sliced', 'position', {})",
+                    arguments
+                )),
+                expected,
+                "{}",
+                arguments
+            );
+        }
+        assert_eq!(
+            select_text(
+                "SELECT count(*)::text FROM lexo_slice('sliced', 'position', 'id', NULL, NULL, 1000)"
+            ),
+            "100"
+        );
+        assert_eq!(
+            select_text(&format!(
+                "SELECT rank::text FROM lexo_slice('sliced', 'position', 'id', {}, NULL, 1)",
+                low
+            )),
+            select_text("SELECT position::text FROM sliced WHERE id = 11")
+        );
+
+        Spi::run("SET LOCAL enable_seqscan = off").unwrap();
+        let plan = Spi::explain(
+            "SELECT id::text, position FROM sliced \
+             WHERE position IS NOT NULL AND position > 'A'::lexo AND position < 'V'::lexo \
+             ORDER BY position LIMIT 5",
+        )
+        .unwrap()
+        .0
+        .to_string();
+        assert!(
+            plan.contains("\"Index Scan\"") && plan.contains("sliced_position_idx"),
+            "{}",
+            plan
+        );
+        assert!(!plan.contains("Sort"), "{}", plan);
+
+        Spi::run("SET LOCAL lexo.slice_max_rows = 10").unwrap();
+        let error =
+            error_of("SELECT lexo_slice('sliced', 'position', 'id', NULL, NULL, 11)").unwrap();
+        assert!(error.starts_with("54000"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_insert_sorted_keeps_a_list_sorted_by_another_column() {
+        Spi::run(
+            "CREATE TABLE songs (id serial PRIMARY KEY, album int NOT NULL, title text NOT NULL, position lexo)",
+        )
+        .unwrap();
+        for title in [
+            "Michelle",
+            "Help",
+            "Yesterday",
+            "Anna",
+            "Help",
+            "Something",
+            "Zoo",
+        ] {
+            Spi::run(&format!(
+                "INSERT INTO songs (album, title, position)
+                 VALUES (1, '{0}', lexo_insert_sorted('songs', 'position', 'lower(title)', lower('{0}'), 'album', '1'))",
+                title
+            ))
+            .unwrap();
+        }
+        Spi::run(
+            "INSERT INTO songs (album, title, position)
+             VALUES (2, 'Zoo', lexo_insert_sorted('songs', 'position', 'lower(title)', 'zoo', 'album', '2'))",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(title, ',' ORDER BY position) FROM songs WHERE album = 1"
+            ),
+            "Anna,Help,Help,Michelle,Something,Yesterday,Zoo"
+        );
+        assert_eq!(ordered_ids("songs WHERE title = 'Help'"), "2,5");
+        assert_eq!(
+            select_text("SELECT position::text FROM songs WHERE album = 2"),
+            "H"
+        );
+
+        let error = error_of(
+            "SELECT lexo_insert_sorted('songs', 'position', 'title); DROP TABLE songs; --', 'x')",
+        )
+        .unwrap();
+        assert!(
+            error.contains(": invalid sort_expr expression"),
+            "{}",
+            error
+        );
+
+        Spi::run(
+            "UPDATE songs SET position = lexo_after((SELECT max(position) FROM songs)) WHERE title = 'Anna'",
+        )
+        .unwrap();
+        let error = error_of(
+            "SELECT lexo_insert_sorted('songs', 'position', 'lower(title)', 'b', 'album', '1')",
+        )
+        .unwrap();
+        assert!(error.starts_with("22000"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_merge_lists_appends_or_interleaves() {
+        Spi::run(
+            "CREATE TABLE board_cards (id int PRIMARY KEY, board int NOT NULL, position lexo);
+             INSERT INTO board_cards
+                 VALUES (1, 7, 'B'), (2, 7, 'H'), (3, 7, 'V'), (4, 8, 'A'), (5, 8, 'H'), (6, 8, 'X')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT lexo_merge_lists('board_cards', 'position', 'board', '8', '7')::text"
+            ),
+            "3"
+        );
+        assert_eq!(ordered_ids("board_cards"), "1,2,3,4,5,6");
+        assert_eq!(
+            select_text("SELECT count(*)::text FROM board_cards WHERE board <> 7"),
+            "0"
+        );
+
+        Spi::run(
+            "TRUNCATE board_cards;
+             INSERT INTO board_cards VALUES
+                 (1, 7, 'B'), (2, 7, 'H'), (3, 7, 'V'), (4, 8, 'A'), (5, 8, 'H'), (6, 8, 'X'), (7, 8, NULL)",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT lexo_merge_lists('board_cards', 'position', 'board', '8', '7', \
+                 mode => 'interleave', rewrite_key => false)::text"
+            ),
+            "2"
+        );
+        assert_eq!(ordered_ids("board_cards"), "4,1,2,5,3,6,7");
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(position::text, ',' ORDER BY id) FROM board_cards WHERE id IN (1, 2, 3, 4, 6)"
+            ),
+            "B,H,V,A,X"
+        );
+        assert_eq!(
+            select_text("SELECT count(*)::text FROM board_cards WHERE board = 8"),
+            "4"
+        );
+
+        Spi::run(
+            "UPDATE board_cards SET board = 9, position = 'H' WHERE id = 5;
+             UPDATE board_cards SET position = 'H0' WHERE id = 3",
+        )
+        .unwrap();
+        let error = error_of(
+            "SELECT lexo_merge_lists('board_cards', 'position', 'board', '9', '7', 'interleave')",
+        )
+        .unwrap();
+        assert!(error.starts_with("54000"), "{}", error);
+    }
+
+    #[pg_test]
+    fn test_clone_order_ranks_a_copy_like_its_original() {
+        Spi::run(
+            "CREATE TABLE playlist_copies (
+                 id int PRIMARY KEY, playlist int NOT NULL, song int NOT NULL, copied_from int, position lexo);
+             INSERT INTO playlist_copies VALUES
+                 (1, 1, 10, NULL, 'V'), (2, 1, 20, NULL, 'B'), (3, 1, 30, NULL, 'H'),
+                 (4, 2, 10, 1, NULL), (5, 2, 20, 2, NULL), (6, 2, 30, 3, NULL), (7, 2, 40, NULL, NULL),
+                 (8, 3, 30, NULL, 'z'), (9, 3, 10, NULL, 'y')",
+        )
+        .unwrap();
+        assert_eq!(
+            select_text(
+                "SELECT lexo_clone_order('playlist_copies', 'position', 'playlist', '1', '2', 'copied_from')::text"
+            ),
+            "4"
+        );
+        assert_eq!(ordered_ids("playlist_copies WHERE playlist = 2"), "5,6,4,7");
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(position::text, ',' ORDER BY id) FROM playlist_copies WHERE playlist = 1"
+            ),
+            "V,B,H"
+        );
+
+        assert_eq!(
+            select_text(
+                "SELECT lexo_clone_order('playlist_copies', 'position', 'playlist', '1', '3', 'song', 'song')::text"
+            ),
+            "2"
+        );
+        assert_eq!(ordered_ids("playlist_copies WHERE playlist = 3"), "8,9");
+    }
+
+    #[pg_test]
+    fn test_reverse_flips_each_list_in_place() {
+        Spi::run(
+            "CREATE TABLE reversed (id int PRIMARY KEY, list int, position lexo);
+             INSERT INTO reversed VALUES
+                 (1, 1, 'B'), (2, 1, 'H'), (3, 1, 'H'), (4, 1, 'V'), (5, 1, NULL),
+                 (6, 2, 'A'), (7, 2, 'Z'), (8, 3, 'A'), (9, 3, 'Z'), (10, NULL, 'A'), (11, NULL, 'Z')",
+        )
+        .unwrap();
+        // Rows 2 and 3 share a position, so either may come first
+        let list_order = "SELECT string_agg(id::text, ',' ORDER BY position NULLS LAST, id) \
+                          FROM reversed WHERE list = 1";
+        assert_eq!(
+            select_text("SELECT lexo_reverse('reversed', 'position', 'list', '1')::text"),
+            "4"
+        );
+        let reversed = select_text(list_order);
+        assert!(
+            ["4,2,3,1,5", "4,3,2,1,5"].contains(&reversed.as_str()),
+            "{}",
+            reversed
+        );
+        assert_eq!(
+            select_text("SELECT count(DISTINCT position)::text FROM reversed WHERE list = 1"),
+            "4"
+        );
+        assert_eq!(
+            select_text("SELECT (position IS NULL)::text FROM reversed WHERE id = 5"),
+            "true"
+        );
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(position::text, ',' ORDER BY id) FROM reversed WHERE list = 2"
+            ),
+            "A,Z"
+        );
+
+        assert_eq!(
+            select_text("SELECT lexo_reverse('reversed', 'position', 'list')::text"),
+            "8"
+        );
+        let restored = select_text(list_order);
+        assert!(
+            ["1,2,3,4,5", "1,3,2,4,5"].contains(&restored.as_str()),
+            "{}",
+            restored
+        );
+        assert_eq!(ordered_ids("reversed WHERE list = 2"), "7,6");
+        assert_eq!(ordered_ids("reversed WHERE list = 3"), "9,8");
+        assert_eq!(
+            select_text(
+                "SELECT string_agg(position::text, ',' ORDER BY id) FROM reversed WHERE list IS NULL"
+            ),
+            "A,Z"
+        );
+    }
 }
@@ -0,0 +1,317 @@
+//! `smalllexo`: a fixed-size, pass-by-value companion to `lexo`.
+//!
+//! Positions of up to `SMALL_MAX_LENGTH` characters are packed into 8 bytes
+//! so that unsigned integer order is position order. Values are stored inline
+//! like a `bigint`, without varlena headers, and sorts compare the packed
+//! Datums directly.
+
+use std::ffi::{CStr, CString, c_int};
+
+use pgrx::callconv::{Arg, ArgAbi, BoxRet, FcInfo};
+use pgrx::datum::Datum;
+use pgrx::pgrx_sql_entity_graph::metadata::{
+    ArgumentError, Returns, ReturnsError, SqlMapping, SqlTranslatable,
+};
+use pgrx::prelude::*;
+
+use crate::Lexo;
+use crate::lexo::report_invalid;
+use crate::operations::{
+    SMALL_MAX_LENGTH, pack_small_position, parse_base62_input, unpack_small_position,
+};
+
+/// A position of at most `SMALL_MAX_LENGTH` characters, packed into a `u64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SmallLexo(u64);
+
+impl SmallLexo {
+    /// Packs a valid Base62 position, raising an error if it is too long.
+    pub fn from_position(position: &str) -> Self {
+        match pack_small_position(position) {
+            Some(packed) => SmallLexo(packed),
+            None => report_too_long(position),
+        }
+    }
+
+    /// Returns the position as a string.
+    pub fn to_position(self) -> String {
+        unpack_small_position(self.0)
+    }
+}
+
+/// Raises the error for a position that does not fit in a `smalllexo`
+pub(crate) fn report_too_long(position: &str) -> ! {
+    raise_error!(
+        PgSqlErrorCode::ERRCODE_STRING_DATA_RIGHT_TRUNCATION,
+        format!("position '{}' is too long for type smalllexo", position),
+        detail = format!(
+            "A smalllexo holds at most {} characters; this position has {}.",
+            SMALL_MAX_LENGTH,
+            position.len()
+        ),
+        hint = "Use the lexo type for lists whose positions grow longer, or rebalance the list.",
+    )
+}
+
+impl FromDatum for SmallLexo {
+    unsafe fn from_polymorphic_datum(
+        datum: pg_sys::Datum,
+        is_null: bool,
+        _typoid: pg_sys::Oid,
+    ) -> Option<Self> {
+        (!is_null).then(|| SmallLexo(datum.value() as u64))
+    }
+}
+
+impl IntoDatum for SmallLexo {
+    fn into_datum(self) -> Option<pg_sys::Datum> {
+        Some(pg_sys::Datum::from(self.0))
+    }
+
+    fn type_oid() -> pg_sys::Oid {
+        pgrx::wrappers::regtypein("smalllexo")
+    }
+}
+
+unsafe impl<'fcx> ArgAbi<'fcx> for SmallLexo {
+    unsafe fn unbox_arg_unchecked(arg: Arg<'_, 'fcx>) -> Self {
+        let index = arg.index();
+        unsafe {
+            arg.unbox_arg_using_from_datum()
+                .unwrap_or_else(|| panic!("argument {index} must not be null"))
+        }
+    }
+}
+
+unsafe impl BoxRet for SmallLexo {
+    unsafe fn box_into<'fcx>(self, fcinfo: &mut FcInfo<'fcx>) -> Datum<'fcx> {
+        unsafe { fcinfo.return_raw_datum(pg_sys::Datum::from(self.0)) }
+    }
+}
+
+unsafe impl SqlTranslatable for SmallLexo {
+    fn argument_sql() -> Result<SqlMapping, ArgumentError> {
+        Ok(SqlMapping::literal("smalllexo"))
+    }
+
+    fn return_sql() -> Result<Returns, ReturnsError> {
+        Ok(Returns::One(SqlMapping::literal("smalllexo")))
+    }
+}
+
+// The I/O functions need the type to exist, and the type needs them.
+extension_sql!(
+    r#"
+CREATE TYPE smalllexo;
+"#,
+    name = "smalllexo_shell"
+);
+
+/// Type input function for `smalllexo`.
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_shell"])]
+pub fn smalllexo_in(input: &CStr) -> SmallLexo {
+    let value = input.to_string_lossy();
+    match parse_base62_input(&value) {
+        Ok(trimmed) => SmallLexo::from_position(trimmed),
+        Err((offset, character)) => report_invalid(&value, offset, character),
+    }
+}
+
+/// Type output function for `smalllexo`.
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_shell"])]
+pub fn smalllexo_out(value: SmallLexo) -> CString {
+    CString::new(value.to_position()).expect("positions never contain NUL")
+}
+
+extension_sql!(
+    r#"
+CREATE TYPE smalllexo (
+    INPUT = smalllexo_in,
+    OUTPUT = smalllexo_out,
+    INTERNALLENGTH = 8,
+    PASSEDBYVALUE,
+    ALIGNMENT = double,
+    STORAGE = plain
+);
+"#,
+    name = "smalllexo_type",
+    requires = [smalllexo_in, smalllexo_out]
+);
+
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_type"])]
+pub fn smalllexo_eq(a: SmallLexo, b: SmallLexo) -> bool {
+    a == b
+}
+
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_type"])]
+pub fn smalllexo_ne(a: SmallLexo, b: SmallLexo) -> bool {
+    a != b
+}
+
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_type"])]
+pub fn smalllexo_lt(a: SmallLexo, b: SmallLexo) -> bool {
+    a < b
+}
+
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_type"])]
+pub fn smalllexo_le(a: SmallLexo, b: SmallLexo) -> bool {
+    a <= b
+}
+
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_type"])]
+pub fn smalllexo_gt(a: SmallLexo, b: SmallLexo) -> bool {
+    a > b
+}
+
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_type"])]
+pub fn smalllexo_ge(a: SmallLexo, b: SmallLexo) -> bool {
+    a >= b
+}
+
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_type"])]
+pub fn smalllexo_cmp(a: SmallLexo, b: SmallLexo) -> i32 {
+    a.cmp(&b) as i32
+}
+
+/// State and combine function for the `min(smalllexo)` aggregate.
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_type"])]
+pub fn smalllexo_smaller(a: SmallLexo, b: SmallLexo) -> SmallLexo {
+    a.min(b)
+}
+
+/// State and combine function for the `max(smalllexo)` aggregate.
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_type"])]
+pub fn smalllexo_larger(a: SmallLexo, b: SmallLexo) -> SmallLexo {
+    a.max(b)
+}
+
+/// Hash support for `smalllexo`: the packed value run through a 64-bit mixer
+/// and folded to 32 bits.
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_type"])]
+pub fn smalllexo_hash(value: SmallLexo) -> i32 {
+    let mut z = value.0;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z ^ (z >> 32)) as u32 as i32
+}
+
+/// Btree sort support for `smalllexo`: packed values compare as unsigned
+/// integers, so sorts use PostgreSQL's own unsigned Datum comparator.
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_type"])]
+pub fn smalllexo_sortsupport(ssup: pgrx::Internal) {
+    let ssup = ssup
+        .unwrap()
+        .expect("sortsupport called without SortSupport")
+        .cast_mut_ptr::<pg_sys::SortSupportData>();
+
+    unsafe {
+        (*ssup).comparator = Some(smalllexo_sort_cmp);
+    }
+}
+
+#[pg_guard]
+unsafe extern "C-unwind" fn smalllexo_sort_cmp(
+    x: pg_sys::Datum,
+    y: pg_sys::Datum,
+    _ssup: pg_sys::SortSupport,
+) -> c_int {
+    (x.value() as u64).cmp(&(y.value() as u64)) as c_int
+}
+
+/// Cast from `smalllexo` to `lexo`; always succeeds.
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_type"])]
+pub fn smalllexo_to_lexo(value: SmallLexo) -> Lexo {
+    Lexo::new(value.to_position())
+}
+
+/// Cast from `lexo` to `smalllexo`; fails for positions that are too long.
+#[pg_extern(immutable, parallel_safe, requires = ["smalllexo_type"])]
+pub fn smalllexo_from_lexo(value: Lexo) -> SmallLexo {
+    SmallLexo::from_position(value.as_str())
+}
+
+extension_sql!(
+    r#"
+CREATE OPERATOR = (
+    LEFTARG = smalllexo, RIGHTARG = smalllexo, FUNCTION = smalllexo_eq,
+    COMMUTATOR = =, NEGATOR = <>, RESTRICT = eqsel, JOIN = eqjoinsel, HASHES, MERGES
+);
+CREATE OPERATOR <> (
+    LEFTARG = smalllexo, RIGHTARG = smalllexo, FUNCTION = smalllexo_ne,
+    COMMUTATOR = <>, NEGATOR = =, RESTRICT = neqsel, JOIN = neqjoinsel
+);
+CREATE OPERATOR < (
+    LEFTARG = smalllexo, RIGHTARG = smalllexo, FUNCTION = smalllexo_lt,
+    COMMUTATOR = >, NEGATOR = >=, RESTRICT = scalarltsel, JOIN = scalarltjoinsel
+);
+CREATE OPERATOR <= (
+    LEFTARG = smalllexo, RIGHTARG = smalllexo, FUNCTION = smalllexo_le,
+    COMMUTATOR = >=, NEGATOR = >, RESTRICT = scalarlesel, JOIN = scalarlejoinsel
+);
+CREATE OPERATOR > (
+    LEFTARG = smalllexo, RIGHTARG = smalllexo, FUNCTION = smalllexo_gt,
+    COMMUTATOR = <, NEGATOR = <=, RESTRICT = scalargtsel, JOIN = scalargtjoinsel
+);
+CREATE OPERATOR >= (
+    LEFTARG = smalllexo, RIGHTARG = smalllexo, FUNCTION = smalllexo_ge,
+    COMMUTATOR = <=, NEGATOR = <, RESTRICT = scalargesel, JOIN = scalargejoinsel
+);
+
+CREATE OPERATOR CLASS smalllexo_btree_ops DEFAULT FOR TYPE smalllexo USING btree AS
+    OPERATOR 1 <,
+    OPERATOR 2 <=,
+    OPERATOR 3 =,
+    OPERATOR 4 >=,
+    OPERATOR 5 >,
+    FUNCTION 1 smalllexo_cmp(smalllexo, smalllexo),
+    FUNCTION 2 smalllexo_sortsupport(internal);
+CREATE OPERATOR CLASS smalllexo_hash_ops DEFAULT FOR TYPE smalllexo USING hash AS
+    OPERATOR 1 =,
+    FUNCTION 1 smalllexo_hash(smalllexo);
+CREATE OPERATOR CLASS smalllexo_minmax_ops DEFAULT FOR TYPE smalllexo USING brin AS
+    OPERATOR 1 <,
+    OPERATOR 2 <=,
+    OPERATOR 3 =,
+    OPERATOR 4 >=,
+    OPERATOR 5 >,
+    FUNCTION 1 brin_minmax_opcinfo(internal),
+    FUNCTION 2 brin_minmax_add_value(internal, internal, internal, internal),
+    FUNCTION 3 brin_minmax_consistent(internal, internal, internal),
+    FUNCTION 4 brin_minmax_union(internal, internal, internal);
+
+CREATE AGGREGATE min(smalllexo) (
+    SFUNC = smalllexo_smaller,
+    STYPE = smalllexo,
+    COMBINEFUNC = smalllexo_smaller,
+    SORTOP = <,
+    PARALLEL = SAFE
+);
+CREATE AGGREGATE max(smalllexo) (
+    SFUNC = smalllexo_larger,
+    STYPE = smalllexo,
+    COMBINEFUNC = smalllexo_larger,
+    SORTOP = >,
+    PARALLEL = SAFE
+);
+
+CREATE CAST (smalllexo AS lexo) WITH FUNCTION smalllexo_to_lexo(smalllexo) AS IMPLICIT;
+CREATE CAST (lexo AS smalllexo) WITH FUNCTION smalllexo_from_lexo(lexo) AS ASSIGNMENT;
+"#,
+    name = "smalllexo_operators",
+    requires = [
+        smalllexo_eq,
+        smalllexo_ne,
+        smalllexo_lt,
+        smalllexo_le,
+        smalllexo_gt,
+        smalllexo_ge,
+        smalllexo_cmp,
+        smalllexo_smaller,
+        smalllexo_larger,
+        smalllexo_hash,
+        smalllexo_sortsupport,
+        smalllexo_to_lexo,
+        smalllexo_from_lexo
+    ]
+);
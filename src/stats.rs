@@ -0,0 +1,311 @@
+//! Shared-memory usage counters behind the `lexo_stat` view.
+//!
+//! Counters are kept per database in a fixed number of slots of atomics, so
+//! counting never waits on another backend, and live for the lifetime of the
+//! postmaster. They require pg_lexo to be listed in `shared_preload_libraries`;
+//! otherwise counting is a no-op and the view is empty.
+
+use pgrx::atomics::PgAtomic;
+use pgrx::pg_shmem_init;
+use pgrx::prelude::*;
+use pgrx::shmem::PGRXSharedMemory;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, Ordering};
+
+/// Number of databases that can be tracked at once.
+const MAX_DATABASES: usize = 64;
+
+/// Generated positions longer than this are counted as long ranks.
+pub const LONG_RANK_LENGTH: usize = 16;
+
+/// The operations counted in the `lexo_stat` view.
+#[derive(Copy, Clone, Debug)]
+pub enum Operation {
+    After,
+    Before,
+    Between,
+    Next,
+    Rebalance,
+}
+
+impl Operation {
+    /// The operation that places a position between two bounds, either of
+    /// which may be open: after `before` alone, before `after` alone, or
+    /// between both.
+    pub fn placing(has_before: bool, has_after: bool) -> Self {
+        match (has_before, has_after) {
+            (true, false) => Operation::After,
+            (false, true) => Operation::Before,
+            _ => Operation::Between,
+        }
+    }
+}
+
+/// A copy of the counters of one database.
+#[derive(Copy, Clone, Debug)]
+pub struct DbStats {
+    pub database: u32,
+    pub after_calls: i64,
+    pub before_calls: i64,
+    pub between_calls: i64,
+    pub next_calls: i64,
+    pub rebalance_calls: i64,
+    pub rows_rebalanced: i64,
+    pub long_ranks: i64,
+}
+
+/// Counters for one database, claimed by storing its OID in `database`.
+///
+/// A slot keeps its database once claimed, so a backend that looked it up
+/// never counts into a slot another database has taken over.
+#[derive(Debug)]
+struct DbCounters {
+    database: AtomicU32,
+    after_calls: AtomicI64,
+    before_calls: AtomicI64,
+    between_calls: AtomicI64,
+    next_calls: AtomicI64,
+    rebalance_calls: AtomicI64,
+    rows_rebalanced: AtomicI64,
+    long_ranks: AtomicI64,
+}
+
+impl DbCounters {
+    const fn new() -> Self {
+        DbCounters {
+            database: AtomicU32::new(0),
+            after_calls: AtomicI64::new(0),
+            before_calls: AtomicI64::new(0),
+            between_calls: AtomicI64::new(0),
+            next_calls: AtomicI64::new(0),
+            rebalance_calls: AtomicI64::new(0),
+            rows_rebalanced: AtomicI64::new(0),
+            long_ranks: AtomicI64::new(0),
+        }
+    }
+
+    fn add(counter: &AtomicI64, n: i64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn count(&self, op: Operation) {
+        let counter = match op {
+            Operation::After => &self.after_calls,
+            Operation::Before => &self.before_calls,
+            Operation::Between => &self.between_calls,
+            Operation::Next => &self.next_calls,
+            Operation::Rebalance => &self.rebalance_calls,
+        };
+        Self::add(counter, 1);
+    }
+
+    fn reset(&self) {
+        for counter in [
+            &self.after_calls,
+            &self.before_calls,
+            &self.between_calls,
+            &self.next_calls,
+            &self.rebalance_calls,
+            &self.rows_rebalanced,
+            &self.long_ranks,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn snapshot(&self, database: u32) -> DbStats {
+        DbStats {
+            database,
+            after_calls: self.after_calls.load(Ordering::Relaxed),
+            before_calls: self.before_calls.load(Ordering::Relaxed),
+            between_calls: self.between_calls.load(Ordering::Relaxed),
+            next_calls: self.next_calls.load(Ordering::Relaxed),
+            rebalance_calls: self.rebalance_calls.load(Ordering::Relaxed),
+            rows_rebalanced: self.rows_rebalanced.load(Ordering::Relaxed),
+            long_ranks: self.long_ranks.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Fixed-size table of per-database counters.
+#[derive(Debug)]
+pub struct StatsTable {
+    slots: [DbCounters; MAX_DATABASES],
+}
+
+unsafe impl PGRXSharedMemory for StatsTable {}
+
+impl StatsTable {
+    const fn new() -> Self {
+        StatsTable {
+            slots: [const { DbCounters::new() }; MAX_DATABASES],
+        }
+    }
+
+    /// Returns the slot of a database, claiming a free one if needed.
+    ///
+    /// Returns `None` when every slot is taken by another database.
+    fn slot(&self, database: u32) -> Option<&DbCounters> {
+        if let Some(slot) = self.find(database) {
+            return Some(slot);
+        }
+        // Another backend may claim the same free slot first, for this
+        // database or another one
+        self.slots.iter().find(|s| {
+            match s
+                .database
+                .compare_exchange(0, database, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => true,
+                Err(claimed) => claimed == database,
+            }
+        })
+    }
+
+    fn find(&self, database: u32) -> Option<&DbCounters> {
+        self.slots
+            .iter()
+            .find(|s| s.database.load(Ordering::Acquire) == database)
+    }
+
+    fn reset(&self, database: u32) {
+        if let Some(slot) = self.find(database) {
+            slot.reset();
+        }
+    }
+
+    fn used(&self) -> impl Iterator<Item = DbStats> + '_ {
+        self.slots.iter().filter_map(|s| {
+            let database = s.database.load(Ordering::Acquire);
+            (database != 0).then(|| s.snapshot(database))
+        })
+    }
+}
+
+static STATS: PgAtomic<StatsTable> = unsafe { PgAtomic::new(c"pg_lexo_stats") };
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Requests the shared memory; only effective while shared_preload_libraries is processed.
+// pg_shmem_init! checks pgrx's pg13-pg15 features, which this crate doesn't declare.
+#[allow(unexpected_cfgs)]
+pub fn init() {
+    if !unsafe { pg_sys::process_shared_preload_libraries_in_progress } {
+        return;
+    }
+
+    pg_shmem_init!(STATS = StatsTable::new());
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn current_database() -> u32 {
+    unsafe { pg_sys::MyDatabaseId }.to_u32()
+}
+
+fn with_current_slot(update: impl FnOnce(&DbCounters)) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    if let Some(slot) = STATS.get().slot(current_database()) {
+        update(slot);
+    }
+}
+
+/// Counts one call of `op`, and a long rank if `generated` exceeds [`LONG_RANK_LENGTH`].
+pub fn count_call(op: Operation, generated: &str) {
+    with_current_slot(|slot| {
+        slot.count(op);
+        if generated.len() > LONG_RANK_LENGTH {
+            DbCounters::add(&slot.long_ranks, 1);
+        }
+    });
+}
+
+/// Counts one rebalance call that rewrote `rows` rows.
+pub fn count_rebalance(rows: i64) {
+    with_current_slot(|slot| {
+        slot.count(Operation::Rebalance);
+        DbCounters::add(&slot.rows_rebalanced, rows);
+    });
+}
+
+/// Returns a copy of the counters of every tracked database.
+///
+/// Each counter is read on its own, so a copy taken while other backends
+/// count can be a few calls apart between columns.
+pub fn snapshot() -> Vec<DbStats> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Vec::new();
+    }
+
+    STATS.get().used().collect()
+}
+
+/// Clears the counters of the current database.
+pub fn reset_current() {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    STATS.get().reset(current_database());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_table_slots() {
+        let table = StatsTable::new();
+        table.slot(5).unwrap().count(Operation::Next);
+        table.slot(7).unwrap().count(Operation::Between);
+        table.slot(5).unwrap().count(Operation::Next);
+        table
+            .slot(7)
+            .unwrap()
+            .count(Operation::placing(true, false));
+
+        let used: Vec<_> = table.used().collect();
+        assert_eq!(used.len(), 2);
+        assert_eq!(used[0].database, 5);
+        assert_eq!(used[0].next_calls, 2);
+        assert_eq!(used[1].between_calls, 1);
+        assert_eq!(used[1].after_calls, 1);
+
+        table.reset(5);
+        let used: Vec<_> = table.used().collect();
+        assert_eq!(used.len(), 2);
+        assert_eq!(used[0].next_calls, 0);
+        assert_eq!(used[1].between_calls, 1);
+    }
+
+    #[test]
+    fn test_stats_table_full() {
+        let table = StatsTable::new();
+        for db in 1..=MAX_DATABASES as u32 {
+            assert!(table.slot(db).is_some());
+        }
+        assert!(table.slot(1000).is_none());
+        assert!(table.slot(1).is_some());
+    }
+
+    #[test]
+    fn test_stats_table_counts_from_many_threads() {
+        let table = StatsTable::new();
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for db in [3, 4] {
+                        for _ in 0..1000 {
+                            table.slot(db).unwrap().count(Operation::Between);
+                        }
+                    }
+                });
+            }
+        });
+
+        let used: Vec<_> = table.used().collect();
+        assert_eq!(used.len(), 2);
+        assert!(used.iter().all(|s| s.between_calls == 8000));
+    }
+}
@@ -0,0 +1,328 @@
+//! Background worker that rebalances degraded lists automatically.
+//!
+//! The worker is registered when pg_lexo is listed in `shared_preload_libraries`.
+//! Each cycle, if `lexo.autorebalance` is on and the current time falls inside
+//! `lexo.autorebalance_window`, it scans the columns registered in
+//! `lexo_autorebalance_targets`, finds lists with positions longer than the
+//! target's `max_length`, and rebalances them within the
+//! `lexo.autorebalance_max_rows` budget. A list whose rows can't be locked
+//! quickly is skipped rather than waited for. Lists of rows whose key is NULL
+//! are left alone, since without a key value a rebalance would renumber the
+//! whole table as one list.
+
+use pgrx::bgworkers::{BackgroundWorker, BackgroundWorkerBuilder, SignalWakeFlags};
+use pgrx::pg_sys::panic::CaughtError;
+use pgrx::prelude::*;
+use pgrx::spi::{quote_identifier, quote_literal};
+use std::time::Duration;
+
+use crate::gucs::{
+    AUTOREBALANCE, AUTOREBALANCE_DATABASE, AUTOREBALANCE_MAX_ROWS, AUTOREBALANCE_NAPTIME,
+    AUTOREBALANCE_WINDOW,
+};
+use crate::lexo_rebalance;
+use crate::schema::quote_table_name;
+
+/// How long a rebalance may wait for a lock before the list is skipped.
+const LOCK_TIMEOUT: &str = "500ms";
+
+/// Registers the worker; only effective while shared_preload_libraries is processed.
+pub fn init() {
+    if !unsafe { pg_sys::process_shared_preload_libraries_in_progress } {
+        return;
+    }
+
+    BackgroundWorkerBuilder::new("pg_lexo autorebalance")
+        .set_type("pg_lexo autorebalance")
+        .set_library("pg_lexo")
+        .set_function("lexo_autorebalance_main")
+        .enable_spi_access()
+        .set_restart_time(Some(Duration::from_secs(60)))
+        .load();
+}
+
+/// Entry point of the autorebalance background worker.
+#[pg_guard]
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn lexo_autorebalance_main(_arg: pg_sys::Datum) {
+    BackgroundWorker::attach_signal_handlers(SignalWakeFlags::SIGHUP | SignalWakeFlags::SIGTERM);
+
+    let database = AUTOREBALANCE_DATABASE
+        .get()
+        .and_then(|db| db.into_string().ok())
+        .unwrap_or_else(|| "postgres".to_string());
+    BackgroundWorker::connect_worker_to_spi(Some(&database), None);
+
+    log!(
+        "pg_lexo autorebalance worker started in database {}",
+        database
+    );
+
+    let naptime = || Duration::from_secs(AUTOREBALANCE_NAPTIME.get() as u64);
+    while BackgroundWorker::wait_latch(Some(naptime())) {
+        if BackgroundWorker::sighup_received() {
+            unsafe { pg_sys::ProcessConfigFile(pg_sys::GucContext::PGC_SIGHUP) };
+        }
+        if !AUTOREBALANCE.get() {
+            continue;
+        }
+
+        let window = AUTOREBALANCE_WINDOW
+            .get()
+            .and_then(|w| w.into_string().ok())
+            .unwrap_or_default();
+        let Some(window) = parse_window(&window) else {
+            warning!(
+                "invalid lexo.autorebalance_window '{}', skipping cycle",
+                window
+            );
+            continue;
+        };
+        let minute_of_day = BackgroundWorker::transaction(|| {
+            Spi::get_one::<i32>(
+                "SELECT (extract(hour FROM localtime) * 60 + extract(minute FROM localtime))::int",
+            )
+            .expect("Failed to read the current time")
+            .unwrap_or(0)
+        });
+        if !in_window(minute_of_day as u32, window) {
+            continue;
+        }
+
+        run_cycle();
+    }
+
+    log!("pg_lexo autorebalance worker shutting down");
+}
+
+/// A registered column and the lists (key values) in it that need rebalancing.
+struct Candidate {
+    table_name: String,
+    column_name: String,
+    key_column: Option<String>,
+    key_value: Option<String>,
+    rows: i64,
+}
+
+fn run_cycle() {
+    let candidates = BackgroundWorker::transaction(find_candidates);
+    let mut budget = AUTOREBALANCE_MAX_ROWS.get() as i64;
+
+    for candidate in candidates {
+        if candidate.rows > budget {
+            continue;
+        }
+
+        let list = format!(
+            "{}.{}{}",
+            candidate.table_name,
+            candidate.column_name,
+            describe_key(&candidate)
+        );
+        let rebalanced = BackgroundWorker::transaction(move || {
+            try_in_subtransaction(|| {
+                Spi::run(&format!(
+                    "SET LOCAL lock_timeout = {}",
+                    quote_literal(LOCK_TIMEOUT)
+                ))
+                .expect("Failed to set lock_timeout");
+                lexo_rebalance(
+                    &candidate.table_name,
+                    &candidate.column_name,
+                    candidate.key_column.as_deref(),
+                    candidate.key_value.as_deref(),
+                    None,
+                    "equal",
+                    None,
+                    false,
+                    true,
+                    "append",
+                    "keep_order",
+                    false,
+                    false,
+                    None,
+                )
+            })
+        });
+
+        match rebalanced {
+            Ok(rows) => {
+                budget -= rows;
+                log!(
+                    "pg_lexo autorebalance: rebalanced {} rows in {}",
+                    rows,
+                    list
+                );
+            }
+            Err(message) => log!("pg_lexo autorebalance: skipped {}: {}", list, message),
+        }
+    }
+}
+
+fn describe_key(candidate: &Candidate) -> String {
+    match (&candidate.key_column, &candidate.key_value) {
+        (Some(col), Some(val)) => format!(" ({} = {})", col, val),
+        _ => String::new(),
+    }
+}
+
+/// Lists every degraded list of every registered column.
+fn find_candidates() -> Vec<Candidate> {
+    let registered: Option<bool> =
+        Spi::get_one("SELECT to_regclass('lexo_autorebalance_targets') IS NOT NULL")
+            .expect("Failed to look up lexo_autorebalance_targets");
+    if registered != Some(true) {
+        return Vec::new();
+    }
+
+    Spi::connect(|client| {
+        let targets: Vec<(String, String, Option<String>, i32)> = client
+            .select(
+                "SELECT n.nspname || '.' || c.relname, t.column_name::text, \
+                        t.key_column::text, t.max_length \
+                 FROM lexo_autorebalance_targets t \
+                 JOIN pg_catalog.pg_class c ON c.oid = t.table_name \
+                 JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace",
+                None,
+                &[],
+            )
+            .expect("Failed to read lexo_autorebalance_targets")
+            .map(|row| {
+                (
+                    row.get(1).ok().flatten().unwrap_or_default(),
+                    row.get(2).ok().flatten().unwrap_or_default(),
+                    row.get(3).ok().flatten(),
+                    row.get(4).ok().flatten().unwrap_or(16),
+                )
+            })
+            .collect();
+
+        let mut candidates = Vec::new();
+        for (table_name, column_name, key_column, max_length) in targets {
+            let quoted_column = quote_identifier(&column_name);
+            let (key_expr, group_by) = match &key_column {
+                Some(col) => {
+                    let quoted = quote_identifier(col);
+                    (
+                        format!("{}::text", quoted),
+                        format!(" WHERE {quoted} IS NOT NULL GROUP BY {quoted}"),
+                    )
+                }
+                None => ("NULL::text".to_string(), String::new()),
+            };
+            let quoted_table = quote_table_name(&table_name);
+            let query = format!(
+                "SELECT {key}, count(*) FROM {table}{group_by} \
+                 HAVING max(length({col}::text)) > {max_length}",
+                key = key_expr,
+                table = quoted_table,
+                group_by = group_by,
+                col = quoted_column,
+                max_length = max_length
+            );
+            let groups = client
+                .select(&query, None, &[])
+                .expect("Failed to find lists that need rebalancing");
+            for row in groups {
+                candidates.push(Candidate {
+                    table_name: table_name.clone(),
+                    column_name: column_name.clone(),
+                    key_column: key_column.clone(),
+                    key_value: row.get(1).ok().flatten(),
+                    rows: row.get(2).ok().flatten().unwrap_or(0),
+                });
+            }
+        }
+        candidates
+    })
+}
+
+/// Runs `body` in a subtransaction, rolling the subtransaction back and
+/// returning the error message if it raises an error.
+fn try_in_subtransaction<R>(
+    body: impl FnOnce() -> R + std::panic::UnwindSafe,
+) -> Result<R, String> {
+    let old_context = unsafe { pg_sys::CurrentMemoryContext };
+    let old_owner = unsafe { pg_sys::CurrentResourceOwner };
+    unsafe { pg_sys::BeginInternalSubTransaction(std::ptr::null()) };
+
+    PgTryBuilder::new(|| {
+        let result = body();
+        unsafe {
+            pg_sys::ReleaseCurrentSubTransaction();
+            pg_sys::MemoryContextSwitchTo(old_context);
+            pg_sys::CurrentResourceOwner = old_owner;
+        }
+        Ok(result)
+    })
+    .catch_others(|error| {
+        unsafe {
+            pg_sys::RollbackAndReleaseCurrentSubTransaction();
+            pg_sys::MemoryContextSwitchTo(old_context);
+            pg_sys::CurrentResourceOwner = old_owner;
+        }
+        match error {
+            CaughtError::PostgresError(report)
+            | CaughtError::ErrorReport(report)
+            | CaughtError::RustPanic {
+                ereport: report, ..
+            } => Err(report.message().to_string()),
+        }
+    })
+    .execute()
+}
+
+/// Parses a `HH:MM-HH:MM` maintenance window into minutes of the day.
+///
+/// An empty string means "any time" and yields `(0, 0)`.
+pub(crate) fn parse_window(window: &str) -> Option<(u32, u32)> {
+    let window = window.trim();
+    if window.is_empty() {
+        return Some((0, 0));
+    }
+
+    let parse_time = |s: &str| {
+        let (hours, minutes) = s.trim().split_once(':')?;
+        let hours: u32 = hours.parse().ok()?;
+        let minutes: u32 = minutes.parse().ok()?;
+        (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+    };
+
+    let (start, end) = window.split_once('-')?;
+    Some((parse_time(start)?, parse_time(end)?))
+}
+
+/// Checks whether a minute of the day falls in a window; the window may wrap
+/// past midnight, and an empty window (`start == end`) covers the whole day.
+fn in_window(minute: u32, (start, end): (u32, u32)) -> bool {
+    match start.cmp(&end) {
+        std::cmp::Ordering::Equal => true,
+        std::cmp::Ordering::Less => minute >= start && minute < end,
+        std::cmp::Ordering::Greater => minute >= start || minute < end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window(""), Some((0, 0)));
+        assert_eq!(parse_window("01:00-05:30"), Some((60, 330)));
+        assert_eq!(parse_window(" 22:00 - 02:00 "), Some((1320, 120)));
+        assert_eq!(parse_window("24:00-01:00"), None);
+        assert_eq!(parse_window("01:60-02:00"), None);
+        assert_eq!(parse_window("night"), None);
+    }
+
+    #[test]
+    fn test_in_window() {
+        assert!(in_window(0, (0, 0)));
+        assert!(in_window(90, (60, 330)));
+        assert!(!in_window(330, (60, 330)));
+        assert!(in_window(1380, (1320, 120)));
+        assert!(in_window(60, (1320, 120)));
+        assert!(!in_window(600, (1320, 120)));
+    }
+}
@@ -0,0 +1,246 @@
+-- pg_lexo 0.6.0 install script, as generated by `cargo pgrx schema` for the
+-- 0.6.0 release. Used by tests/upgrade/run.sh to install the previous version
+-- before running ALTER EXTENSION pg_lexo UPDATE.
+
+\echo Use "CREATE EXTENSION pg_lexo VERSION '0.6.0'" to load this file. \quit
+
+-- pg_lexo::Lexo
+CREATE TYPE Lexo;
+
+-- pg_lexo::lexo_in
+CREATE FUNCTION "lexo_in"(
+	"input" cstring /* core::option::Option<&core::ffi::c_str::CStr> */
+) RETURNS Lexo /* core::option::Option<pg_lexo::Lexo> */
+IMMUTABLE PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_in_wrapper';
+
+-- pg_lexo::lexo_out
+CREATE FUNCTION "lexo_out"(
+	"input" Lexo /* pg_lexo::Lexo */
+) RETURNS cstring /* alloc::ffi::c_str::CString */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_out_wrapper';
+
+-- pg_lexo::Lexo
+CREATE TYPE Lexo (
+	INTERNALLENGTH = variable,
+	INPUT = lexo_in, /* pg_lexo::lexo_in */
+	OUTPUT = lexo_out, /* pg_lexo::lexo_out */
+	STORAGE = extended
+);
+
+-- pg_lexo::schema::lexo_add_column
+CREATE FUNCTION "lexo_add_column"(
+	"table_name" TEXT, /* &str */
+	"column_name" TEXT /* &str */
+) RETURNS void
+STRICT
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_add_column_wrapper';
+
+-- pg_lexo::schema::lexo_after
+CREATE FUNCTION "lexo_after"(
+	"current" Lexo /* pg_lexo::Lexo */
+) RETURNS Lexo /* pg_lexo::Lexo */
+STRICT
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_after_wrapper';
+
+-- pg_lexo::schema::lexo_before
+CREATE FUNCTION "lexo_before"(
+	"current" Lexo /* pg_lexo::Lexo */
+) RETURNS Lexo /* pg_lexo::Lexo */
+STRICT
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_before_wrapper';
+
+-- pg_lexo::schema::lexo_between
+CREATE FUNCTION "lexo_between"(
+	"before_pos" Lexo, /* core::option::Option<pg_lexo::Lexo> */
+	"after_pos" Lexo /* core::option::Option<pg_lexo::Lexo> */
+) RETURNS Lexo /* pg_lexo::Lexo */
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_between_wrapper';
+
+-- pg_lexo::lexo_cmp
+CREATE FUNCTION "lexo_cmp"(
+	"left" Lexo, /* pg_lexo::Lexo */
+	"right" Lexo /* pg_lexo::Lexo */
+) RETURNS INT /* i32 */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_cmp_wrapper';
+
+-- pg_lexo::lexo_eq
+CREATE FUNCTION "lexo_eq"(
+	"left" Lexo, /* pg_lexo::Lexo */
+	"right" Lexo /* pg_lexo::Lexo */
+) RETURNS bool /* bool */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_eq_wrapper';
+
+-- pg_lexo::lexo_eq
+CREATE OPERATOR = (
+	PROCEDURE="lexo_eq",
+	LEFTARG=Lexo, /* pg_lexo::Lexo */
+	RIGHTARG=Lexo, /* pg_lexo::Lexo */
+	COMMUTATOR = =,
+	NEGATOR = <>,
+	RESTRICT = eqsel,
+	JOIN = eqjoinsel,
+	HASHES,
+	MERGES
+);
+
+-- pg_lexo::schema::lexo_first
+CREATE FUNCTION "lexo_first"() RETURNS Lexo /* pg_lexo::Lexo */
+STRICT
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_first_wrapper';
+
+-- pg_lexo::lexo_ge
+CREATE FUNCTION "lexo_ge"(
+	"left" Lexo, /* pg_lexo::Lexo */
+	"right" Lexo /* pg_lexo::Lexo */
+) RETURNS bool /* bool */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_ge_wrapper';
+
+-- pg_lexo::lexo_ge
+CREATE OPERATOR >= (
+	PROCEDURE="lexo_ge",
+	LEFTARG=Lexo, /* pg_lexo::Lexo */
+	RIGHTARG=Lexo, /* pg_lexo::Lexo */
+	COMMUTATOR = <=,
+	NEGATOR = <,
+	RESTRICT = scalargesel,
+	JOIN = scalargejoinsel
+);
+
+-- pg_lexo::lexo_gt
+CREATE FUNCTION "lexo_gt"(
+	"left" Lexo, /* pg_lexo::Lexo */
+	"right" Lexo /* pg_lexo::Lexo */
+) RETURNS bool /* bool */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_gt_wrapper';
+
+-- pg_lexo::lexo_gt
+CREATE OPERATOR > (
+	PROCEDURE="lexo_gt",
+	LEFTARG=Lexo, /* pg_lexo::Lexo */
+	RIGHTARG=Lexo, /* pg_lexo::Lexo */
+	COMMUTATOR = <,
+	NEGATOR = <=,
+	RESTRICT = scalargtsel,
+	JOIN = scalargtjoinsel
+);
+
+-- pg_lexo::lexo_hash
+CREATE FUNCTION "lexo_hash"(
+	"value" Lexo /* pg_lexo::Lexo */
+) RETURNS INT /* i32 */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_hash_wrapper';
+
+-- pg_lexo::lexo_le
+CREATE FUNCTION "lexo_le"(
+	"left" Lexo, /* pg_lexo::Lexo */
+	"right" Lexo /* pg_lexo::Lexo */
+) RETURNS bool /* bool */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_le_wrapper';
+
+-- pg_lexo::lexo_le
+CREATE OPERATOR <= (
+	PROCEDURE="lexo_le",
+	LEFTARG=Lexo, /* pg_lexo::Lexo */
+	RIGHTARG=Lexo, /* pg_lexo::Lexo */
+	COMMUTATOR = >=,
+	NEGATOR = >,
+	RESTRICT = scalarlesel,
+	JOIN = scalarlejoinsel
+);
+
+-- pg_lexo::lexo_lt
+CREATE FUNCTION "lexo_lt"(
+	"left" Lexo, /* pg_lexo::Lexo */
+	"right" Lexo /* pg_lexo::Lexo */
+) RETURNS bool /* bool */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_lt_wrapper';
+
+-- pg_lexo::lexo_lt
+CREATE OPERATOR < (
+	PROCEDURE="lexo_lt",
+	LEFTARG=Lexo, /* pg_lexo::Lexo */
+	RIGHTARG=Lexo, /* pg_lexo::Lexo */
+	COMMUTATOR = >,
+	NEGATOR = >=,
+	RESTRICT = scalarltsel,
+	JOIN = scalarltjoinsel
+);
+
+-- pg_lexo::lexo_ne
+CREATE FUNCTION "lexo_ne"(
+	"left" Lexo, /* pg_lexo::Lexo */
+	"right" Lexo /* pg_lexo::Lexo */
+) RETURNS bool /* bool */
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_ne_wrapper';
+
+-- pg_lexo::lexo_ne
+CREATE OPERATOR <> (
+	PROCEDURE="lexo_ne",
+	LEFTARG=Lexo, /* pg_lexo::Lexo */
+	RIGHTARG=Lexo, /* pg_lexo::Lexo */
+	COMMUTATOR = <>,
+	NEGATOR = =,
+	RESTRICT = neqsel,
+	JOIN = neqjoinsel
+);
+
+-- pg_lexo::schema::lexo_next
+CREATE FUNCTION "lexo_next"(
+	"table_name" TEXT, /* &str */
+	"lexo_column_name" TEXT, /* &str */
+	"identifier_column_name" TEXT, /* core::option::Option<&str> */
+	"identifier_value" TEXT /* core::option::Option<&str> */
+) RETURNS Lexo /* pg_lexo::Lexo */
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_next_wrapper';
+
+-- pg_lexo::schema::lexo_rebalance
+CREATE FUNCTION "lexo_rebalance"(
+	"table_name" TEXT, /* &str */
+	"lexo_column_name" TEXT, /* &str */
+	"key_column_name" TEXT, /* core::option::Option<&str> */
+	"key_value" TEXT /* core::option::Option<&str> */
+) RETURNS bigint /* i64 */
+LANGUAGE c /* Rust */
+AS 'MODULE_PATHNAME', 'lexo_rebalance_wrapper';
+
+-- pg_lexo::Lexo
+CREATE OPERATOR FAMILY Lexo_btree_ops USING btree;
+CREATE OPERATOR CLASS Lexo_btree_ops DEFAULT FOR TYPE Lexo USING btree FAMILY Lexo_btree_ops AS
+	OPERATOR 1 <,
+	OPERATOR 2 <=,
+	OPERATOR 3 =,
+	OPERATOR 4 >=,
+	OPERATOR 5 >,
+	FUNCTION 1 lexo_cmp(Lexo, Lexo);
+
+-- pg_lexo::Lexo
+CREATE OPERATOR FAMILY Lexo_hash_ops USING hash;
+CREATE OPERATOR CLASS Lexo_hash_ops DEFAULT FOR TYPE Lexo USING hash FAMILY Lexo_hash_ops AS
+	OPERATOR    1   =  (Lexo, Lexo),
+	FUNCTION    1   lexo_hash(Lexo);
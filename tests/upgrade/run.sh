@@ -0,0 +1,90 @@
+#!/bin/sh
+# Installs pg_lexo 0.6.0 in a scratch database, fills a table, runs
+# ALTER EXTENSION pg_lexo UPDATE and checks that the table keeps working and
+# that the generators can be used in indexes and parallel plans afterwards.
+# The functions themselves are covered by the pg_test suite.
+#
+# Usage: sh tests/upgrade/run.sh [PG_CONFIG]
+#
+# The current version must already be installed (cargo pgrx install), and the
+# user running the script must be able to write to the extension directory.
+# Set PSQL to change how psql is invoked, e.g. PSQL="sudo -u postgres psql".
+set -e
+
+PG_CONFIG="${1:-pg_config}"
+PSQL="${PSQL:-psql}"
+DB=lexo_upgrade_test
+DIR=$(dirname "$0")
+EXTDIR="$("$PG_CONFIG" --sharedir)/extension"
+
+cp "$DIR/pg_lexo--0.6.0.sql" "$EXTDIR/pg_lexo--0.6.0.sql"
+
+$PSQL -v ON_ERROR_STOP=1 -d postgres -c "DROP DATABASE IF EXISTS $DB" -c "CREATE DATABASE $DB"
+
+$PSQL -v ON_ERROR_STOP=1 -d "$DB" <<'SQL'
+CREATE EXTENSION pg_lexo VERSION '0.6.0';
+
+CREATE TABLE items (id serial PRIMARY KEY, position lexo NOT NULL);
+CREATE INDEX items_position_idx ON items (position);
+INSERT INTO items (position) VALUES (lexo_first());
+INSERT INTO items (position) VALUES (lexo_after(lexo_first()));
+INSERT INTO items (position) VALUES (lexo_between(lexo_first(), lexo_after(lexo_first())));
+
+ALTER EXTENSION pg_lexo UPDATE;
+
+DO $$
+DECLARE
+    failed text;
+BEGIN
+    IF (SELECT extversion FROM pg_extension WHERE extname = 'pg_lexo') = '0.6.0' THEN
+        RAISE EXCEPTION 'extension was not updated';
+    END IF;
+
+    SELECT string_agg(h."check" || ': ' || h.detail, '; ') INTO failed
+    FROM lexo_health() h WHERE NOT h.ok;
+    IF failed IS NOT NULL THEN
+        RAISE EXCEPTION 'health checks failed after update: %', failed;
+    END IF;
+
+    IF (SELECT array_agg(id ORDER BY position) FROM items) <> ARRAY[1, 3, 2] THEN
+        RAISE EXCEPTION 'order changed across the update';
+    END IF;
+
+    INSERT INTO items (position) VALUES (lexo_next('items', 'position', NULL, NULL));
+    PERFORM lexo_rebalance('items', 'position', NULL, NULL);
+    IF (SELECT array_agg(id ORDER BY position) FROM items) <> ARRAY[1, 3, 2, 4] THEN
+        RAISE EXCEPTION 'order is wrong after update and rebalance';
+    END IF;
+
+    SET LOCAL enable_seqscan = off;
+    IF (SELECT array_agg(id ORDER BY position) FROM items
+        WHERE position > (SELECT min(position) FROM items)) <> ARRAY[3, 2, 4] THEN
+        RAISE EXCEPTION 'the index built before the update returned wrong rows';
+    END IF;
+END $$;
+
+-- The generators are immutable and parallel safe after the update
+CREATE INDEX items_after_idx ON items (lexo_after(position));
+ALTER TABLE items ADD COLUMN next_position lexo GENERATED ALWAYS AS (lexo_after(position)) STORED;
+
+SET debug_parallel_query = on;
+DO $$
+DECLARE
+    line text;
+    plan text := '';
+BEGIN
+    FOR line IN EXPLAIN SELECT count(*) FROM items WHERE lexo_between(position, NULL) > position LOOP
+        plan := plan || line || E'\n';
+    END LOOP;
+    IF plan NOT LIKE '%Gather%' THEN
+        RAISE EXCEPTION 'lexo_between() prevented a parallel plan:%', E'\n' || plan;
+    END IF;
+
+    IF (SELECT count(*) FROM items WHERE lexo_between(position, NULL) > position) <> 4 THEN
+        RAISE EXCEPTION 'parallel scan returned wrong rows';
+    END IF;
+END $$;
+SQL
+
+$PSQL -v ON_ERROR_STOP=1 -d postgres -c "DROP DATABASE $DB"
+echo "upgrade from 0.6.0 OK"